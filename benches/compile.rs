@@ -0,0 +1,68 @@
+//! Times a formula evaluated for many values of its variables, parsed again
+//! for each row with `evaluate_with_context`, with `eval_ast` and with
+//! `compile`
+//!
+//! Run with `cargo bench --bench compile`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use calculator_rust::{Context, compile, eval_ast, evaluate_with_context, parse};
+
+const ROWS: usize = 200_000;
+
+const FORMULA: &str =
+    "price * qty * (1 + tax / 100) - (qty > 10 ? 0.05 * price * qty : 0) + sqrt(qty)";
+
+fn main() {
+    let rows: Vec<(f64, f64, f64)> = (0..ROWS)
+        .map(|i| ((i % 97) as f64 + 0.5, (i % 23) as f64, (i % 5) as f64 * 2.5))
+        .collect();
+
+    let mut context = Context::new();
+    let start = Instant::now();
+    let mut text_total = 0.0;
+    for &(price, qty, tax) in &rows {
+        context.set("price", price).unwrap();
+        context.set("qty", qty).unwrap();
+        context.set("tax", tax).unwrap();
+        text_total += evaluate_with_context(black_box(FORMULA), &mut context).unwrap();
+    }
+    let text = start.elapsed();
+
+    let expr = parse(FORMULA).unwrap();
+    let start = Instant::now();
+    let mut tree_total = 0.0;
+    for &(price, qty, tax) in &rows {
+        context.set("price", price).unwrap();
+        context.set("qty", qty).unwrap();
+        context.set("tax", tax).unwrap();
+        tree_total += eval_ast(black_box(&expr), &context).unwrap();
+    }
+    let tree = start.elapsed();
+
+    let compiled = compile(FORMULA).unwrap();
+    let slots = ["price", "qty", "tax"].map(|name| compiled.slot(name).unwrap());
+    let mut vars = compiled.vars();
+    let start = Instant::now();
+    let mut compiled_total = 0.0;
+    for &(price, qty, tax) in &rows {
+        vars.set_slot(slots[0], price);
+        vars.set_slot(slots[1], qty);
+        vars.set_slot(slots[2], tax);
+        compiled_total += black_box(&compiled).eval(&vars).unwrap();
+    }
+    let program = start.elapsed();
+
+    assert_eq!(text_total, tree_total);
+    assert_eq!(tree_total, compiled_total);
+    println!("{} rows of {}", ROWS, FORMULA);
+    println!("evaluate:        {:>10.2?}", text);
+    println!("eval_ast:        {:>10.2?}", tree);
+    println!("CompiledExpr:    {:>10.2?}", program);
+    println!(
+        "speedup:         {:>10.1}x over evaluate, {:.1}x over eval_ast",
+        text.as_secs_f64() / program.as_secs_f64(),
+        tree.as_secs_f64() / program.as_secs_f64()
+    );
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+
+use crate::error::CalcError;
+
+/// Decimal places of seconds kept by [`dms`], which hides the rounding error of
+/// converting to decimal degrees and back
+const SECONDS_DIGITS: i32 = 6;
+
+/// An angle in degrees, minutes and seconds, as returned by [`dms`]
+///
+/// Displays in the form accepted as input, such as `45°30'15"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dms {
+    pub negative: bool,
+    /// Whole degrees
+    pub degrees: f64,
+    /// Whole minutes, from 0 to 59
+    pub minutes: f64,
+    /// Seconds, at least 0 and less than 60
+    pub seconds: f64,
+}
+
+impl fmt::Display for Dms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}°{}'{}\"", self.degrees, self.minutes, self.seconds)
+    }
+}
+
+/// Splits an angle in decimal degrees into degrees, minutes and seconds
+///
+/// This is the library form of the `dms` function, whose result isn't a number
+/// and so can't be part of an expression. Seconds are rounded to 6 decimal
+/// places.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{dms, evaluate};
+///
+/// assert_eq!(dms(45.5).unwrap().to_string(), "45°30'0\"");
+/// assert_eq!(dms(-10.25).unwrap().to_string(), "-10°15'0\"");
+/// assert_eq!(dms(0.0001).unwrap().to_string(), "0°0'0.36\"");
+/// assert_eq!(dms(359.9999999999).unwrap().to_string(), "360°0'0\"");
+/// // In radian mode, `°` makes an angle in radians
+/// let angle = evaluate("45°30'15\"").unwrap();
+/// assert_eq!(dms(angle.to_degrees()).unwrap().to_string(), "45°30'15\"");
+/// assert!(dms(f64::NAN).is_err());
+/// ```
+pub fn dms(angle: f64) -> Result<Dms, CalcError> {
+    if !angle.is_finite() {
+        return Err(CalcError::other(format!(
+            "dms argument must be finite, got {}",
+            angle
+        )));
+    }
+    let scale = 10f64.powi(SECONDS_DIGITS);
+    // Rounding the total first carries a second of 59.9999999 into the minutes
+    let total = (angle.abs() * 3600.0 * scale).round() / scale;
+    let degrees = (total / 3600.0).floor();
+    let minutes = ((total - degrees * 3600.0) / 60.0).floor();
+    let seconds = ((total - degrees * 3600.0 - minutes * 60.0) * scale).round() / scale;
+    Ok(Dms {
+        negative: angle < 0.0 && total != 0.0,
+        degrees,
+        minutes,
+        seconds,
+    })
+}
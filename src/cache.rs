@@ -0,0 +1,145 @@
+//! A bounded cache of parsed inputs, so that a line evaluated again isn't
+//! tokenized and parsed again
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::CalcError;
+use crate::options::{AngleMode, EvalOptions, Separator};
+use crate::parser::{self, Statement};
+
+/// The settings that change how an input is tokenized and parsed, once it has
+/// been normalized, which a cached parse is only reused with
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Syntax {
+    implicit_multiplication: bool,
+    angle_mode: AngleMode,
+    decimal_separator: Separator,
+    si_suffixes: bool,
+    currency_symbols: bool,
+}
+
+impl Syntax {
+    fn of(options: &EvalOptions) -> Self {
+        Syntax {
+            implicit_multiplication: options.implicit_multiplication,
+            angle_mode: options.angle_mode,
+            decimal_separator: options.decimal_separator,
+            si_suffixes: options.si_suffixes,
+            currency_symbols: options.currency_symbols,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    syntax: Syntax,
+    statements: Vec<Statement>,
+    /// When the entry was last used, its key in `ExprCache::recent`
+    used: u64,
+}
+
+/// A cache of the parsed forms of the most recently evaluated inputs, for a
+/// [`Calculator`](crate::Calculator) made with
+/// [`Calculator::with_cache`](crate::Calculator::with_cache)
+///
+/// Once the cache is full, the input used least recently is forgotten to make
+/// room for another. A parse is only reused with the settings that it was made
+/// with, such as [`EvalOptions::decimal_separator`], so changing one of them
+/// parses the input again. Inputs that fail to parse aren't kept.
+#[derive(Debug, Clone, Default)]
+pub struct ExprCache {
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+    /// The inputs in `entries` by when they were last used
+    recent: BTreeMap<u64, String>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl ExprCache {
+    /// Creates a cache that holds up to `capacity` inputs
+    pub fn new(capacity: usize) -> Self {
+        ExprCache {
+            capacity,
+            ..ExprCache::default()
+        }
+    }
+
+    /// Returns how many inputs the cache can hold
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns how many inputs the cache holds
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache holds no inputs
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns how many times an input was found already parsed
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns how many times an input had to be parsed
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Forgets every input, keeping the counts of hits and misses
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recent.clear();
+    }
+
+    /// Parses normalized input like [`parser::parse_statements`], reusing an
+    /// earlier parse of the same input with the same syntax
+    pub(crate) fn parse(
+        &mut self,
+        input: &str,
+        options: &EvalOptions,
+    ) -> Result<Vec<Statement>, CalcError> {
+        let syntax = Syntax::of(options);
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(input)
+            && entry.syntax == syntax
+        {
+            self.hits += 1;
+            let input = self
+                .recent
+                .remove(&entry.used)
+                .expect("entries are in recent");
+            entry.used = self.clock;
+            self.recent.insert(self.clock, input);
+            return Ok(entry.statements.clone());
+        }
+
+        self.misses += 1;
+        let statements = parser::parse_statements(input, options)?;
+        if self.capacity == 0 {
+            return Ok(statements);
+        }
+        if let Some(stale) = self.entries.remove(input) {
+            self.recent.remove(&stale.used);
+        } else if self.entries.len() == self.capacity
+            && let Some((_, oldest)) = self.recent.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.recent.insert(self.clock, input.to_string());
+        self.entries.insert(
+            input.to_string(),
+            Entry {
+                syntax,
+                statements: statements.clone(),
+                used: self.clock,
+            },
+        );
+        Ok(statements)
+    }
+}
@@ -0,0 +1,201 @@
+//! A calculator session that keeps its variables, settings and history
+
+use crate::cache::ExprCache;
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::options::EvalOptions;
+use crate::value::Value;
+use crate::{continue_previous, evaluate_value_cached, evaluate_value_with_context};
+
+/// An input given to [`Calculator::eval`] and what it evaluated to
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub input: String,
+    pub result: Result<Value, CalcError>,
+}
+
+/// A calculator that remembers what happened on earlier lines, for embedding
+/// in a program such as a chat bot or a GUI
+///
+/// A calculator holds a [`Context`] with its variables, functions, settings
+/// and `ans`, the result of the last evaluation, along with the history of
+/// the inputs it evaluated. A line that starts with an operator, such as
+/// `*2`, continues from `ans`, as [`continue_previous`] describes.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{AngleMode, CalcError, Calculator, EvalOptions, Value};
+///
+/// let mut calculator = Calculator::new();
+/// assert_eq!(calculator.eval("x = 6 * 7"), Ok(Value::Number(42.0)));
+/// assert_eq!(calculator.eval("x / 2"), Ok(Value::Number(21.0)));
+/// assert_eq!(calculator.eval("* 2"), Ok(Value::Number(42.0)));
+/// assert_eq!(calculator.ans(), Some(42.0));
+/// assert_eq!(calculator.eval("f(t) = t^2; f(3)"), Ok(Value::Number(9.0)));
+/// assert_eq!(calculator.eval("hex(255)"), Ok(Value::Text("0xff".to_string())));
+/// assert!(matches!(calculator.eval("y + 1"), Err(CalcError::UnknownIdentifier { .. })));
+///
+/// calculator.set_var("rate", 0.25).unwrap();
+/// assert_eq!(calculator.get_var("rate"), Some(0.25));
+/// assert_eq!(calculator.eval("100 * rate"), Ok(Value::Number(25.0)));
+/// assert!(calculator.set_var("pi", 3.0).is_err());
+///
+/// let history = calculator.history();
+/// assert_eq!(history.len(), 7);
+/// assert_eq!(history[2].input, "* 2");
+/// assert_eq!(history[2].result, Ok(Value::Number(42.0)));
+/// assert!(history[5].result.is_err());
+///
+/// calculator.clear();
+/// assert!(calculator.history().is_empty());
+/// assert_eq!(calculator.get_var("x"), None);
+/// assert_eq!(calculator.ans(), None);
+///
+/// calculator.context_mut().register_fn("half", 1, |args| Ok(args[0] / 2.0)).unwrap();
+/// calculator.clear();
+/// assert_eq!(calculator.eval("half(9)"), Ok(Value::Number(4.5)));
+///
+/// let mut degrees = Calculator::with_options(EvalOptions::new().angle_mode(AngleMode::Degrees));
+/// assert_eq!(degrees.eval("round(sin(30), 9)"), Ok(Value::Number(0.5)));
+/// degrees.clear();
+/// assert_eq!(degrees.options().angle_mode, AngleMode::Degrees);
+///
+/// // A calculator can be shared between threads behind a mutex
+/// fn assert_send<T: Send>() {}
+/// assert_send::<Calculator>();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Calculator {
+    context: Context,
+    history: Vec<HistoryEntry>,
+    cache: Option<ExprCache>,
+}
+
+impl Calculator {
+    /// Creates a calculator with the default settings
+    pub fn new() -> Self {
+        Calculator::default()
+    }
+
+    /// Creates a calculator with custom settings
+    pub fn with_options(options: EvalOptions) -> Self {
+        Calculator {
+            context: Context::with_options(options),
+            history: Vec::new(),
+            cache: None,
+        }
+    }
+
+    /// Creates a calculator with the default settings that keeps the parsed
+    /// forms of the last `capacity` inputs in an [`ExprCache`], so that an
+    /// input evaluated again isn't parsed again
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{Calculator, Separator, Value};
+    ///
+    /// let mut calculator = Calculator::with_cache(2);
+    /// calculator.set_var("x", 3.0).unwrap();
+    /// assert_eq!(calculator.eval("2x + 1"), Ok(Value::Number(7.0)));
+    /// calculator.set_var("x", 4.0).unwrap();
+    /// assert_eq!(calculator.eval("2x + 1"), Ok(Value::Number(9.0)));
+    /// let cache = calculator.cache().unwrap();
+    /// assert_eq!((cache.hits(), cache.misses()), (1, 1));
+    ///
+    /// // A parse is only reused with the settings it was made with
+    /// calculator.options_mut().implicit_multiplication = false;
+    /// assert!(calculator.eval("2x + 1").is_err());
+    /// calculator.options_mut().implicit_multiplication = true;
+    /// assert_eq!(calculator.eval("2x + 1"), Ok(Value::Number(9.0)));
+    /// assert_eq!(calculator.eval("1.250 * 2"), Ok(Value::Number(2.5)));
+    /// calculator.options_mut().decimal_separator = Separator::Comma;
+    /// assert_eq!(calculator.eval("1.250 * 2"), Ok(Value::Number(2500.0)));
+    /// let cache = calculator.cache().unwrap();
+    /// assert_eq!((cache.hits(), cache.misses()), (2, 4));
+    ///
+    /// // The input used least recently makes room for another
+    /// assert_eq!(cache.len(), 2);
+    /// calculator.eval("1 + 1").unwrap();
+    /// calculator.eval("1.250 * 2").unwrap();
+    /// calculator.eval("2x + 1").unwrap();
+    /// let cache = calculator.cache().unwrap();
+    /// assert_eq!((cache.hits(), cache.misses(), cache.len()), (3, 6, 2));
+    /// assert!(Calculator::new().cache().is_none());
+    /// ```
+    pub fn with_cache(capacity: usize) -> Self {
+        Calculator {
+            cache: Some(ExprCache::new(capacity)),
+            ..Calculator::default()
+        }
+    }
+
+    /// Evaluates a line of input like
+    /// [`evaluate_value_with_context`], keeping any variables and functions it
+    /// defines for later lines, and adds it to the history
+    pub fn eval(&mut self, input: &str) -> Result<Value, CalcError> {
+        let result =
+            continue_previous(input, &self.context).and_then(|input| match &mut self.cache {
+                Some(cache) => evaluate_value_cached(&input, &mut self.context, cache),
+                None => evaluate_value_with_context(&input, &mut self.context),
+            });
+        self.history.push(HistoryEntry {
+            input: input.to_string(),
+            result: result.clone(),
+        });
+        result
+    }
+
+    /// Assigns a variable like [`Context::set`]
+    pub fn set_var(&mut self, name: &str, value: f64) -> Result<(), CalcError> {
+        self.context.set(name, value)
+    }
+
+    /// Returns the value of a variable, or `None` if it has not been assigned
+    pub fn get_var(&self, name: &str) -> Option<f64> {
+        self.context.get(name)
+    }
+
+    /// Returns the result of the last evaluation to a number
+    pub fn ans(&self) -> Option<f64> {
+        self.context.ans()
+    }
+
+    /// Returns the inputs evaluated so far, oldest first
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    /// Forgets the variables, defined functions, `ans` and history, keeping
+    /// the settings, the functions registered with [`Context::register_fn`]
+    /// and the cache of parsed inputs
+    pub fn clear(&mut self) {
+        self.context.clear();
+        self.history.clear();
+    }
+
+    /// Returns the cache of parsed inputs, if the calculator was made with
+    /// [`Calculator::with_cache`]
+    pub fn cache(&self) -> Option<&ExprCache> {
+        self.cache.as_ref()
+    }
+
+    /// Returns the settings used when evaluating
+    pub fn options(&self) -> &EvalOptions {
+        self.context.options()
+    }
+
+    /// Returns the settings for changing, for example to switch the angle mode
+    pub fn options_mut(&mut self) -> &mut EvalOptions {
+        self.context.options_mut()
+    }
+
+    /// Returns the context that lines are evaluated in
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Returns the context for changing, for example to insert constants
+    pub fn context_mut(&mut self) -> &mut Context {
+        &mut self.context
+    }
+}
@@ -0,0 +1,395 @@
+//! Compilation of an expression to a flat program, for evaluating it with
+//! many values of its variables
+
+use std::sync::Arc;
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions;
+use crate::lexer::Span;
+use crate::options::EvalOptions;
+use crate::parser::{BinaryOp, Expr, UnaryOp};
+use crate::{constant, start_evaluation};
+
+/// A built-in function as a compiled program calls it
+type Call = fn(&[f64], &EvalOptions) -> Result<f64, CalcError>;
+
+/// One step of a compiled program, which works on a stack of numbers
+#[derive(Debug, Clone)]
+enum Op {
+    Push(f64),
+    /// Pushes the value of the variable in `slot`, which is written at
+    /// `span`
+    Load {
+        slot: usize,
+        span: Span,
+    },
+    /// Applies the operator written at `span` to the top number
+    Unary {
+        op: UnaryOp,
+        span: Span,
+    },
+    /// Applies the operator written at `span` to the top two numbers
+    Binary {
+        op: BinaryOp,
+        span: Span,
+    },
+    /// Replaces the top `count` numbers with the result of a built-in
+    /// function of them, whose name is written at `span`
+    Call {
+        name: String,
+        call: Call,
+        count: usize,
+        span: Span,
+    },
+    /// Pops a number, going on at `target` if it is zero
+    JumpIfZero(usize),
+    Jump(usize),
+    /// Pushes the value of a tree, such as `sum(i, 1, n, i^2)`, which is
+    /// evaluated as it is with the variables in scope
+    Tree(Box<Expr>),
+}
+
+/// An expression compiled by [`compile`], which evaluates it for the values of
+/// its variables in a [`Vars`] without reading it again
+///
+/// A compiled expression can be shared between threads, each evaluating it
+/// with variables of its own.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    ops: Vec<Op>,
+    /// The names of the variables, by slot
+    names: Arc<[String]>,
+    /// The most numbers on the stack at once
+    depth: usize,
+    /// The settings, for the operators and functions that depend on them
+    context: Context,
+}
+
+/// Values for the variables of a [`CompiledExpr`], made by
+/// [`CompiledExpr::vars`]
+///
+/// Each variable has a slot, so that setting it by [`Vars::set_slot`] in a
+/// loop doesn't look its name up.
+#[derive(Debug, Clone)]
+pub struct Vars {
+    names: Arc<[String]>,
+    values: Vec<Option<f64>>,
+}
+
+impl Vars {
+    /// Sets the variable called `name`, failing if the expression has none
+    pub fn set(&mut self, name: &str, value: f64) -> Result<(), CalcError> {
+        let Some(slot) = self.names.iter().position(|known| known == name) else {
+            return Err(CalcError::other(format!(
+                "the expression has no variable '{}'",
+                name
+            )));
+        };
+        self.values[slot] = Some(value);
+        Ok(())
+    }
+
+    /// Sets the variable in `slot`, as given by [`CompiledExpr::slot`]
+    ///
+    /// # Panics
+    /// If the expression has fewer variables than `slot + 1`.
+    pub fn set_slot(&mut self, slot: usize, value: f64) {
+        self.values[slot] = Some(value);
+    }
+
+    /// Returns the value of the variable called `name`, or `None` if it isn't
+    /// set
+    pub fn get(&self, name: &str) -> Option<f64> {
+        let slot = self.names.iter().position(|known| known == name)?;
+        self.values[slot]
+    }
+}
+
+impl CompiledExpr {
+    /// Returns the names of the variables that the expression reads, in the
+    /// order of their slots
+    pub fn variables(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the slot of the variable called `name`, for [`Vars::set_slot`]
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|known| known == name)
+    }
+
+    /// Returns values for the variables of the expression, none of them set
+    pub fn vars(&self) -> Vars {
+        Vars {
+            names: Arc::clone(&self.names),
+            values: vec![None; self.names.len()],
+        }
+    }
+
+    /// Evaluates the expression with the variables in `vars`
+    ///
+    /// Results and errors are those of [`eval_ast`](crate::eval_ast) for the
+    /// same expression, with the variables in a context. A variable that
+    /// isn't set is a [`CalcError::UnknownIdentifier`] when its value is
+    /// needed, so `x > 0 ? x : y` needs no `y` while `x` is positive.
+    pub fn eval(&self, vars: &Vars) -> Result<f64, CalcError> {
+        if !Arc::ptr_eq(&self.names, &vars.names) {
+            return Err(CalcError::other(
+                "the variables were made for another expression",
+            ));
+        }
+        start_evaluation(&self.context);
+        self.run(&vars.values)
+    }
+
+    fn run(&self, values: &[Option<f64>]) -> Result<f64, CalcError> {
+        let options = self.context.options();
+        let mut stack = Vec::with_capacity(self.depth);
+        let mut pc = 0;
+        while let Some(op) = self.ops.get(pc) {
+            pc += 1;
+            match op {
+                Op::Push(value) => stack.push(*value),
+                Op::Load { slot, span } => match values[*slot] {
+                    Some(value) => stack.push(value),
+                    None => {
+                        return Err(CalcError::UnknownIdentifier {
+                            name: self.names[*slot].clone(),
+                            span: *span,
+                        });
+                    }
+                },
+                Op::Unary { op, span } => {
+                    let value = stack.pop().expect("an operand");
+                    stack.push(eval::unary(*op, value, options).map_err(|e| e.at(*span))?);
+                }
+                Op::Binary { op, span } => {
+                    let rhs = stack.pop().expect("a right operand");
+                    let lhs = stack.pop().expect("a left operand");
+                    stack.push(eval::binary(*op, lhs, rhs, options).map_err(|e| e.at(*span))?);
+                }
+                Op::Call {
+                    name,
+                    call,
+                    count,
+                    span,
+                } => {
+                    let args = stack.split_off(stack.len() - count);
+                    let result = call(&args, options).map_err(|e| e.at(*span))?;
+                    if args.iter().all(|x| x.is_finite()) {
+                        eval::overflowed(result, || name.clone(), options)
+                            .map_err(|e| e.at(*span))?;
+                    }
+                    stack.push(result);
+                }
+                Op::JumpIfZero(target) => {
+                    if stack.pop().expect("a condition") == 0.0 {
+                        pc = *target;
+                    }
+                }
+                Op::Jump(target) => pc = *target,
+                Op::Tree(expr) => {
+                    let locals: Vec<(&str, f64)> = self
+                        .names
+                        .iter()
+                        .zip(values)
+                        .filter_map(|(name, value)| Some((name.as_str(), (*value)?)))
+                        .collect();
+                    stack.push(eval::eval_scoped(expr, &self.context, &locals)?);
+                }
+            }
+        }
+        Ok(stack.pop().expect("a result"))
+    }
+}
+
+/// Compiles a parsed expression to the ops of a [`CompiledExpr`]
+struct Compiler {
+    ops: Vec<Op>,
+    names: Vec<String>,
+    /// Whether the names are all the variables there may be
+    fixed: bool,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Compiler {
+    /// The slot of the variable called `name`, written at `span`, which gets
+    /// one if it has none and the variables aren't fixed
+    fn slot(&mut self, name: &str, span: Span) -> Result<usize, CalcError> {
+        match self.names.iter().position(|known| known == name) {
+            Some(slot) => Ok(slot),
+            None if self.fixed => Err(CalcError::UnknownIdentifier {
+                name: name.to_string(),
+                span,
+            }),
+            None => {
+                self.names.push(name.to_string());
+                Ok(self.names.len() - 1)
+            }
+        }
+    }
+
+    /// Adds an op, which changes the height of the stack by `change`
+    fn emit(&mut self, op: Op, change: isize) {
+        self.ops.push(op);
+        self.depth = self.depth.saturating_add_signed(change);
+        self.max_depth = self.max_depth.max(self.depth);
+    }
+
+    fn compile(&mut self, expr: &Expr) -> Result<(), CalcError> {
+        match expr {
+            Expr::Number { value, .. } => self.emit(Op::Push(*value), 1),
+            Expr::Var { name, span } => match constant(name) {
+                Some(value) => self.emit(Op::Push(value), 1),
+                None => {
+                    let slot = self.slot(name, *span)?;
+                    self.emit(Op::Load { slot, span: *span }, 1);
+                }
+            },
+            Expr::Unary { op, operand, span } => {
+                self.compile(operand)?;
+                self.emit(
+                    Op::Unary {
+                        op: *op,
+                        span: *span,
+                    },
+                    0,
+                );
+            }
+            Expr::Binary { op, lhs, rhs, span } => {
+                self.compile(lhs)?;
+                self.compile(rhs)?;
+                self.emit(
+                    Op::Binary {
+                        op: *op,
+                        span: *span,
+                    },
+                    -1,
+                );
+            }
+            Expr::Call { name, args, span } => {
+                let function =
+                    functions::lookup(name).ok_or_else(|| CalcError::UnknownFunction {
+                        name: name.clone(),
+                        span: *span,
+                    })?;
+                function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+                for arg in args {
+                    self.compile(arg)?;
+                }
+                let count = args.len();
+                let op = Op::Call {
+                    name: name.clone(),
+                    call: function.call,
+                    count,
+                    span: *span,
+                };
+                self.emit(op, 1 - count as isize);
+            }
+            // Only the branch taken is evaluated, as in `eval`
+            Expr::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.compile(condition)?;
+                let jump_if_zero = self.ops.len();
+                self.emit(Op::JumpIfZero(0), -1);
+                self.compile(then)?;
+                let jump = self.ops.len();
+                self.emit(Op::Jump(0), -1);
+                self.ops[jump_if_zero] = Op::JumpIfZero(self.ops.len());
+                self.compile(otherwise)?;
+                self.ops[jump] = Op::Jump(self.ops.len());
+            }
+            Expr::List { span, .. } => {
+                return Err(CalcError::other_at(
+                    "a list such as [1, 2] isn't a single number",
+                    *span,
+                ));
+            }
+            Expr::Binding { .. } => {
+                self.slots_in(expr, &mut Vec::new())?;
+                self.emit(Op::Tree(Box::new(expr.clone())), 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives a slot to each variable in `expr` that isn't a constant or bound
+    /// by a form around it, whose names are in `bound`
+    fn slots_in<'a>(&mut self, expr: &'a Expr, bound: &mut Vec<&'a str>) -> Result<(), CalcError> {
+        match expr {
+            Expr::Number { .. } => {}
+            Expr::Var { name, span } => {
+                if !bound.contains(&name.as_str()) && constant(name).is_none() {
+                    self.slot(name, *span)?;
+                }
+            }
+            Expr::Unary { operand, .. } => self.slots_in(operand, bound)?,
+            Expr::Binary { lhs, rhs, .. } => {
+                self.slots_in(lhs, bound)?;
+                self.slots_in(rhs, bound)?;
+            }
+            Expr::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                for expr in [condition, then, otherwise] {
+                    self.slots_in(expr, bound)?;
+                }
+            }
+            Expr::Call { args, .. } | Expr::List { items: args, .. } => {
+                for arg in args {
+                    self.slots_in(arg, bound)?;
+                }
+            }
+            Expr::Binding {
+                var, body, args, ..
+            } => {
+                for arg in args {
+                    self.slots_in(arg, bound)?;
+                }
+                bound.push(var);
+                self.slots_in(body, bound)?;
+                bound.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compiles a parsed expression, with its variables limited to `variables`
+/// if given, in the order of their slots
+pub(crate) fn compile(
+    expr: &Expr,
+    variables: Option<&[&str]>,
+    options: &EvalOptions,
+) -> Result<CompiledExpr, CalcError> {
+    let mut compiler = Compiler {
+        ops: Vec::new(),
+        names: Vec::new(),
+        fixed: variables.is_some(),
+        depth: 0,
+        max_depth: 0,
+    };
+    for name in variables.unwrap_or_default() {
+        if constant(name).is_some() {
+            return Err(CalcError::other(format!(
+                "cannot assign to constant '{}'",
+                name
+            )));
+        }
+        compiler.names.push(name.to_string());
+    }
+    compiler.compile(expr)?;
+    Ok(CompiledExpr {
+        ops: compiler.ops,
+        names: compiler.names.into(),
+        depth: compiler.max_depth,
+        context: Context::with_options(options.clone()),
+    })
+}
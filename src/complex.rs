@@ -0,0 +1,320 @@
+//! Evaluation over complex numbers, enabled by the `complex` feature
+
+use std::fmt;
+
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions;
+use crate::options::EvalOptions;
+use crate::parser::{self, BinaryOp, Expr, UnaryOp};
+use crate::unicode;
+
+/// A complex number `re + im*i`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    /// The imaginary unit `i`
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    /// Returns true if the imaginary part is zero
+    pub fn is_real(&self) -> bool {
+        self.im == 0.0
+    }
+
+    fn real(re: f64) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+
+    fn div(self, other: Complex) -> Result<Complex, CalcError> {
+        let denominator = other.re * other.re + other.im * other.im;
+        if denominator == 0.0 {
+            return Err(CalcError::division_by_zero());
+        }
+        Ok(Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        ))
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    fn exp(self) -> Complex {
+        let scale = self.re.exp();
+        Complex::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+
+    /// Principal natural logarithm, undefined at zero
+    fn ln(self) -> Result<Complex, CalcError> {
+        if self.re == 0.0 && self.im == 0.0 {
+            return Err(CalcError::domain("ln is undefined for 0"));
+        }
+        Ok(Complex::new(self.abs().ln(), self.arg()))
+    }
+
+    /// Principal square root, which is exact for non-negative reals
+    fn sqrt(self) -> Complex {
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    /// Raises to a power, by repeated squaring for integer exponents so that
+    /// `(1+i)^2` is exactly `2i`
+    fn pow(self, exponent: Complex) -> Result<Complex, CalcError> {
+        if exponent.is_real() && exponent.re.fract() == 0.0 && exponent.re.abs() <= 1024.0 {
+            let mut result = Complex::real(1.0);
+            let mut base = self;
+            let mut n = exponent.re.abs() as u32;
+            while n > 0 {
+                if n & 1 == 1 {
+                    result = result.mul(base);
+                }
+                base = base.mul(base);
+                n >>= 1;
+            }
+            return if exponent.re < 0.0 {
+                Complex::real(1.0).div(result)
+            } else {
+                Ok(result)
+            };
+        }
+        if self.re == 0.0 && self.im == 0.0 {
+            return if exponent.re > 0.0 {
+                Ok(Complex::real(0.0))
+            } else {
+                Err(CalcError::division_by_zero())
+            };
+        }
+        Ok(exponent.mul(self.ln()?).exp())
+    }
+}
+
+/// Formats as `a+bi`, leaving out a zero part, so real numbers print as usual
+///
+/// ```
+/// use calculator_rust::Complex;
+///
+/// assert_eq!(Complex::new(11.0, -2.0).to_string(), "11-2i");
+/// assert_eq!(Complex::new(0.0, 1.0).to_string(), "i");
+/// assert_eq!(Complex::new(0.0, -2.5).to_string(), "-2.5i");
+/// assert_eq!(Complex::new(3.0, 0.0).to_string(), "3");
+/// ```
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.im == 0.0 {
+            return write!(f, "{}", self.re);
+        }
+        if self.re != 0.0 {
+            write!(f, "{}", self.re)?;
+            if self.im > 0.0 {
+                write!(f, "+")?;
+            }
+        }
+        match self.im {
+            1.0 => write!(f, "i"),
+            -1.0 => write!(f, "-i"),
+            im => write!(f, "{}i", im),
+        }
+    }
+}
+
+/// Evaluates a mathematical expression over the complex numbers
+///
+/// The syntax is the same as for [`evaluate`](crate::evaluate), with `i` as
+/// the imaginary unit, so `3+4i` is a complex number. `sqrt` and `ln` accept
+/// negative numbers, `abs` gives the modulus, and `re`, `im`, `arg` and `conj`
+/// take a complex number apart. `sin` and `cos` of a non-real argument work in
+/// radians. Other operators and functions work when their operands are real,
+/// and fail with "not supported for complex values" otherwise.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Complex, Span, evaluate_complex};
+///
+/// assert_eq!(evaluate_complex("(3+4i)*(1-2i)"), Ok(Complex::new(11.0, -2.0)));
+/// assert_eq!(evaluate_complex("(3+4i)*(1-2i)").unwrap().to_string(), "11-2i");
+/// assert_eq!(evaluate_complex("sqrt(-1)"), Ok(Complex::I));
+/// assert_eq!(evaluate_complex("sqrt(-4) + 1"), Ok(Complex::new(1.0, 2.0)));
+/// assert_eq!(evaluate_complex("i^2"), Ok(Complex::new(-1.0, 0.0)));
+/// assert_eq!(evaluate_complex("(1+i)^2").unwrap().to_string(), "2i");
+/// assert_eq!(evaluate_complex("1/i"), Ok(Complex::new(0.0, -1.0)));
+/// assert_eq!(evaluate_complex("abs(3+4i)"), Ok(Complex::new(5.0, 0.0)));
+/// assert_eq!(evaluate_complex("conj(2+3i)").unwrap().to_string(), "2-3i");
+/// assert_eq!(evaluate_complex("re(2+3i) + im(2+3i)"), Ok(Complex::new(5.0, 0.0)));
+/// assert_eq!(evaluate_complex("2^10 + max(1, 2)").unwrap().to_string(), "1026");
+/// assert!((evaluate_complex("e^(i*pi)").unwrap().re + 1.0).abs() < 1e-12);
+/// assert_eq!(
+///     evaluate_complex("(2i)/0"),
+///     Err(CalcError::DivisionByZero { span: Some(Span { start: 4, end: 5 }) })
+/// );
+/// assert!(evaluate_complex("floor(1+i)").unwrap_err().to_string().contains("not supported for complex values"));
+/// assert!(evaluate_complex("i < 1").unwrap_err().to_string().contains("not supported for complex values"));
+/// ```
+pub fn evaluate_complex(input: &str) -> Result<Complex, CalcError> {
+    let options = EvalOptions::default();
+    let (input, origins) = unicode::normalize_with_origins(input, &options);
+    let expr = parser::parse(&input, &options).map_err(|e| origins.error(e))?;
+    eval_complex(&expr, &options).map_err(|e| origins.error(e))
+}
+
+fn eval_complex(expr: &Expr, options: &EvalOptions) -> Result<Complex, CalcError> {
+    let eval = |expr| eval_complex(expr, options);
+    match expr {
+        Expr::Number { value, .. } => Ok(Complex::real(*value)),
+        Expr::Var { name, .. } if name == "i" => Ok(Complex::I),
+        Expr::Var { name, span } => {
+            crate::constant(name)
+                .map(Complex::real)
+                .ok_or_else(|| CalcError::UnknownIdentifier {
+                    name: name.clone(),
+                    span: *span,
+                })
+        }
+        Expr::Unary { op, operand, span } => {
+            let value = eval(operand)?;
+            let result = match op {
+                UnaryOp::Neg => Ok(Complex::new(-value.re, -value.im)),
+                UnaryOp::Pos => Ok(value),
+                UnaryOp::Percent => Ok(Complex::new(value.re / 100.0, value.im / 100.0)),
+                _ => {
+                    let value = real_operand(value, || "this operator".to_string())?;
+                    eval::unary(*op, value, options).map(Complex::real)
+                }
+            };
+            result.map_err(|e| e.at(*span))
+        }
+        Expr::Binary { op, lhs, rhs, span } => {
+            let (lhs, rhs) = (eval(lhs)?, eval(rhs)?);
+            let result = match op {
+                BinaryOp::Add => Ok(lhs.add(rhs)),
+                BinaryOp::Sub => Ok(lhs.sub(rhs)),
+                BinaryOp::Mul => Ok(lhs.mul(rhs)),
+                BinaryOp::Div => lhs.div(rhs),
+                // Keep the real result where there is one, so `(-8)^(1/3)` is
+                // still NaN rather than a complex root
+                BinaryOp::Pow if lhs.is_real() && rhs.is_real() => {
+                    Ok(Complex::real(lhs.re.powf(rhs.re)))
+                }
+                BinaryOp::Pow => lhs.pow(rhs),
+                BinaryOp::Equal | BinaryOp::NotEqual if !lhs.is_real() || !rhs.is_real() => {
+                    let equal = lhs == rhs;
+                    let holds = equal == (*op == BinaryOp::Equal);
+                    Ok(Complex::real(if holds { 1.0 } else { 0.0 }))
+                }
+                _ => {
+                    let name = || format!("operator '{}'", op.symbol());
+                    let lhs = real_operand(lhs, name)?;
+                    let rhs = real_operand(rhs, name)?;
+                    eval::binary(*op, lhs, rhs, options).map(Complex::real)
+                }
+            };
+            result.map_err(|e| e.at(*span))
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            if eval(condition)? != Complex::real(0.0) {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+        Expr::Binding { form, .. } => Err(CalcError::other(format!(
+            "{} is not supported for complex values",
+            form.name()
+        ))),
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "lists are not supported for complex values",
+            *span,
+        )),
+        Expr::Call { name, args, span } => {
+            let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            let result = call(name, &values, options).unwrap_or_else(|| {
+                let function =
+                    functions::lookup(name).ok_or_else(|| CalcError::UnknownFunction {
+                        name: name.clone(),
+                        span: *span,
+                    })?;
+                function.check_arity(values.len())?;
+                let reals = values
+                    .iter()
+                    .map(|value| real_operand(*value, || format!("function '{}'", name)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                (function.call)(&reals, options).map(Complex::real)
+            });
+            result.map_err(|e| e.at(*span))
+        }
+    }
+}
+
+/// Calls a function that has a complex extension, or returns `None` to fall
+/// back to the real version
+fn call(name: &str, args: &[Complex], options: &EvalOptions) -> Option<Result<Complex, CalcError>> {
+    let [z] = args else {
+        return None;
+    };
+    let result = match name {
+        "sqrt" => Ok(z.sqrt()),
+        "ln" => z.ln(),
+        "exp" => Ok(z.exp()),
+        "abs" => Ok(Complex::real(z.abs())),
+        "arg" => Ok(Complex::real(options.radians_to_angle(z.arg()))),
+        "re" => Ok(Complex::real(z.re)),
+        "im" => Ok(Complex::real(z.im)),
+        "conj" => Ok(Complex::new(z.re, -z.im)),
+        "sin" if !z.is_real() => Ok(Complex::new(
+            z.re.sin() * z.im.cosh(),
+            z.re.cos() * z.im.sinh(),
+        )),
+        "cos" if !z.is_real() => Ok(Complex::new(
+            z.re.cos() * z.im.cosh(),
+            -z.re.sin() * z.im.sinh(),
+        )),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Returns the real part of an operand, failing if it has an imaginary part
+fn real_operand(value: Complex, what: impl Fn() -> String) -> Result<f64, CalcError> {
+    if value.is_real() {
+        Ok(value.re)
+    } else {
+        Err(CalcError::other(format!(
+            "{} is not supported for complex values",
+            what()
+        )))
+    }
+}
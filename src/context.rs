@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{RangeFrom, RangeInclusive};
+use std::sync::Arc;
+
+use crate::error::CalcError;
+use crate::functions;
+use crate::lexer::{TokenKind, tokenize};
+use crate::options::EvalOptions;
+use crate::parser::Expr;
+use crate::physics::ConstantSet;
+
+/// A function defined during a session, such as `f(x) = x^2 + 1`
+#[derive(Debug, Clone)]
+pub(crate) struct UserFunction {
+    pub params: Vec<String>,
+    pub body: Expr,
+}
+
+/// How many arguments a registered function takes, written as a count such
+/// as `1` or a range such as `1..=3`, or `1..` for any number from one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: usize,
+    /// `None` for no limit
+    pub max: Option<usize>,
+}
+
+impl From<usize> for Arity {
+    fn from(count: usize) -> Self {
+        Arity {
+            min: count,
+            max: Some(count),
+        }
+    }
+}
+
+impl From<RangeInclusive<usize>> for Arity {
+    fn from(range: RangeInclusive<usize>) -> Self {
+        Arity {
+            min: *range.start(),
+            max: Some(*range.end()),
+        }
+    }
+}
+
+impl From<RangeFrom<usize>> for Arity {
+    fn from(range: RangeFrom<usize>) -> Self {
+        Arity {
+            min: range.start,
+            max: None,
+        }
+    }
+}
+
+impl Arity {
+    /// Parameter names for help output, one letter per argument, as in `a, b`,
+    /// ending with `...` when there is no limit
+    fn params(&self) -> String {
+        let count = self.max.unwrap_or(self.min).min(26);
+        let mut params: Vec<String> = (b'a'..)
+            .take(count)
+            .map(|c| (c as char).to_string())
+            .collect();
+        if self.max.is_none() {
+            params.push("...".to_string());
+        }
+        params.join(", ")
+    }
+
+    /// Checks the number of arguments in a call to `name`
+    pub(crate) fn check(&self, name: &str, count: usize) -> Result<(), CalcError> {
+        functions::check_arity(name, self.min, self.max, count)
+    }
+}
+
+/// The closure behind a function registered with [`Context::register_fn`]
+type NativeCall = dyn Fn(&[f64]) -> Result<f64, String> + Send + Sync;
+
+/// A function registered from Rust, which is called with the values of its
+/// arguments
+#[derive(Clone)]
+pub(crate) struct NativeFunction {
+    pub arity: Arity,
+    pub call: Arc<NativeCall>,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Variables and settings that persist from one evaluation to the next
+///
+/// A context is what [`evaluate_with_context`](crate::evaluate_with_context)
+/// reads and updates, so that `x = 5` on one line makes `x*2` work on the next.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::Context;
+///
+/// let mut context = Context::new();
+/// context.set("rate", 0.2).unwrap();
+/// assert_eq!(context.get("rate"), Some(0.2));
+/// assert!(context.set("pi", 3.0).is_err());
+/// assert!(context.set("phys.c", 3.0).is_err());
+/// assert!(context.set("my.rate", 3.0).is_err());
+/// assert!(context.set("2x", 1.0).is_err());
+/// assert!(context.set("ans", 1.0).is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    variables: HashMap<String, f64>,
+    /// Variables holding a list, such as `c = [1, -3, 2]`
+    lists: HashMap<String, Vec<f64>>,
+    functions: HashMap<String, UserFunction>,
+    /// Functions registered from Rust, such as `fahrenheit(c)`
+    natives: HashMap<String, NativeFunction>,
+    ans: Option<f64>,
+    options: EvalOptions,
+}
+
+impl Context {
+    /// Creates an empty context with the default settings
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    /// Creates an empty context with custom settings
+    pub fn with_options(options: EvalOptions) -> Self {
+        Context {
+            variables: HashMap::new(),
+            lists: HashMap::new(),
+            functions: HashMap::new(),
+            natives: HashMap::new(),
+            ans: None,
+            options,
+        }
+    }
+
+    /// Returns the settings used when evaluating in this context
+    pub fn options(&self) -> &EvalOptions {
+        &self.options
+    }
+
+    /// Returns the settings for changing, for example to switch the angle mode
+    pub fn options_mut(&mut self) -> &mut EvalOptions {
+        &mut self.options
+    }
+
+    /// Returns the value of a variable, or `None` if it has not been assigned
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+
+    /// Returns the result of the last successful evaluation, which expressions
+    /// can refer to as `ans`
+    pub fn ans(&self) -> Option<f64> {
+        self.ans
+    }
+
+    pub(crate) fn set_ans(&mut self, value: f64) {
+        self.ans = Some(value);
+    }
+
+    /// Assigns a variable, replacing any previous value
+    ///
+    /// Fails if `name` is not a valid identifier (letters, digits and `_`, not
+    /// starting with a digit), is the name of a built-in constant, or is `ans`.
+    pub fn set(&mut self, name: &str, value: f64) -> Result<(), CalcError> {
+        check_variable_name(name)?;
+        self.lists.remove(name);
+        self.variables.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Returns the items of a variable holding a list, or `None` if it has not
+    /// been assigned one
+    pub fn get_list(&self, name: &str) -> Option<&[f64]> {
+        self.lists.get(name).map(Vec::as_slice)
+    }
+
+    /// Assigns a list to a variable, replacing any previous value
+    ///
+    /// Fails for the same names as [`Context::set`].
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{Context, evaluate_value_with_context};
+    ///
+    /// let mut context = Context::new();
+    /// context.set_list("c", vec![1.0, -3.0, 2.0]).unwrap();
+    /// assert_eq!(context.get_list("c"), Some(&[1.0, -3.0, 2.0][..]));
+    /// assert_eq!(evaluate_value_with_context("c * 2", &mut context).unwrap().to_string(), "[2, -6, 4]");
+    /// context.set("c", 5.0).unwrap();
+    /// assert_eq!(context.get_list("c"), None);
+    /// assert!(context.set_list("pi", vec![]).is_err());
+    /// ```
+    pub fn set_list(&mut self, name: &str, items: Vec<f64>) -> Result<(), CalcError> {
+        check_variable_name(name)?;
+        self.variables.remove(name);
+        self.lists.insert(name.to_string(), items);
+        Ok(())
+    }
+
+    /// Returns the assigned variables as `(name, value)` pairs, sorted by name
+    pub fn variables(&self) -> Vec<(&str, f64)> {
+        let mut variables: Vec<_> = self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    /// Returns the user-defined function called `name`, if there is one
+    pub(crate) fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name)
+    }
+
+    /// Defines a function, replacing any previous definition with the same name
+    ///
+    /// Built-in functions can't be redefined, parameters can't hide constants,
+    /// and a function may not call itself, directly or through other functions.
+    pub(crate) fn define(&mut self, name: &str, function: UserFunction) -> Result<(), CalcError> {
+        if functions::is_builtin(name) {
+            return Err(CalcError::other(format!(
+                "cannot redefine built-in function '{}'",
+                name
+            )));
+        }
+        if !is_plain_identifier(name) {
+            return Err(CalcError::other(format!(
+                "invalid function name '{}'",
+                name
+            )));
+        }
+        for param in &function.params {
+            if param == "ans" || crate::constant(param).is_some() || !is_plain_identifier(param) {
+                return Err(CalcError::other(format!(
+                    "cannot use '{}' as a parameter name",
+                    param
+                )));
+            }
+        }
+        if self.calls(&function.body, name, &mut Vec::new()) {
+            return Err(CalcError::other("recursive functions are not supported"));
+        }
+
+        self.natives.remove(name);
+        self.functions.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    /// Forgets the variables, lists, defined functions and `ans`, keeping the
+    /// settings and the registered functions
+    pub(crate) fn clear(&mut self) {
+        self.variables.clear();
+        self.lists.clear();
+        self.functions.clear();
+        self.ans = None;
+    }
+
+    /// Returns the function registered as `name` with [`Context::register_fn`],
+    /// if there is one
+    pub(crate) fn native(&self, name: &str) -> Option<&NativeFunction> {
+        self.natives.get(name)
+    }
+
+    /// Registers a function written in Rust, which expressions can then call as
+    /// `name(args)`, replacing any function of the same name defined before
+    ///
+    /// `arity` is the number of arguments, such as `2`, or a range of numbers,
+    /// such as `1..=3` or `1..`. A call with any other number fails without
+    /// calling `call`, which gets the values of the arguments. An error from
+    /// `call` becomes a [`CalcError::FunctionError`](crate::CalcError::FunctionError)
+    /// pointing at the call. Only floating point evaluation, as in
+    /// [`evaluate_with_context`](crate::evaluate_with_context), calls registered
+    /// functions, which can't take lists.
+    ///
+    /// Fails if `name` isn't a valid identifier or is the name of a built-in
+    /// function; use [`Context::override_fn`] to replace one.
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{CalcError, Context, Span, evaluate_with_context};
+    ///
+    /// let mut context = Context::new();
+    /// context
+    ///     .register_fn("fahrenheit", 1, |args| Ok(args[0] * 9.0 / 5.0 + 32.0))
+    ///     .unwrap();
+    /// assert_eq!(evaluate_with_context("fahrenheit(100)", &mut context), Ok(212.0));
+    /// assert_eq!(evaluate_with_context("fahrenheit(-40) + 1", &mut context), Ok(-39.0));
+    ///
+    /// context
+    ///     .register_fn("geomean", 1.., |args| {
+    ///         if args.iter().any(|x| *x < 0.0) {
+    ///             return Err("arguments must not be negative".to_string());
+    ///         }
+    ///         Ok(args.iter().product::<f64>().powf(1.0 / args.len() as f64))
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(evaluate_with_context("geomean(2, 8)", &mut context), Ok(4.0));
+    /// assert_eq!(
+    ///     evaluate_with_context("1 + geomean(-1, 4)", &mut context),
+    ///     Err(CalcError::FunctionError {
+    ///         name: "geomean".to_string(),
+    ///         message: "arguments must not be negative".to_string(),
+    ///         span: Span { start: 4, end: 11 },
+    ///     })
+    /// );
+    /// assert_eq!(
+    ///     evaluate_with_context("geomean()", &mut context).unwrap_err().to_string(),
+    ///     "geomean expects at least 1 argument, got 0"
+    /// );
+    /// assert_eq!(
+    ///     evaluate_with_context("fahrenheit(1, 2)", &mut context).unwrap_err().to_string(),
+    ///     "fahrenheit expects 1 argument, got 2"
+    /// );
+    ///
+    /// assert_eq!(
+    ///     context.register_fn("sqrt", 1, |args| Ok(args[0])).unwrap_err().to_string(),
+    ///     "cannot redefine built-in function 'sqrt'"
+    /// );
+    /// assert!(context.register_fn("2x", 1, |args| Ok(args[0])).is_err());
+    /// ```
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        arity: impl Into<Arity>,
+        call: impl Fn(&[f64]) -> Result<f64, String> + Send + Sync + 'static,
+    ) -> Result<(), CalcError> {
+        if functions::is_builtin(name) {
+            return Err(CalcError::other(format!(
+                "cannot redefine built-in function '{}'",
+                name
+            )));
+        }
+        self.insert_native(name, arity.into(), Arc::new(call))
+    }
+
+    /// Registers a function like [`Context::register_fn`], which may also
+    /// replace a built-in function, such as `ln`, in expressions evaluated in
+    /// this context
+    ///
+    /// Forms that are read specially, such as `sum(i, 1, 10, i^2)` and `if`,
+    /// can't be replaced.
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{Context, evaluate, evaluate_with_context};
+    ///
+    /// let mut context = Context::new();
+    /// context.override_fn("ln", 1, |args| Ok(args[0].log10())).unwrap();
+    /// assert_eq!(evaluate_with_context("ln(1000)", &mut context), Ok(3.0));
+    /// assert_eq!(evaluate("ln(1)"), Ok(0.0));
+    /// assert!(context.override_fn("if", 3, |args| Ok(args[0])).is_err());
+    /// ```
+    pub fn override_fn(
+        &mut self,
+        name: &str,
+        arity: impl Into<Arity>,
+        call: impl Fn(&[f64]) -> Result<f64, String> + Send + Sync + 'static,
+    ) -> Result<(), CalcError> {
+        if functions::lookup(name).is_none() && functions::is_builtin(name) {
+            return Err(CalcError::other(format!(
+                "cannot redefine '{}', which is read specially",
+                name
+            )));
+        }
+        self.insert_native(name, arity.into(), Arc::new(call))
+    }
+
+    fn insert_native(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        call: Arc<NativeCall>,
+    ) -> Result<(), CalcError> {
+        if !is_plain_identifier(name) {
+            return Err(CalcError::other(format!(
+                "invalid function name '{}'",
+                name
+            )));
+        }
+        self.functions.remove(name);
+        self.natives
+            .insert(name.to_string(), NativeFunction { arity, call });
+        Ok(())
+    }
+
+    /// Returns the functions that expressions in this context can call as
+    /// `(signature, description)` pairs, like [`functions`](crate::functions),
+    /// including those registered with [`Context::register_fn`]
+    ///
+    /// Registered functions are described as such, taking the place of any
+    /// built-in function they replace, and the others follow the built-in
+    /// ones in order of their names.
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{Context, functions};
+    ///
+    /// let mut context = Context::new();
+    /// context.register_fn("clamp01", 1, |args| Ok(args[0].clamp(0.0, 1.0))).unwrap();
+    /// context.register_fn("avg", 2.., |args| Ok(args.iter().sum::<f64>() / args.len() as f64)).unwrap();
+    /// context.override_fn("sqrt", 1..=2, |args| Ok(args[0].sqrt())).unwrap();
+    /// let listed = context.functions();
+    /// assert_eq!(listed.len(), functions().len() + 2);
+    /// assert!(listed.contains(&("sqrt(a, b)".to_string(), "registered function")));
+    /// assert_eq!(listed[listed.len() - 2], ("avg(a, b, ...)".to_string(), "registered function"));
+    /// assert_eq!(listed[listed.len() - 1], ("clamp01(a)".to_string(), "registered function"));
+    /// ```
+    pub fn functions(&self) -> Vec<(String, &'static str)> {
+        let registered = |name: &str, native: &NativeFunction| {
+            (
+                format!("{}({})", name, native.arity.params()),
+                "registered function",
+            )
+        };
+        let mut listed: Vec<_> = crate::functions()
+            .into_iter()
+            .map(|(signature, description)| {
+                let name = signature.split('(').next().unwrap_or_default();
+                match self.natives.get(name) {
+                    Some(native) => registered(name, native),
+                    None => (signature, description),
+                }
+            })
+            .collect();
+        let mut added: Vec<_> = self
+            .natives
+            .iter()
+            .filter(|(name, _)| !functions::is_builtin(name))
+            .collect();
+        added.sort_by(|a, b| a.0.cmp(b.0));
+        listed.extend(
+            added
+                .into_iter()
+                .map(|(name, native)| registered(name, native)),
+        );
+        listed
+    }
+
+    /// Returns true if evaluating `expr` could call `target`, following calls
+    /// into other user-defined functions
+    fn calls<'a>(&'a self, expr: &'a Expr, target: &str, visited: &mut Vec<&'a str>) -> bool {
+        match expr {
+            Expr::Number { .. } | Expr::Var { .. } => false,
+            Expr::Unary { operand, .. } => self.calls(operand, target, visited),
+            Expr::Binary { lhs, rhs, .. } => {
+                self.calls(lhs, target, visited) || self.calls(rhs, target, visited)
+            }
+            Expr::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => [condition, then, otherwise]
+                .iter()
+                .any(|expr| self.calls(expr, target, visited)),
+            Expr::List { items, .. } => items.iter().any(|item| self.calls(item, target, visited)),
+            Expr::Binding { body, args, .. } => std::iter::once(&**body)
+                .chain(args)
+                .any(|expr| self.calls(expr, target, visited)),
+            Expr::Call { name, args, .. } => {
+                if name == target || args.iter().any(|arg| self.calls(arg, target, visited)) {
+                    return true;
+                }
+                if visited.contains(&name.as_str()) {
+                    return false;
+                }
+                visited.push(name);
+                self.functions
+                    .get(name)
+                    .is_some_and(|function| self.calls(&function.body, target, visited))
+            }
+        }
+    }
+
+    /// Resolves a name to a built-in constant, `ans` or an assigned variable
+    pub(crate) fn lookup(&self, name: &str) -> Option<f64> {
+        if name == "ans" {
+            return self.ans;
+        }
+        crate::constant(name).or_else(|| self.get(name))
+    }
+
+    /// Assigns each constant in `set` to a variable of its short name, so that
+    /// `c` can be written for `phys.c`
+    ///
+    /// The names are single letters that are easily reused, so they are
+    /// ordinary variables that later assignments replace. The namespaced names
+    /// always keep their values.
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{ConstantSet, Context, evaluate_with_context};
+    ///
+    /// let mut context = Context::new();
+    /// assert!(evaluate_with_context("c", &mut context).is_err());
+    /// context.insert_constants(ConstantSet::Physics);
+    /// assert_eq!(evaluate_with_context("c", &mut context), Ok(299792458.0));
+    /// assert_eq!(evaluate_with_context("kB", &mut context), Ok(1.380649e-23));
+    /// assert_eq!(evaluate_with_context("g = 9.81; g", &mut context), Ok(9.81));
+    /// assert_eq!(evaluate_with_context("phys.g", &mut context), Ok(9.80665));
+    /// ```
+    pub fn insert_constants(&mut self, set: ConstantSet) {
+        for (name, value, _) in set.constants() {
+            self.variables.insert(name.to_string(), *value);
+        }
+    }
+}
+
+/// Checks that a variable may be called `name`
+fn check_variable_name(name: &str) -> Result<(), CalcError> {
+    if crate::constant(name).is_some() {
+        return Err(CalcError::other(format!(
+            "cannot assign to constant '{}'",
+            name
+        )));
+    }
+    if !is_plain_identifier(name) {
+        return Err(CalcError::other(format!(
+            "invalid variable name '{}'",
+            name
+        )));
+    }
+    if name == "ans" {
+        return Err(CalcError::other(
+            "cannot assign to 'ans', it always holds the previous result",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns whether `name` is a single identifier without a namespace, which is
+/// what variables, functions and parameters may be called
+fn is_plain_identifier(name: &str) -> bool {
+    !name.contains('.')
+        && matches!(
+            tokenize(name, &EvalOptions::default()).as_deref(),
+            Ok([token]) if token.kind == TokenKind::Identifier(name.to_string())
+        )
+}
@@ -0,0 +1,553 @@
+//! Decimal evaluation, enabled by the `decimal` feature
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions;
+use crate::lexer::{self, number_text};
+use crate::options::{EvalOptions, RoundingMode, Separator};
+use crate::parser::{self, BinaryOp, Expr, UnaryOp};
+use crate::words;
+
+/// A decimal number `mantissa * 10^-scale`
+///
+/// The scale is kept as written and as produced by arithmetic, so `1.10*3`
+/// is `3.30` rather than `3.3`.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+/// Compares by value, so `1.10` equals `1.1`
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.checked_cmp(*other) == Some(Ordering::Equal)
+    }
+}
+
+fn overflow() -> CalcError {
+    CalcError::overflow("decimal overflow, the result has too many digits")
+}
+
+fn pow10(exponent: u32) -> Result<i128, CalcError> {
+    10i128.checked_pow(exponent).ok_or_else(overflow)
+}
+
+/// Divides, rounding half to even, so `0.125` at two places is `0.12`
+fn div_half_even(numer: i128, denom: i128) -> i128 {
+    div_rounded(numer, denom, RoundingMode::HalfEven)
+}
+
+/// Divides, rounding the way `mode` does
+fn div_rounded(numer: i128, denom: i128, mode: RoundingMode) -> i128 {
+    let (quotient, remainder) = (numer / denom, numer % denom);
+    let twice = remainder.unsigned_abs() * 2;
+    let away = match (twice.cmp(&denom.unsigned_abs()), mode) {
+        (_, RoundingMode::TowardZero) => false,
+        (Ordering::Greater, _) => true,
+        (Ordering::Equal, RoundingMode::HalfUp) => true,
+        (Ordering::Equal, RoundingMode::HalfEven) => quotient % 2 != 0,
+        (Ordering::Equal, RoundingMode::HalfDown) | (Ordering::Less, _) => false,
+    };
+    if !away {
+        quotient
+    } else if (numer < 0) != (denom < 0) {
+        quotient - 1
+    } else {
+        quotient + 1
+    }
+}
+
+impl Decimal {
+    pub fn new(mantissa: i128, scale: u32) -> Self {
+        Decimal { mantissa, scale }
+    }
+
+    fn from_integer(n: i128) -> Self {
+        Decimal::new(n, 0)
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// Number of digits after the decimal point
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Number of significant digits in the mantissa
+    pub fn precision(&self) -> u32 {
+        self.mantissa.unsigned_abs().checked_ilog10().unwrap_or(0) + 1
+    }
+
+    /// Returns the nearest `f64`
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Parses a decimal literal such as `1.10`, `1_000` or `2.5e-3`, written
+    /// with `separator` as the decimal point
+    fn parse(text: &str, separator: Separator) -> Option<Result<Self, CalcError>> {
+        let text = number_text(text, separator);
+        let (digits, exponent) = match text.split_once(['e', 'E']) {
+            Some((digits, exponent)) => (digits.to_string(), exponent.parse::<i32>().ok()?),
+            None => (text, 0),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+        if !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let Ok(mantissa) = format!("{}{}", whole, fraction).parse::<i128>() else {
+            return Some(Err(overflow()));
+        };
+        let scale = fraction.len() as i64 - exponent as i64;
+        Some(if scale >= 0 {
+            Ok(Decimal::new(mantissa, scale as u32))
+        } else {
+            pow10((-scale) as u32)
+                .and_then(|factor| mantissa.checked_mul(factor).ok_or_else(overflow))
+                .map(Decimal::from_integer)
+        })
+    }
+
+    /// Converts a number through its shortest decimal form, rounded to `scale`
+    fn from_f64(value: f64, scale: u32) -> Result<Self, CalcError> {
+        if !value.is_finite() {
+            return Err(CalcError::other(format!(
+                "{} can't be represented as a decimal",
+                value
+            )));
+        }
+        let decimal = Decimal::parse(&value.to_string(), Separator::Period)
+            .unwrap_or_else(|| Err(overflow()))?;
+        decimal.round_to(scale)
+    }
+
+    /// Returns the value as an integer if it has no fractional part
+    fn to_integer(self) -> Option<i128> {
+        let factor = 10i128.checked_pow(self.scale)?;
+        (self.mantissa % factor == 0).then(|| self.mantissa / factor)
+    }
+
+    /// Rescales to `scale` digits, rounding half to even if that drops digits
+    fn round_to(self, scale: u32) -> Result<Self, CalcError> {
+        self.round_with(scale, RoundingMode::HalfEven)
+    }
+
+    /// Rescales to `scale` digits, rounding the way `mode` does if that drops
+    /// digits
+    fn round_with(self, scale: u32, mode: RoundingMode) -> Result<Self, CalcError> {
+        if scale >= self.scale {
+            let factor = pow10(scale - self.scale)?;
+            let mantissa = self.mantissa.checked_mul(factor).ok_or_else(overflow)?;
+            return Ok(Decimal::new(mantissa, scale));
+        }
+        let mantissa = match 10i128.checked_pow(self.scale - scale) {
+            Some(factor) => div_rounded(self.mantissa, factor, mode),
+            // Dropping more digits than an i128 can hold leaves nothing
+            None => 0,
+        };
+        Ok(Decimal::new(mantissa, scale))
+    }
+
+    /// Removes trailing zeros, but keeps at least `min_scale` digits
+    fn trim(mut self, min_scale: u32) -> Self {
+        while self.scale > min_scale && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+
+    /// Returns both mantissas at a common scale
+    fn align(self, other: Decimal) -> Result<(i128, i128, u32), CalcError> {
+        let scale = self.scale.max(other.scale);
+        Ok((
+            self.round_to(scale)?.mantissa,
+            other.round_to(scale)?.mantissa,
+            scale,
+        ))
+    }
+
+    fn checked_cmp(self, other: Decimal) -> Option<Ordering> {
+        let (a, b, _) = self.align(other).ok()?;
+        Some(a.cmp(&b))
+    }
+
+    fn add(self, other: Decimal) -> Result<Self, CalcError> {
+        let (a, b, scale) = self.align(other)?;
+        Ok(Decimal::new(a.checked_add(b).ok_or_else(overflow)?, scale))
+    }
+
+    fn neg(self) -> Result<Self, CalcError> {
+        let mantissa = self.mantissa.checked_neg().ok_or_else(overflow)?;
+        Ok(Decimal::new(mantissa, self.scale))
+    }
+
+    /// Multiplies, rounding to `max_scale` digits if the product has more
+    fn mul(self, other: Decimal, max_scale: u32) -> Result<Self, CalcError> {
+        let mantissa = self
+            .mantissa
+            .checked_mul(other.mantissa)
+            .ok_or_else(overflow)?;
+        let product = Decimal::new(mantissa, self.scale + other.scale);
+        if product.scale > max_scale {
+            product.round_to(max_scale)
+        } else {
+            Ok(product)
+        }
+    }
+
+    /// Divides to `scale` digits, then drops trailing zeros that the
+    /// dividend didn't have, so `1/4` is `0.25` and `3.30/3` is `1.10`
+    fn div(self, other: Decimal, scale: u32) -> Result<Self, CalcError> {
+        if other.mantissa == 0 {
+            return Err(CalcError::division_by_zero());
+        }
+        // self / other = (a * 10^shift / b) * 10^-scale
+        let shift = scale as i64 + other.scale as i64 - self.scale as i64;
+        let (numer, denom) = if shift >= 0 {
+            let factor = pow10(shift as u32)?;
+            (
+                self.mantissa.checked_mul(factor).ok_or_else(overflow)?,
+                other.mantissa,
+            )
+        } else {
+            let factor = pow10((-shift) as u32)?;
+            (
+                self.mantissa,
+                other.mantissa.checked_mul(factor).ok_or_else(overflow)?,
+            )
+        };
+        Ok(Decimal::new(div_half_even(numer, denom), scale).trim(self.scale.min(scale)))
+    }
+
+    /// Raises to a whole power, exactly unless the result has more than
+    /// `max_scale` digits after the point
+    fn pow(self, exponent: u32, max_scale: u32) -> Result<Self, CalcError> {
+        let mantissa = self.mantissa.checked_pow(exponent).ok_or_else(overflow)?;
+        let scale = self.scale.checked_mul(exponent).ok_or_else(overflow)?;
+        let power = Decimal::new(mantissa, scale);
+        if scale > max_scale.max(self.scale) {
+            power.round_to(max_scale.max(self.scale))
+        } else {
+            Ok(power)
+        }
+    }
+
+    fn floor(self) -> Result<Self, CalcError> {
+        let factor = pow10(self.scale)?;
+        Ok(Decimal::from_integer(self.mantissa.div_euclid(factor)))
+    }
+
+    fn trunc(self) -> Result<Self, CalcError> {
+        let factor = pow10(self.scale)?;
+        Ok(Decimal::from_integer(self.mantissa / factor))
+    }
+}
+
+/// Formats with exactly `scale` digits after the decimal point
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = padded.split_at(padded.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// Evaluates a mathematical expression with decimal arithmetic
+///
+/// Literals keep the digits they were written with, and `+`, `-`, `*`, `%`,
+/// `//`, integer powers and comparisons are exact. A division that doesn't
+/// terminate, such as `1/3`, and a product with more than
+/// [`EvalOptions::decimal_scale`] digits after the point are rounded to that
+/// many digits, with ties going to the even digit. `abs`, `min`, `max`,
+/// `floor`, `ceil`, `trunc` and `round` work on decimals, and `round` breaks
+/// ties as [`EvalOptions::rounding`] says, away from zero by default. Constants such as `pi` are rounded to the scale; other
+/// functions and fractional powers are not supported in decimal mode.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{
+///     CalcError, EvalOptions, NumberMode, RoundingMode, Span, evaluate_decimal,
+///     evaluate_with_options,
+/// };
+///
+/// let options = EvalOptions::default();
+/// assert_eq!(evaluate_decimal("0.1+0.2", &options).unwrap().to_string(), "0.3");
+/// assert_eq!(evaluate_decimal("1.10*3", &options).unwrap().to_string(), "3.30");
+/// assert_eq!(evaluate_decimal("1/4", &options).unwrap().to_string(), "0.25");
+/// assert_eq!(evaluate_decimal("3.30/3", &options).unwrap().to_string(), "1.10");
+/// assert_eq!(evaluate_decimal("2.5 - 2.50 == 0", &options).unwrap().to_string(), "1");
+/// assert_eq!(evaluate_decimal("round(2.5) + round(0.125, 2)", &options).unwrap().to_string(), "3.13");
+/// let half_even = EvalOptions::new().rounding(RoundingMode::HalfEven);
+/// assert_eq!(evaluate_decimal("round(2.5) + round(0.125, 2)", &half_even).unwrap().to_string(), "2.12");
+/// assert_eq!(evaluate_decimal("round(-3.5) + round(-0.135, 2)", &half_even).unwrap().to_string(), "-4.14");
+/// let toward_zero = EvalOptions::new().rounding(RoundingMode::TowardZero);
+/// assert_eq!(evaluate_decimal("round(-2.99, 1)", &toward_zero).unwrap().to_string(), "-2.9");
+/// assert_eq!(evaluate_decimal("1.05^2", &options).unwrap().to_string(), "1.1025");
+///
+/// let third = evaluate_decimal("1/3", &options).unwrap();
+/// assert_eq!(third.to_string(), "0.33333333333333333333");
+/// assert_eq!((third.scale(), third.precision()), (20, 20));
+///
+/// let coarse = EvalOptions::new().decimal_scale(2);
+/// assert_eq!(evaluate_decimal("2/3", &coarse).unwrap().to_string(), "0.67");
+/// assert_eq!(evaluate_decimal("0.125*1", &coarse).unwrap().to_string(), "0.125");
+/// assert_eq!(evaluate_decimal("0.125*0.1", &coarse).unwrap().to_string(), "0.012");
+/// assert!(evaluate_decimal("sqrt(2)", &options).unwrap_err().to_string().contains("not supported in decimal mode"));
+/// assert_eq!(
+///     evaluate_decimal("1/0", &options),
+///     Err(CalcError::DivisionByZero { span: Some(Span { start: 1, end: 2 }) })
+/// );
+/// assert_eq!(evaluate_decimal("$0.10 + $0.20", &options).unwrap().to_string(), "0.30");
+/// assert_eq!(evaluate_decimal("1:30 + 0:45", &options).unwrap().to_string(), "8100");
+///
+/// let decimal = EvalOptions::new().number_mode(NumberMode::Decimal);
+/// assert_eq!(evaluate_with_options("0.1+0.2", &decimal), Ok(0.3));
+/// assert_eq!(evaluate_with_options("0.1+0.2 == 0.3", &decimal), Ok(1.0));
+///
+/// let si = EvalOptions::new().si_suffixes(true);
+/// assert_eq!(evaluate_decimal("4.7k + 1m", &si).unwrap().to_string(), "4700.001");
+/// assert_eq!(evaluate_decimal("1.5n", &si).unwrap().to_string(), "0.0000000015");
+/// ```
+pub fn evaluate_decimal(input: &str, options: &EvalOptions) -> Result<Decimal, CalcError> {
+    let (rewritten, origins) = words::rewrite_with_origins(input, options, |_| false)?;
+    let input = &rewritten;
+    let expr = parser::parse(input, options).map_err(|e| origins.error(e))?;
+    eval_decimal(&expr, input, &Context::with_options(options.clone()))
+        .map_err(|e| origins.error(e))
+}
+
+/// Evaluates an expression tree with decimal arithmetic, reading literals from
+/// `input` and variables from `context`
+pub(crate) fn eval_decimal(
+    expr: &Expr,
+    input: &str,
+    context: &Context,
+) -> Result<Decimal, CalcError> {
+    let eval = |expr| eval_decimal(expr, input, context);
+    let options = context.options();
+    let scale = options.decimal_scale;
+    match expr {
+        Expr::Number { value, span } => {
+            let text = &input[span.start..span.end];
+            // Minutes and seconds of an angle such as 45°20' may not have a
+            // finite decimal form
+            if text.contains('°') {
+                return Decimal::from_f64(*value, scale);
+            }
+            // A duration such as 1:30 is a whole number of seconds
+            if text.contains(':') {
+                return Ok(Decimal::from_integer(*value as i128));
+            }
+            let text = match lexer::split_si_prefix(text) {
+                Some((digits, power)) if options.si_suffixes => {
+                    Cow::Owned(format!("{}e{}", digits, power))
+                }
+                _ => Cow::Borrowed(text),
+            };
+            Decimal::parse(&text, options.decimal_separator)
+                // Hexadecimal, binary and octal literals are always integers,
+                // while one such as `2#0.1` may have a fraction
+                .unwrap_or_else(|| {
+                    if value.fract() == 0.0 {
+                        Ok(Decimal::from_integer(*value as i128))
+                    } else {
+                        Decimal::from_f64(*value, scale)
+                    }
+                })
+        }
+        Expr::Var { name, span } => {
+            let value = context.lookup(name).ok_or_else(|| {
+                if name == "ans" {
+                    CalcError::other_at("no previous result for 'ans'", *span)
+                } else {
+                    CalcError::UnknownIdentifier {
+                        name: name.clone(),
+                        span: *span,
+                    }
+                }
+            })?;
+            Decimal::from_f64(value, scale)
+        }
+        Expr::Unary { op, operand, span } => {
+            let value = eval(operand)?;
+            let result = match op {
+                UnaryOp::Neg => value.neg(),
+                UnaryOp::Pos => Ok(value),
+                UnaryOp::Percent => Ok(Decimal::new(value.mantissa, value.scale + 2)),
+                _ => integer_operation(
+                    || eval::unary(*op, value.to_f64(), context.options()),
+                    &[value],
+                ),
+            };
+            result.map_err(|e| e.at(*span))
+        }
+        Expr::Binary { op, lhs, rhs, span } => {
+            let (a, b) = (eval(lhs)?, eval(rhs)?);
+            let truth = |holds: bool| Ok(Decimal::from_integer(holds as i128));
+            let ordering = || a.checked_cmp(b).ok_or_else(overflow);
+            let result = match op {
+                BinaryOp::Add => a.add(b),
+                BinaryOp::Sub => a.add(b.neg()?),
+                BinaryOp::Mul => a.mul(b, scale.max(a.scale).max(b.scale)),
+                BinaryOp::Div => a.div(b, scale),
+                BinaryOp::FloorDiv | BinaryOp::Rem => {
+                    if b.mantissa == 0 {
+                        return Err(CalcError::DivisionByZero { span: Some(*span) });
+                    }
+                    let (x, y, common) = a.align(b).map_err(|e| e.at(*span))?;
+                    if *op == BinaryOp::Rem {
+                        let r = x % y;
+                        let k = options.remainder_mode.correction(r.cmp(&0), y < 0);
+                        y.checked_mul(k.into())
+                            .and_then(|correction| r.checked_add(correction))
+                            .map(|r| Decimal::new(r, common))
+                            .ok_or_else(overflow)
+                    } else {
+                        // Rounds towards negative infinity, like `//` on floats
+                        let quotient = x / y;
+                        let floored = quotient - (x % y != 0 && (x < 0) != (y < 0)) as i128;
+                        Ok(Decimal::from_integer(floored))
+                    }
+                }
+                BinaryOp::Pow => {
+                    let exponent = b
+                        .to_integer()
+                        .and_then(|n| i32::try_from(n).ok())
+                        .ok_or_else(|| {
+                            CalcError::other("fractional powers are not supported in decimal mode")
+                        })?;
+                    a.pow(exponent.unsigned_abs(), scale).and_then(|power| {
+                        if exponent < 0 {
+                            Decimal::from_integer(1).div(power, scale)
+                        } else {
+                            Ok(power)
+                        }
+                    })
+                }
+                BinaryOp::Less => truth(ordering()? == Ordering::Less),
+                BinaryOp::LessEqual => truth(ordering()? != Ordering::Greater),
+                BinaryOp::Greater => truth(ordering()? == Ordering::Greater),
+                BinaryOp::GreaterEqual => truth(ordering()? != Ordering::Less),
+                BinaryOp::Equal => truth(ordering()? == Ordering::Equal),
+                BinaryOp::NotEqual => truth(ordering()? != Ordering::Equal),
+                _ => integer_operation(
+                    || eval::binary(*op, a.to_f64(), b.to_f64(), options),
+                    &[a, b],
+                ),
+            };
+            result.map_err(|e| e.at(*span))
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            if eval(condition)?.mantissa != 0 {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+        Expr::Binding { form, .. } => Err(CalcError::other(format!(
+            "function '{}' is not supported in decimal mode",
+            form.name()
+        ))),
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "lists are not supported in decimal mode",
+            *span,
+        )),
+        Expr::Call { name, args, span } => {
+            let Some(function) = functions::lookup(name) else {
+                return Err(if context.function(name).is_some() {
+                    CalcError::other(format!(
+                        "user-defined function '{}' is not supported in decimal mode",
+                        name
+                    ))
+                } else {
+                    CalcError::UnknownFunction {
+                        name: name.clone(),
+                        span: *span,
+                    }
+                });
+            };
+            function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+            let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            let result = call(name, &values, context.options().rounding).unwrap_or_else(|| {
+                Err(CalcError::other(format!(
+                    "function '{}' is not supported in decimal mode",
+                    name
+                )))
+            });
+            result.map_err(|e| e.at(*span))
+        }
+    }
+}
+
+/// Runs an integer-only operation such as `!` or `&` through floating point,
+/// which is exact as long as the operands are integers
+fn integer_operation(
+    operation: impl FnOnce() -> Result<f64, CalcError>,
+    operands: &[Decimal],
+) -> Result<Decimal, CalcError> {
+    if operands
+        .iter()
+        .any(|operand| operand.to_integer().is_none())
+    {
+        return Err(CalcError::other(
+            "this operator requires integers in decimal mode",
+        ));
+    }
+    Decimal::from_f64(operation()?, 0)
+}
+
+/// Calls a function that works on decimals, or returns `None` if there is
+/// no decimal version. `round` breaks ties the way `rounding` does.
+fn call(
+    name: &str,
+    args: &[Decimal],
+    rounding: RoundingMode,
+) -> Option<Result<Decimal, CalcError>> {
+    let pick = |wanted: Ordering| {
+        args.iter().try_fold(args[0], |best, x| {
+            let ordering = x.checked_cmp(best).ok_or_else(overflow)?;
+            Ok(if ordering == wanted { *x } else { best })
+        })
+    };
+    let result = match (name, args) {
+        ("abs", [x]) if x.mantissa < 0 => x.neg(),
+        ("abs", [x]) => Ok(*x),
+        ("floor", [x]) => x.floor(),
+        ("ceil", [x]) => x.neg().and_then(Decimal::floor).and_then(Decimal::neg),
+        ("trunc", [x]) => x.trunc(),
+        ("round", [x]) => x.round_with(0, rounding),
+        ("round", [x, places]) => match places.to_integer().and_then(|n| u32::try_from(n).ok()) {
+            Some(places) => x.round_with(places.min(x.scale), rounding),
+            None => Err(CalcError::other(
+                "round precision must be a non-negative integer in decimal mode",
+            )),
+        },
+        ("min", [_, _, ..]) => pick(Ordering::Less),
+        ("max", [_, _, ..]) => pick(Ordering::Greater),
+        _ => return None,
+    };
+    Some(result)
+}
@@ -0,0 +1,167 @@
+use crate::error::CalcError;
+use crate::lexer::Span;
+use crate::parser::{BinaryOp, Expr, UnaryOp};
+
+/// Whether an expression stands for a plain number or a duration in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Number,
+    Duration,
+}
+
+/// Returns whether `expr`, parsed from `input`, is a duration because of an
+/// `h:mm` or `h:mm:ss` literal in it, failing if it combines a duration with
+/// a number in a way that has no meaning, such as `1:30 + 5`
+///
+/// Durations can be added to and subtracted from each other, scaled by a
+/// number and divided by one another to give a ratio. Variables always hold
+/// numbers.
+pub(crate) fn is_duration(expr: &Expr, input: &str) -> Result<bool, CalcError> {
+    kind(expr, input).map(|kind| kind == Kind::Duration)
+}
+
+fn kind(expr: &Expr, input: &str) -> Result<Kind, CalcError> {
+    use Kind::{Duration, Number};
+
+    match expr {
+        Expr::Number { span, .. } if input[span.start..span.end].contains(':') => Ok(Duration),
+        Expr::Number { .. } | Expr::Var { .. } => Ok(Number),
+        Expr::Unary {
+            op, operand, span, ..
+        } => {
+            let operand = kind(operand, input)?;
+            match op {
+                UnaryOp::Neg | UnaryOp::Pos => Ok(operand),
+                _ if operand == Duration => {
+                    let symbol = match op {
+                        UnaryOp::BitNot => "~",
+                        UnaryOp::Factorial => "!",
+                        _ => "%",
+                    };
+                    Err(not_applicable(symbol, *span))
+                }
+                _ => Ok(Number),
+            }
+        }
+        Expr::Binary {
+            op, lhs, rhs, span, ..
+        } => {
+            let (lhs, rhs) = (kind(lhs, input)?, kind(rhs, input)?);
+            match (op, lhs, rhs) {
+                (_, Number, Number) => Ok(Number),
+                (BinaryOp::Add, _, _) if lhs == rhs => Ok(lhs),
+                (BinaryOp::Add, _, _) => Err(CalcError::other_at(
+                    "cannot add a number to a duration",
+                    *span,
+                )),
+                (BinaryOp::Sub, _, _) if lhs == rhs => Ok(lhs),
+                (BinaryOp::Sub, Duration, _) => Err(CalcError::other_at(
+                    "cannot subtract a number from a duration",
+                    *span,
+                )),
+                (BinaryOp::Sub, _, _) => Err(CalcError::other_at(
+                    "cannot subtract a duration from a number",
+                    *span,
+                )),
+                (BinaryOp::Mul, Duration, Duration) => {
+                    Err(CalcError::other_at("cannot multiply two durations", *span))
+                }
+                (BinaryOp::Mul, _, _) => Ok(Duration),
+                (BinaryOp::Div | BinaryOp::FloorDiv, Duration, Number) => Ok(Duration),
+                (BinaryOp::Div | BinaryOp::FloorDiv, Duration, Duration) => Ok(Number),
+                (BinaryOp::Div | BinaryOp::FloorDiv, _, _) => Err(CalcError::other_at(
+                    "cannot divide a number by a duration",
+                    *span,
+                )),
+                (BinaryOp::Rem, Duration, Duration) => Ok(Duration),
+                (op, _, _) if op.is_comparison() && lhs == rhs => Ok(Number),
+                (op, _, _) if op.is_comparison() => Err(CalcError::other_at(
+                    "cannot compare a duration with a number",
+                    *span,
+                )),
+                (op, _, _) => Err(not_applicable(op.symbol(), *span)),
+            }
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            kind(condition, input)?;
+            let (then_kind, otherwise_kind) = (kind(then, input)?, kind(otherwise, input)?);
+            if then_kind != otherwise_kind {
+                return Err(CalcError::other(
+                    "both branches of a conditional must be durations, or neither",
+                ));
+            }
+            Ok(then_kind)
+        }
+        // `abs`, `min` and `max` of durations are durations, while no other
+        // function takes one
+        Expr::Call { name, args, span } => {
+            let kinds = args
+                .iter()
+                .map(|arg| kind(arg, input))
+                .collect::<Result<Vec<_>, _>>()?;
+            if !kinds.contains(&Duration) {
+                return Ok(Number);
+            }
+            if matches!(name.as_str(), "abs" | "min" | "max") && !kinds.contains(&Number) {
+                return Ok(Duration);
+            }
+            Err(CalcError::other_at(
+                format!("{} doesn't take a duration", name),
+                *span,
+            ))
+        }
+        Expr::List { items, span } => {
+            for item in items {
+                if kind(item, input)? == Duration {
+                    return Err(CalcError::other_at("a list can't hold durations", *span));
+                }
+            }
+            Ok(Number)
+        }
+        Expr::Binding {
+            form,
+            body,
+            args,
+            span,
+            ..
+        } => {
+            for arg in args.iter().chain([body.as_ref()]) {
+                if kind(arg, input)? == Duration {
+                    return Err(CalcError::other_at(
+                        format!("{} doesn't take a duration", form.name()),
+                        *span,
+                    ));
+                }
+            }
+            Ok(Number)
+        }
+    }
+}
+
+/// The error for an operator that has no meaning for a duration
+fn not_applicable(symbol: &str, span: Span) -> CalcError {
+    CalcError::other_at(format!("cannot apply '{}' to a duration", symbol), span)
+}
+
+/// Writes a duration in seconds as `h:mm`, or `h:mm:ss` when it isn't a whole
+/// number of minutes, rounded to the nearest second
+pub(crate) fn format(seconds: f64) -> String {
+    let total = seconds.abs().round();
+    let sign = if seconds < 0.0 && total != 0.0 {
+        "-"
+    } else {
+        ""
+    };
+    let hours = (total / 3600.0).floor();
+    let minutes = ((total - hours * 3600.0) / 60.0).floor();
+    let seconds = total - hours * 3600.0 - minutes * 60.0;
+    if seconds == 0.0 {
+        format!("{}{}:{:02}", sign, hours, minutes)
+    } else {
+        format!("{}{}:{:02}:{:02}", sign, hours, minutes, seconds)
+    }
+}
@@ -0,0 +1,906 @@
+//! Errors from evaluating an expression, by the kind of problem
+
+use std::fmt;
+
+use crate::lexer::Span;
+
+/// An error from [`evaluate`](crate::evaluate), which says what kind of
+/// problem it was and where
+///
+/// Each error displays as the message the calculator reports for it, so
+/// `to_string()` gives the text that the REPL shows. Input that can't be read
+/// for a reason without a kind of its own, such as a `%` that follows no
+/// number, is a [`CalcError::Syntax`], and any other error without a kind of
+/// its own is a [`CalcError::Other`]. [`CalcError::is_parse_error`] tells the
+/// errors of reading the input from those of evaluating it. The error of one
+/// of several statements is a [`CalcError::InStatement`] holding the error of
+/// that statement. Errors that point at part of the input have its [`Span`],
+/// which [`render_error`] draws a caret under.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Context, Span, evaluate, evaluate_with_context};
+///
+/// assert_eq!(
+///     evaluate("2 $ 3"),
+///     Err(CalcError::InvalidCharacter { ch: '$', span: Span { start: 2, end: 3 } })
+/// );
+/// assert_eq!(evaluate("1 / (2 - 2)"), Err(CalcError::DivisionByZero { span: Some(Span { start: 2, end: 3 }) }));
+/// assert!(matches!(evaluate("172!"), Err(CalcError::Overflow { .. })));
+///
+/// let error = evaluate_with_context("a = 2; 1/0", &mut Context::new()).unwrap_err();
+/// assert!(matches!(error, CalcError::InStatement { index: 1, .. }));
+/// assert!(!error.is_parse_error());
+///
+/// let error = evaluate("ln(0)").unwrap_err();
+/// assert_eq!(error.to_string(), "domain error: ln is undefined for 0");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    /// A character that starts no token, such as the `$` of `2 $ 3`
+    InvalidCharacter { ch: char, span: Span },
+    /// Digits that don't make a number, such as the `1.2.3` of `1.2.3 + 4`
+    InvalidNumber { text: String, span: Span },
+    /// A token where none of its kind can go, such as the `*` of `2 + * 3`
+    UnexpectedToken { token: String, span: Span },
+    /// Input that ends where an operand should be, as in `2 +`, with the
+    /// empty span at its end
+    UnexpectedEnd { span: Span },
+    /// Input that ends before the `)`, `]` or `|` that closes the `(`, `[` or
+    /// `|` at `span`, or before the `:` of the `?` at `span`
+    UnclosedDelimiter { delimiter: char, span: Span },
+    /// A division or remainder by zero, with the span of its operator or of
+    /// the function that divides
+    DivisionByZero { span: Option<Span> },
+    /// A name that is neither a variable nor a constant
+    UnknownIdentifier { name: String, span: Span },
+    /// A call to a function that doesn't exist
+    UnknownFunction { name: String, span: Span },
+    /// A call with the wrong number of arguments, with the numbers it takes
+    /// written out as in `2 arguments` or `at least 1 argument`, and the span
+    /// of the name of the function
+    ArityMismatch {
+        name: String,
+        expected: String,
+        got: usize,
+        span: Option<Span>,
+    },
+    /// An argument outside the domain of a function, as in `sqrt(-4)`, with
+    /// the span of the function or operator
+    DomainError { message: String, span: Option<Span> },
+    /// The error of a function registered with
+    /// [`Context::register_fn`](crate::Context::register_fn), with the span
+    /// of its name in the call
+    FunctionError {
+        name: String,
+        message: String,
+        span: Span,
+    },
+    /// A result too large to represent, as in `172!`, with the span of the
+    /// operator or function that gave it
+    Overflow { message: String, span: Option<Span> },
+    /// Input with nothing to evaluate, such as `` or `;;`
+    EmptyExpression,
+    /// Input that can't be read for a reason without a kind of its own, such
+    /// as the `%` of `1 + %50`
+    Syntax { message: String, span: Span },
+    /// The error of one of several statements separated by `;`, counting
+    /// from zero
+    InStatement { index: usize, error: Box<CalcError> },
+    /// Any other error, with the part of the input it is about, if any
+    Other { message: String, span: Option<Span> },
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::InvalidCharacter { ch, span } => {
+                write!(
+                    f,
+                    "unexpected character '{}' at position {}",
+                    ch, span.start
+                )
+            }
+            CalcError::InvalidNumber { text, span } => {
+                write!(f, "invalid number '{}' at position {}", text, span.start)
+            }
+            CalcError::UnexpectedToken { token, span } => {
+                write!(f, "unexpected '{}' at position {}", token, span.start)
+            }
+            CalcError::UnexpectedEnd { span } => write!(
+                f,
+                "unexpected end of input, expected a number at position {}",
+                span.start
+            ),
+            CalcError::UnclosedDelimiter { delimiter, span } => {
+                match delimiter {
+                    '(' => write!(f, "unclosed parenthesis")?,
+                    '[' => write!(f, "unclosed bracket")?,
+                    '|' => write!(f, "unclosed absolute value bar")?,
+                    '?' => write!(f, "missing ':' for the '?'")?,
+                    other => write!(f, "unclosed '{}'", other)?,
+                }
+                write!(f, " at position {}", span.start)
+            }
+            CalcError::DivisionByZero { .. } => write!(f, "division by zero"),
+            CalcError::UnknownIdentifier { name, span } => {
+                write!(
+                    f,
+                    "undefined variable '{}' at position {}",
+                    name, span.start
+                )
+            }
+            CalcError::UnknownFunction { name, span } => {
+                write!(f, "unknown function '{}' at position {}", name, span.start)
+            }
+            CalcError::ArityMismatch {
+                name,
+                expected,
+                got,
+                ..
+            } => write!(f, "{} expects {}, got {}", name, expected, got),
+            CalcError::DomainError { message, .. } => write!(f, "domain error: {}", message),
+            CalcError::FunctionError {
+                name,
+                message,
+                span,
+            } => write!(f, "{} failed at position {}: {}", name, span.start, message),
+            CalcError::Overflow { message, .. } => write!(f, "{}", message),
+            CalcError::EmptyExpression => write!(f, "empty expression"),
+            CalcError::Syntax { message, span } => {
+                write!(f, "{} at position {}", message, span.start)
+            }
+            CalcError::InStatement { index, error } => {
+                write!(f, "error in statement {}: {}", index + 1, error)
+            }
+            CalcError::Other { message, span } => {
+                write!(f, "{}", message)?;
+                match span {
+                    Some(span) => write!(f, " at position {}", span.start),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl CalcError {
+    /// Returns the part of the input the error points at, if it points at one
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CalcError::InvalidCharacter { span, .. }
+            | CalcError::InvalidNumber { span, .. }
+            | CalcError::UnexpectedToken { span, .. }
+            | CalcError::UnexpectedEnd { span }
+            | CalcError::UnclosedDelimiter { span, .. }
+            | CalcError::UnknownIdentifier { span, .. }
+            | CalcError::UnknownFunction { span, .. }
+            | CalcError::FunctionError { span, .. }
+            | CalcError::Syntax { span, .. } => Some(*span),
+            CalcError::DivisionByZero { span }
+            | CalcError::ArityMismatch { span, .. }
+            | CalcError::DomainError { span, .. }
+            | CalcError::Overflow { span, .. }
+            | CalcError::Other { span, .. } => *span,
+            CalcError::InStatement { error, .. } => error.span(),
+            CalcError::EmptyExpression => None,
+        }
+    }
+
+    /// Returns the error itself, or for [`CalcError::InStatement`] the error
+    /// of the statement, which says what kind of problem it was
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{CalcError, Context, Span, evaluate_with_context};
+    ///
+    /// let error = evaluate_with_context("a = 2; 1/0", &mut Context::new()).unwrap_err();
+    /// assert!(matches!(error, CalcError::InStatement { index: 1, .. }));
+    /// assert_eq!(
+    ///     error.inner(),
+    ///     &CalcError::DivisionByZero { span: Some(Span { start: 8, end: 9 }) }
+    /// );
+    /// assert_eq!(error.to_string(), "error in statement 2: division by zero");
+    /// ```
+    pub fn inner(&self) -> &CalcError {
+        match self {
+            CalcError::InStatement { error, .. } => error.inner(),
+            error => error,
+        }
+    }
+
+    /// Returns whether the error is one of reading the input, rather than of
+    /// evaluating what was read
+    ///
+    /// A call with the wrong number of arguments or to a function that
+    /// doesn't exist is an error of evaluating, even where it is found while
+    /// reading, as for `prod(i, 1, 3)`, since the input reads as a call.
+    ///
+    /// # Examples
+    /// ```
+    /// use calculator_rust::{Context, evaluate, evaluate_with_context};
+    ///
+    /// assert!(evaluate("2 +").unwrap_err().is_parse_error());
+    /// assert!(evaluate("(1 + 2").unwrap_err().is_parse_error());
+    /// assert!(evaluate("1 + %50").unwrap_err().is_parse_error());
+    /// assert!(evaluate("").unwrap_err().is_parse_error());
+    /// assert!(!evaluate("1/0").unwrap_err().is_parse_error());
+    /// assert!(!evaluate("1 + pie").unwrap_err().is_parse_error());
+    /// assert!(!evaluate("prod(i, 1, 3)").unwrap_err().is_parse_error());
+    ///
+    /// let mut context = Context::new();
+    /// assert!(evaluate_with_context("a = 2; 2 *", &mut context).unwrap_err().is_parse_error());
+    /// assert!(!evaluate_with_context("a = 2; sqrt(-a)", &mut context).unwrap_err().is_parse_error());
+    /// ```
+    pub fn is_parse_error(&self) -> bool {
+        match self {
+            CalcError::InvalidCharacter { .. }
+            | CalcError::InvalidNumber { .. }
+            | CalcError::UnexpectedToken { .. }
+            | CalcError::UnexpectedEnd { .. }
+            | CalcError::UnclosedDelimiter { .. }
+            | CalcError::EmptyExpression
+            | CalcError::Syntax { .. } => true,
+            CalcError::InStatement { error, .. } => error.is_parse_error(),
+            CalcError::DivisionByZero { .. }
+            | CalcError::UnknownIdentifier { .. }
+            | CalcError::UnknownFunction { .. }
+            | CalcError::ArityMismatch { .. }
+            | CalcError::DomainError { .. }
+            | CalcError::FunctionError { .. }
+            | CalcError::Overflow { .. }
+            | CalcError::Other { .. } => false,
+        }
+    }
+
+    /// An error of reading the input at `span`, of no other kind
+    pub(crate) fn syntax(message: impl Into<String>, span: Span) -> Self {
+        CalcError::Syntax {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// An error of no other kind, which points nowhere
+    pub(crate) fn other(message: impl Into<String>) -> Self {
+        CalcError::Other {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// An error of no other kind about `span`, whose position follows the
+    /// message
+    pub(crate) fn other_at(message: impl Into<String>, span: Span) -> Self {
+        CalcError::Other {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+
+    /// An argument outside the domain of a function
+    pub(crate) fn domain(message: impl Into<String>) -> Self {
+        CalcError::DomainError {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// A result too large to represent
+    pub(crate) fn overflow(message: impl Into<String>) -> Self {
+        CalcError::Overflow {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// A division by zero, found where the operator or function isn't known
+    pub(crate) fn division_by_zero() -> Self {
+        CalcError::DivisionByZero { span: None }
+    }
+
+    /// Points the error at `span`, the operator or call that it comes from,
+    /// unless it already points somewhere
+    ///
+    /// Only the kinds of error found without the input at hand, such as a
+    /// division by zero, are moved.
+    pub(crate) fn at(mut self, at: Span) -> Self {
+        if let CalcError::DivisionByZero { span }
+        | CalcError::ArityMismatch { span, .. }
+        | CalcError::DomainError { span, .. }
+        | CalcError::Overflow { span, .. } = &mut self
+        {
+            span.get_or_insert(at);
+        }
+        self
+    }
+
+    /// Marks the error as that of statement `index` of `count`, when there
+    /// are several
+    pub(crate) fn in_statement(self, index: usize, count: usize) -> Self {
+        if count < 2 {
+            return self;
+        }
+        CalcError::InStatement {
+            index,
+            error: Box::new(self),
+        }
+    }
+
+    /// Moves the span of the error with `f`
+    pub(crate) fn map_span(mut self, f: impl Fn(Span) -> Span) -> Self {
+        match &mut self {
+            CalcError::InvalidCharacter { span, .. }
+            | CalcError::InvalidNumber { span, .. }
+            | CalcError::UnexpectedToken { span, .. }
+            | CalcError::UnexpectedEnd { span }
+            | CalcError::UnclosedDelimiter { span, .. }
+            | CalcError::UnknownIdentifier { span, .. }
+            | CalcError::UnknownFunction { span, .. }
+            | CalcError::FunctionError { span, .. }
+            | CalcError::Syntax { span, .. } => *span = f(*span),
+            CalcError::DivisionByZero { span }
+            | CalcError::ArityMismatch { span, .. }
+            | CalcError::DomainError { span, .. }
+            | CalcError::Overflow { span, .. }
+            | CalcError::Other { span, .. } => *span = span.map(f),
+            CalcError::InStatement { error, .. } => {
+                let inner = std::mem::replace(error.as_mut(), CalcError::EmptyExpression);
+                **error = inner.map_span(f);
+            }
+            CalcError::EmptyExpression => {}
+        }
+        self
+    }
+}
+
+impl std::error::Error for CalcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CalcError::InStatement { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// How serious a problem found by [`validate`](crate::validate) is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// A problem that stops the input from being read
+    Error,
+    /// Input that can be read but won't evaluate, such as a division by a
+    /// literal zero
+    Warning,
+}
+
+/// A problem found in the input by [`validate`](crate::validate), with the
+/// part of the input it is about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl From<CalcError> for Diagnostic {
+    /// An error where the input can't be read, which points at the start of
+    /// the input if the error points nowhere
+    fn from(error: CalcError) -> Self {
+        Diagnostic {
+            span: error.span().unwrap_or(Span { start: 0, end: 0 }),
+            message: error.to_string(),
+            severity: Severity::Error,
+        }
+    }
+}
+
+/// Writes the line of `input` that `error` points at, with carets under the
+/// part it points at on the next line, followed by the message
+///
+/// Carets line up with what a terminal shows, counting a character such as
+/// `×` or `π` as one column however many bytes it takes, and a wide one such
+/// as `漢` as two. An error that points at nothing, or at the end of the
+/// input, gets a single caret. A division by zero or a domain error points at
+/// its operator or function. An error that doesn't point anywhere gives the
+/// input and the message on the next line.
+/// Positions are byte offsets into `input`, so the carets are under what was
+/// typed even where it has characters such as full-width digits.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{evaluate, render_error};
+///
+/// let input = "3+*2";
+/// assert_eq!(
+///     render_error(input, &evaluate(input).unwrap_err()),
+///     "3+*2\n  ^ unexpected '*' at position 2"
+/// );
+/// let input = "2×π + pie";
+/// assert_eq!(
+///     render_error(input, &evaluate(input).unwrap_err()),
+///     "2×π + pie\n      ^^^ undefined variable 'pie' at position 8"
+/// );
+/// let input = "2 * sqrt(-4)";
+/// assert_eq!(
+///     render_error(input, &evaluate(input).unwrap_err()),
+///     "2 * sqrt(-4)\n    ^^^^ domain error: sqrt is undefined for -4"
+/// );
+/// ```
+pub fn render_error(input: &str, error: &CalcError) -> String {
+    render(input, error.span(), error)
+}
+
+/// Writes a problem found by [`validate`](crate::validate) like
+/// [`render_error`], with carets under its span and a warning marked as one
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{render_diagnostic, validate};
+///
+/// let input = "1/0 + @2";
+/// let diagnostics = validate(input).unwrap_err();
+/// assert_eq!(
+///     render_diagnostic(input, &diagnostics[0]),
+///     "1/0 + @2\n ^ warning: division by zero"
+/// );
+/// assert_eq!(
+///     render_diagnostic(input, &diagnostics[1]),
+///     "1/0 + @2\n      ^ unexpected character '@' at position 6"
+/// );
+/// ```
+pub fn render_diagnostic(input: &str, diagnostic: &Diagnostic) -> String {
+    let message = match diagnostic.severity {
+        Severity::Error => diagnostic.message.clone(),
+        Severity::Warning => format!("warning: {}", diagnostic.message),
+    };
+    render(input, Some(diagnostic.span), &message)
+}
+
+/// Writes the line of `input` that `span` is on and carets under it,
+/// followed by `message`
+fn render(input: &str, span: Option<Span>, message: &dyn fmt::Display) -> String {
+    let Some(span) = span.filter(|span| input.is_char_boundary(span.start.min(input.len()))) else {
+        return format!("{}\n{}", input, message);
+    };
+    let start = span.start.min(input.len());
+    let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+    let end = span.end.clamp(start, line_end);
+    let end = if input.is_char_boundary(end) {
+        end
+    } else {
+        start
+    };
+
+    // A tab is copied so that it lines up as far as it does above
+    let padding: String = input[line_start..start]
+        .chars()
+        .map(|ch| match ch {
+            '\t' => "\t".to_string(),
+            ch => " ".repeat(display_width(ch)),
+        })
+        .collect();
+    let width: usize = input[start..end].chars().map(display_width).sum();
+    format!(
+        "{}\n{}{} {}",
+        input[line_start..line_end].trim_end_matches('\r'),
+        padding,
+        "^".repeat(width.max(1)),
+        message
+    )
+}
+
+/// The number of columns a terminal shows `ch` in: none for combining marks,
+/// two for the wide characters of East Asian scripts and emoji and one for
+/// anything else
+fn display_width(ch: char) -> usize {
+    match ch {
+        '\u{0300}'..='\u{036F}' | '\u{200B}'..='\u{200F}' | '\u{FE00}'..='\u{FE0F}' => 0,
+        '\u{1100}'..='\u{115F}'
+        | '\u{2E80}'..='\u{303E}'
+        | '\u{3041}'..='\u{A4CF}'
+        | '\u{AC00}'..='\u{D7A3}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{1F300}'..='\u{1F64F}'
+        | '\u{1F900}'..='\u{1F9FF}'
+        | '\u{20000}'..='\u{3FFFD}' => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, evaluate, evaluate_with_context};
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn invalid_character() {
+        let error = evaluate("2 $ 3").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::InvalidCharacter {
+                ch: '$',
+                span: span(2, 3)
+            }
+        );
+        assert_eq!(error.to_string(), "unexpected character '$' at position 2");
+        assert!(error.is_parse_error());
+    }
+
+    #[test]
+    fn invalid_number() {
+        let error = evaluate("1.2.3 + 4").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::InvalidNumber {
+                text: "1.2.3".to_string(),
+                span: span(0, 5)
+            }
+        );
+        assert_eq!(error.to_string(), "invalid number '1.2.3' at position 0");
+        assert!(error.is_parse_error());
+    }
+
+    #[test]
+    fn unexpected_token() {
+        let error = evaluate("2 + * 3").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::UnexpectedToken {
+                token: "*".to_string(),
+                span: span(4, 5)
+            }
+        );
+        assert_eq!(error.to_string(), "unexpected '*' at position 4");
+        assert!(error.is_parse_error());
+    }
+
+    #[test]
+    fn unexpected_end() {
+        let error = evaluate("2 +").unwrap_err();
+        assert_eq!(error, CalcError::UnexpectedEnd { span: span(3, 3) });
+        assert_eq!(
+            error.to_string(),
+            "unexpected end of input, expected a number at position 3"
+        );
+        assert!(error.is_parse_error());
+    }
+
+    #[test]
+    fn unclosed_delimiter() {
+        let error = evaluate("(1 + 2").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::UnclosedDelimiter {
+                delimiter: '(',
+                span: span(0, 1)
+            }
+        );
+        assert_eq!(error.to_string(), "unclosed parenthesis at position 0");
+        assert!(error.is_parse_error());
+        assert!(matches!(
+            evaluate("[1, 2"),
+            Err(CalcError::UnclosedDelimiter { delimiter: '[', .. })
+        ));
+        assert!(matches!(
+            evaluate("|2-5"),
+            Err(CalcError::UnclosedDelimiter { delimiter: '|', .. })
+        ));
+        assert_eq!(
+            evaluate("1 ? 2").unwrap_err().to_string(),
+            "missing ':' for the '?' at position 2"
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let error = evaluate("1 / (2 - 2)").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::DivisionByZero {
+                span: Some(span(2, 3))
+            }
+        );
+        assert_eq!(error.to_string(), "division by zero");
+        assert!(!error.is_parse_error());
+        assert_eq!(
+            evaluate("7 % 0"),
+            Err(CalcError::DivisionByZero {
+                span: Some(span(2, 3))
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_identifier() {
+        let error = evaluate("1 + pie").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::UnknownIdentifier {
+                name: "pie".to_string(),
+                span: span(4, 7)
+            }
+        );
+        assert_eq!(error.to_string(), "undefined variable 'pie' at position 4");
+        assert!(!error.is_parse_error());
+    }
+
+    #[test]
+    fn unknown_function() {
+        let error = evaluate("foo(1)").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::UnknownFunction {
+                name: "foo".to_string(),
+                span: span(0, 3)
+            }
+        );
+        assert_eq!(error.to_string(), "unknown function 'foo' at position 0");
+        assert!(!error.is_parse_error());
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        let error = evaluate("hypot(3)").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::ArityMismatch {
+                name: "hypot".to_string(),
+                expected: "2 arguments".to_string(),
+                got: 1,
+                span: Some(span(0, 5)),
+            }
+        );
+        assert_eq!(error.to_string(), "hypot expects 2 arguments, got 1");
+        assert!(!error.is_parse_error());
+
+        // Found while reading the input, but still an error of evaluating
+        let error = evaluate("1 + prod(i, 1, 3)").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::ArityMismatch {
+                name: "prod".to_string(),
+                expected: "4 arguments".to_string(),
+                got: 3,
+                span: Some(span(4, 8)),
+            }
+        );
+        assert_eq!(error.to_string(), "prod expects 4 arguments, got 3");
+        assert!(!error.is_parse_error());
+        assert!(matches!(
+            evaluate("max()"),
+            Err(CalcError::ArityMismatch { got: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn domain_error() {
+        let error = evaluate("sqrt(-4)").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::DomainError {
+                message: "sqrt is undefined for -4".to_string(),
+                span: Some(span(0, 4)),
+            }
+        );
+        assert_eq!(error.to_string(), "domain error: sqrt is undefined for -4");
+        assert!(!error.is_parse_error());
+        assert_eq!(
+            evaluate("(-1)!"),
+            Err(CalcError::DomainError {
+                message: "factorial requires a non-negative integer".to_string(),
+                span: Some(span(4, 5)),
+            })
+        );
+    }
+
+    #[test]
+    fn function_error() {
+        let mut context = Context::new();
+        context
+            .register_fn("half", 1, |args| match args[0] {
+                x if x < 0.0 => Err("only halves positive numbers".to_string()),
+                x => Ok(x / 2.0),
+            })
+            .unwrap();
+        let error = evaluate_with_context("1 + half(-2)", &mut context).unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::FunctionError {
+                name: "half".to_string(),
+                message: "only halves positive numbers".to_string(),
+                span: span(4, 8),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "half failed at position 4: only halves positive numbers"
+        );
+        assert!(!error.is_parse_error());
+    }
+
+    #[test]
+    fn overflow() {
+        let error = evaluate("172!").unwrap_err();
+        assert!(matches!(error, CalcError::Overflow { span: Some(_), .. }));
+        assert!(!error.is_parse_error());
+        let error = evaluate("fib(79)").unwrap_err();
+        assert!(matches!(error, CalcError::Overflow { .. }));
+        assert_eq!(
+            error.to_string(),
+            "fib result is too large to represent exactly"
+        );
+    }
+
+    #[test]
+    fn empty_expression() {
+        for input in ["", "  ", " # nothing"] {
+            let error = evaluate(input).unwrap_err();
+            assert_eq!(error, CalcError::EmptyExpression, "{:?}", input);
+            assert_eq!(error.to_string(), "empty expression");
+            assert_eq!(error.span(), None);
+            assert!(error.is_parse_error());
+        }
+        assert_eq!(
+            evaluate_with_context(";;", &mut Context::new()),
+            Err(CalcError::EmptyExpression)
+        );
+    }
+
+    #[test]
+    fn syntax() {
+        let error = evaluate("1 + %50").unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::Syntax {
+                message: "'%' must follow a number".to_string(),
+                span: span(4, 5),
+            }
+        );
+        assert_eq!(error.to_string(), "'%' must follow a number at position 4");
+        assert!(error.is_parse_error());
+    }
+
+    #[test]
+    fn in_statement() {
+        let mut context = Context::new();
+        let error = evaluate_with_context("a = 2; 1/0", &mut context).unwrap_err();
+        assert_eq!(
+            error,
+            CalcError::InStatement {
+                index: 1,
+                error: Box::new(CalcError::DivisionByZero {
+                    span: Some(span(8, 9))
+                }),
+            }
+        );
+        assert_eq!(error.to_string(), "error in statement 2: division by zero");
+        assert_eq!(error.span(), Some(span(8, 9)));
+        assert_eq!(
+            error.inner(),
+            &CalcError::DivisionByZero {
+                span: Some(span(8, 9))
+            }
+        );
+        assert!(!error.is_parse_error());
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), "division by zero");
+
+        assert!(matches!(
+            evaluate_with_context("x = 1; sqrt(-1)", &mut context)
+                .unwrap_err()
+                .inner(),
+            CalcError::DomainError { .. }
+        ));
+        assert_eq!(
+            evaluate_with_context("a = 1; y + 1", &mut context)
+                .unwrap_err()
+                .inner(),
+            &CalcError::UnknownIdentifier {
+                name: "y".to_string(),
+                span: span(7, 8)
+            }
+        );
+        assert!(
+            evaluate_with_context("a = 2; 2 *", &mut context)
+                .unwrap_err()
+                .is_parse_error()
+        );
+
+        // A single statement is never wrapped
+        let error = CalcError::division_by_zero().in_statement(0, 1);
+        assert_eq!(error, CalcError::DivisionByZero { span: None });
+    }
+
+    #[test]
+    fn other() {
+        let error = CalcError::other("too many iterations");
+        assert_eq!(error.to_string(), "too many iterations");
+        assert_eq!(error.span(), None);
+        assert!(!error.is_parse_error());
+        let error = CalcError::other_at("cannot mix currencies", span(5, 6));
+        assert_eq!(error.to_string(), "cannot mix currencies at position 5");
+        assert_eq!(error.span(), Some(span(5, 6)));
+        assert!(matches!(
+            evaluate("1:30 + 5"),
+            Err(CalcError::Other { span: Some(_), .. })
+        ));
+    }
+
+    #[test]
+    fn at_only_fills_a_missing_span() {
+        let error = CalcError::domain("ln is undefined for 0").at(span(0, 2));
+        assert_eq!(error.span(), Some(span(0, 2)));
+        assert_eq!(error.at(span(5, 6)).span(), Some(span(0, 2)));
+        assert_eq!(
+            CalcError::overflow("too large").at(span(1, 2)).span(),
+            Some(span(1, 2))
+        );
+        assert_eq!(CalcError::other("no kind").at(span(1, 2)).span(), None);
+    }
+
+    #[test]
+    fn map_span_moves_every_span() {
+        let shift = |span: Span| Span {
+            start: span.start + 10,
+            end: span.end + 10,
+        };
+        let error = CalcError::syntax("unexpected", span(1, 2)).in_statement(1, 2);
+        assert_eq!(error.map_span(shift).span(), Some(span(11, 12)));
+        assert_eq!(CalcError::division_by_zero().map_span(shift).span(), None);
+        assert_eq!(
+            CalcError::EmptyExpression.map_span(shift),
+            CalcError::EmptyExpression
+        );
+    }
+
+    #[test]
+    fn the_error_trait() {
+        let error: Box<dyn std::error::Error> = Box::new(evaluate("ln(0)").unwrap_err());
+        assert_eq!(error.to_string(), "domain error: ln is undefined for 0");
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn diagnostics_from_errors() {
+        let diagnostic = Diagnostic::from(CalcError::EmptyExpression);
+        assert_eq!(diagnostic.span, span(0, 0));
+        assert_eq!(diagnostic.message, "empty expression");
+        assert_eq!(diagnostic.severity, Severity::Error);
+    }
+
+    #[test]
+    fn render_error_columns() {
+        let render = |input: &str| render_error(input, &evaluate(input).unwrap_err());
+        assert_eq!(
+            render("π ×"),
+            "π ×\n   ^ unexpected end of input, expected a number at position 5"
+        );
+        assert_eq!(
+            render("(1 + 2"),
+            "(1 + 2\n^ unclosed parenthesis at position 0"
+        );
+        assert!(render("2 + 漢").starts_with("2 + 漢\n    ^^ "));
+        assert_eq!(render("1 + 1/0"), "1 + 1/0\n     ^ division by zero");
+        assert_eq!(
+            render("sqrt(1, 2)"),
+            "sqrt(1, 2)\n^^^^ sqrt expects 1 argument, got 2"
+        );
+        assert_eq!(
+            render("(-1)!"),
+            "(-1)!\n    ^ domain error: factorial requires a non-negative integer"
+        );
+        assert_eq!(
+            render("1 +\n\t2 $"),
+            "\t2 $\n\t  ^ unexpected character '$' at position 7"
+        );
+        assert_eq!(render(" # nothing"), " # nothing\nempty expression");
+    }
+}
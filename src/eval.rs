@@ -0,0 +1,2179 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::functions;
+use crate::options::{DivisionByZero, EvalOptions, Overflow, RemainderMode};
+use crate::parser::{BinaryOp, BindingForm, Expr, UnaryOp};
+
+/// Evaluates an expression tree to a number, looking names up in `context`
+pub(crate) fn eval(expr: &Expr, context: &Context) -> Result<f64, CalcError> {
+    eval_scoped(expr, context, &[])
+}
+
+/// Evaluates an expression with `locals` binding the parameters of the
+/// user-defined function being called, which take precedence over other names
+pub(crate) fn eval_scoped(
+    expr: &Expr,
+    context: &Context,
+    locals: &[(&str, f64)],
+) -> Result<f64, CalcError> {
+    let eval = |expr| eval_scoped(expr, context, locals);
+    match expr {
+        Expr::Number { value, .. } => Ok(*value),
+        Expr::Var { name, span } => locals
+            .iter()
+            .find(|(local, _)| local == name)
+            .map(|(_, value)| *value)
+            .or_else(|| context.lookup(name))
+            .ok_or_else(|| {
+                if name == "ans" {
+                    CalcError::other_at("no previous result for 'ans'", *span)
+                } else if context.get_list(name).is_some() {
+                    let message = format!("'{}' holds a list, which isn't a single number", name);
+                    CalcError::other_at(message, *span)
+                } else {
+                    CalcError::UnknownIdentifier {
+                        name: name.clone(),
+                        span: *span,
+                    }
+                }
+            }),
+        Expr::Unary { op, operand, span } => {
+            unary(*op, eval(operand)?, context.options()).map_err(|e| e.at(*span))
+        }
+        Expr::Binary { op, lhs, rhs, span } => {
+            binary(*op, eval(lhs)?, eval(rhs)?, context.options()).map_err(|e| e.at(*span))
+        }
+        // Any value other than zero counts as true
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            if eval(condition)? != 0.0 {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+        Expr::Binding {
+            form,
+            var,
+            body,
+            args,
+            ..
+        } => {
+            let args = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            // The bound variable comes first, so it hides any other name
+            let mut scope = vec![(var.as_str(), 0.0)];
+            scope.extend_from_slice(locals);
+            let at = |value| {
+                scope[0].1 = value;
+                eval_scoped(body, context, &scope)
+            };
+            let (depth, terms) = SERIES_TERMS.get();
+            SERIES_TERMS.set((depth + 1, if depth == 0 { 0 } else { terms }));
+            let result = match form {
+                BindingForm::Sum | BindingForm::Product => series(*form, args[0], args[1], at),
+                BindingForm::Derivative => derivative(args[0], at),
+                BindingForm::Integral => integral(args[0], args[1], at),
+                BindingForm::Solve => solve(body, var, &args, at),
+            };
+            SERIES_TERMS.set((depth, SERIES_TERMS.get().1));
+            result
+        }
+        Expr::Call { name, args, span } => {
+            // A registered function may replace a built-in one
+            if let Some(native) = context.native(name) {
+                native
+                    .arity
+                    .check(name, args.len())
+                    .map_err(|e| e.at(*span))?;
+                let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+                return (native.call)(&values).map_err(|message| CalcError::FunctionError {
+                    name: name.clone(),
+                    message,
+                    span: *span,
+                });
+            }
+            if let Some(function) = functions::lookup(name) {
+                function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+                let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+                let result =
+                    (function.call)(&values, context.options()).map_err(|e| e.at(*span))?;
+                if values.iter().all(|x| x.is_finite()) {
+                    overflowed(result, || name.clone(), context.options())
+                        .map_err(|e| e.at(*span))?;
+                }
+                return Ok(result);
+            }
+
+            let function = context
+                .function(name)
+                .ok_or_else(|| CalcError::UnknownFunction {
+                    name: name.clone(),
+                    span: *span,
+                })?;
+            if args.len() != function.params.len() {
+                return Err(CalcError::ArityMismatch {
+                    name: name.clone(),
+                    expected: functions::plural(function.params.len()),
+                    got: args.len(),
+                    span: Some(*span),
+                });
+            }
+            let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            // The body only sees its own parameters, not the caller's
+            let params: Vec<_> = function
+                .params
+                .iter()
+                .map(String::as_str)
+                .zip(values)
+                .collect();
+            // Positions in the body are in the text of the definition, so
+            // errors point at the call instead
+            eval_scoped(&function.body, context, &params)
+                .map_err(|e| e.map_span(|_| *span).at(*span))
+        }
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "a list such as [1, 2] isn't a single number",
+            *span,
+        )),
+    }
+}
+
+/// Largest number of terms in a `sum` or `prod`, so that a huge range fails
+/// rather than running for a very long time. It also bounds all the terms of
+/// the sums and products inside one, or inside an integral or the like, which
+/// would otherwise multiply.
+const MAX_SERIES_TERMS: u64 = 1_000_000;
+
+thread_local! {
+    // How many forms such as `sum` are being evaluated, one inside the other,
+    // and the terms of sums and products counted since the outermost began
+    static SERIES_TERMS: Cell<(usize, u64)> = const { Cell::new((0, 0)) };
+}
+
+/// Adds up or multiplies `term(i)` for each integer `i` from `lo` to `hi`
+fn series(
+    form: BindingForm,
+    lo: f64,
+    hi: f64,
+    mut term: impl FnMut(f64) -> Result<f64, CalcError>,
+) -> Result<f64, CalcError> {
+    for bound in [lo, hi] {
+        if bound.fract() != 0.0 || !bound.is_finite() {
+            return Err(CalcError::other(format!(
+                "{} bounds must be integers, got {}",
+                form.name(),
+                bound
+            )));
+        }
+    }
+    if hi - lo >= MAX_SERIES_TERMS as f64 {
+        return Err(CalcError::other(format!(
+            "too many iterations, {} is limited to {} terms",
+            form.name(),
+            MAX_SERIES_TERMS
+        )));
+    }
+    let mut result = if form == BindingForm::Sum { 0.0 } else { 1.0 };
+    // A range with `lo` above `hi` has no terms
+    let count = (hi - lo + 1.0).max(0.0) as u64;
+    let (depth, terms) = SERIES_TERMS.get();
+    if terms + count > MAX_SERIES_TERMS {
+        return Err(CalcError::other(format!(
+            "too many iterations, nested sums and products are limited to {} terms in all",
+            MAX_SERIES_TERMS
+        )));
+    }
+    SERIES_TERMS.set((depth, terms + count));
+    for i in 0..count {
+        let value = term(lo + i as f64)?;
+        if form == BindingForm::Sum {
+            result += value;
+        } else {
+            result *= value;
+        }
+    }
+    Ok(result)
+}
+
+/// Estimates the derivative of `f` at `at` with a central difference
+///
+/// The step is `max(|at|, 1) * cbrt(eps)`, which balances the error of the
+/// approximation against rounding error in the difference.
+pub(crate) fn derivative(
+    at: f64,
+    mut f: impl FnMut(f64) -> Result<f64, CalcError>,
+) -> Result<f64, CalcError> {
+    // Surfaces an error at the point itself, such as for 1/x at 0, which the
+    // points either side of it would miss
+    f(at)?;
+    let step = at.abs().max(1.0) * f64::EPSILON.cbrt();
+    let (above, below) = (at + step, at - step);
+    Ok((f(above)? - f(below)?) / (above - below))
+}
+
+/// Largest absolute error `integrate` aims for
+const INTEGRATION_TOLERANCE: f64 = 1e-10;
+
+/// Largest number of times `integrate` splits an interval, so that an
+/// integrand it can't handle fails rather than running for a very long time
+const MAX_SUBDIVISIONS: usize = 10_000;
+
+/// Integrates `f` from `a` to `b` with adaptive Simpson quadrature
+///
+/// Each interval is split in two until the estimates of both halves agree with
+/// the estimate of the whole to within the tolerance, which is shared out
+/// between the halves.
+fn integral(
+    a: f64,
+    b: f64,
+    mut f: impl FnMut(f64) -> Result<f64, CalcError>,
+) -> Result<f64, CalcError> {
+    for bound in [a, b] {
+        if !bound.is_finite() {
+            return Err(CalcError::other(format!(
+                "integrate bounds must be finite, got {}",
+                bound
+            )));
+        }
+    }
+    if a == b {
+        return Ok(0.0);
+    }
+    // Reversed bounds negate the integral
+    let (lo, hi, sign) = if a < b { (a, b, 1.0) } else { (b, a, -1.0) };
+    let simpson = |lo: f64, hi: f64, f_lo: f64, f_mid: f64, f_hi: f64| {
+        (hi - lo) / 6.0 * (f_lo + 4.0 * f_mid + f_hi)
+    };
+
+    let mid = (lo + hi) / 2.0;
+    let (f_lo, f_mid, f_hi) = (f(lo)?, f(mid)?, f(hi)?);
+    let whole = simpson(lo, hi, f_lo, f_mid, f_hi);
+    let mut pending = vec![(lo, hi, f_lo, f_mid, f_hi, whole, INTEGRATION_TOLERANCE)];
+    let (mut total, mut subdivisions) = (0.0, 0);
+    while let Some((lo, hi, f_lo, f_mid, f_hi, whole, tolerance)) = pending.pop() {
+        let mid = (lo + hi) / 2.0;
+        let (left_mid, right_mid) = ((lo + mid) / 2.0, (mid + hi) / 2.0);
+        let (f_left, f_right) = (f(left_mid)?, f(right_mid)?);
+        let left = simpson(lo, mid, f_lo, f_left, f_mid);
+        let right = simpson(mid, hi, f_mid, f_right, f_hi);
+        let error = left + right - whole;
+        if error.abs() <= 15.0 * tolerance {
+            // Richardson extrapolation removes most of the remaining error
+            total += left + right + error / 15.0;
+            continue;
+        }
+        subdivisions += 1;
+        if subdivisions > MAX_SUBDIVISIONS {
+            return Err(CalcError::other(format!(
+                "integrate did not converge after {} subdivisions",
+                MAX_SUBDIVISIONS
+            )));
+        }
+        pending.push((lo, mid, f_lo, f_left, f_mid, left, tolerance / 2.0));
+        pending.push((mid, hi, f_mid, f_right, f_hi, right, tolerance / 2.0));
+    }
+    Ok(sign * total)
+}
+
+/// Range `solve` searches for a root of a nonlinear equation when none is given
+const DEFAULT_SOLVE_RANGE: (f64, f64) = (-100.0, 100.0);
+
+/// Number of pieces `solve` splits the range into when looking for a change of
+/// sign, so that roots closer together than a piece may be missed
+const SOLVE_SAMPLES: usize = 1000;
+
+/// Finds a value of `var` at which `f`, the difference between the two sides
+/// of the equation `body`, is zero
+///
+/// A linear equation is solved directly from its coefficients. Otherwise the
+/// range is searched for a change of sign, and the lowest one that is a root
+/// rather than a pole is narrowed down by bisection.
+fn solve(
+    body: &Expr,
+    var: &str,
+    range: &[f64],
+    mut f: impl FnMut(f64) -> Result<f64, CalcError>,
+) -> Result<f64, CalcError> {
+    if !mentions(body, var) {
+        return Err(CalcError::other(format!(
+            "'{}' does not appear in the equation",
+            var
+        )));
+    }
+    let (lo, hi) = match range {
+        [lo, hi] => (*lo, *hi),
+        _ => DEFAULT_SOLVE_RANGE,
+    };
+    if !lo.is_finite() || !hi.is_finite() || lo >= hi {
+        return Err(CalcError::other(format!(
+            "solve range must be finite and increasing, got {} to {}",
+            lo, hi
+        )));
+    }
+    let no_root = || {
+        CalcError::other(format!(
+            "no solution for '{}' found between {} and {}",
+            var, lo, hi
+        ))
+    };
+
+    if is_linear(body, var) {
+        // f(x) = slope * x + constant
+        let constant = f(0.0)?;
+        let slope = f(1.0)? - constant;
+        if slope == 0.0 {
+            return Err(if constant == 0.0 {
+                CalcError::other(format!(
+                    "'{}' cancels out, every value solves the equation",
+                    var
+                ))
+            } else {
+                CalcError::other(format!(
+                    "'{}' cancels out, the equation has no solution",
+                    var
+                ))
+            });
+        }
+        let root = -constant / slope;
+        if !range.is_empty() && !(lo..=hi).contains(&root) {
+            return Err(no_root());
+        }
+        return Ok(root);
+    }
+
+    let step = (hi - lo) / SOLVE_SAMPLES as f64;
+    let mut previous: Option<(f64, f64)> = None;
+    for i in 0..=SOLVE_SAMPLES {
+        let x = if i == SOLVE_SAMPLES {
+            hi
+        } else {
+            lo + step * i as f64
+        };
+        // Points where the equation is undefined, such as the 0 in 1/x = 2,
+        // are skipped
+        let Ok(y) = f(x) else {
+            previous = None;
+            continue;
+        };
+        if y == 0.0 {
+            return Ok(x);
+        }
+        if let Some((a, f_a)) = previous
+            && (f_a < 0.0) != (y < 0.0)
+            && let Some(root) = bisect(a, f_a, x, y, &mut f)?
+        {
+            return Ok(root);
+        }
+        previous = Some((x, y));
+    }
+    Err(no_root())
+}
+
+/// Narrows down a change of sign of `f` between `a` and `b`, returning `None`
+/// if it turns out to be a pole, where `f` grows rather than reaching zero
+fn bisect(
+    mut a: f64,
+    mut f_a: f64,
+    mut b: f64,
+    f_b: f64,
+    f: &mut impl FnMut(f64) -> Result<f64, CalcError>,
+) -> Result<Option<f64>, CalcError> {
+    let bound = f_a.abs().min(f_b.abs());
+    loop {
+        let mid = (a + b) / 2.0;
+        // Stops once no float lies between the ends
+        if mid <= a || mid >= b {
+            break;
+        }
+        let f_mid = f(mid)?;
+        if f_mid == 0.0 {
+            return Ok(Some(mid));
+        }
+        if (f_mid < 0.0) == (f_a < 0.0) {
+            (a, f_a) = (mid, f_mid);
+        } else {
+            b = mid;
+        }
+    }
+    let root = (a + b) / 2.0;
+    Ok((f(root)?.abs() <= bound).then_some(root))
+}
+
+/// Returns whether `var` is used anywhere in `expr`
+fn mentions(expr: &Expr, var: &str) -> bool {
+    match expr {
+        Expr::Number { .. } => false,
+        Expr::Var { name, .. } => name == var,
+        Expr::Unary { operand, .. } => mentions(operand, var),
+        Expr::Binary { lhs, rhs, .. } => mentions(lhs, var) || mentions(rhs, var),
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => mentions(condition, var) || mentions(then, var) || mentions(otherwise, var),
+        Expr::Call { args, .. } => args.iter().any(|arg| mentions(arg, var)),
+        Expr::List { items, .. } => items.iter().any(|item| mentions(item, var)),
+        // A binding of the same name hides `var` inside its body
+        Expr::Binding {
+            var: bound,
+            body,
+            args,
+            ..
+        } => (bound != var && mentions(body, var)) || args.iter().any(|arg| mentions(arg, var)),
+    }
+}
+
+/// Returns whether `expr` is of the form `a * var + b`, judging by its shape,
+/// where `a` and `b` don't depend on `var`
+fn is_linear(expr: &Expr, var: &str) -> bool {
+    if !mentions(expr, var) {
+        return true;
+    }
+    match expr {
+        Expr::Var { .. } => true,
+        Expr::Unary {
+            op: UnaryOp::Neg | UnaryOp::Pos | UnaryOp::Percent,
+            operand,
+            ..
+        } => is_linear(operand, var),
+        Expr::Binary {
+            op: BinaryOp::Add | BinaryOp::Sub,
+            lhs,
+            rhs,
+            ..
+        } => is_linear(lhs, var) && is_linear(rhs, var),
+        Expr::Binary {
+            op: BinaryOp::Mul,
+            lhs,
+            rhs,
+            ..
+        } => {
+            (!mentions(lhs, var) && is_linear(rhs, var))
+                || (!mentions(rhs, var) && is_linear(lhs, var))
+        }
+        Expr::Binary {
+            op: BinaryOp::Div,
+            lhs,
+            rhs,
+            ..
+        } => !mentions(rhs, var) && is_linear(lhs, var),
+        _ => false,
+    }
+}
+
+/// Returns the remainder of dividing `lhs` by a non-zero `rhs`, with the sign
+/// chosen by `mode`
+pub(crate) fn remainder(lhs: f64, rhs: f64, mode: RemainderMode) -> f64 {
+    let truncated = lhs % rhs;
+    let sign = truncated.partial_cmp(&0.0).unwrap_or(Ordering::Equal);
+    truncated + f64::from(mode.correction(sign, rhs < 0.0)) * rhs
+}
+
+/// Applies a prefix or postfix operator to a number
+pub(crate) fn unary(op: UnaryOp, value: f64, options: &EvalOptions) -> Result<f64, CalcError> {
+    match op {
+        UnaryOp::Neg => Ok(-value),
+        UnaryOp::Pos => Ok(value),
+        UnaryOp::BitNot => bitwise_result(!bitwise_operand(value, options)?, options),
+        UnaryOp::Factorial => factorial(value),
+        UnaryOp::Percent => Ok(value / 100.0),
+    }
+}
+
+/// Applies a binary operator to two numbers
+pub(crate) fn binary(
+    op: BinaryOp,
+    lhs: f64,
+    rhs: f64,
+    options: &EvalOptions,
+) -> Result<f64, CalcError> {
+    let result = operate(op, lhs, rhs, options)?;
+    // Dividing by zero and raising zero to a negative power give infinity
+    // from finite operands without overflowing
+    let pole = match op {
+        BinaryOp::Div | BinaryOp::FloorDiv => rhs == 0.0,
+        BinaryOp::Pow => lhs == 0.0,
+        _ => false,
+    };
+    if !pole && lhs.is_finite() && rhs.is_finite() {
+        overflowed(result, || format!("'{}'", op.symbol()), options)?;
+    }
+    Ok(result)
+}
+
+/// Fails if `result` is infinite and [`EvalOptions::overflow`] makes that an
+/// error, with `what` naming the operator or function that gave it
+pub(crate) fn overflowed(
+    result: f64,
+    what: impl FnOnce() -> String,
+    options: &EvalOptions,
+) -> Result<(), CalcError> {
+    if result.is_infinite() && options.overflow == Overflow::Error {
+        return Err(CalcError::overflow(format!(
+            "the result of {} is too large",
+            what()
+        )));
+    }
+    Ok(())
+}
+
+/// Fails on a divisor of zero unless [`EvalOptions::division_by_zero`] asks
+/// for the IEEE 754 result
+fn check_divisor(rhs: f64, options: &EvalOptions) -> Result<(), CalcError> {
+    if rhs == 0.0 && options.division_by_zero == DivisionByZero::Error {
+        return Err(CalcError::division_by_zero());
+    }
+    Ok(())
+}
+
+fn operate(op: BinaryOp, lhs: f64, rhs: f64, options: &EvalOptions) -> Result<f64, CalcError> {
+    match op {
+        BinaryOp::Add => Ok(lhs + rhs),
+        BinaryOp::Sub => Ok(lhs - rhs),
+        BinaryOp::Mul => Ok(lhs * rhs),
+        BinaryOp::Div => {
+            check_divisor(rhs, options)?;
+            Ok(lhs / rhs)
+        }
+        // Floor division rounds towards negative infinity, so `-7//2` is `-4`
+        BinaryOp::FloorDiv => {
+            check_divisor(rhs, options)?;
+            Ok((lhs / rhs).floor())
+        }
+        // Truncated remainder: the result takes the sign of the dividend,
+        // so `-7%3` is `-1`
+        BinaryOp::Rem => {
+            check_divisor(rhs, options)?;
+            Ok(remainder(lhs, rhs, options.remainder_mode))
+        }
+        // Follows `f64::powf`, so a negative base with a fractional
+        // exponent such as `(-8)^(1/3)` yields NaN
+        BinaryOp::Pow => Ok(lhs.powf(rhs)),
+        BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::ShiftLeft => {
+            let lhs = bitwise_operand(lhs, options)?;
+            let result = match op {
+                BinaryOp::BitAnd => lhs & bitwise_operand(rhs, options)?,
+                BinaryOp::BitOr => lhs | bitwise_operand(rhs, options)?,
+                BinaryOp::BitXor => lhs ^ bitwise_operand(rhs, options)?,
+                _ => lhs << shift_amount(rhs, options)?,
+            };
+            bitwise_result(result, options)
+        }
+        BinaryOp::ShiftRight => {
+            let lhs = bitwise_operand(lhs, options)?;
+            let amount = shift_amount(rhs, options)?;
+            match options.bit_width {
+                // Zeros shift in, as they do for the unsigned number shown
+                Some(width) => {
+                    bitwise_result((bit_pattern(lhs, Some(width)) >> amount) as i64, options)
+                }
+                None => Ok((lhs >> amount) as f64),
+            }
+        }
+        // Only parsed for `evaluate_units`, which converts without coming here
+        BinaryOp::Convert => Err(CalcError::other("converting with 'in' requires units")),
+        // Only evaluated by `uncertain`, which `evaluate_value` uses for it
+        BinaryOp::PlusMinus => Err(CalcError::other(
+            "a value with an uncertainty such as 5 ± 0.1 isn't a single number",
+        )),
+        _ => Ok(compare(op, lhs, rhs, options.comparison_tolerance)),
+    }
+}
+
+/// Converts an operand of a bitwise operator to a two's complement `i64`
+fn bitwise_operand(value: f64, options: &EvalOptions) -> Result<i64, CalcError> {
+    integer_bits(value, options.bit_width, "bitwise operators require")
+}
+
+/// Converts an integer to a two's complement `i64`, which must fit in `width`
+/// bits either as a signed or an unsigned number. `requires` starts the error
+/// for a number that isn't such an integer.
+pub(crate) fn integer_bits(
+    value: f64,
+    width: Option<u32>,
+    requires: &str,
+) -> Result<i64, CalcError> {
+    if value.fract() != 0.0 || !value.is_finite() {
+        return Err(CalcError::other(format!(
+            "{} integers, got {}",
+            requires, value
+        )));
+    }
+    if value.abs() > functions::MAX_EXACT_INTEGER {
+        return Err(CalcError::overflow(format!(
+            "{} integers, got {} which is too large to be exact",
+            requires, value
+        )));
+    }
+    let n = value as i64;
+    if let Some(width) = width {
+        if !(1..=64).contains(&width) {
+            return Err(CalcError::other(format!(
+                "bit width must be from 1 to 64, got {}",
+                width
+            )));
+        }
+        let (lo, hi) = (-(1i128 << (width - 1)), (1i128 << width) - 1);
+        if !(lo..=hi).contains(&i128::from(n)) {
+            return Err(CalcError::other(format!(
+                "{} integers from {} to {} at a width of {} bits, got {}",
+                requires, lo, hi, width, value
+            )));
+        }
+    }
+    Ok(n)
+}
+
+/// The bits of `n` in `width` bits of two's complement, or those of its
+/// magnitude without a width
+pub(crate) fn bit_pattern(n: i64, width: Option<u32>) -> u64 {
+    match width {
+        Some(64) => n as u64,
+        Some(width) => n as u64 & ((1 << width) - 1),
+        None => n.unsigned_abs(),
+    }
+}
+
+/// The result of a bitwise operator, which is unsigned within a width
+fn bitwise_result(n: i64, options: &EvalOptions) -> Result<f64, CalcError> {
+    let Some(width) = options.bit_width else {
+        return Ok(n as f64);
+    };
+    let bits = bit_pattern(n, Some(width));
+    if bits as f64 > functions::MAX_EXACT_INTEGER {
+        return Err(CalcError::overflow(format!(
+            "bitwise result {} is too large to be exact at a width of {} bits",
+            bits, width
+        )));
+    }
+    Ok(bits as f64)
+}
+
+/// Converts the right operand of a shift to a number of bits, which must be
+/// less than the width of an `i64`, or the bit width if one is set
+fn shift_amount(value: f64, options: &EvalOptions) -> Result<u32, CalcError> {
+    let amount = integer_bits(value, None, "bitwise operators require")?;
+    let width = options.bit_width.unwrap_or(64);
+    if !(0..i64::from(width)).contains(&amount) {
+        return Err(CalcError::other(format!(
+            "shift amount must be between 0 and {}, got {}",
+            width - 1,
+            amount
+        )));
+    }
+    Ok(amount as u32)
+}
+
+/// Evaluates a comparison to 1 when it holds and 0 otherwise
+///
+/// Numbers within `tolerance` of each other are equal. NaN is not equal to
+/// anything, itself included, and every comparison with it is false apart
+/// from `!=`.
+fn compare(op: BinaryOp, lhs: f64, rhs: f64, tolerance: f64) -> f64 {
+    let equal = lhs == rhs || (lhs - rhs).abs() <= tolerance;
+    let holds = match op {
+        BinaryOp::Less => lhs < rhs && !equal,
+        BinaryOp::LessEqual => lhs < rhs || equal,
+        BinaryOp::Greater => lhs > rhs && !equal,
+        BinaryOp::GreaterEqual => lhs > rhs || equal,
+        BinaryOp::Equal => equal,
+        BinaryOp::NotEqual => !equal,
+        _ => unreachable!("not a comparison: {:?}", op),
+    };
+    if holds { 1.0 } else { 0.0 }
+}
+
+/// Computes `n!` for a non-negative integer `n`
+fn factorial(n: f64) -> Result<f64, CalcError> {
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(CalcError::domain(
+            "factorial requires a non-negative integer",
+        ));
+    }
+
+    let mut result = 1.0;
+    let mut i = 2.0;
+    while i <= n {
+        result *= i;
+        if result.is_infinite() {
+            return Err(CalcError::overflow(format!(
+                "factorial of {} is too large",
+                n
+            )));
+        }
+        i += 1.0;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        AngleMode, CalcError, Context, DEFAULT_MAX_DENOMINATOR, EvalOptions, IntegerResult,
+        RemainderMode, RoundingMode, Span, Value, evaluate, evaluate_exact,
+        evaluate_integer_with_options, evaluate_value, evaluate_value_with_context,
+        evaluate_with_context, evaluate_with_options, format_repeating, to_fraction,
+    };
+
+    #[test]
+    fn bit_counting() {
+        assert_eq!(evaluate("~0"), Ok(-1.0));
+        assert_eq!(evaluate("popcount(255) + popcount(0)"), Ok(8.0));
+        assert_eq!(evaluate("popcount(-1)"), Ok(1.0));
+        assert_eq!(evaluate("bit_length(255)"), Ok(8.0));
+        assert_eq!(evaluate("bit_length(256)"), Ok(9.0));
+        assert_eq!(evaluate("bit_length(0)"), Ok(0.0));
+        assert_eq!(evaluate("bit_length(-5)"), Ok(3.0));
+        assert_eq!(evaluate("popcount(2^53)"), Ok(1.0));
+        assert_eq!(
+            evaluate("popcount(1.5)").unwrap_err().to_string(),
+            "popcount requires integers, got 1.5"
+        );
+        assert!(
+            evaluate("bit_length(2^60)")
+                .unwrap_err()
+                .to_string()
+                .contains("too large to be exact")
+        );
+    }
+
+    #[test]
+    fn bit_width() {
+        let value = |input, options: &EvalOptions| {
+            evaluate_value_with_context(input, &mut Context::with_options(options.clone()))
+        };
+        let text = |input, options: &EvalOptions| match value(input, options) {
+            Ok(Value::Text(text)) => text,
+            other => panic!("{:?}", other),
+        };
+        for (width, max, not_zero, minus_one) in [
+            (8, 255.0, "0xff", "0b11111111"),
+            (16, 65535.0, "0xffff", "0b1111111111111111"),
+            (
+                32,
+                4294967295.0,
+                "0xffffffff",
+                "0b11111111111111111111111111111111",
+            ),
+        ] {
+            let options = EvalOptions::new().bit_width(width);
+            let evaluate = |input: &str| evaluate_with_options(input, &options);
+            assert_eq!(evaluate("~0"), Ok(max));
+            assert_eq!(evaluate("-1 & -1"), Ok(max));
+            assert_eq!(evaluate("~5 & 7"), Ok(2.0));
+            assert_eq!(evaluate("-2 xor -1"), Ok(1.0));
+            assert_eq!(
+                evaluate(&format!("1 << {}", width - 1)),
+                Ok(max / 2.0 + 0.5)
+            );
+            assert_eq!(evaluate(&format!("{} << 1", max)), Ok(max - 1.0));
+            assert_eq!(evaluate("-1 >> 1"), Ok(max / 2.0 - 0.5));
+            assert_eq!(evaluate("popcount(-1)"), Ok(f64::from(width)));
+            assert_eq!(evaluate("bit_length(-1)"), Ok(f64::from(width)));
+            assert_eq!(evaluate("popcount(-2)"), Ok(f64::from(width - 1)));
+            assert_eq!(text("hex(-1)", &options), not_zero);
+            assert_eq!(text("bin(-1)", &options), minus_one);
+            assert_eq!(text("hex(~0)", &options), not_zero);
+            assert_eq!(text("hex(10)", &options), "0xa");
+            assert_eq!(
+                evaluate(&format!("1 << {}", width))
+                    .unwrap_err()
+                    .to_string(),
+                format!(
+                    "shift amount must be between 0 and {}, got {}",
+                    width - 1,
+                    width
+                )
+            );
+            assert!(
+                evaluate(&format!("{} | 0", max + 1.0))
+                    .unwrap_err()
+                    .to_string()
+                    .contains("at a width of")
+            );
+            assert!(
+                evaluate("popcount(0.5)")
+                    .unwrap_err()
+                    .to_string()
+                    .contains("requires integers")
+            );
+        }
+
+        let eight = EvalOptions::new().bit_width(8);
+        assert_eq!(evaluate_with_options("-16 >> 2", &eight), Ok(60.0));
+        assert_eq!(evaluate_with_options("~(-128)", &eight), Ok(127.0));
+        assert_eq!(text("oct(-8)", &eight), "0o370");
+        assert_eq!(
+            evaluate_with_options("300 & 1", &eight)
+                .unwrap_err()
+                .to_string(),
+            "bitwise operators require integers from -128 to 255 at a width of 8 bits, got 300"
+        );
+        assert_eq!(
+            value("hex(-129)", &eight).unwrap_err().to_string(),
+            "hex requires integers from -128 to 255 at a width of 8 bits, got -129"
+        );
+
+        let sixty_four = EvalOptions::new().bit_width(64);
+        assert_eq!(text("hex(-1)", &sixty_four), "0xffffffffffffffff");
+        assert_eq!(text("hex(-2^53)", &sixty_four), "0xffe0000000000000");
+        assert_eq!(evaluate_with_options("~(-1)", &sixty_four), Ok(0.0));
+        assert_eq!(evaluate_with_options("-8 & 15", &sixty_four), Ok(8.0));
+        assert_eq!(evaluate_with_options("popcount(-1)", &sixty_four), Ok(64.0));
+        assert_eq!(evaluate_with_options("-2^53 >> 60", &sixty_four), Ok(15.0));
+        assert!(
+            evaluate_with_options("~0", &sixty_four)
+                .unwrap_err()
+                .to_string()
+                .contains("too large to be exact")
+        );
+        assert!(evaluate_with_options("1 << 64", &sixty_four).is_err());
+
+        let odd = EvalOptions::new().bit_width(0);
+        assert_eq!(
+            evaluate_with_options("1 & 1", &odd)
+                .unwrap_err()
+                .to_string(),
+            "bit width must be from 1 to 64, got 0"
+        );
+    }
+
+    #[test]
+    fn remainder_modes() {
+        let cases = [
+            "7 % 3",
+            "-7 % 3",
+            "7 % (-3)",
+            "-7 % (-3)",
+            "6 % (-3)",
+            "7.5 % (-2)",
+        ];
+        let expected = [
+            (RemainderMode::Truncated, [1.0, -1.0, 1.0, -1.0, 0.0, 1.5]),
+            (RemainderMode::Floored, [1.0, 2.0, -2.0, -1.0, 0.0, -0.5]),
+            (RemainderMode::Euclidean, [1.0, 2.0, 1.0, 2.0, 0.0, 1.5]),
+        ];
+        for (remainder_mode, results) in expected {
+            let options = EvalOptions::new().remainder_mode(remainder_mode);
+            for (case, result) in cases.iter().zip(results) {
+                assert_eq!(
+                    evaluate_with_options(case, &options),
+                    Ok(result),
+                    "{} in {}",
+                    case,
+                    remainder_mode
+                );
+            }
+            for (case, result) in cases[..5].iter().zip(results) {
+                let integer = evaluate_integer_with_options(case, &options);
+                assert_eq!(
+                    integer,
+                    Ok(IntegerResult::Integer(result as i128)),
+                    "{} in {}",
+                    case,
+                    remainder_mode
+                );
+            }
+        }
+        assert_eq!(evaluate("mod(-7, 3) + mod(7, -3)"), Ok(0.0));
+        assert_eq!(evaluate("mod(-7, -3)"), Ok(-1.0));
+        assert_eq!(evaluate("mod(7.5, -2)"), Ok(-0.5));
+        assert_eq!(
+            evaluate("mod(1, 0)").unwrap_err().to_string(),
+            "division by zero"
+        );
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(evaluate("2*pi"), Ok(std::f64::consts::TAU));
+        assert_eq!(evaluate("2π"), evaluate("tau"));
+        assert_eq!(evaluate("e^2"), Ok(std::f64::consts::E.powi(2)));
+        assert_eq!(
+            evaluate("2*pie").unwrap_err().to_string(),
+            "undefined variable 'pie' at position 2"
+        );
+        assert!(evaluate("PI").is_err());
+    }
+
+    #[test]
+    fn physical_constants() {
+        assert_eq!(evaluate("phys.c"), Ok(299792458.0));
+        assert_eq!(evaluate("phys.G"), Ok(6.67430e-11));
+        assert_eq!(evaluate("phys.h"), Ok(6.62607015e-34));
+        assert_eq!(evaluate("phys.Na"), Ok(6.02214076e23));
+        assert_eq!(evaluate("phys.kB"), Ok(1.380649e-23));
+        assert_eq!(evaluate("phys.g"), Ok(9.80665));
+        assert_eq!(evaluate("2phys.g"), Ok(19.6133));
+        assert_eq!(
+            evaluate("phys.h*phys.c/500e-9"),
+            Ok(6.62607015e-34 * 299792458.0 / 500e-9)
+        );
+        assert_eq!(
+            evaluate("phys.x").unwrap_err().to_string(),
+            "undefined variable 'phys.x' at position 0"
+        );
+        assert!(evaluate("c").is_err());
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let ties = "round(0.5) round(1.5) round(2.5) round(-0.5) round(-1.5) round(-2.5)";
+        for (rounding, results) in [
+            (RoundingMode::HalfUp, [1.0, 2.0, 3.0, -1.0, -2.0, -3.0]),
+            (RoundingMode::HalfEven, [0.0, 2.0, 2.0, 0.0, -2.0, -2.0]),
+            (RoundingMode::HalfDown, [0.0, 1.0, 2.0, 0.0, -1.0, -2.0]),
+            (RoundingMode::TowardZero, [0.0, 1.0, 2.0, 0.0, -1.0, -2.0]),
+        ] {
+            let options = EvalOptions::new().rounding(rounding);
+            for (case, result) in ties.split(' ').zip(results) {
+                assert_eq!(
+                    evaluate_with_options(case, &options),
+                    Ok(result),
+                    "{} in {}",
+                    case,
+                    rounding
+                );
+            }
+        }
+
+        let options = EvalOptions::new().rounding(RoundingMode::HalfEven);
+        assert_eq!(evaluate_with_options("round(2.6)", &options), Ok(3.0));
+        assert_eq!(
+            evaluate_with_options("round(1250, -2)", &options),
+            Ok(1200.0)
+        );
+        let options = EvalOptions::new().rounding(RoundingMode::TowardZero);
+        assert_eq!(evaluate_with_options("round(-2.9)", &options), Ok(-2.0));
+    }
+
+    #[test]
+    fn roots_and_signs() {
+        assert_eq!(evaluate("sqrt(16)+1"), Ok(5.0));
+        assert_eq!(evaluate("cbrt(27)"), Ok(3.0));
+        assert_eq!(evaluate("sign(-7.5)"), Ok(-1.0));
+        assert_eq!(evaluate("sign(0.001) + sign(0)"), Ok(1.0));
+        assert_eq!(evaluate("sign(-0)").map(f64::is_sign_negative), Ok(false));
+        assert_eq!(evaluate("copysign(3, -1)"), Ok(-3.0));
+        assert_eq!(evaluate("copysign(-3, 2)"), Ok(3.0));
+        assert_eq!(evaluate("copysign(2, -0)"), Ok(-2.0));
+        assert_eq!(evaluate("hypot(3, 4)"), Ok(5.0));
+        assert_eq!(evaluate("hypot(-5, 12)"), Ok(13.0));
+        assert!((evaluate("hypot(3e200, 4e200)").unwrap() / 5e200 - 1.0).abs() < 1e-15);
+        assert_eq!(
+            evaluate("hypot(3)").unwrap_err().to_string(),
+            "hypot expects 2 arguments, got 1"
+        );
+        assert_eq!(
+            evaluate("copysign(1, 2, 3)").unwrap_err().to_string(),
+            "copysign expects 2 arguments, got 3"
+        );
+        assert_eq!(evaluate("root(27, 3)"), Ok(3.0));
+        assert_eq!(evaluate("root(16, 4)"), Ok(2.0));
+        assert_eq!(evaluate("root(-8, 3)"), Ok(-2.0));
+        assert_eq!(evaluate("root(-32, 5)"), Ok(-2.0));
+        assert_eq!(evaluate("root(10^10, 10)"), Ok(10.0));
+        assert_eq!(evaluate("root(9, 0.5)"), Ok(81.0));
+        assert_eq!(
+            evaluate("root(-16, 4)").unwrap_err().to_string(),
+            "domain error: root of degree 4 is undefined for -16"
+        );
+        assert!(
+            evaluate("root(-8, 2.5)")
+                .unwrap_err()
+                .to_string()
+                .contains("domain error")
+        );
+        assert!(
+            evaluate("root(5, 0)")
+                .unwrap_err()
+                .to_string()
+                .contains("domain error")
+        );
+        for x in [-1000.0, -2.5, 0.001, 0.5, 2.0, 7.0, 123.456, 1e6, 1e100] {
+            for n in [3.0, 5.0, 7.0, 2.0, 4.0, 10.0] {
+                if x < 0.0 && n % 2.0 == 0.0 {
+                    continue;
+                }
+                let root = evaluate(&format!("root({}, {})", x, n)).unwrap();
+                assert!(
+                    (root.powf(n) - x).abs() <= 1e-12 * x.abs(),
+                    "root({}, {})",
+                    x,
+                    n
+                );
+            }
+        }
+        assert_eq!(evaluate("sqrt(sqrt(81))"), Ok(3.0));
+        assert_eq!(evaluate("2sqrt(4)"), Ok(4.0));
+        assert_eq!(
+            evaluate("sqrt(-4)").unwrap_err().to_string(),
+            "domain error: sqrt is undefined for -4"
+        );
+        assert_eq!(
+            evaluate("sqrt(1, 2)").unwrap_err().to_string(),
+            "sqrt expects 1 argument, got 2"
+        );
+        assert!(
+            evaluate("sqrt(4")
+                .unwrap_err()
+                .to_string()
+                .contains("unclosed parenthesis")
+        );
+        assert!(
+            evaluate("foo(1)")
+                .unwrap_err()
+                .to_string()
+                .contains("unknown function 'foo'")
+        );
+    }
+
+    #[test]
+    fn absolute_value() {
+        assert_eq!(evaluate("abs(-3)+1"), Ok(4.0));
+        assert_eq!(evaluate("2*abs(3-10)"), Ok(14.0));
+        assert_eq!(evaluate("|2-5|*2"), Ok(6.0));
+        assert_eq!(evaluate("|-3|-1"), Ok(2.0));
+        assert!(
+            evaluate("||2|-5|")
+                .unwrap_err()
+                .to_string()
+                .contains("instead of nested absolute value bars")
+        );
+        assert!(
+            evaluate("|2-5")
+                .unwrap_err()
+                .to_string()
+                .contains("unclosed absolute value bar")
+        );
+    }
+
+    #[test]
+    fn trigonometry() {
+        assert_eq!(evaluate("sin(0)+cos(0)"), Ok(1.0));
+        assert!((evaluate("tan(pi/4)").unwrap() - 1.0).abs() < 1e-12);
+        assert!(evaluate("sin(pi)").unwrap().abs() < 1e-12);
+        assert!((evaluate("cos(pi)").unwrap() + 1.0).abs() < 1e-12);
+        assert!(evaluate("tan(pi/2)").unwrap() > 1e15);
+
+        assert_eq!(evaluate("asin(1)"), Ok(std::f64::consts::FRAC_PI_2));
+        assert_eq!(evaluate("atan2(1, 1)"), Ok(std::f64::consts::FRAC_PI_4));
+        assert_eq!(
+            evaluate("acos(2)").unwrap_err().to_string(),
+            "domain error: acos is undefined for 2"
+        );
+        assert_eq!(
+            evaluate("atan2(1)").unwrap_err().to_string(),
+            "atan2 expects 2 arguments, got 1"
+        );
+
+        let degrees = EvalOptions::new().angle_mode(AngleMode::Degrees);
+        assert_eq!(evaluate_with_options("sin(90)", &degrees), Ok(1.0));
+        assert_eq!(evaluate("sin(pi/2)"), Ok(1.0));
+        assert_eq!(evaluate_with_options("asin(1)", &degrees), Ok(90.0));
+        assert_eq!(evaluate_with_options("atan2(1, 1)", &degrees), Ok(45.0));
+    }
+
+    #[test]
+    fn logarithms() {
+        assert_eq!(evaluate("log(8, 2)"), Ok(3.0));
+        assert_eq!(evaluate("ln(e)"), Ok(1.0));
+        assert_eq!(evaluate("ln(1)"), Ok(0.0));
+        assert_eq!(evaluate("log10(1000)"), Ok(3.0));
+        assert_eq!(evaluate("log2(1024)"), Ok(10.0));
+        assert_eq!(evaluate("log(1, 10)"), Ok(0.0));
+        assert_eq!(evaluate("log(100)"), Ok(2.0));
+        assert_eq!(
+            evaluate("ln(0)").unwrap_err().to_string(),
+            "domain error: ln is undefined for 0"
+        );
+        assert_eq!(
+            evaluate("log10(-1)").unwrap_err().to_string(),
+            "domain error: log10 is undefined for -1"
+        );
+        assert_eq!(
+            evaluate("log(8, 1)").unwrap_err().to_string(),
+            "domain error: log is undefined for base 1"
+        );
+    }
+
+    #[test]
+    fn exponentials_and_gamma() {
+        let close = |input: &str, expected: f64| {
+            let value = evaluate(input).unwrap();
+            assert!(
+                (value - expected).abs() <= 1e-14 * expected.abs(),
+                "{} = {}",
+                input,
+                value
+            );
+        };
+        close("exp(1)", std::f64::consts::E);
+        close("exp(-2)", 0.1353352832366127);
+        close("expm1(1e-10)", 1.00000000005e-10);
+        close("ln1p(1e-10)", 9.9999999995e-11);
+        close("expm1(ln1p(0.5))", 0.5);
+        assert!((evaluate("exp(1e-10) - 1").unwrap() - 1.00000000005e-10).abs() > 1e-18);
+        assert_eq!(evaluate("gamma(5)"), Ok(24.0));
+        assert_eq!(evaluate("gamma(1)"), Ok(1.0));
+        close("gamma(0.5)", std::f64::consts::PI.sqrt());
+        close("gamma(-0.5)", -2.0 * std::f64::consts::PI.sqrt());
+        close("gamma(4.5)", 11.631728396567448);
+        close("gamma(100.5) / gamma(99.5)", 99.5);
+        close("lgamma(0.5)", std::f64::consts::PI.sqrt().ln());
+        close("lgamma(1000)", 5905.220423209181);
+        close("lgamma(-0.5)", (2.0 * std::f64::consts::PI.sqrt()).ln());
+        assert_eq!(
+            evaluate("gamma(0)").unwrap_err().to_string(),
+            "domain error: gamma is undefined for 0"
+        );
+        assert_eq!(
+            evaluate("gamma(-3)").unwrap_err().to_string(),
+            "domain error: gamma is undefined for -3"
+        );
+        assert!(
+            evaluate("lgamma(-2)")
+                .unwrap_err()
+                .to_string()
+                .starts_with("domain error")
+        );
+        assert_eq!(
+            evaluate("gamma(172)").unwrap_err().to_string(),
+            "gamma of 172 is too large"
+        );
+        assert_eq!(
+            evaluate("ln1p(-1)").unwrap_err().to_string(),
+            "domain error: ln1p is undefined for -1"
+        );
+    }
+
+    #[test]
+    fn rounding_functions() {
+        assert_eq!(evaluate("floor(3.7)"), Ok(3.0));
+        assert_eq!(evaluate("ceil(3.2)"), Ok(4.0));
+        assert_eq!(evaluate("round(2.5)"), Ok(3.0));
+        assert_eq!(evaluate("round(-2.5)"), Ok(-3.0));
+        assert_eq!(evaluate("trunc(-3.7)"), Ok(-3.0));
+        assert_eq!(evaluate("round(2.71828, 2)"), Ok(2.72));
+        assert_eq!(evaluate("round(1234, -2)"), Ok(1200.0));
+        assert_eq!(evaluate("floor(2.567, 1)"), Ok(2.5));
+        assert!(
+            evaluate("round(1.5, 0.5)")
+                .unwrap_err()
+                .to_string()
+                .contains("precision must be an integer")
+        );
+        assert_eq!(
+            evaluate("round(1, 2, 3)").unwrap_err().to_string(),
+            "round expects 1 to 2 arguments, got 3"
+        );
+    }
+
+    #[test]
+    fn min_max_and_clamp() {
+        assert_eq!(evaluate("max(1, 5, 3)"), Ok(5.0));
+        assert_eq!(evaluate("min(4, -2, 7, 0)"), Ok(-2.0));
+        assert_eq!(evaluate("min(max(1,2), 10/4)"), Ok(2.0));
+        assert_eq!(evaluate("clamp(10, 0, 5)"), Ok(5.0));
+        assert_eq!(evaluate("clamp(-1, 0, 5)"), Ok(0.0));
+        assert_eq!(
+            evaluate("min(1)").unwrap_err().to_string(),
+            "min expects at least 2 arguments, got 1"
+        );
+        assert!(
+            evaluate("clamp(1, 5, 0)")
+                .unwrap_err()
+                .to_string()
+                .contains("greater than upper bound")
+        );
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(evaluate("lerp(10, 20, 0)"), Ok(10.0));
+        assert_eq!(evaluate("lerp(10, 20, 1)"), Ok(20.0));
+        assert_eq!(evaluate("lerp(10, 20, 0.25)"), Ok(12.5));
+        assert_eq!(evaluate("lerp(10, 20, 1.5)"), Ok(25.0));
+        assert_eq!(evaluate("lerp(10, 20, -1)"), Ok(0.0));
+        assert_eq!(evaluate("lerp(20, 10, 0.25)"), Ok(17.5));
+        assert_eq!(evaluate("lerp_clamped(10, 20, 1.5)"), Ok(20.0));
+        assert_eq!(evaluate("lerp_clamped(10, 20, -1)"), Ok(10.0));
+        assert_eq!(evaluate("lerp_clamped(10, 20, 0.5)"), Ok(15.0));
+        assert_eq!(evaluate("map_range(5, 0, 10, 0, 100)"), Ok(50.0));
+        assert_eq!(evaluate("map_range(0, 0, 10, 100, 200)"), Ok(100.0));
+        assert_eq!(evaluate("map_range(10, 0, 10, 100, 200)"), Ok(200.0));
+        assert_eq!(evaluate("map_range(15, 0, 10, 0, 100)"), Ok(150.0));
+        assert_eq!(evaluate("map_range(-5, 0, 10, 0, 100)"), Ok(-50.0));
+        assert_eq!(evaluate("map_range(32, 32, 212, 0, 100)"), Ok(0.0));
+        assert_eq!(evaluate("map_range(212, 212, 32, 0, 100)"), Ok(0.0));
+        assert_eq!(
+            evaluate("map_range(1, 3, 3, 0, 1)")
+                .unwrap_err()
+                .to_string(),
+            "map_range input range is empty, from 3 to 3"
+        );
+        assert_eq!(
+            evaluate("lerp(1, 2)").unwrap_err().to_string(),
+            "lerp expects 3 arguments, got 2"
+        );
+    }
+
+    #[test]
+    fn float_classification() {
+        assert_eq!(evaluate("isinf(1e308 * 10) + isinf(-1e308 * 10)"), Ok(2.0));
+        assert_eq!(evaluate("isfinite(1e308 * 10)"), Ok(0.0));
+        assert_eq!(
+            evaluate("isfinite(1e308) + isinf(1e308) + isnan(1e308)"),
+            Ok(1.0)
+        );
+        assert_eq!(evaluate("isnan(1e308 * 10 - 1e308 * 10)"), Ok(1.0));
+        assert_eq!(evaluate("isinf(1e308 * 10) ? 0 : 1"), Ok(0.0));
+        assert_eq!(evaluate("ulp(1)"), Ok(f64::EPSILON));
+        assert_eq!(evaluate("ulp(-1)"), Ok(f64::EPSILON));
+        assert_eq!(evaluate("ulp(0)"), Ok(f64::from_bits(1)));
+        assert_eq!(evaluate("ulp(2^52)"), Ok(1.0));
+        assert_eq!(evaluate("ulp(1e308 * 10)"), Ok(f64::INFINITY));
+        assert_eq!(evaluate("nextafter(1, 2)"), Ok(1.0 + f64::EPSILON));
+        assert_eq!(evaluate("nextafter(1, 0)"), Ok(1.0 - f64::EPSILON / 2.0));
+        assert_eq!(evaluate("nextafter(0, -1)"), Ok(-f64::from_bits(1)));
+        assert_eq!(evaluate("nextafter(3, 3)"), Ok(3.0));
+        assert_eq!(evaluate("nextafter(1e308 * 10, 0)"), Ok(f64::MAX));
+        assert!(
+            evaluate("isnan(sqrt(-1))")
+                .unwrap_err()
+                .to_string()
+                .starts_with("domain error")
+        );
+
+        let lenient = EvalOptions::new().nan_on_domain_error(true);
+        assert_eq!(evaluate_with_options("isnan(sqrt(-1))", &lenient), Ok(1.0));
+        assert_eq!(
+            evaluate_with_options("isfinite(ln(-1)) + isinf(ln(-1))", &lenient),
+            Ok(0.0)
+        );
+        assert_eq!(
+            evaluate_with_options("isinf(1e308 * 10)", &lenient),
+            Ok(1.0)
+        );
+        assert!(
+            evaluate_with_options("nextafter(sqrt(-1), 1)", &lenient)
+                .unwrap()
+                .is_nan()
+        );
+        assert!(
+            evaluate_with_options("ulp(acos(2))", &lenient)
+                .unwrap()
+                .is_nan()
+        );
+        assert_eq!(
+            evaluate("map_range(1, 2, 3, 4)").unwrap_err().to_string(),
+            "map_range expects 5 arguments, got 4"
+        );
+    }
+
+    #[test]
+    fn gcd_and_lcm() {
+        assert_eq!(evaluate("gcd(12, 18)"), Ok(6.0));
+        assert_eq!(evaluate("lcm(4, 6)"), Ok(12.0));
+        assert_eq!(evaluate("gcd(12, 18, 24)"), Ok(6.0));
+        assert_eq!(evaluate("gcd(-12, 18)"), Ok(6.0));
+        assert_eq!(evaluate("lcm(-4, 6)"), Ok(12.0));
+        assert_eq!(evaluate("lcm(0, 6)"), Ok(0.0));
+        assert!(
+            evaluate("lcm(4294967296, 4294967297)")
+                .unwrap_err()
+                .to_string()
+                .contains("too large")
+        );
+        assert_eq!(
+            evaluate("gcd(2.5, 5)").unwrap_err().to_string(),
+            "gcd argument 1 must be an integer, got 2.5"
+        );
+    }
+
+    #[test]
+    fn if_function() {
+        assert_eq!(evaluate("if(-3 > 0, -3, 3)"), Ok(3.0));
+        assert_eq!(evaluate("if(1 > 0, 5, 1/0)"), Ok(5.0));
+        assert_eq!(evaluate("max(if(0, 1, 2), if(1, if(0, 3, 4), 5))"), Ok(4.0));
+        assert_eq!(
+            evaluate("if(1, 2)").unwrap_err().to_string(),
+            "if expects 3 arguments, got 2"
+        );
+    }
+
+    #[test]
+    fn sums_and_products() {
+        assert_eq!(evaluate("sum(i, 1, 10, i^2)"), Ok(385.0));
+        assert_eq!(evaluate("prod(i, 1, 5, i)"), Ok(120.0));
+        assert_eq!(evaluate("sum(i, 1, 3, sum(j, 1, i, j))"), Ok(10.0));
+        assert_eq!(
+            evaluate("sum(e, 1, 2, e) + e"),
+            Ok(3.0 + std::f64::consts::E)
+        );
+        assert_eq!(evaluate("sum(i, 5, 1, i) + prod(i, 5, 1, i)"), Ok(1.0));
+        assert_eq!(evaluate("sum(i, 1, 1e6, 1)"), Ok(1e6));
+        assert_eq!(
+            evaluate("sum(i, 1, 1e18, i)").unwrap_err().to_string(),
+            "too many iterations, sum is limited to 1000000 terms"
+        );
+        assert_eq!(evaluate("sum(i, 1, 1000, sum(j, 1, 999, 1))"), Ok(999000.0));
+        assert_eq!(
+            evaluate("sum(i, 1, 1e6, sum(j, 1, 1e6, 1))")
+                .unwrap_err()
+                .to_string(),
+            "too many iterations, nested sums and products are limited to 1000000 terms in all"
+        );
+        assert!(evaluate("integral(x, 0, 1, sum(i, 1, 1e5, x))").is_err());
+        assert_eq!(evaluate("sum(i, 1, 1e6, 1) + sum(i, 1, 1e6, 1)"), Ok(2e6));
+        assert_eq!(
+            evaluate("prod(i, 1, 2.5, i)").unwrap_err().to_string(),
+            "prod bounds must be integers, got 2.5"
+        );
+        assert_eq!(
+            evaluate("prod(i, 1, 3)").unwrap_err().to_string(),
+            "prod expects 4 arguments, got 3"
+        );
+        assert!(
+            evaluate("prod(2, 1, 3, 1)")
+                .unwrap_err()
+                .to_string()
+                .contains("expects a variable name")
+        );
+        assert!(
+            evaluate("sum(i, 1, 3, j)")
+                .unwrap_err()
+                .to_string()
+                .contains("undefined variable 'j'")
+        );
+    }
+
+    #[test]
+    fn statistics() {
+        assert_eq!(evaluate("sum(1, 2, 3)"), Ok(6.0));
+        assert_eq!(evaluate("sum(-4)"), Ok(-4.0));
+        assert_eq!(evaluate("sum(1, 2, 3, 4)"), Ok(10.0));
+        assert_eq!(
+            evaluate("sum(+i, 2, 3, 4)").unwrap_err().to_string(),
+            "undefined variable 'i' at position 5"
+        );
+        assert_eq!(evaluate("count(5, -5, 5)"), Ok(3.0));
+        assert_eq!(evaluate("mean(1, 2, 3, 4)"), Ok(2.5));
+        assert_eq!(evaluate("mean(-2, -2, 7)"), Ok(1.0));
+        assert_eq!(evaluate("median(3, -1, 2)"), Ok(2.0));
+        assert_eq!(evaluate("median(4, 1, -3, 1)"), Ok(1.0));
+        assert_eq!(evaluate("median(7, 7, 7, 10)"), Ok(7.0));
+        assert_eq!(evaluate("var(2, 4, 4, 4, 5, 5, 7, 9)"), Ok(32.0 / 7.0));
+        assert_eq!(evaluate("stddev(-1, 1, -1, 1)"), Ok((4.0f64 / 3.0).sqrt()));
+        assert_eq!(evaluate("stddev(3, 3, 3)"), Ok(0.0));
+        assert_eq!(
+            evaluate("stddev(5)").unwrap_err().to_string(),
+            "stddev expects at least 2 arguments, got 1"
+        );
+        assert_eq!(
+            evaluate("mean()").unwrap_err().to_string(),
+            "mean expects at least 1 argument, got 0"
+        );
+    }
+
+    #[test]
+    fn derivatives() {
+        let close = |input: &str, expected: f64| (evaluate(input).unwrap() - expected).abs() < 1e-8;
+        assert!(close("deriv(x^2, x, 3)", 6.0));
+        assert!(close("deriv(x^3 - 2x, x, -2)", 10.0));
+        assert!(close("deriv(sin(x), x, 0)", 1.0));
+        assert!(close("deriv(cos(t), t, pi/2)", -1.0));
+        assert!(close("deriv(e^x, x, 1)", std::f64::consts::E));
+        assert_eq!(
+            evaluate("deriv(1/x, x, 0)").unwrap_err().to_string(),
+            "division by zero"
+        );
+        assert!(
+            evaluate("deriv(sqrt(x), x, 0)")
+                .unwrap_err()
+                .to_string()
+                .contains("domain error")
+        );
+        assert!(
+            evaluate("deriv(x^2, 3, 1)")
+                .unwrap_err()
+                .to_string()
+                .contains("expects a variable name as argument 2")
+        );
+    }
+
+    #[test]
+    fn integrals() {
+        let close = |input: &str, expected: f64| (evaluate(input).unwrap() - expected).abs() < 1e-8;
+        assert!(close("integrate(sin(x), x, 0, pi)", 2.0));
+        assert!(close("integrate(3x^2 + 1, x, 0, 2)", 10.0));
+        assert!(close("integrate(x^5, x, -1, 2)", 10.5));
+        assert!(close("integrate(e^x, x, 0, 1)", std::f64::consts::E - 1.0));
+        assert!(close(
+            "integrate(e^(-t), t, 1, 0)",
+            1.0 / std::f64::consts::E - 1.0
+        ));
+        assert_eq!(evaluate("integrate(x, x, 2, 2)"), Ok(0.0));
+        assert_eq!(
+            evaluate("integrate(1/x, x, 0, 1)").unwrap_err().to_string(),
+            "division by zero"
+        );
+        assert!(
+            evaluate("integrate(1/(x-0.3), x, 0, 1)")
+                .unwrap_err()
+                .to_string()
+                .contains("did not converge")
+        );
+        assert!(evaluate("integrate(x, x, 0, 1/0)").is_err());
+        assert!(
+            evaluate("integrate(x, x, 0, 10^400)")
+                .unwrap_err()
+                .to_string()
+                .contains("must be finite")
+        );
+    }
+
+    #[test]
+    fn solve() {
+        let close = |input: &str, expected: f64| (evaluate(input).unwrap() - expected).abs() < 1e-8;
+        assert_eq!(evaluate("solve(2*x + 3 = 11, x)"), Ok(4.0));
+        assert_eq!(evaluate("solve(x/4 - 1 = (x + 1)/2, x)"), Ok(-6.0));
+        assert!(close("solve(x^2 = 2, x, 0, 5)", std::f64::consts::SQRT_2));
+        assert!(close("solve(x^2 = 2, x)", -std::f64::consts::SQRT_2));
+        assert!(close("solve(cos(x) - x, x)", 0.7390851332));
+        assert_eq!(evaluate("solve(x^2 = 9, x, 0, 10)"), Ok(3.0));
+        assert_eq!(
+            evaluate("solve(x^2 = -1, x)").unwrap_err().to_string(),
+            "no solution for 'x' found between -100 and 100"
+        );
+        assert!(
+            evaluate("solve(tan(x) = 0, x, 1, 2)")
+                .unwrap_err()
+                .to_string()
+                .contains("no solution")
+        );
+        assert!(
+            evaluate("solve(2x = 4, x, 5, 10)")
+                .unwrap_err()
+                .to_string()
+                .contains("no solution")
+        );
+        assert_eq!(
+            evaluate("solve(3 = 4, x)").unwrap_err().to_string(),
+            "'x' does not appear in the equation"
+        );
+        assert!(
+            evaluate("solve(x + 1 = x, x)")
+                .unwrap_err()
+                .to_string()
+                .contains("cancels out")
+        );
+        assert_eq!(
+            evaluate("solve(x = 1, x, 0)").unwrap_err().to_string(),
+            "solve expects 2 or 4 arguments, got 3"
+        );
+    }
+
+    #[test]
+    fn powmod() {
+        assert_eq!(evaluate("powmod(7, 128, 13)"), Ok(3.0));
+        assert_eq!(evaluate("powmod(2, 10, 1000)"), Ok(24.0));
+        assert_eq!(evaluate("powmod(5, 0, 7) + powmod(0, 0, 7)"), Ok(2.0));
+        assert_eq!(evaluate("powmod(12, 34, 1)"), Ok(0.0));
+        assert_eq!(
+            evaluate("powmod(2^53 - 1, 2^53 - 1, 2^53 - 111)"),
+            Ok(5558404220026795.0)
+        );
+        assert_eq!(
+            evaluate("powmod(3, 2^53, 2^53 - 111)"),
+            Ok(844222340131485.0)
+        );
+        assert_eq!(
+            evaluate("powmod(2, 3, 0)").unwrap_err().to_string(),
+            "powmod modulus must be at least 1, got 0"
+        );
+        assert_eq!(
+            evaluate("powmod(-2, 3, 5)").unwrap_err().to_string(),
+            "powmod argument 1 must be non-negative, got -2"
+        );
+        assert!(
+            evaluate("powmod(2, 0.5, 5)")
+                .unwrap_err()
+                .to_string()
+                .contains("must be an integer")
+        );
+    }
+
+    #[test]
+    fn integer_sequences() {
+        assert_eq!(evaluate("fib(0) + fib(1) + fib(2)"), Ok(2.0));
+        assert_eq!(evaluate("fib(10)"), Ok(55.0));
+        assert_eq!(evaluate("fib(50)"), Ok(12586269025.0));
+        assert_eq!(evaluate("fib(78)"), Ok(8944394323791464.0));
+        assert_eq!(
+            evaluate("fib(79)").unwrap_err().to_string(),
+            "fib result is too large to represent exactly"
+        );
+        assert_eq!(
+            evaluate("fib(90)").unwrap_err().to_string(),
+            "fib result is too large to represent exactly"
+        );
+        assert!(
+            evaluate("fib(2^53)")
+                .unwrap_err()
+                .to_string()
+                .contains("too large")
+        );
+        assert_eq!(evaluate("triangular(0) + triangular(1)"), Ok(1.0));
+        assert_eq!(evaluate("triangular(100)"), Ok(5050.0));
+        assert_eq!(evaluate("triangular(2^27 - 1)"), Ok(9007199187632128.0));
+        assert!(
+            evaluate("triangular(2^27)")
+                .unwrap_err()
+                .to_string()
+                .contains("too large")
+        );
+        assert_eq!(evaluate("isqrt(0) + isqrt(1) + isqrt(3)"), Ok(2.0));
+        assert_eq!(evaluate("isqrt(10^15)"), Ok(31622776.0));
+        assert_eq!(evaluate("isqrt(2^53)"), Ok(94906265.0));
+        assert_eq!(evaluate("isqrt(94906265^2)"), Ok(94906265.0));
+        assert_eq!(evaluate("isqrt(94906265^2 - 1)"), Ok(94906264.0));
+        assert_eq!(evaluate("floor(sqrt(94906265^2 - 1))"), Ok(94906265.0));
+        assert_eq!(
+            evaluate("isqrt(-4)").unwrap_err().to_string(),
+            "isqrt argument must be a non-negative integer up to 2^53, got -4"
+        );
+        assert!(
+            evaluate("fib(2.5)")
+                .unwrap_err()
+                .to_string()
+                .contains("must be a non-negative integer")
+        );
+        assert!(
+            evaluate("triangular(2^53 + 2)")
+                .unwrap_err()
+                .to_string()
+                .contains("up to 2^53")
+        );
+    }
+
+    #[test]
+    fn primes() {
+        assert_eq!(evaluate("isprime(97) + isprime(1) + isprime(91)"), Ok(1.0));
+        assert_eq!(evaluate("isprime(2) + isprime(3)"), Ok(2.0));
+        assert_eq!(evaluate("isprime(9007199254740881)"), Ok(1.0));
+        assert_eq!(evaluate("isprime(9007199254740883)"), Ok(0.0));
+        assert_eq!(evaluate("nextprime(13)"), Ok(17.0));
+        assert_eq!(evaluate("nextprime(1)"), Ok(2.0));
+        assert_eq!(evaluate("nextprime(2^53 - 112)"), Ok(9007199254740881.0));
+        assert!(
+            evaluate("nextprime(2^53)")
+                .unwrap_err()
+                .to_string()
+                .contains("too large")
+        );
+        assert!(
+            evaluate("isprime(-7)")
+                .unwrap_err()
+                .to_string()
+                .contains("must be a positive integer")
+        );
+        assert!(
+            evaluate("isprime(2^60)")
+                .unwrap_err()
+                .to_string()
+                .contains("up to 2^53")
+        );
+        assert!(
+            evaluate("factorize(60) + 1")
+                .unwrap_err()
+                .to_string()
+                .contains("not a number")
+        );
+    }
+
+    #[test]
+    fn random_numbers() {
+        let r = evaluate("random()").unwrap();
+        assert!((0.0..1.0).contains(&r));
+        let roll = evaluate("randint(1, 6)").unwrap();
+        assert!((1.0..=6.0).contains(&roll) && roll.fract() == 0.0);
+        assert_eq!(evaluate("randint(3, 3)"), Ok(3.0));
+        assert!(
+            evaluate("randint(6, 1)")
+                .unwrap_err()
+                .to_string()
+                .contains("greater than upper bound")
+        );
+        assert_eq!(
+            evaluate("randint(1, 6.5)").unwrap_err().to_string(),
+            "randint argument 2 must be an integer, got 6.5"
+        );
+
+        let seeded = EvalOptions::new().seed(42);
+        let first = evaluate_with_options("random()+randint(1, 100)", &seeded);
+        assert_eq!(
+            evaluate_with_options("random()+randint(1, 100)", &seeded),
+            first
+        );
+        assert_ne!(evaluate_with_options("random()-random()", &seeded), Ok(0.0));
+    }
+
+    #[test]
+    fn temperatures() {
+        assert_eq!(evaluate("c_to_f(100)"), Ok(212.0));
+        assert_eq!(evaluate("f_to_c(32)"), Ok(0.0));
+        assert_eq!(evaluate("c_to_k(0)"), Ok(273.15));
+        assert_eq!(evaluate("f_to_c(98.6)+1"), Ok(38.0));
+        assert_eq!(evaluate("f_to_k(212)"), Ok(373.15));
+        assert_eq!(
+            evaluate("c_to_k(-300)").unwrap_err().to_string(),
+            "-300 degC is below absolute zero"
+        );
+    }
+
+    #[test]
+    fn hyperbolic_functions() {
+        assert_eq!(evaluate("cosh(0)"), Ok(1.0));
+        assert_eq!(evaluate("tanh(1000)"), Ok(1.0));
+        assert!((evaluate("asinh(sinh(2))").unwrap() - 2.0).abs() < 1e-12);
+        assert_eq!(
+            evaluate("acosh(0.5)").unwrap_err().to_string(),
+            "domain error: acosh is undefined for 0.5"
+        );
+        assert_eq!(
+            evaluate("atanh(1)").unwrap_err().to_string(),
+            "domain error: atanh is undefined for 1"
+        );
+
+        let lenient = EvalOptions::new().nan_on_domain_error(true);
+        assert!(
+            evaluate_with_options("sqrt(-4)", &lenient)
+                .unwrap()
+                .is_nan()
+        );
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(evaluate("0.1+0.2"), Ok(0.30000000000000004));
+        assert_eq!(evaluate_exact("0.1+0.2").unwrap().to_string(), "3/10 (0.3)");
+        assert_eq!(evaluate_value("frac(0.375)").unwrap().to_string(), "3/8");
+        let (numer, denom, _) = to_fraction(evaluate("22/7").unwrap(), DEFAULT_MAX_DENOMINATOR);
+        assert_eq!(format_repeating(numer, denom), "3.(142857)");
+        assert_eq!(evaluate_value("frac(-6/4)").unwrap().to_string(), "-3/2");
+        assert_eq!(
+            evaluate_value("frac(0.1+0.2)").unwrap().to_string(),
+            "3/10 (approx)"
+        );
+        assert_eq!(
+            evaluate_value("frac(0.333333)").unwrap().to_string(),
+            "1/3 (approx)"
+        );
+        assert_eq!(
+            evaluate_value("frac(pi)").unwrap().to_string(),
+            "355/113 (approx)"
+        );
+        assert_eq!(evaluate_value("frac(12)").unwrap().to_string(), "12");
+        assert_eq!(
+            evaluate_value("frac(1e-9)").unwrap().to_string(),
+            "0 (approx)"
+        );
+        assert!(
+            evaluate_value("frac(2^60)")
+                .unwrap_err()
+                .to_string()
+                .contains("at most 2^53")
+        );
+        assert!(
+            evaluate("frac(0.5) * 2")
+                .unwrap_err()
+                .to_string()
+                .contains("not a number")
+        );
+    }
+
+    #[test]
+    fn uncertainties() {
+        assert_eq!(
+            evaluate_value("(5 ± 0.1) * (2 ± 0.05)")
+                .unwrap()
+                .to_string(),
+            "10 ± 0.45"
+        );
+        assert_eq!(
+            evaluate_value("(5 +- 0.1) * (2 +- 0.05)")
+                .unwrap()
+                .to_string(),
+            "10 ± 0.45"
+        );
+        assert_eq!(
+            evaluate_value("(10 ± 0.1) - (4 ± 0.2)")
+                .unwrap()
+                .to_string(),
+            "6 ± 0.3"
+        );
+        assert_eq!(
+            evaluate_value("(10 ± 0.6) / 4").unwrap().to_string(),
+            "2.5 ± 0.15"
+        );
+        assert_eq!(
+            evaluate_value("(2 ± 0.1)^3").unwrap().to_string(),
+            "8 ± 1.2"
+        );
+        assert_eq!(
+            evaluate_value("sqrt(16 ± 0.4)").unwrap().to_string(),
+            "4 ± 0.05"
+        );
+        assert_eq!(
+            evaluate_value("-(1234.5 ± 12.345)").unwrap().to_string(),
+            "-1235 ± 12"
+        );
+        assert_eq!(
+            evaluate_value("2 * 9.81 ± 0.02"),
+            Ok(Value::Uncertain {
+                value: 19.62,
+                sigma: 0.02
+            })
+        );
+        assert_eq!(evaluate("3+ -2"), Ok(1.0));
+        assert_eq!(evaluate("2*+-3"), Ok(-6.0));
+
+        assert_eq!(
+            evaluate_value("(2 ± 0.1) < 3").unwrap_err().to_string(),
+            "'<' has no rule for propagating an uncertainty at position 11"
+        );
+        assert!(
+            evaluate_value("max(1 ± 0.1, 2)")
+                .unwrap_err()
+                .to_string()
+                .contains("max has no rule")
+        );
+        assert!(
+            evaluate_value("(1 ± 0.1) ± 0.2")
+                .unwrap_err()
+                .to_string()
+                .contains("must be exact numbers")
+        );
+        assert!(
+            evaluate("5 ± 0.1")
+                .unwrap_err()
+                .to_string()
+                .contains("isn't a single number")
+        );
+    }
+
+    #[test]
+    fn list_operations() {
+        assert_eq!(
+            evaluate_value("[1, 2, 3] * 2"),
+            Ok(Value::List(vec![2.0, 4.0, 6.0]))
+        );
+        assert_eq!(
+            evaluate_value("[1, 2, 3] + [10, 20, 30]")
+                .unwrap()
+                .to_string(),
+            "[11, 22, 33]"
+        );
+        assert_eq!(
+            evaluate_value("2^[1, 2, 3] - 1").unwrap().to_string(),
+            "[1, 3, 7]"
+        );
+        assert_eq!(
+            evaluate_value("sqrt([4, 9, 16])").unwrap().to_string(),
+            "[2, 3, 4]"
+        );
+        assert_eq!(
+            evaluate_value("round([1.26, 2.71], 1)")
+                .unwrap()
+                .to_string(),
+            "[1.3, 2.7]"
+        );
+        assert_eq!(evaluate_value("sum([1, 2, 3])"), Ok(Value::Number(6.0)));
+        assert_eq!(evaluate_value("mean([2, 4, 9])"), Ok(Value::Number(5.0)));
+        assert_eq!(evaluate_value("max([4, 9, 2], 7)"), Ok(Value::Number(9.0)));
+        assert_eq!(evaluate_value("[]").unwrap().to_string(), "[]");
+        assert_eq!(
+            evaluate_value("dot([1, 2, 3], [4, 5, 6])"),
+            Ok(Value::Number(32.0))
+        );
+        assert_eq!(evaluate_value("dot([2], [5])"), Ok(Value::Number(10.0)));
+        assert_eq!(evaluate_value("dot([], [])"), Ok(Value::Number(0.0)));
+        assert_eq!(evaluate_value("norm([3, 4])"), Ok(Value::Number(5.0)));
+        assert_eq!(evaluate_value("norm([-7])"), Ok(Value::Number(7.0)));
+        assert_eq!(evaluate_value("norm([])"), Ok(Value::Number(0.0)));
+        assert_eq!(
+            evaluate_value("cross([1, 0, 0], [0, 1, 0])")
+                .unwrap()
+                .to_string(),
+            "[0, 0, 1]"
+        );
+        assert_eq!(
+            evaluate_value("polyval([1, -3, 2], 5)"),
+            Ok(Value::Number(12.0))
+        );
+        assert_eq!(evaluate_value("polyval([7], 100)"), Ok(Value::Number(7.0)));
+        assert_eq!(
+            evaluate_value("polyval([1, -3, 2], [0, 1, 2, 3])")
+                .unwrap()
+                .to_string(),
+            "[2, 0, 0, 2]"
+        );
+    }
+
+    #[test]
+    fn list_errors() {
+        assert_eq!(
+            evaluate_value("[1, 2, 3] + [1, 2]")
+                .unwrap_err()
+                .to_string(),
+            "lists of different lengths 3 and 2 at position 10"
+        );
+        assert_eq!(
+            evaluate_value("[[1, 2], 3]").unwrap_err().to_string(),
+            "nested lists are not supported at position 1"
+        );
+        assert!(
+            evaluate_value("sum([])")
+                .unwrap_err()
+                .to_string()
+                .contains("can't take an empty list")
+        );
+        assert_eq!(
+            evaluate_value("dot([1, 2], [1])").unwrap_err().to_string(),
+            "dot expects two lists of equal length, got 2 and 1"
+        );
+        assert_eq!(
+            evaluate_value("dot([1, 2], 3)").unwrap_err().to_string(),
+            "dot expects two lists of equal length, got a number"
+        );
+        assert_eq!(
+            evaluate("norm(5)").unwrap_err().to_string(),
+            "norm expects a list, got a number"
+        );
+        assert_eq!(
+            evaluate_value("polyval([], 1)").unwrap_err().to_string(),
+            "polyval expects at least one coefficient, got an empty list"
+        );
+        assert_eq!(
+            evaluate_value("cross([1], [2])").unwrap_err().to_string(),
+            "cross expects two lists of three numbers, got 1 and 1"
+        );
+    }
+
+    #[test]
+    fn divmod() {
+        assert_eq!(
+            evaluate_value("divmod(17, 5)"),
+            Ok(Value::List(vec![3.0, 2.0]))
+        );
+        assert_eq!(
+            evaluate_value("divmod(-17, 5)").unwrap().to_string(),
+            "[-4, 3]"
+        );
+        assert_eq!(
+            evaluate_value("divmod(17, -5)").unwrap().to_string(),
+            "[-4, -3]"
+        );
+        assert_eq!(
+            evaluate_value("divmod(-17, -5)").unwrap().to_string(),
+            "[3, -2]"
+        );
+        assert_eq!(
+            evaluate_value("divmod(0, 7)").unwrap().to_string(),
+            "[0, 0]"
+        );
+        assert_eq!(
+            evaluate_value("divmod(17, 5) * [5, 1]")
+                .unwrap()
+                .to_string(),
+            "[15, 2]"
+        );
+        assert_eq!(
+            evaluate_value("dot(divmod(17, 5), [5, 1])"),
+            Ok(Value::Number(17.0))
+        );
+        for (a, b) in [(17, 5), (-17, 5), (17, -5), (-17, -5), (9, 3)] {
+            let pair = format!("[{a} // {b}, mod({a}, {b})]");
+            assert_eq!(
+                evaluate_value(&format!("divmod({a}, {b})")),
+                evaluate_value(&pair)
+            );
+        }
+        assert_eq!(
+            evaluate_value("divmod(1, 0)"),
+            Err(CalcError::DivisionByZero {
+                span: Some(Span { start: 0, end: 6 })
+            })
+        );
+        assert_eq!(
+            evaluate_value("divmod(7.5, 2)").unwrap_err().to_string(),
+            "divmod requires integers, got 7.5"
+        );
+        assert_eq!(
+            evaluate_value("divmod(7, 0.5)").unwrap_err().to_string(),
+            "divmod requires integers, got 0.5"
+        );
+        assert_eq!(
+            evaluate_value("divmod([7, 8], 2)").unwrap_err().to_string(),
+            "divmod expects two integers, got a list"
+        );
+        assert_eq!(
+            evaluate_value("divmod(7)").unwrap_err().to_string(),
+            "divmod expects 2 arguments, got 1"
+        );
+        assert!(
+            evaluate("divmod(17, 5)")
+                .unwrap_err()
+                .to_string()
+                .contains("isn't a single number")
+        );
+    }
+
+    #[test]
+    fn continued_fractions() {
+        assert_eq!(
+            evaluate_value("cf(pi, 5)"),
+            Ok(Value::List(vec![3.0, 7.0, 15.0, 1.0, 292.0]))
+        );
+        assert_eq!(
+            evaluate_value("cf(415 / 93, 10)").unwrap().to_string(),
+            "[4, 2, 6, 7]"
+        );
+        assert_eq!(
+            evaluate_value("cf(0.1, 10)").unwrap().to_string(),
+            "[0, 10]"
+        );
+        assert_eq!(
+            evaluate_value("cf(-3.5, 5)").unwrap().to_string(),
+            "[-4, 2]"
+        );
+        assert_eq!(evaluate_value("cf(7, 3)").unwrap().to_string(), "[7]");
+        assert_eq!(
+            evaluate_value("cf(e, 11)").unwrap().to_string(),
+            "[2, 1, 2, 1, 1, 4, 1, 1, 6, 1, 1]"
+        );
+        assert_eq!(
+            evaluate_value("cf(sqrt(2), 64)")
+                .unwrap()
+                .to_string()
+                .matches(", 2")
+                .count(),
+            20
+        );
+        assert_eq!(
+            evaluate_value("cf(pi, 0)").unwrap_err().to_string(),
+            "cf count must be a whole number from 1 to 64, got 0"
+        );
+        assert!(
+            evaluate_value("cf(pi, 65)")
+                .unwrap_err()
+                .to_string()
+                .contains("from 1 to 64, got 65")
+        );
+        assert!(
+            evaluate_value("cf(pi, 2.5)")
+                .unwrap_err()
+                .to_string()
+                .contains("got 2.5")
+        );
+        assert_eq!(
+            evaluate_value("cf([1, 2], 3)").unwrap_err().to_string(),
+            "cf expects a number and a count of terms, got a list"
+        );
+        assert!(
+            evaluate_value("cf(2^60, 3)")
+                .unwrap_err()
+                .to_string()
+                .contains("at most 2^53 in size")
+        );
+        assert!(
+            evaluate("cf(pi, 3)")
+                .unwrap_err()
+                .to_string()
+                .contains("isn't a single number")
+        );
+        assert!(
+            evaluate_value("[1, 2")
+                .unwrap_err()
+                .to_string()
+                .contains("unclosed bracket")
+        );
+        assert!(
+            evaluate("[1, 2] * 2")
+                .unwrap_err()
+                .to_string()
+                .contains("isn't a single number")
+        );
+    }
+
+    #[test]
+    fn list_variables() {
+        let mut context = Context::new();
+        evaluate_value_with_context("c = [1, -3, 2]", &mut context).unwrap();
+        assert_eq!(
+            evaluate_value_with_context("polyval(c, 2 + 1)", &mut context),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            evaluate_value_with_context("c * 2", &mut context)
+                .unwrap()
+                .to_string(),
+            "[2, -6, 4]"
+        );
+        assert_eq!(context.ans(), None);
+        evaluate_value_with_context("qr = divmod(23, 4)", &mut context).unwrap();
+        assert_eq!(context.get_list("qr"), Some(&[5.0, 3.0][..]));
+        assert_eq!(
+            evaluate_value_with_context("dot(qr, [4, 1])", &mut context),
+            Ok(Value::Number(23.0))
+        );
+        assert_eq!(
+            evaluate_value_with_context("c = 4; c * 2", &mut context),
+            Ok(Value::Number(8.0))
+        );
+    }
+
+    #[test]
+    fn polyval_agrees_with_expansion() {
+        let mut seed = 12345u64;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 40) as f64 / (1u64 << 24) as f64 * 20.0 - 10.0
+        };
+        for _ in 0..20 {
+            let (c, x) = ([next(), next(), next(), next()], next());
+            let list = format!("[{}, {}, {}, {}]", c[0], c[1], c[2], c[3]);
+            let Ok(Value::Number(horner)) = evaluate_value(&format!("polyval({}, {})", list, x))
+            else {
+                panic!("polyval of {} at {}", list, x);
+            };
+            let expanded = evaluate(&format!(
+                "({})*({x})^3 + ({})*({x})^2 + ({})*({x}) + ({})",
+                c[0], c[1], c[2], c[3]
+            ))
+            .unwrap();
+            assert!((horner - expanded).abs() <= 1e-9 * expanded.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn variables() {
+        let mut context = Context::new();
+        assert_eq!(evaluate_with_context("x = 5", &mut context), Ok(5.0));
+        assert_eq!(evaluate_with_context("x*2+1", &mut context), Ok(11.0));
+        assert_eq!(evaluate_with_context("x = x + 1", &mut context), Ok(6.0));
+        assert_eq!(evaluate_with_context("big_2 = 2x", &mut context), Ok(12.0));
+        assert_eq!(
+            evaluate_with_context("y+1", &mut context)
+                .unwrap_err()
+                .to_string(),
+            "undefined variable 'y' at position 0"
+        );
+        assert_eq!(
+            evaluate_with_context("pi = 3", &mut context)
+                .unwrap_err()
+                .to_string(),
+            "cannot assign to constant 'pi'"
+        );
+        assert!(evaluate_with_context("2x = 3", &mut context).is_err());
+        assert!(evaluate_with_context("x = ", &mut context).is_err());
+        assert!(evaluate("x = 5").is_err());
+    }
+
+    #[test]
+    fn previous_result() {
+        let mut context = Context::new();
+        assert_eq!(
+            evaluate_with_context("ans+1", &mut context)
+                .unwrap_err()
+                .to_string(),
+            "no previous result for 'ans' at position 0"
+        );
+        assert_eq!(evaluate_with_context("6*7", &mut context), Ok(42.0));
+        assert_eq!(evaluate_with_context("ans/2", &mut context), Ok(21.0));
+        assert_eq!(evaluate_with_context("ans^2+1", &mut context), Ok(442.0));
+        assert!(evaluate_with_context("1/0", &mut context).is_err());
+        assert_eq!(evaluate_with_context("ans", &mut context), Ok(442.0));
+        assert!(evaluate_with_context("ans = 1", &mut context).is_err());
+    }
+
+    #[test]
+    fn user_functions() {
+        let mut context = Context::new();
+        assert!(
+            evaluate_with_context("f(x) = x^2 + 1", &mut context)
+                .unwrap()
+                .is_nan()
+        );
+        assert_eq!(evaluate_with_context("f(3)", &mut context), Ok(10.0));
+        evaluate_with_context("hyp(a, b) = sqrt(a^2 + b^2)", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("hyp(3, 4) + f(1)", &mut context),
+            Ok(7.0)
+        );
+        assert_eq!(evaluate_with_context("k = 10", &mut context), Ok(10.0));
+        evaluate_with_context("g(x) = k*x", &mut context).unwrap();
+        assert_eq!(evaluate_with_context("g(2)", &mut context), Ok(20.0));
+        evaluate_with_context("f(x) = 2x", &mut context).unwrap();
+        assert_eq!(evaluate_with_context("f(3)", &mut context), Ok(6.0));
+        assert_eq!(
+            evaluate_with_context("f(1, 2)", &mut context)
+                .unwrap_err()
+                .to_string(),
+            "f expects 1 argument, got 2"
+        );
+        assert_eq!(
+            evaluate_with_context("r(n) = n*r(n-1)", &mut context)
+                .unwrap_err()
+                .to_string(),
+            "recursive functions are not supported"
+        );
+        evaluate_with_context("a(x) = x", &mut context).unwrap();
+        evaluate_with_context("b(x) = a(x)", &mut context).unwrap();
+        assert!(evaluate_with_context("a(x) = b(x)", &mut context).is_err());
+        evaluate_with_context("sgn(x) = x > 0 ? 1 : x < 0 ? -1 : 0", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("sgn(-4) + sgn(0) + 10sgn(2)", &mut context),
+            Ok(9.0)
+        );
+        assert!(evaluate_with_context("if(x) = x", &mut context).is_err());
+        evaluate_with_context("triangle(n) = sum(k, 1, n, k)", &mut context).unwrap();
+        assert_eq!(evaluate_with_context("triangle(4)", &mut context), Ok(10.0));
+        assert!(evaluate_with_context("sum(x) = x", &mut context).is_err());
+        assert_eq!(
+            evaluate_with_context("sqrt(x) = x", &mut context)
+                .unwrap_err()
+                .to_string(),
+            "cannot redefine built-in function 'sqrt'"
+        );
+        assert!(
+            evaluate_with_context("h(x, x) = x", &mut context)
+                .unwrap_err()
+                .to_string()
+                .contains("duplicate parameter")
+        );
+        assert!(
+            evaluate_with_context("g(x)", &mut context)
+                .unwrap_err()
+                .to_string()
+                .contains("undefined variable 'x'")
+        );
+        evaluate_with_context("g2(t) = t + q", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("1 + g2(1)", &mut context),
+            Err(CalcError::UnknownIdentifier {
+                name: "q".to_string(),
+                span: Span { start: 4, end: 6 }
+            })
+        );
+    }
+}
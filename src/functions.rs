@@ -0,0 +1,1211 @@
+use crate::error::CalcError;
+use crate::eval;
+use crate::list;
+use crate::options::{EvalOptions, RemainderMode};
+use crate::primes;
+use crate::random;
+use crate::units;
+use crate::value;
+
+/// A built-in function that can be called as `name(args)`
+pub(crate) struct Function {
+    pub name: &'static str,
+    /// Parameter list shown in help output, such as `x` or `x, n`
+    pub params: &'static str,
+    pub description: &'static str,
+    pub min_args: usize,
+    /// `None` for functions taking any number of arguments above `min_args`
+    pub max_args: Option<usize>,
+    pub call: fn(&[f64], &EvalOptions) -> Result<f64, CalcError>,
+}
+
+impl Function {
+    /// A function taking exactly `arity` arguments
+    const fn fixed(
+        name: &'static str,
+        params: &'static str,
+        arity: usize,
+        description: &'static str,
+        call: fn(&[f64], &EvalOptions) -> Result<f64, CalcError>,
+    ) -> Self {
+        Function {
+            name,
+            params,
+            description,
+            min_args: arity,
+            max_args: Some(arity),
+            call,
+        }
+    }
+
+    /// A function taking between `min_args` and `max_args` arguments
+    const fn range(
+        name: &'static str,
+        params: &'static str,
+        min_args: usize,
+        max_args: usize,
+        description: &'static str,
+        call: fn(&[f64], &EvalOptions) -> Result<f64, CalcError>,
+    ) -> Self {
+        Function {
+            name,
+            params,
+            description,
+            min_args,
+            max_args: Some(max_args),
+            call,
+        }
+    }
+
+    /// A function taking `min_args` or more arguments
+    const fn variadic(
+        name: &'static str,
+        params: &'static str,
+        min_args: usize,
+        description: &'static str,
+        call: fn(&[f64], &EvalOptions) -> Result<f64, CalcError>,
+    ) -> Self {
+        Function {
+            name,
+            params,
+            description,
+            min_args,
+            max_args: None,
+            call,
+        }
+    }
+
+    /// Checks the number of arguments in a call, naming the function on failure
+    pub fn check_arity(&self, count: usize) -> Result<(), CalcError> {
+        check_arity(self.name, self.min_args, self.max_args, count)
+    }
+}
+
+/// Fails unless `count` arguments are from `min_args` to `max_args`, which is
+/// `None` for no limit, saying how many the function called `name` takes
+pub(crate) fn check_arity(
+    name: &str,
+    min_args: usize,
+    max_args: Option<usize>,
+    count: usize,
+) -> Result<(), CalcError> {
+    let expected = match max_args {
+        Some(max) if max == min_args => {
+            if count == max {
+                return Ok(());
+            }
+            plural(max)
+        }
+        Some(max) => {
+            if (min_args..=max).contains(&count) {
+                return Ok(());
+            }
+            format!("{} to {} arguments", min_args, max)
+        }
+        None => {
+            if count >= min_args {
+                return Ok(());
+            }
+            format!("at least {}", plural(min_args))
+        }
+    };
+    Err(CalcError::ArityMismatch {
+        name: name.to_string(),
+        expected,
+        got: count,
+        span: None,
+    })
+}
+
+pub(crate) fn plural(count: usize) -> String {
+    if count == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", count)
+    }
+}
+
+/// Reports an argument outside a function's domain, or yields NaN when the
+/// options ask for it
+fn domain_error(name: &str, value: f64, options: &EvalOptions) -> Result<f64, CalcError> {
+    if options.nan_on_domain_error {
+        Ok(f64::NAN)
+    } else {
+        Err(CalcError::domain(format!(
+            "{} is undefined for {}",
+            name, value
+        )))
+    }
+}
+
+/// Largest integer up to which every integer is exactly representable in f64
+pub(crate) const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+/// Converts the argument at `index` to an integer, naming it in the error when
+/// it has a fractional part or is too large to be an exact integer
+fn integer_arg(name: &str, index: usize, value: f64) -> Result<i64, CalcError> {
+    if value.fract() != 0.0 || value.abs() > MAX_EXACT_INTEGER {
+        return Err(CalcError::other(format!(
+            "{} argument {} must be an integer, got {}",
+            name,
+            index + 1,
+            value
+        )));
+    }
+    Ok(value as i64)
+}
+
+/// Converts an exact integer result back to f64, failing if it lost precision
+fn exact_result(name: &str, value: u64) -> Result<f64, CalcError> {
+    if value as f64 > MAX_EXACT_INTEGER {
+        return Err(CalcError::overflow(format!(
+            "{} result is too large to represent exactly",
+            name
+        )));
+    }
+    Ok(value as f64)
+}
+
+fn abs(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].abs())
+}
+
+// Both zeros have sign 0, unlike `f64::signum` which gives 1 and -1 for them
+fn sign(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] == 0.0 {
+        return Ok(0.0);
+    }
+    Ok(args[0].signum())
+}
+
+fn copysign(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].copysign(args[1]))
+}
+
+fn sqrt(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] < 0.0 {
+        return domain_error("sqrt", args[0], options);
+    }
+    Ok(args[0].sqrt())
+}
+
+fn cbrt(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].cbrt())
+}
+
+// Odd roots of negative numbers are negative, unlike `powf(x, 1/n)` which gives
+// NaN, and perfect powers such as 27 for n = 3 give an exact result
+fn root(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    let (x, n) = (args[0], args[1]);
+    let odd = n.fract() == 0.0 && n.rem_euclid(2.0) == 1.0;
+    if n == 0.0 || (x < 0.0 && !odd) {
+        if options.nan_on_domain_error {
+            return Ok(f64::NAN);
+        }
+        return Err(CalcError::domain(format!(
+            "root of degree {} is undefined for {}",
+            n, x
+        )));
+    }
+    let magnitude = match n {
+        2.0 => x.abs().sqrt(),
+        3.0 => x.abs().cbrt(),
+        _ => x.abs().powf(1.0 / n),
+    };
+    let rounded = magnitude.round();
+    let magnitude = if n.fract() == 0.0 && rounded.powf(n) == x.abs() {
+        rounded
+    } else {
+        magnitude
+    };
+    Ok(magnitude.copysign(x))
+}
+
+fn sin(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(options.angle_to_radians(args[0]).sin())
+}
+
+fn cos(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(options.angle_to_radians(args[0]).cos())
+}
+
+// π/2 is not exactly representable, so `tan` near its poles returns a very
+// large finite value instead of failing
+fn tan(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(options.angle_to_radians(args[0]).tan())
+}
+
+fn asin(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if !(-1.0..=1.0).contains(&args[0]) {
+        return domain_error("asin", args[0], options);
+    }
+    Ok(options.radians_to_angle(args[0].asin()))
+}
+
+fn acos(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if !(-1.0..=1.0).contains(&args[0]) {
+        return domain_error("acos", args[0], options);
+    }
+    Ok(options.radians_to_angle(args[0].acos()))
+}
+
+fn atan(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(options.radians_to_angle(args[0].atan()))
+}
+
+fn atan2(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(options.radians_to_angle(args[0].atan2(args[1])))
+}
+
+// Scales internally, so legs whose squares would overflow still work
+fn hypot(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].hypot(args[1]))
+}
+
+fn sinh(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].sinh())
+}
+
+fn cosh(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].cosh())
+}
+
+// Saturates to ±1 for large arguments instead of overflowing
+fn tanh(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].tanh())
+}
+
+fn asinh(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].asinh())
+}
+
+fn acosh(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] < 1.0 {
+        return domain_error("acosh", args[0], options);
+    }
+    Ok(args[0].acosh())
+}
+
+fn atanh(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] <= -1.0 || args[0] >= 1.0 {
+        return domain_error("atanh", args[0], options);
+    }
+    Ok(args[0].atanh())
+}
+
+fn ln(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] <= 0.0 {
+        return domain_error("ln", args[0], options);
+    }
+    Ok(args[0].ln())
+}
+
+fn exp(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].exp())
+}
+
+// `expm1` and `ln1p` stay accurate for tiny arguments, where `exp(x) - 1` and
+// `ln(1 + x)` cancel away most of the digits
+fn expm1(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args[0].exp_m1())
+}
+
+fn ln1p(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] <= -1.0 {
+        return domain_error("ln1p", args[0], options);
+    }
+    Ok(args[0].ln_1p())
+}
+
+/// Coefficients of the Lanczos approximation with `g = 7`, which is accurate
+/// to about 15 significant digits
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// The series of the Lanczos approximation and the point `t` it is scaled
+/// around, for `x` of at least 0.5
+fn lanczos(x: f64) -> (f64, f64) {
+    let x = x - 1.0;
+    let series = LANCZOS[1..]
+        .iter()
+        .zip(1..)
+        .fold(LANCZOS[0], |sum, (c, i)| sum + c / (x + i as f64));
+    (series, x + LANCZOS_G + 0.5)
+}
+
+/// Returns whether the gamma function has a pole at `x`
+fn is_gamma_pole(x: f64) -> bool {
+    x <= 0.0 && x.fract() == 0.0
+}
+
+// Positive integers are exact factorials, and arguments below 0.5 use the
+// reflection formula gamma(x) gamma(1 - x) = pi / sin(pi x)
+fn gamma(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    let x = args[0];
+    if is_gamma_pole(x) || x.is_nan() {
+        return domain_error("gamma", x, options);
+    }
+    let result = if x.fract() == 0.0 && x <= 171.0 {
+        (2..x as u64).fold(1.0, |product, i| product * i as f64)
+    } else if x < 0.5 {
+        std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(&[1.0 - x], options)?)
+    } else {
+        let (series, t) = lanczos(x);
+        // t^(x - 1/2) is split in two so that it doesn't overflow before the
+        // exponential brings it back down
+        let half_power = t.powf((x - 0.5) / 2.0);
+        (2.0 * std::f64::consts::PI).sqrt() * half_power * (-t).exp() * half_power * series
+    };
+    if result.is_infinite() {
+        return Err(CalcError::overflow(format!("gamma of {} is too large", x)));
+    }
+    Ok(result)
+}
+
+// The logarithm of the absolute value of gamma, which stays finite long after
+// gamma overflows
+fn lgamma(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    let x = args[0];
+    if is_gamma_pole(x) || x.is_nan() {
+        return domain_error("lgamma", x, options);
+    }
+    if x < 0.5 {
+        let reflected = lgamma(&[1.0 - x], options)?;
+        return Ok(
+            (std::f64::consts::PI / (std::f64::consts::PI * x).sin().abs()).ln() - reflected,
+        );
+    }
+    let (series, t) = lanczos(x);
+    Ok(0.5 * (2.0 * std::f64::consts::PI).ln() + (x - 0.5) * t.ln() - t + series.ln())
+}
+
+fn log10(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] <= 0.0 {
+        return domain_error("log10", args[0], options);
+    }
+    Ok(args[0].log10())
+}
+
+fn log2(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] <= 0.0 {
+        return domain_error("log2", args[0], options);
+    }
+    Ok(args[0].log2())
+}
+
+// With a single argument the base defaults to 10
+fn log(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    if args[0] <= 0.0 {
+        return domain_error("log", args[0], options);
+    }
+    match args.get(1) {
+        None => Ok(args[0].log10()),
+        Some(&base) if base <= 0.0 || base == 1.0 => {
+            if options.nan_on_domain_error {
+                Ok(f64::NAN)
+            } else {
+                Err(CalcError::domain(format!(
+                    "log is undefined for base {}",
+                    base
+                )))
+            }
+        }
+        Some(&base) => Ok(args[0].log(base)),
+    }
+}
+
+/// Applies a rounding function at `args[1]` decimal places, or to an integer
+/// when no precision is given
+///
+/// A negative precision rounds to tens, hundreds and so on, so
+/// `round(1234, -2)` is `1200`.
+fn at_precision(name: &str, args: &[f64], round: impl Fn(f64) -> f64) -> Result<f64, CalcError> {
+    let Some(&digits) = args.get(1) else {
+        return Ok(round(args[0]));
+    };
+    if digits.fract() != 0.0 {
+        return Err(CalcError::other(format!(
+            "{} precision must be an integer, got {}",
+            name, digits
+        )));
+    }
+
+    if digits >= 0.0 {
+        let scale = 10f64.powi(digits as i32);
+        Ok(round(args[0] * scale) / scale)
+    } else {
+        // Dividing by 10^n is exact where multiplying by 10^-n is not
+        let scale = 10f64.powi(-digits as i32);
+        Ok(round(args[0] / scale) * scale)
+    }
+}
+
+fn floor(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    at_precision("floor", args, f64::floor)
+}
+
+fn ceil(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    at_precision("ceil", args, f64::ceil)
+}
+
+// Ties follow the rounding mode, by default away from zero, so `round(2.5)`
+// is 3 and `round(-2.5)` is -3
+fn round(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    at_precision("round", args, |x| options.rounding.round(x))
+}
+
+fn trunc(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    at_precision("trunc", args, f64::trunc)
+}
+
+fn min(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args.iter().copied().fold(f64::INFINITY, f64::min))
+}
+
+fn max(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max))
+}
+
+fn sum(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args.iter().sum())
+}
+
+fn count(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args.len() as f64)
+}
+
+fn mean(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(args.iter().sum::<f64>() / args.len() as f64)
+}
+
+// With an even number of arguments, the two in the middle are averaged
+fn median(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let mut sorted = args.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        Ok((sorted[mid - 1] + sorted[mid]) / 2.0)
+    } else {
+        Ok(sorted[mid])
+    }
+}
+
+// The sample variance, dividing by one less than the number of arguments, so
+// at least two are needed
+fn var(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    let mean = mean(args, options)?;
+    let squares: f64 = args.iter().map(|arg| (arg - mean).powi(2)).sum();
+    Ok(squares / (args.len() - 1) as f64)
+}
+
+fn stddev(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(var(args, options)?.sqrt())
+}
+
+// The remainder with the sign of the divisor, whatever `%` is set to
+fn modulo(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    if args[1] == 0.0 {
+        return Err(CalcError::division_by_zero());
+    }
+    Ok(eval::remainder(args[0], args[1], RemainderMode::Floored))
+}
+
+fn clamp(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let (x, lo, hi) = (args[0], args[1], args[2]);
+    if lo > hi {
+        return Err(CalcError::other(format!(
+            "clamp lower bound {} is greater than upper bound {}",
+            lo, hi
+        )));
+    }
+    Ok(x.clamp(lo, hi))
+}
+
+fn lerp(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let (a, b, t) = (args[0], args[1], args[2]);
+    Ok(a + (b - a) * t)
+}
+
+fn lerp_clamped(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    lerp(&[args[0], args[1], args[2].clamp(0.0, 1.0)], options)
+}
+
+fn isnan(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(if args[0].is_nan() { 1.0 } else { 0.0 })
+}
+
+fn isinf(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(if args[0].is_infinite() { 1.0 } else { 0.0 })
+}
+
+fn isfinite(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(if args[0].is_finite() { 1.0 } else { 0.0 })
+}
+
+fn ulp(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let x = args[0].abs();
+    Ok(if !x.is_finite() {
+        x
+    } else if x == f64::MAX {
+        // Nothing finite is larger, so take the gap below instead
+        x - x.next_down()
+    } else {
+        x.next_up() - x
+    })
+}
+
+fn nextafter(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let (x, y) = (args[0], args[1]);
+    Ok(if x.is_nan() || y.is_nan() {
+        f64::NAN
+    } else if x < y {
+        x.next_up()
+    } else if x > y {
+        x.next_down()
+    } else {
+        y
+    })
+}
+
+fn map_range(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    let (x, in_lo, in_hi) = (args[0], args[1], args[2]);
+    if in_lo == in_hi {
+        return Err(CalcError::other(format!(
+            "map_range input range is empty, from {} to {}",
+            in_lo, in_hi
+        )));
+    }
+    lerp(&[args[3], args[4], (x - in_lo) / (in_hi - in_lo)], options)
+}
+
+pub(crate) fn gcd_u64(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// Signs are ignored, so `gcd(-12, 18)` is 6
+fn gcd(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let mut result = 0;
+    for (i, &arg) in args.iter().enumerate() {
+        result = gcd_u64(result, integer_arg("gcd", i, arg)?.unsigned_abs());
+    }
+    exact_result("gcd", result)
+}
+
+// Signs are ignored, and the running result is divided by the gcd before
+// multiplying so intermediate values stay as small as possible
+fn lcm(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let mut result: u64 = 1;
+    for (i, &arg) in args.iter().enumerate() {
+        let n = integer_arg("lcm", i, arg)?.unsigned_abs();
+        if n == 0 {
+            return Ok(0.0);
+        }
+        result = (result / gcd_u64(result, n))
+            .checked_mul(n)
+            .ok_or_else(|| CalcError::overflow("lcm result is too large to represent exactly"))?;
+    }
+    exact_result("lcm", result)
+}
+
+fn powmod(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let mut values = [0; 3];
+    for (i, &arg) in args.iter().enumerate() {
+        let n = integer_arg("powmod", i, arg)?;
+        if n < 0 {
+            return Err(CalcError::other(format!(
+                "powmod argument {} must be non-negative, got {}",
+                i + 1,
+                n
+            )));
+        }
+        values[i] = n as u64;
+    }
+    let [base, exponent, modulus] = values;
+    if modulus == 0 {
+        return Err(CalcError::other("powmod modulus must be at least 1, got 0"));
+    }
+    exact_result("powmod", primes::pow_mod(base, exponent, modulus) % modulus)
+}
+
+/// Converts the argument of `name` to an integer from 0 to 2^53
+fn non_negative_integer(name: &str, value: f64) -> Result<u64, CalcError> {
+    if value.fract() != 0.0 || !(0.0..=MAX_EXACT_INTEGER).contains(&value) {
+        return Err(CalcError::other(format!(
+            "{} argument must be a non-negative integer up to 2^53, got {}",
+            name, value
+        )));
+    }
+    Ok(value as u64)
+}
+
+fn fib(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let n = non_negative_integer("fib", args[0])?;
+    let too_large = || CalcError::overflow("fib result is too large to represent exactly");
+    exact_result("fib", fib_pair(n).ok_or_else(too_large)?.0)
+}
+
+/// The Fibonacci numbers `F(n)` and `F(n + 1)` by fast doubling, which takes
+/// a step per bit of `n`, or `None` if they don't fit in a `u64`
+fn fib_pair(n: u64) -> Option<(u64, u64)> {
+    if n == 0 {
+        return Some((0, 1));
+    }
+    let (a, b) = fib_pair(n / 2)?;
+    // F(2k) = F(k) * (2F(k + 1) - F(k)) and F(2k + 1) = F(k)^2 + F(k + 1)^2
+    let even = a.checked_mul(b.checked_mul(2)?.checked_sub(a)?)?;
+    let odd = a.checked_mul(a)?.checked_add(b.checked_mul(b)?)?;
+    if n.is_multiple_of(2) {
+        Some((even, odd))
+    } else {
+        Some((odd, even.checked_add(odd)?))
+    }
+}
+
+fn triangular(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let n = u128::from(non_negative_integer("triangular", args[0])?);
+    let sum = n * (n + 1) / 2;
+    exact_result("triangular", u64::try_from(sum).unwrap_or(u64::MAX))
+}
+
+fn isqrt(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let n = non_negative_integer("isqrt", args[0])?;
+    // The floating point root may be one off near a perfect square
+    let mut root = (n as f64).sqrt() as u64;
+    while root * root > n {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= n {
+        root += 1;
+    }
+    Ok(root as f64)
+}
+
+/// The bits of the integer `n` in the bit width of `options`, or those of
+/// its magnitude if there is none
+fn bits(name: &str, n: f64, options: &EvalOptions) -> Result<u64, CalcError> {
+    let requires = format!("{} requires", name);
+    let n = eval::integer_bits(n, options.bit_width, &requires)?;
+    Ok(eval::bit_pattern(n, options.bit_width))
+}
+
+fn popcount(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(f64::from(bits("popcount", args[0], options)?.count_ones()))
+}
+
+fn bit_length(args: &[f64], options: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(f64::from(
+        64 - bits("bit_length", args[0], options)?.leading_zeros(),
+    ))
+}
+
+fn isprime(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let n = primes::positive_integer("isprime", args[0])?;
+    Ok(if primes::is_prime(n) { 1.0 } else { 0.0 })
+}
+
+fn nextprime(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let n = primes::positive_integer("nextprime", args[0])?;
+    let prime = primes::next_prime(n).expect("a prime follows every integer up to 2^53");
+    exact_result("nextprime", prime)
+}
+
+// Functions whose result is text are evaluated by `value` when they make up
+// the whole expression, and are an error anywhere else
+fn factorize(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(value::text_in_expression("factorize"))
+}
+
+fn dms(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(value::text_in_expression("dms"))
+}
+
+fn frac(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(value::text_in_expression("frac"))
+}
+
+// Functions of lists are evaluated by `list`, and reach these only when given
+// numbers
+fn dot(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(list::not_a_list("dot"))
+}
+
+fn cross(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(list::not_a_list("cross"))
+}
+
+fn norm(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(list::not_a_list("norm"))
+}
+
+fn polyval(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(list::not_a_list("polyval"))
+}
+
+// These give lists, so `list` evaluates every call to them, and they are only
+// reached where lists aren't supported
+fn divmod(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(list::list_result("divmod"))
+}
+
+fn cf(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(list::list_result("cf"))
+}
+
+fn hex(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(value::text_in_expression("hex"))
+}
+
+fn bin(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(value::text_in_expression("bin"))
+}
+
+fn oct(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Err(value::text_in_expression("oct"))
+}
+
+fn random(_: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    Ok(random::next_f64())
+}
+
+fn randint(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    let lo = integer_arg("randint", 0, args[0])?;
+    let hi = integer_arg("randint", 1, args[1])?;
+    if lo > hi {
+        return Err(CalcError::other(format!(
+            "randint lower bound {} is greater than upper bound {}",
+            lo, hi
+        )));
+    }
+    let offset = random::below((hi - lo) as u64 + 1);
+    Ok((lo + offset as i64) as f64)
+}
+
+// Temperature readings convert through the scales of the unit system
+fn c_to_f(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    units::convert_temperature(args[0], "degC", "degF")
+}
+
+fn f_to_c(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    units::convert_temperature(args[0], "degF", "degC")
+}
+
+fn c_to_k(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    units::convert_temperature(args[0], "degC", "K")
+}
+
+fn k_to_c(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    units::convert_temperature(args[0], "K", "degC")
+}
+
+fn f_to_k(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    units::convert_temperature(args[0], "degF", "K")
+}
+
+fn k_to_f(args: &[f64], _: &EvalOptions) -> Result<f64, CalcError> {
+    units::convert_temperature(args[0], "K", "degF")
+}
+
+pub(crate) const FUNCTIONS: &[Function] = &[
+    Function::fixed("abs", "x", 1, "absolute value, also written |x|", abs),
+    Function::fixed(
+        "sign",
+        "x",
+        1,
+        "-1, 0 or 1 depending on the sign of x",
+        sign,
+    ),
+    Function::fixed(
+        "copysign",
+        "x, y",
+        2,
+        "magnitude of x with the sign of y",
+        copysign,
+    ),
+    Function::fixed("sqrt", "x", 1, "square root", sqrt),
+    Function::fixed("cbrt", "x", 1, "cube root", cbrt),
+    Function::fixed(
+        "root",
+        "x, n",
+        2,
+        "nth root, negative for odd roots of negative x",
+        root,
+    ),
+    Function::fixed("sin", "x", 1, "sine of an angle", sin),
+    Function::fixed("cos", "x", 1, "cosine of an angle", cos),
+    Function::fixed("tan", "x", 1, "tangent of an angle", tan),
+    Function::fixed("asin", "x", 1, "inverse sine", asin),
+    Function::fixed("acos", "x", 1, "inverse cosine", acos),
+    Function::fixed("atan", "x", 1, "inverse tangent", atan),
+    Function::fixed(
+        "atan2",
+        "y, x",
+        2,
+        "angle of the point (x, y) from the positive x axis",
+        atan2,
+    ),
+    Function::fixed(
+        "hypot",
+        "a, b",
+        2,
+        "length of the hypotenuse with legs a and b",
+        hypot,
+    ),
+    Function::fixed("sinh", "x", 1, "hyperbolic sine", sinh),
+    Function::fixed("cosh", "x", 1, "hyperbolic cosine", cosh),
+    Function::fixed("tanh", "x", 1, "hyperbolic tangent", tanh),
+    Function::fixed("asinh", "x", 1, "inverse hyperbolic sine", asinh),
+    Function::fixed("acosh", "x", 1, "inverse hyperbolic cosine", acosh),
+    Function::fixed("atanh", "x", 1, "inverse hyperbolic tangent", atanh),
+    Function::fixed("exp", "x", 1, "e raised to the power x", exp),
+    Function::fixed("expm1", "x", 1, "exp(x) - 1, accurate for x near 0", expm1),
+    Function::fixed("ln", "x", 1, "natural logarithm", ln),
+    Function::fixed("ln1p", "x", 1, "ln(1 + x), accurate for x near 0", ln1p),
+    Function::fixed(
+        "gamma",
+        "x",
+        1,
+        "gamma function, (x - 1)! for positive integers",
+        gamma,
+    ),
+    Function::fixed(
+        "lgamma",
+        "x",
+        1,
+        "natural logarithm of the absolute value of gamma",
+        lgamma,
+    ),
+    Function::fixed("log10", "x", 1, "base-10 logarithm", log10),
+    Function::fixed("log2", "x", 1, "base-2 logarithm", log2),
+    Function::range(
+        "log",
+        "x, base",
+        1,
+        2,
+        "logarithm in the given base, 10 if omitted",
+        log,
+    ),
+    Function::range(
+        "floor",
+        "x, digits",
+        1,
+        2,
+        "round down, optionally to a number of decimal places",
+        floor,
+    ),
+    Function::range(
+        "ceil",
+        "x, digits",
+        1,
+        2,
+        "round up, optionally to a number of decimal places",
+        ceil,
+    ),
+    Function::range(
+        "round",
+        "x, digits",
+        1,
+        2,
+        "round to nearest with ties away from zero, optionally to a number of decimal places",
+        round,
+    ),
+    Function::range(
+        "trunc",
+        "x, digits",
+        1,
+        2,
+        "round towards zero, optionally to a number of decimal places",
+        trunc,
+    ),
+    Function::variadic("min", "a, b, ...", 2, "smallest argument", min),
+    Function::variadic("max", "a, b, ...", 2, "largest argument", max),
+    Function::variadic("sum", "a, ...", 1, "sum of the arguments", sum),
+    Function::variadic("count", "a, ...", 1, "number of arguments", count),
+    Function::variadic(
+        "mean",
+        "a, ...",
+        1,
+        "arithmetic mean of the arguments",
+        mean,
+    ),
+    Function::variadic(
+        "median",
+        "a, ...",
+        1,
+        "middle value of the arguments",
+        median,
+    ),
+    Function::variadic(
+        "var",
+        "a, b, ...",
+        2,
+        "sample variance of the arguments",
+        var,
+    ),
+    Function::variadic(
+        "stddev",
+        "a, b, ...",
+        2,
+        "sample standard deviation of the arguments",
+        stddev,
+    ),
+    Function::fixed(
+        "dot",
+        "a, b",
+        2,
+        "dot product of two lists of equal length",
+        dot,
+    ),
+    Function::fixed(
+        "cross",
+        "a, b",
+        2,
+        "cross product of two lists of three numbers",
+        cross,
+    ),
+    Function::fixed("norm", "a", 1, "Euclidean length of a list", norm),
+    Function::fixed(
+        "polyval",
+        "coefficients, x",
+        2,
+        "polynomial with a list of coefficients, highest degree first, at x",
+        polyval,
+    ),
+    Function::fixed(
+        "divmod",
+        "a, b",
+        2,
+        "the list [a // b, mod(a, b)] of the quotient and remainder of integers",
+        divmod,
+    ),
+    Function::fixed(
+        "cf",
+        "x, n",
+        2,
+        "list of the first n terms of the continued fraction of x",
+        cf,
+    ),
+    Function::fixed(
+        "mod",
+        "a, b",
+        2,
+        "remainder of a/b with the sign of b",
+        modulo,
+    ),
+    Function::fixed(
+        "clamp",
+        "x, lo, hi",
+        3,
+        "x limited to the range lo to hi",
+        clamp,
+    ),
+    Function::fixed(
+        "lerp",
+        "a, b, t",
+        3,
+        "a + (b-a)*t, from a at t = 0 to b at t = 1 and beyond",
+        lerp,
+    ),
+    Function::fixed(
+        "lerp_clamped",
+        "a, b, t",
+        3,
+        "lerp with t limited to the range 0 to 1",
+        lerp_clamped,
+    ),
+    Function::fixed(
+        "map_range",
+        "x, in_lo, in_hi, out_lo, out_hi",
+        5,
+        "x moved from the range in_lo to in_hi to the range out_lo to out_hi",
+        map_range,
+    ),
+    Function::fixed("isnan", "x", 1, "1 if x is NaN, otherwise 0", isnan),
+    Function::fixed(
+        "isinf",
+        "x",
+        1,
+        "1 if x is infinite, of either sign, otherwise 0",
+        isinf,
+    ),
+    Function::fixed(
+        "isfinite",
+        "x",
+        1,
+        "1 if x is neither infinite nor NaN, otherwise 0",
+        isfinite,
+    ),
+    Function::fixed(
+        "ulp",
+        "x",
+        1,
+        "gap from |x| to the next larger floating point number",
+        ulp,
+    ),
+    Function::fixed(
+        "nextafter",
+        "x, y",
+        2,
+        "floating point number next to x in the direction of y",
+        nextafter,
+    ),
+    Function::variadic(
+        "gcd",
+        "a, b, ...",
+        2,
+        "greatest common divisor of integers",
+        gcd,
+    ),
+    Function::variadic(
+        "lcm",
+        "a, b, ...",
+        2,
+        "least common multiple of integers",
+        lcm,
+    ),
+    Function::fixed(
+        "powmod",
+        "b, e, m",
+        3,
+        "b^e modulo m, computed exactly for integers",
+        powmod,
+    ),
+    Function::fixed(
+        "fib",
+        "n",
+        1,
+        "nth Fibonacci number, with fib(0) = 0 and fib(1) = 1",
+        fib,
+    ),
+    Function::fixed(
+        "triangular",
+        "n",
+        1,
+        "nth triangular number, 1 + 2 + ... + n",
+        triangular,
+    ),
+    Function::fixed(
+        "isqrt",
+        "n",
+        1,
+        "largest integer whose square is at most n",
+        isqrt,
+    ),
+    Function::fixed(
+        "popcount",
+        "n",
+        1,
+        "number of bits set in the integer n",
+        popcount,
+    ),
+    Function::fixed(
+        "bit_length",
+        "n",
+        1,
+        "number of bits needed to write the integer n, up to its highest set bit",
+        bit_length,
+    ),
+    Function::fixed("isprime", "n", 1, "1 if n is prime, otherwise 0", isprime),
+    Function::fixed(
+        "nextprime",
+        "n",
+        1,
+        "smallest prime greater than n",
+        nextprime,
+    ),
+    Function::fixed(
+        "factorize",
+        "n",
+        1,
+        "prime factorization of n as text, such as 2^2*3*5",
+        factorize,
+    ),
+    Function::fixed("hex", "n", 1, "integer n in hexadecimal, as text", hex),
+    Function::fixed("bin", "n", 1, "integer n in binary, as text", bin),
+    Function::fixed("oct", "n", 1, "integer n in octal, as text", oct),
+    Function::fixed(
+        "dms",
+        "x",
+        1,
+        "angle x in degrees as text in degrees, minutes and seconds",
+        dms,
+    ),
+    Function::fixed(
+        "frac",
+        "x",
+        1,
+        "nearest fraction to x with a denominator up to 10000, as text",
+        frac,
+    ),
+    Function::fixed("random", "", 0, "uniform random number in [0, 1)", random),
+    Function::fixed(
+        "randint",
+        "a, b",
+        2,
+        "random integer between a and b inclusive",
+        randint,
+    ),
+    Function::fixed("c_to_f", "x", 1, "degrees Celsius to Fahrenheit", c_to_f),
+    Function::fixed("f_to_c", "x", 1, "degrees Fahrenheit to Celsius", f_to_c),
+    Function::fixed("c_to_k", "x", 1, "degrees Celsius to kelvins", c_to_k),
+    Function::fixed("k_to_c", "x", 1, "kelvins to degrees Celsius", k_to_c),
+    Function::fixed("f_to_k", "x", 1, "degrees Fahrenheit to kelvins", f_to_k),
+    Function::fixed("k_to_f", "x", 1, "kelvins to degrees Fahrenheit", k_to_f),
+];
+
+/// Built-in functions whose arguments are not all evaluated, which the parser
+/// turns into dedicated expressions, as `(name, params, description)`
+pub(crate) const SPECIAL_FORMS: &[(&str, &str, &str)] = &[
+    (
+        "if",
+        "cond, a, b",
+        "a if cond is non-zero, otherwise b; only one is evaluated",
+    ),
+    (
+        "sum",
+        "i, lo, hi, body",
+        "sum of body for each integer i from lo to hi",
+    ),
+    (
+        "prod",
+        "i, lo, hi, body",
+        "product of body for each integer i from lo to hi",
+    ),
+    (
+        "deriv",
+        "body, x, at",
+        "numeric derivative of body with respect to x at x = at",
+    ),
+    (
+        "integrate",
+        "body, x, a, b",
+        "numeric integral of body with respect to x from a to b",
+    ),
+    (
+        "solve",
+        "lhs = rhs, x[, lo, hi]",
+        "value of x at which lhs equals rhs, searched for between lo and hi",
+    ),
+];
+
+/// Returns true if `name` is a built-in function or special form
+pub(crate) fn is_builtin(name: &str) -> bool {
+    lookup(name).is_some() || SPECIAL_FORMS.iter().any(|(form, _, _)| *form == name)
+}
+
+/// Looks up a built-in function by name
+pub(crate) fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|function| function.name == name)
+}
@@ -0,0 +1,255 @@
+//! Exact evaluation over 128-bit integers
+
+use std::fmt;
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions;
+use crate::options::{EvalOptions, IntegerDivision};
+use crate::parser::{self, BinaryOp, Expr, UnaryOp};
+use crate::words;
+
+/// The result of [`evaluate_integer`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntegerResult {
+    /// An exact integer result
+    Integer(i128),
+    /// A result that needed floating point, such as one involving `sqrt`
+    Float(f64),
+}
+
+/// Formats an integer with all of its digits, and a float as usual
+impl fmt::Display for IntegerResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegerResult::Integer(n) => write!(f, "{}", n),
+            IntegerResult::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Evaluates a mathematical expression with exact integer arithmetic
+///
+/// Integer literals, `+`, `-`, `*`, `//`, `%`, `^` with a non-negative
+/// exponent, `!`, comparisons and the bitwise operators work on 128-bit
+/// integers, so results are exact up to about 1.7e38. A result that doesn't fit
+/// is an error rather than a rounded value. `/` gives an integer when the
+/// division is exact, and otherwise follows [`EvalOptions::integer_division`].
+/// Integer literals are read exactly up to 2^53, like in [`evaluate`](crate::evaluate).
+/// Anything else, such as a decimal literal or a function call, falls back to
+/// floating point for the rest of the expression, and the result is then
+/// [`IntegerResult::Float`].
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, IntegerResult, Span, evaluate_integer};
+///
+/// assert_eq!(
+///     evaluate_integer("999999999999*999999999999"),
+///     Ok(IntegerResult::Integer(999999999998000000000001))
+/// );
+/// assert_eq!(
+///     evaluate_integer("2^100").unwrap().to_string(),
+///     "1267650600228229401496703205376"
+/// );
+/// assert_eq!(evaluate_integer("30!/29!"), Ok(IntegerResult::Integer(30)));
+/// assert_eq!(evaluate_integer("-7 // 2 + 2^64 % 10"), Ok(IntegerResult::Integer(2)));
+/// assert_eq!(evaluate_integer("1 << 100 > 2^99"), Ok(IntegerResult::Integer(1)));
+/// assert_eq!(evaluate_integer("7/2"), Ok(IntegerResult::Float(3.5)));
+/// assert_eq!(evaluate_integer("sqrt(16) + 1"), Ok(IntegerResult::Float(5.0)));
+/// assert!(evaluate_integer("2^200").unwrap_err().to_string().contains("integer overflow"));
+/// assert_eq!(
+///     evaluate_integer("5 % 0"),
+///     Err(CalcError::DivisionByZero { span: Some(Span { start: 2, end: 3 }) })
+/// );
+/// ```
+pub fn evaluate_integer(input: &str) -> Result<IntegerResult, CalcError> {
+    evaluate_integer_with_options(input, &EvalOptions::default())
+}
+
+/// Evaluates with exact integer arithmetic like [`evaluate_integer`], with
+/// custom settings
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{EvalOptions, IntegerDivision, IntegerResult, evaluate_integer_with_options};
+///
+/// let strict = EvalOptions::new().integer_division(IntegerDivision::RequireExact);
+/// assert_eq!(evaluate_integer_with_options("12/4", &strict), Ok(IntegerResult::Integer(3)));
+/// assert_eq!(
+///     evaluate_integer_with_options("7/2", &strict).unwrap_err().to_string(),
+///     "7/2 is not an integer"
+/// );
+/// ```
+pub fn evaluate_integer_with_options(
+    input: &str,
+    options: &EvalOptions,
+) -> Result<IntegerResult, CalcError> {
+    let (input, origins) = words::rewrite_with_origins(input, options, |_| false)?;
+    let expr = parser::parse(&input, options).map_err(|e| origins.error(e))?;
+    eval_integer(&expr, options).map_err(|e| origins.error(e))
+}
+
+fn overflow() -> CalcError {
+    CalcError::overflow("integer overflow, the result needs more than 128 bits")
+}
+
+impl IntegerResult {
+    fn to_f64(self) -> f64 {
+        match self {
+            IntegerResult::Integer(n) => n as f64,
+            IntegerResult::Float(value) => value,
+        }
+    }
+}
+
+fn eval_integer(expr: &Expr, options: &EvalOptions) -> Result<IntegerResult, CalcError> {
+    use IntegerResult::{Float, Integer};
+
+    let eval = |expr| eval_integer(expr, options);
+    match expr {
+        // Literals that are whole numbers are integers, others are floats
+        Expr::Number { value, .. }
+            if value.fract() == 0.0 && value.abs() <= functions::MAX_EXACT_INTEGER =>
+        {
+            Ok(Integer(*value as i128))
+        }
+        Expr::Number { value, .. } => Ok(Float(*value)),
+        Expr::Var { name, span } => {
+            crate::constant(name)
+                .map(Float)
+                .ok_or_else(|| CalcError::UnknownIdentifier {
+                    name: name.clone(),
+                    span: *span,
+                })
+        }
+        Expr::Unary { op, operand, span } => match (op, eval(operand)?) {
+            (UnaryOp::Neg, Integer(n)) => n.checked_neg().map(Integer).ok_or_else(overflow),
+            (UnaryOp::Pos, Integer(n)) => Ok(Integer(n)),
+            (UnaryOp::BitNot, Integer(n)) => Ok(Integer(!n)),
+            (UnaryOp::Factorial, Integer(n)) if n >= 0 => (2..=n)
+                .try_fold(1i128, |acc, i| acc.checked_mul(i))
+                .map(Integer)
+                .ok_or_else(overflow),
+            (op, value) => eval::unary(*op, value.to_f64(), options).map(Float),
+        }
+        .map_err(|e| e.at(*span)),
+        Expr::Binary { op, lhs, rhs, span } => {
+            let (lhs, rhs) = (eval(lhs)?, eval(rhs)?);
+            let (Integer(a), Integer(b)) = (lhs, rhs) else {
+                return eval::binary(*op, lhs.to_f64(), rhs.to_f64(), options)
+                    .map(Float)
+                    .map_err(|e| e.at(*span));
+            };
+            integer_binary(*op, a, b, options).map_err(|e| e.at(*span))
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            if eval(condition)?.to_f64() != 0.0 {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+        // Sums and the like are computed in floating point, which binds variables
+        Expr::Binding { .. } => {
+            eval::eval(expr, &Context::with_options(options.clone())).map(Float)
+        }
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "lists are not supported in integer mode",
+            *span,
+        )),
+        Expr::Call { name, args, span } => {
+            let function = functions::lookup(name).ok_or_else(|| CalcError::UnknownFunction {
+                name: name.clone(),
+                span: *span,
+            })?;
+            function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+            let values = args
+                .iter()
+                .map(|arg| eval(arg).map(IntegerResult::to_f64))
+                .collect::<Result<Vec<_>, _>>()?;
+            (function.call)(&values, options)
+                .map(Float)
+                .map_err(|e| e.at(*span))
+        }
+    }
+}
+
+fn integer_binary(
+    op: BinaryOp,
+    a: i128,
+    b: i128,
+    options: &EvalOptions,
+) -> Result<IntegerResult, CalcError> {
+    use IntegerResult::{Float, Integer};
+
+    let truth = |holds: bool| Ok(Integer(holds as i128));
+    if b == 0 && matches!(op, BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem) {
+        return Err(CalcError::division_by_zero());
+    }
+    let exact = match op {
+        BinaryOp::Add => a.checked_add(b),
+        BinaryOp::Sub => a.checked_sub(b),
+        BinaryOp::Mul => a.checked_mul(b),
+        BinaryOp::Div if a % b == 0 => a.checked_div(b),
+        BinaryOp::Div => {
+            return match options.integer_division {
+                IntegerDivision::Promote => Ok(Float(a as f64 / b as f64)),
+                IntegerDivision::RequireExact => {
+                    Err(CalcError::other(format!("{}/{} is not an integer", a, b)))
+                }
+            };
+        }
+        // Floor division rounds towards negative infinity, like in float mode
+        BinaryOp::FloorDiv => {
+            let quotient = a.checked_div(b).ok_or_else(overflow)?;
+            Some(if a % b != 0 && (a < 0) != (b < 0) {
+                quotient - 1
+            } else {
+                quotient
+            })
+        }
+        BinaryOp::Rem => a.checked_rem(b).and_then(|r| {
+            let k = options.remainder_mode.correction(r.cmp(&0), b < 0);
+            r.checked_add(b.checked_mul(k.into())?)
+        }),
+        BinaryOp::Pow if b >= 0 => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+        BinaryOp::Pow => return Ok(Float((a as f64).powf(b as f64))),
+        BinaryOp::Convert | BinaryOp::PlusMinus => {
+            return eval::binary(op, a as f64, b as f64, options).map(Float);
+        }
+        BinaryOp::Less => return truth(a < b),
+        BinaryOp::LessEqual => return truth(a <= b),
+        BinaryOp::Greater => return truth(a > b),
+        BinaryOp::GreaterEqual => return truth(a >= b),
+        BinaryOp::Equal => return truth(a == b),
+        BinaryOp::NotEqual => return truth(a != b),
+        BinaryOp::BitAnd => Some(a & b),
+        BinaryOp::BitOr => Some(a | b),
+        BinaryOp::BitXor => Some(a ^ b),
+        BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+            if !(0..128).contains(&b) {
+                return Err(CalcError::other(format!(
+                    "shift amount must be between 0 and 127, got {}",
+                    b
+                )));
+            }
+            if op == BinaryOp::ShiftLeft {
+                a.checked_mul(
+                    1i128
+                        .checked_shl(b as u32)
+                        .filter(|n| *n > 0)
+                        .ok_or_else(overflow)?,
+                )
+            } else {
+                Some(a >> b)
+            }
+        }
+    };
+    exact.map(Integer).ok_or_else(overflow)
+}
@@ -0,0 +1,446 @@
+//! Interval evaluation, which bounds the rounding error of floating point
+
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+use std::fmt;
+
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions::{self, MAX_EXACT_INTEGER};
+use crate::options::EvalOptions;
+use crate::parser::{self, BinaryOp, Expr, UnaryOp};
+use crate::words;
+
+/// A closed range of numbers `lo..=hi` that contains the exact result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    /// The interval holding only `value`, which is known to be exact
+    pub fn point(value: f64) -> Self {
+        Interval {
+            lo: value,
+            hi: value,
+        }
+    }
+
+    /// `hi - lo`, rounded up
+    pub fn width(&self) -> f64 {
+        (self.hi - self.lo).next_up()
+    }
+
+    /// Returns true if `value` lies within the bounds
+    pub fn contains(&self, value: f64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+
+    /// The interval around a rounded `value`, one ulp either side of it
+    /// unless it is an integer and so exact
+    fn around(value: f64) -> Self {
+        let point = Interval::point(value);
+        if point.is_integer() {
+            point
+        } else {
+            widen(value, value)
+        }
+    }
+
+    fn is_point(&self) -> bool {
+        self.lo == self.hi
+    }
+
+    /// Returns true for a single integer small enough that every integer
+    /// close to it is an `f64` too, so that it can't be the result of rounding
+    fn is_integer(&self) -> bool {
+        self.is_point() && self.lo.fract() == 0.0 && self.lo.abs() < MAX_EXACT_INTEGER
+    }
+
+    /// Applies `f` to two integers, if the result is an integer that
+    /// doesn't need rounding
+    fn integer_arithmetic(self, other: Interval, f: impl Fn(f64, f64) -> f64) -> Option<Self> {
+        let result = Interval::point(f(self.lo, other.lo));
+        (self.is_integer() && other.is_integer() && result.is_integer()).then_some(result)
+    }
+
+    fn neg(self) -> Self {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+
+    /// The smallest interval holding every `f(a, b)` with `a` and `b` at the
+    /// bounds, which is enough when `f` is monotonic in each argument
+    fn corners(self, other: Interval, f: impl Fn(f64, f64) -> f64) -> Self {
+        let values = [
+            f(self.lo, other.lo),
+            f(self.lo, other.hi),
+            f(self.hi, other.lo),
+            f(self.hi, other.hi),
+        ];
+        let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        widen(lo, hi)
+    }
+
+    fn div(self, other: Interval) -> Result<Self, CalcError> {
+        if other.contains(0.0) {
+            return Err(if other.is_point() {
+                CalcError::division_by_zero()
+            } else {
+                CalcError::other(format!(
+                    "division by an interval containing zero, {}",
+                    other
+                ))
+            });
+        }
+        Ok(self.corners(other, |a, b| a / b))
+    }
+
+    /// Raises to a whole power, keeping track of the sign of the base
+    fn powi(self, n: i32) -> Result<Self, CalcError> {
+        if n < 0 {
+            return Interval::point(1.0).div(self.powi(-n)?);
+        }
+        if let Some(power) = self.integer_arithmetic(Interval::point(0.0), |x, _| x.powi(n)) {
+            return Ok(power);
+        }
+        let (lo, hi) = (self.lo.powi(n), self.hi.powi(n));
+        Ok(if n % 2 == 1 || self.lo >= 0.0 {
+            widen(lo, hi)
+        } else if self.hi <= 0.0 {
+            widen(hi, lo)
+        } else {
+            // An even power of an interval around zero is smallest at zero
+            widen(0.0, lo.max(hi))
+        })
+    }
+}
+
+/// Formats as `[lo, hi]`
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+/// Moves both bounds outwards by one ulp, to cover the rounding error of the
+/// operation that produced them
+fn widen(lo: f64, hi: f64) -> Interval {
+    Interval {
+        lo: lo.next_down(),
+        hi: hi.next_up(),
+    }
+}
+
+/// Evaluates a mathematical expression over intervals that bracket the exact
+/// result
+///
+/// Every literal, constant and operation widens the bounds outward by one ulp
+/// of its rounding error, so the result contains the value that exact
+/// arithmetic would give. Integers smaller than 2^53 are exact and aren't
+/// widened. Division by an interval that contains zero is an error.
+///
+/// The monotonic functions, such as `sqrt`, `ln` and `atan`, as well as `abs`,
+/// `cosh`, `min`, `max`, `sin`, `cos` and `tan`, take intervals as arguments.
+/// Other functions and the integer operators such as `!` and `&` only accept
+/// exact arguments. A comparison or condition whose outcome depends on where
+/// in the intervals the exact values lie is an error.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Interval, Span, evaluate_interval};
+///
+/// let sum = evaluate_interval("0.1+0.2").unwrap();
+/// assert!(sum.contains(0.30000000000000004) && sum.contains(0.3));
+/// assert!(sum.width() < 1e-15);
+/// assert_eq!(evaluate_interval("2+3*4"), Ok(Interval::point(14.0)));
+/// assert_eq!(evaluate_interval("5! - 2^10 // 3"), Ok(Interval::point(-221.0)));
+///
+/// let root = evaluate_interval("sqrt(2)").unwrap();
+/// assert!(root.lo < std::f64::consts::SQRT_2 && std::f64::consts::SQRT_2 < root.hi);
+/// let wave = evaluate_interval("sin(pi/2)").unwrap();
+/// assert!(wave.contains(1.0) && wave.hi == 1.0);
+/// let square = evaluate_interval("(0.1 - 0.2)^2").unwrap();
+/// assert!(square.lo > 0.0 && square.contains(0.01));
+///
+/// assert!(evaluate_interval("1/(0.1+0.2-0.3)").unwrap_err().to_string().contains("interval containing zero"));
+/// assert_eq!(
+///     evaluate_interval("1/(2-2)"),
+///     Err(CalcError::DivisionByZero { span: Some(Span { start: 1, end: 2 }) })
+/// );
+/// assert_eq!(evaluate_interval("0.1 < 0.2"), Ok(Interval::point(1.0)));
+/// assert!(evaluate_interval("0.1+0.2 == 0.3").is_err());
+/// assert!(evaluate_interval("sqrt(-1)").is_err());
+/// ```
+pub fn evaluate_interval(input: &str) -> Result<Interval, CalcError> {
+    evaluate_interval_with_options(input, &EvalOptions::default())
+}
+
+/// Evaluates with interval arithmetic like [`evaluate_interval`], with custom
+/// settings
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{AngleMode, EvalOptions, Separator, evaluate_interval_with_options};
+///
+/// let options = EvalOptions::new()
+///     .angle_mode(AngleMode::Degrees)
+///     .decimal_separator(Separator::Comma);
+/// let wave = evaluate_interval_with_options("sin(90) + 0,5", &options).unwrap();
+/// assert!(wave.contains(1.5) && wave.width() < 1e-15);
+/// ```
+pub fn evaluate_interval_with_options(
+    input: &str,
+    options: &EvalOptions,
+) -> Result<Interval, CalcError> {
+    let (input, origins) = words::rewrite_with_origins(input, options, |_| false)?;
+    let expr = parser::parse(&input, options).map_err(|e| origins.error(e))?;
+    eval_interval(&expr, options).map_err(|e| origins.error(e))
+}
+
+fn eval_interval(expr: &Expr, options: &EvalOptions) -> Result<Interval, CalcError> {
+    let eval = |expr| eval_interval(expr, options);
+    match expr {
+        Expr::Number { value, .. } => Ok(Interval::around(*value)),
+        Expr::Var { name, span } => crate::constant(name)
+            .map(|value| widen(value, value))
+            .ok_or_else(|| CalcError::UnknownIdentifier {
+                name: name.clone(),
+                span: *span,
+            }),
+        Expr::Unary { op, operand, span } => {
+            let value = eval(operand)?;
+            let result = match op {
+                UnaryOp::Neg => Ok(value.neg()),
+                UnaryOp::Pos => Ok(value),
+                UnaryOp::Percent => Ok(widen(value.lo / 100.0, value.hi / 100.0)),
+                _ => {
+                    let value = exact_operand(value)?;
+                    eval::unary(*op, value, options).map(Interval::around)
+                }
+            };
+            result.map_err(|e| e.at(*span))
+        }
+        Expr::Binary { op, lhs, rhs, span } => {
+            let (a, b) = (eval(lhs)?, eval(rhs)?);
+            let result = match op {
+                BinaryOp::Add => Ok(a
+                    .integer_arithmetic(b, |x, y| x + y)
+                    .unwrap_or_else(|| widen(a.lo + b.lo, a.hi + b.hi))),
+                BinaryOp::Sub => Ok(a
+                    .integer_arithmetic(b, |x, y| x - y)
+                    .unwrap_or_else(|| widen(a.lo - b.hi, a.hi - b.lo))),
+                BinaryOp::Mul => Ok(a
+                    .integer_arithmetic(b, |x, y| x * y)
+                    .unwrap_or_else(|| a.corners(b, |x, y| x * y))),
+                BinaryOp::Div => a.div(b),
+                BinaryOp::FloorDiv if a.is_integer() && b.is_integer() => {
+                    eval::binary(*op, a.lo, b.lo, options).map(Interval::point)
+                }
+                BinaryOp::FloorDiv => a.div(b).map(|quotient| Interval {
+                    lo: quotient.lo.floor(),
+                    hi: quotient.hi.floor(),
+                }),
+                BinaryOp::Pow => power(a, b),
+                BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+                | BinaryOp::Equal
+                | BinaryOp::NotEqual => {
+                    compare(*op, a, b).map(|holds| Interval::point(holds as u8 as f64))
+                }
+                _ => {
+                    let (x, y) = (exact_operand(a)?, exact_operand(b)?);
+                    eval::binary(*op, x, y, options).map(Interval::around)
+                }
+            };
+            result.map_err(|e| e.at(*span))
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let condition = eval(condition)?;
+            if condition == Interval::point(0.0) {
+                eval(otherwise)
+            } else if !condition.contains(0.0) {
+                eval(then)
+            } else {
+                Err(CalcError::other(format!(
+                    "condition can't be decided, the interval {} contains zero",
+                    condition
+                )))
+            }
+        }
+        Expr::Binding { form, .. } => Err(CalcError::other(format!(
+            "{} is not supported in interval mode",
+            form.name()
+        ))),
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "lists are not supported in interval mode",
+            *span,
+        )),
+        Expr::Call { name, args, span } => {
+            let function = functions::lookup(name).ok_or_else(|| CalcError::UnknownFunction {
+                name: name.clone(),
+                span: *span,
+            })?;
+            function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+            let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            let call = |x: f64| (function.call)(&[x], options).map_err(|e| e.at(*span));
+            match (name.as_str(), &values[..]) {
+                (
+                    "sqrt" | "cbrt" | "asin" | "atan" | "sinh" | "tanh" | "asinh" | "acosh"
+                    | "atanh" | "exp" | "expm1" | "ln" | "ln1p" | "log" | "log10" | "log2",
+                    [x],
+                ) => Ok(widen(call(x.lo)?, call(x.hi)?)),
+                // Rounding is monotonic and exact, so needs no widening
+                ("floor" | "ceil" | "round" | "trunc", [x]) => Ok(Interval {
+                    lo: call(x.lo)?,
+                    hi: call(x.hi)?,
+                }),
+                ("acos", [x]) => Ok(widen(call(x.hi)?, call(x.lo)?)),
+                ("abs" | "cosh", [x]) if x.contains(0.0) => {
+                    Ok(widen(call(0.0)?, call(x.lo)?.max(call(x.hi)?)))
+                }
+                ("abs" | "cosh", [x]) => {
+                    let (lo, hi) = (call(x.lo)?, call(x.hi)?);
+                    Ok(widen(lo.min(hi), lo.max(hi)))
+                }
+                ("min", _) => Ok(Interval {
+                    lo: values.iter().map(|x| x.lo).fold(f64::INFINITY, f64::min),
+                    hi: values.iter().map(|x| x.hi).fold(f64::INFINITY, f64::min),
+                }),
+                ("max", _) => Ok(Interval {
+                    lo: values
+                        .iter()
+                        .map(|x| x.lo)
+                        .fold(f64::NEG_INFINITY, f64::max),
+                    hi: values
+                        .iter()
+                        .map(|x| x.hi)
+                        .fold(f64::NEG_INFINITY, f64::max),
+                }),
+                // cos peaks where sin does, a quarter turn earlier
+                ("sin", [x]) => Ok(periodic(radians(*x, options), f64::sin, FRAC_PI_2)),
+                ("cos", [x]) => Ok(periodic(radians(*x, options), f64::cos, 0.0)),
+                ("tan", [x]) => {
+                    let x = radians(*x, options);
+                    // The first pole at or above the lower bound
+                    let pole = FRAC_PI_2 + ((x.lo - FRAC_PI_2) / PI).ceil() * PI;
+                    if pole <= x.hi {
+                        return Err(CalcError::other(format!(
+                            "tan is unbounded on the interval {}",
+                            x
+                        )));
+                    }
+                    Ok(widen(x.lo.tan(), x.hi.tan()))
+                }
+                _ => {
+                    let exact = values.into_iter().map(exact_operand);
+                    let args = exact.collect::<Result<Vec<_>, _>>()?;
+                    (function.call)(&args, options)
+                        .map(Interval::around)
+                        .map_err(|e| e.at(*span))
+                }
+            }
+        }
+    }
+}
+
+/// Evaluates a comparison to 1 when it holds for every pair of values in the
+/// intervals, and 0 when it holds for none
+fn compare(op: BinaryOp, a: Interval, b: Interval) -> Result<bool, CalcError> {
+    let (certain, impossible) = match op {
+        BinaryOp::Less => (a.hi < b.lo, a.lo >= b.hi),
+        BinaryOp::LessEqual => (a.hi <= b.lo, a.lo > b.hi),
+        BinaryOp::Greater => (a.lo > b.hi, a.hi <= b.lo),
+        BinaryOp::GreaterEqual => (a.lo >= b.hi, a.hi < b.lo),
+        BinaryOp::Equal | BinaryOp::NotEqual => {
+            let equal = a.is_point() && a == b;
+            let apart = a.hi < b.lo || b.hi < a.lo;
+            if op == BinaryOp::Equal {
+                (equal, apart)
+            } else {
+                (apart, equal)
+            }
+        }
+        _ => unreachable!("not a comparison: {:?}", op),
+    };
+    if certain || impossible {
+        Ok(certain)
+    } else {
+        Err(CalcError::other(format!(
+            "comparison can't be decided, the intervals {} and {} overlap",
+            a, b
+        )))
+    }
+}
+
+/// Returns the value of an operand that has to be known exactly, as for
+/// operators that only work on integers
+fn exact_operand(value: Interval) -> Result<f64, CalcError> {
+    if value.is_point() {
+        Ok(value.lo)
+    } else {
+        Err(CalcError::other(format!(
+            "this operation needs an exact argument, got the interval {}",
+            value
+        )))
+    }
+}
+
+/// Raises `base` to `exponent`, which is exact and whole or has a base that
+/// isn't negative
+fn power(base: Interval, exponent: Interval) -> Result<Interval, CalcError> {
+    if exponent.is_point() && exponent.lo.fract() == 0.0 && exponent.lo.abs() <= i32::MAX as f64 {
+        return base.powi(exponent.lo as i32);
+    }
+    if base.lo < 0.0 {
+        return Err(CalcError::other(format!(
+            "a negative base with a fractional exponent isn't supported for the interval {}",
+            base
+        )));
+    }
+    Ok(base.corners(exponent, f64::powf))
+}
+
+/// Converts an angle interval in the configured unit to radians
+fn radians(angle: Interval, options: &EvalOptions) -> Interval {
+    let (lo, hi) = (
+        options.angle_to_radians(angle.lo),
+        options.angle_to_radians(angle.hi),
+    );
+    if lo == angle.lo && hi == angle.hi {
+        angle
+    } else {
+        widen(lo, hi)
+    }
+}
+
+/// Bounds `f`, a sine wave with a maximum at `peak` and a minimum half a turn
+/// later, over `x`
+fn periodic(x: Interval, f: fn(f64) -> f64, peak: f64) -> Interval {
+    if x.hi - x.lo >= TAU {
+        return Interval { lo: -1.0, hi: 1.0 };
+    }
+    let (a, b) = (f(x.lo), f(x.hi));
+    let mut bounds = widen(a.min(b), a.max(b));
+    // Whether an extremum `offset` after a turn lies within `x`
+    let reaches = |offset: f64| offset + ((x.lo - offset) / TAU).ceil() * TAU <= x.hi;
+    if reaches(peak) {
+        bounds.hi = 1.0;
+    }
+    if reaches(peak + PI) {
+        bounds.lo = -1.0;
+    }
+    Interval {
+        lo: bounds.lo.max(-1.0),
+        hi: bounds.hi.min(1.0),
+    }
+}
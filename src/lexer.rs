@@ -0,0 +1,1399 @@
+use crate::error::CalcError;
+use crate::options::{AngleMode, EvalOptions, Separator};
+use crate::unicode;
+
+/// A byte range into the original input string, from `start` up to but not
+/// including `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The different kinds of tokens understood by the calculator
+///
+/// Operators are named after their symbol rather than their meaning, since
+/// that can depend on their surroundings: a `Minus` may be a subtraction or a
+/// sign, and a `Percent` a remainder or a percent sign. Words such as `and`
+/// and `of`, and the other spellings of a symbol, such as `×` and `**`, have
+/// the kind of the operator they stand for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    Plus,
+    Minus,
+    PlusMinus,
+    Star,
+    Slash,
+    DoubleSlash,
+    Percent,
+    Caret,
+    Ampersand,
+    Xor,
+    Of,
+    And,
+    Or,
+    Not,
+    ShiftLeft,
+    ShiftRight,
+    Tilde,
+    /// `√` or `∛`, by the name of the function it stands for
+    Radical(&'static str),
+    Bang,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Pipe,
+    Comma,
+    Semicolon,
+    Question,
+    Colon,
+    Equals,
+    EqualEqual,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Identifier(String),
+}
+
+impl TokenKind {
+    /// Returns the canonical symbol for binary operator tokens
+    pub fn operator_symbol(&self) -> Option<&'static str> {
+        match self {
+            TokenKind::Plus => Some("+"),
+            TokenKind::Minus => Some("-"),
+            TokenKind::PlusMinus => Some("±"),
+            TokenKind::Star => Some("*"),
+            TokenKind::Slash => Some("/"),
+            TokenKind::DoubleSlash => Some("//"),
+            TokenKind::Percent => Some("%"),
+            TokenKind::Caret => Some("^"),
+            TokenKind::Ampersand => Some("&"),
+            TokenKind::Pipe => Some("|"),
+            TokenKind::Xor => Some("xor"),
+            TokenKind::Of => Some("of"),
+            TokenKind::And => Some("and"),
+            TokenKind::Or => Some("or"),
+            TokenKind::ShiftLeft => Some("<<"),
+            TokenKind::ShiftRight => Some(">>"),
+            TokenKind::EqualEqual => Some("=="),
+            TokenKind::NotEqual => Some("!="),
+            TokenKind::Less => Some("<"),
+            TokenKind::LessEqual => Some("<="),
+            TokenKind::Greater => Some(">"),
+            TokenKind::GreaterEqual => Some(">="),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this token always ends an operand, in which case a
+    /// following `-` is a binary operator rather than a sign
+    ///
+    /// `%` and `|` depend on their surroundings and are not included.
+    pub(crate) fn ends_operand(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Number(_)
+                | TokenKind::Identifier(_)
+                | TokenKind::RParen
+                | TokenKind::RBracket
+                | TokenKind::Bang
+        )
+    }
+}
+
+/// Decides whether a `%` token is a percent sign rather than the modulo operator
+///
+/// `%` followed by the start of an operand (a number, a name, `(` or a radical
+/// sign) is modulo, while `%`
+/// followed by anything else, such as an operator, `)` or the end of the input,
+/// is a percent sign. So `10%3` is a remainder but `50%*200` is `0.5*200`.
+pub(crate) fn is_percent_sign(next: Option<&TokenKind>) -> bool {
+    !matches!(
+        next,
+        Some(
+            TokenKind::Number(_)
+                | TokenKind::Identifier(_)
+                | TokenKind::LParen
+                | TokenKind::LBracket
+                | TokenKind::Radical(_)
+        )
+    )
+}
+
+/// A token of the input and where it was written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// A number written in the input, with any sign written before it, as
+/// [`extract_number_literals`](crate::extract_number_literals) finds it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberLiteral<'a> {
+    pub value: f64,
+    /// The number as it was written, from its sign if it has one
+    pub text: &'a str,
+    pub span: Span,
+}
+
+/// SI prefixes that may follow a number, with their powers of ten. Micro may
+/// be written `u`, or as the micro sign or the Greek letter mu.
+const SI_PREFIXES: &[(char, i32)] = &[
+    ('T', 12),
+    ('G', 9),
+    ('M', 6),
+    ('k', 3),
+    ('m', -3),
+    ('u', -6),
+    ('µ', -6),
+    ('μ', -6),
+    ('n', -9),
+    ('p', -12),
+];
+
+/// Currency symbols that may come directly before a number, as in `$1,299.99`
+const CURRENCIES: [char; 4] = ['$', '€', '£', '¥'];
+
+/// Splits an input string into tokens, one at a time
+///
+/// The lexer stops after the first error, so iterating with `map_while(Result::ok)`
+/// yields every token that precedes a problem in the input.
+pub(crate) struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+    failed: bool,
+    separator: Separator,
+    si_suffixes: bool,
+    currency_symbols: bool,
+    /// The unit that angles such as `45°` are converted to
+    angle_mode: AngleMode,
+    /// The currency symbol written before the numbers so far, which the
+    /// others must share
+    currency: Option<char>,
+    /// Whether the last token was a number, which another number can't follow
+    after_number: bool,
+    /// Whether the last token ended an operand, after which `+-` is `±`
+    after_operand: bool,
+    /// Parentheses and brackets open before the current token
+    depth: usize,
+    /// The depth of each `?` that hasn't met its `:` yet. At that depth, a
+    /// colon after a number belongs to the conditional rather than a duration.
+    open_conditionals: Vec<usize>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer::with_options(input, &EvalOptions::default())
+    }
+
+    /// Creates a lexer for numbers written as `options` say, such as with a
+    /// decimal comma
+    pub fn with_options(input: &'a str, options: &EvalOptions) -> Self {
+        Lexer {
+            input,
+            pos: 0,
+            failed: false,
+            separator: options.decimal_separator,
+            si_suffixes: options.si_suffixes,
+            currency_symbols: options.currency_symbols,
+            angle_mode: options.angle_mode,
+            currency: None,
+            after_number: false,
+            after_operand: false,
+            depth: 0,
+            open_conditionals: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    /// Returns true if the thousands separator, `,` unless the decimal point is
+    /// a comma, is at the current position and separates thousands
+    ///
+    /// A grouping comma must follow a group of one to three digits at the start
+    /// of the number (or exactly three after a previous comma) and be followed
+    /// by exactly three digits, as in `1,234,567`. Any other comma is left for
+    /// the parser, which treats it as a separator or reports it.
+    fn at_grouping_separator(&self, group_len: usize, first_group: bool) -> bool {
+        let (_, grouping) = self.separator.marks();
+        let rest = &self.input.as_bytes()[self.pos..];
+        let group_ok = if first_group {
+            (1..=3).contains(&group_len)
+        } else {
+            group_len == 3
+        };
+        group_ok
+            && rest.len() >= 4
+            && rest[0] == grouping as u8
+            && rest[1..4].iter().all(u8::is_ascii_digit)
+            && !rest
+                .get(4)
+                .is_some_and(|b| b.is_ascii_digit() || *b == b'_')
+    }
+
+    /// Returns the length of the base of a literal such as `16#FF` at the
+    /// current position: digits directly followed by `#` and a digit or letter,
+    /// which tell it apart from a comment
+    fn base_prefix(&self) -> Option<usize> {
+        let rest = &self.input[self.pos..];
+        let len = rest.find(|c: char| !c.is_ascii_digit())?;
+        let after = rest[len..].strip_prefix('#')?;
+        after
+            .starts_with(|c: char| c.is_ascii_alphanumeric())
+            .then_some(len)
+    }
+
+    /// Lexes a literal in any base from 2 to 36 written `base#digits`, as in
+    /// `16#FF` or `2#0.1`, starting at the base, which is `base_len` digits
+    /// long. Letters stand for the digits from 10 up, in either case.
+    fn lex_base_literal(&mut self, base_len: usize) -> Result<Token, CalcError> {
+        let start = self.pos;
+        let base: u32 = self.input[start..start + base_len]
+            .parse()
+            .unwrap_or(u32::MAX);
+        if !(2..=36).contains(&base) {
+            let span = Span {
+                start,
+                end: start + base_len,
+            };
+            return Err(CalcError::syntax(
+                format!(
+                    "base {} must be from 2 to 36",
+                    &self.input[start..start + base_len]
+                ),
+                span,
+            ));
+        }
+        self.pos += base_len + 1;
+
+        let (point, _) = self.separator.marks();
+        let mut integer: u128 = 0;
+        let mut fraction = 0.0;
+        let mut scale = None;
+        while let Some(ch) = self.peek() {
+            if ch == point
+                && scale.is_none()
+                && self.input[self.pos + 1..].starts_with(|c: char| c.is_ascii_alphanumeric())
+            {
+                scale = Some(1.0);
+                self.pos += 1;
+                continue;
+            }
+            if ch == '_' {
+                self.pos += 1;
+                continue;
+            }
+            if !ch.is_ascii_alphanumeric() {
+                break;
+            }
+            let digit = ch
+                .to_digit(36)
+                .filter(|digit| *digit < base)
+                .ok_or_else(|| {
+                    CalcError::syntax(
+                        format!("digit '{}' is out of range for base {}", ch, base),
+                        Span {
+                            start: self.pos,
+                            end: self.pos + 1,
+                        },
+                    )
+                })?;
+            match &mut scale {
+                None => {
+                    integer = integer
+                        .checked_mul(base as u128)
+                        .and_then(|n| n.checked_add(digit as u128))
+                        .ok_or_else(|| {
+                            let span = Span { start, end: start };
+                            CalcError::syntax(format!("base {} literal is too large", base), span)
+                        })?;
+                }
+                Some(scale) => {
+                    *scale /= base as f64;
+                    fraction += digit as f64 * *scale;
+                }
+            }
+            self.pos += 1;
+        }
+
+        let widened = integer as f64;
+        if widened as u128 != integer {
+            return Err(CalcError::syntax(
+                format!(
+                    "base {} literal '{}' cannot be represented exactly",
+                    base,
+                    &self.input[start..self.pos]
+                ),
+                Span {
+                    start,
+                    end: self.pos,
+                },
+            ));
+        }
+        Ok(Token {
+            kind: TokenKind::Number(widened + fraction),
+            span: Span {
+                start,
+                end: self.pos,
+            },
+        })
+    }
+
+    /// Lexes an integer literal with a base prefix such as `0x`, starting at the prefix
+    fn lex_radix_literal(&mut self, radix: u32, name: &str) -> Result<Token, CalcError> {
+        let start = self.pos;
+        self.pos += 2;
+        while let Some(ch) = self.peek() {
+            if ch.is_ascii_alphanumeric() || ch == '_' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let text = &self.input[start..self.pos];
+        let span = Span {
+            start,
+            end: self.pos,
+        };
+        let digits = text[2..].replace('_', "");
+        let value = match u128::from_str_radix(&digits, radix) {
+            Ok(value) => value,
+            Err(e) if *e.kind() == std::num::IntErrorKind::PosOverflow => {
+                let message = format!("{} literal '{}' is too large", name, text);
+                return Err(CalcError::syntax(message, span));
+            }
+            Err(_) => {
+                let message = format!("invalid {} literal '{}'", name, text);
+                return Err(CalcError::syntax(message, span));
+            }
+        };
+
+        // Reject values that would silently lose precision when widened to f64
+        let widened = value as f64;
+        if widened as u128 != value {
+            let message = format!("{} literal '{}' cannot be represented exactly", name, text);
+            return Err(CalcError::syntax(message, span));
+        }
+
+        Ok(Token {
+            kind: TokenKind::Number(widened),
+            span: Span {
+                start,
+                end: self.pos,
+            },
+        })
+    }
+
+    fn lex_number(&mut self) -> Result<Token, CalcError> {
+        let start = self.pos;
+        // Digits since the start of the number or the last grouping comma;
+        // `None` once a `.` or `_` rules out comma grouping
+        let mut group_len = Some(0);
+        let mut first_group = true;
+        let (point, grouping) = self.separator.marks();
+        while let Some(ch) = self.peek() {
+            // A decimal comma must be followed by a digit, so that the comma
+            // of `max(1, 2)` still separates arguments
+            let decimal_comma =
+                ch == ',' && self.input[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit());
+            if ch.is_ascii_digit() {
+                group_len = group_len.map(|len| len + 1);
+            } else if (ch == point && (point == '.' || decimal_comma)) || ch == '_' {
+                group_len = None;
+            } else if ch == grouping
+                && group_len.is_some_and(|len| self.at_grouping_separator(len, first_group))
+            {
+                group_len = Some(0);
+                first_group = false;
+            } else {
+                break;
+            }
+            self.pos += ch.len_utf8();
+        }
+
+        // An exponent is only recognized when digits follow, so `2e3` is 2000
+        // while `2e` is `2*e`
+        let rest = &self.input.as_bytes()[self.pos..];
+        let mut exponent = false;
+        if matches!(rest.first(), Some(b'e' | b'E')) {
+            let digits_at = if matches!(rest.get(1), Some(b'+' | b'-')) {
+                2
+            } else {
+                1
+            };
+            if rest.get(digits_at).is_some_and(u8::is_ascii_digit) {
+                exponent = true;
+                self.pos += digits_at;
+                while self
+                    .input
+                    .as_bytes()
+                    .get(self.pos)
+                    .is_some_and(u8::is_ascii_digit)
+                {
+                    self.pos += 1;
+                }
+            }
+        }
+        // A point after the exponent, as in `1e5.3`, makes one malformed
+        // number rather than two numbers side by side
+        if exponent
+            && self.peek() == Some(point)
+            && (point == '.'
+                || self.input[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            while self
+                .peek()
+                .is_some_and(|c| c.is_ascii_digit() || c == point)
+            {
+                self.pos += 1;
+            }
+            return Err(CalcError::InvalidNumber {
+                text: self.input[start..self.pos].to_string(),
+                span: Span {
+                    start,
+                    end: self.pos,
+                },
+            });
+        }
+
+        let text = &self.input[start..self.pos];
+
+        // `_` may only separate two digits, as in `1_000_000`
+        let bytes = text.as_bytes();
+        for (i, &b) in bytes.iter().enumerate() {
+            if b == b'_' {
+                let digit_before = i > 0 && bytes[i - 1].is_ascii_digit();
+                let digit_after = bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+                if !digit_before || !digit_after {
+                    let span = Span {
+                        start: start + i,
+                        end: start + i + 1,
+                    };
+                    let message = format!("misplaced digit separator in '{}'", text);
+                    return Err(CalcError::syntax(message, span));
+                }
+            }
+        }
+
+        // An SI prefix directly after the digits scales them, as in `4.7k`,
+        // unless more letters follow, as in `3kg`
+        let mut digits = number_text(text, self.separator);
+        if self.si_suffixes
+            && !exponent
+            && let Some((prefix, power)) = si_prefix(&self.input[self.pos..])
+        {
+            self.pos += prefix.len_utf8();
+            digits = format!("{}e{}", digits, power);
+        }
+
+        let span = Span {
+            start,
+            end: self.pos,
+        };
+        match digits.parse::<f64>() {
+            Ok(n) => Ok(Token {
+                kind: TokenKind::Number(n),
+                span,
+            }),
+            Err(_) => Err(CalcError::InvalidNumber {
+                text: text.to_string(),
+                span: Span {
+                    start,
+                    end: start + text.len(),
+                },
+            }),
+        }
+    }
+
+    /// Continues a number followed by `°` as an angle such as `45°30'15"`,
+    /// whose value is in the unit of the angle mode, so that it means degrees
+    /// in either mode. Minutes and seconds are optional, and all but the last
+    /// part present must be whole.
+    fn lex_minutes_seconds(&mut self, degrees: Token) -> Result<Token, CalcError> {
+        let TokenKind::Number(mut value) = degrees.kind else {
+            return Ok(degrees);
+        };
+        if self.peek() != Some('°') {
+            return Ok(degrees);
+        }
+        self.pos += '°'.len_utf8();
+
+        let mut previous = ("degrees", value);
+        for (name, markers, per_degree) in [
+            ("minutes", ['\'', '′'], 60.0),
+            ("seconds", ['"', '″'], 3600.0),
+        ] {
+            let start = self.pos;
+            let Some(part) = self.angle_part(&markers) else {
+                break;
+            };
+            let span = Span {
+                start,
+                end: self.pos,
+            };
+            if previous.1.fract() != 0.0 {
+                let message = format!("{} must be whole when {} follow", previous.0, name);
+                return Err(CalcError::syntax(message, span));
+            }
+            if part >= 60.0 {
+                let message = format!("{} must be less than 60, got {}", name, part);
+                return Err(CalcError::syntax(message, span));
+            }
+            value += part / per_degree;
+            previous = (name, part);
+        }
+        if self.angle_mode == AngleMode::Radians {
+            value = value.to_radians();
+        }
+
+        Ok(Token {
+            kind: TokenKind::Number(value),
+            span: Span {
+                start: degrees.span.start,
+                end: self.pos,
+            },
+        })
+    }
+
+    /// Continues a whole number followed by `:` and two digits as a duration
+    /// such as `1:30` or `1:30:15`, whose value is in seconds. Inside a
+    /// conditional, the colon is the conditional's unless parentheses separate
+    /// them, so `x ? 1:30` is a choice between 1 and 30 and `x ? (1:30) : 0`
+    /// one between durations.
+    fn lex_duration(&mut self, hours: Token) -> Result<Token, CalcError> {
+        let TokenKind::Number(value) = hours.kind else {
+            return Ok(hours);
+        };
+        let text = &self.input[hours.span.start..hours.span.end];
+        if self.in_conditional() || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(hours);
+        }
+
+        let mut seconds = value * 3600.0;
+        for (name, per_unit) in [("minutes", 60.0), ("seconds", 1.0)] {
+            let start = self.pos + 1;
+            let rest = &self.input.as_bytes()[self.pos..];
+            let two_digits = rest.first() == Some(&b':')
+                && rest
+                    .get(1..3)
+                    .is_some_and(|d| d.iter().all(u8::is_ascii_digit))
+                && !rest.get(3).is_some_and(u8::is_ascii_digit);
+            if !two_digits {
+                break;
+            }
+            let part: f64 = self.input[start..start + 2]
+                .parse()
+                .expect("two ASCII digits");
+            if part >= 60.0 {
+                let message = format!("{} must be less than 60, got {}", name, part);
+                let span = Span {
+                    start,
+                    end: start + 2,
+                };
+                return Err(CalcError::syntax(message, span));
+            }
+            seconds += part * per_unit;
+            self.pos += 3;
+        }
+
+        if self.pos == hours.span.end {
+            return Ok(hours);
+        }
+        Ok(Token {
+            kind: TokenKind::Number(seconds),
+            span: Span {
+                start: hours.span.start,
+                end: self.pos,
+            },
+        })
+    }
+
+    /// Returns whether a `:` here would end the innermost `?`
+    fn in_conditional(&self) -> bool {
+        self.open_conditionals.last() == Some(&self.depth)
+    }
+
+    /// Consumes digits followed by one of `markers`, such as the `30'` of
+    /// `45°30'`, returning their value, or consumes nothing if they aren't there
+    fn angle_part(&mut self, markers: &[char]) -> Option<f64> {
+        let rest = &self.input[self.pos..];
+        let digits = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let marker = rest[digits..].chars().next()?;
+        if digits == 0 || !markers.contains(&marker) {
+            return None;
+        }
+        let value = rest[..digits].parse().ok()?;
+        self.pos += digits + marker.len_utf8();
+        Some(value)
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, CalcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.token()?;
+        // Two numbers with only whitespace between them are never a product,
+        // so `1 2` is more likely a missing operator than a typo in a number
+        let is_number = matches!(
+            token,
+            Ok(Token {
+                kind: TokenKind::Number(_),
+                ..
+            })
+        );
+        if is_number && self.after_number {
+            self.failed = true;
+            let span = token.expect("a number token").span;
+            let message = format!(
+                "missing an operator before '{}'",
+                &self.input[span.start..span.end]
+            );
+            return Some(Err(CalcError::syntax(message, span)));
+        }
+        self.after_number = is_number;
+        self.after_operand = token.as_ref().is_ok_and(|token| token.kind.ends_operand());
+        Some(token)
+    }
+}
+
+impl Lexer<'_> {
+    /// Lexes the next token, whatever came before it
+    fn token(&mut self) -> Option<Result<Token, CalcError>> {
+        if self.failed {
+            return None;
+        }
+
+        // Skip whitespace and comments between tokens. A comment runs from `#`
+        // to the end of the line, unless the `#` is part of a literal such as
+        // `16#FF`, which the number takes.
+        while let Some(ch) = self.peek() {
+            if ch.is_whitespace() {
+                self.pos += ch.len_utf8();
+            } else if ch == '#' {
+                let rest = &self.input[self.pos..];
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else {
+                break;
+            }
+        }
+
+        let ch = self.peek()?;
+        let start = self.pos;
+
+        // A currency symbol is left out of the number's span, so that the
+        // number reads as usual, and only remembered
+        if self.currency_symbols && CURRENCIES.contains(&ch) {
+            let next = self.input[start + ch.len_utf8()..].chars().next();
+            if next.is_some_and(|c| c.is_ascii_digit()) {
+                if let Some(currency) = self.currency
+                    && currency != ch
+                {
+                    self.failed = true;
+                    let message = format!("cannot mix currencies '{}' and '{}'", currency, ch);
+                    let span = Span {
+                        start,
+                        end: start + ch.len_utf8(),
+                    };
+                    return Some(Err(CalcError::syntax(message, span)));
+                }
+                self.currency = Some(ch);
+                self.pos += ch.len_utf8();
+                return self.token();
+            }
+        }
+
+        if ch.is_ascii_digit() || (ch == '.' && self.separator == Separator::Period) {
+            let rest = &self.input[start..];
+            let prefix = rest.get(..2).map(str::to_ascii_lowercase);
+            let result = match prefix.as_deref() {
+                Some("0x") => self.lex_radix_literal(16, "hexadecimal"),
+                Some("0b") => self.lex_radix_literal(2, "binary"),
+                Some("0o") => self.lex_radix_literal(8, "octal"),
+                _ if let Some(base_len) = self.base_prefix() => self.lex_base_literal(base_len),
+                _ => self
+                    .lex_number()
+                    .and_then(|degrees| self.lex_minutes_seconds(degrees))
+                    .and_then(|hours| self.lex_duration(hours)),
+            };
+            self.failed = result.is_err();
+            return Some(result);
+        }
+
+        if ch == '_' && self.input[start + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            self.failed = true;
+            let span = Span {
+                start,
+                end: start + 1,
+            };
+            return Some(Err(CalcError::syntax("misplaced digit separator", span)));
+        }
+
+        if ch.is_ascii_alphabetic() || ch == '_' {
+            while let Some(ch) = self.peek() {
+                // A `.` before a letter joins a namespaced name such as `phys.c`
+                let namespaced = ch == '.'
+                    && self.input[self.pos + 1..]
+                        .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+                if ch.is_ascii_alphanumeric() || ch == '_' || namespaced {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+            // `xor`, `of` and the logical operators can't be used as names
+            let kind = match &self.input[start..self.pos] {
+                "xor" => TokenKind::Xor,
+                "of" => TokenKind::Of,
+                "and" => TokenKind::And,
+                "or" => TokenKind::Or,
+                "not" => TokenKind::Not,
+                name => TokenKind::Identifier(name.to_string()),
+            };
+            return Some(Ok(Token {
+                kind,
+                span: Span {
+                    start,
+                    end: self.pos,
+                },
+            }));
+        }
+
+        if ch == 'π' {
+            self.pos += ch.len_utf8();
+            return Some(Ok(Token {
+                kind: TokenKind::Identifier("pi".to_string()),
+                span: Span {
+                    start,
+                    end: self.pos,
+                },
+            }));
+        }
+
+        // Two-character operators must be written without a gap, so that
+        // `2* *3` stays a syntax error instead of becoming a power. `!=` takes
+        // priority over factorial, so `3!=6` is a comparison.
+        let rest = &self.input[start..];
+        let double = match rest.get(..2) {
+            // `**` is an alias for `^`
+            Some("**") => Some(TokenKind::Caret),
+            // `^^` is an alias for `xor`
+            Some("^^") => Some(TokenKind::Xor),
+            Some("&&") => Some(TokenKind::And),
+            Some("//") => Some(TokenKind::DoubleSlash),
+            Some("==") => Some(TokenKind::EqualEqual),
+            Some("!=") => Some(TokenKind::NotEqual),
+            Some("<<") => Some(TokenKind::ShiftLeft),
+            Some(">>") => Some(TokenKind::ShiftRight),
+            Some("<=") => Some(TokenKind::LessEqual),
+            Some(">=") => Some(TokenKind::GreaterEqual),
+            _ => None,
+        };
+        if let Some(kind) = double {
+            self.pos += 2;
+            return Some(Ok(Token {
+                kind,
+                span: Span {
+                    start,
+                    end: self.pos,
+                },
+            }));
+        }
+
+        // `+-` after an operand is the ASCII form of `±`, as in `5 +- 0.1`,
+        // and a sign run anywhere else, as in `2*+-3`. Likewise `||` is `or`
+        // only after an operand, as a bar before one opens `|x|`.
+        let after_operand = match self.input[start..].get(..2) {
+            Some("+-") => Some(TokenKind::PlusMinus),
+            Some("||") => Some(TokenKind::Or),
+            _ => None,
+        };
+        if self.after_operand
+            && let Some(kind) = after_operand
+        {
+            self.pos += 2;
+            return Some(Ok(Token {
+                kind,
+                span: Span {
+                    start,
+                    end: self.pos,
+                },
+            }));
+        }
+
+        let span = Span {
+            start,
+            end: start + ch.len_utf8(),
+        };
+        let kind = match ch {
+            '+' => TokenKind::Plus,
+            '±' => TokenKind::PlusMinus,
+            // U+2212 is the typographic minus sign
+            '-' | '\u{2212}' => TokenKind::Minus,
+            '*' | '×' | '·' => TokenKind::Star,
+            '/' | '÷' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
+            '^' => TokenKind::Caret,
+            '&' => TokenKind::Ampersand,
+            '~' => TokenKind::Tilde,
+            '√' => TokenKind::Radical("sqrt"),
+            '∛' => TokenKind::Radical("cbrt"),
+            '!' => TokenKind::Bang,
+            '(' => {
+                self.depth += 1;
+                TokenKind::LParen
+            }
+            ')' => {
+                self.depth = self.depth.saturating_sub(1);
+                TokenKind::RParen
+            }
+            '[' => {
+                self.depth += 1;
+                TokenKind::LBracket
+            }
+            ']' => {
+                self.depth = self.depth.saturating_sub(1);
+                TokenKind::RBracket
+            }
+            '|' => TokenKind::Pipe,
+            ',' => TokenKind::Comma,
+            ';' => TokenKind::Semicolon,
+            '?' => {
+                self.open_conditionals.push(self.depth);
+                TokenKind::Question
+            }
+            ':' => {
+                if self.in_conditional() {
+                    self.open_conditionals.pop();
+                }
+                TokenKind::Colon
+            }
+            '=' => TokenKind::Equals,
+            '<' => TokenKind::Less,
+            '>' => TokenKind::Greater,
+            '≤' => TokenKind::LessEqual,
+            '≥' => TokenKind::GreaterEqual,
+            '≠' => TokenKind::NotEqual,
+            _ if unicode::superscript(ch).is_some() => {
+                self.failed = true;
+                let message = format!("superscript '{}' must follow a number, a name or ')'", ch);
+                return Some(Err(CalcError::syntax(message, span)));
+            }
+            _ if unicode::is_raised(ch) => {
+                self.failed = true;
+                let message = format!("only digits, '⁺' and '⁻' can be raised, not '{}'", ch);
+                return Some(Err(CalcError::syntax(message, span)));
+            }
+            '°' => {
+                self.failed = true;
+                return Some(Err(CalcError::syntax("'°' must follow a number", span)));
+            }
+            _ => {
+                self.failed = true;
+                return Some(Err(CalcError::InvalidCharacter { ch, span }));
+            }
+        };
+
+        self.pos += ch.len_utf8();
+        Some(Ok(Token { kind, span }))
+    }
+}
+
+/// Tokenizes the whole input, failing on the first invalid character or number
+pub(crate) fn tokenize(input: &str, options: &EvalOptions) -> Result<Vec<Token>, CalcError> {
+    Lexer::with_options(input, options).collect()
+}
+
+/// Returns the currency symbol written before the numbers of `input`, if
+/// [`EvalOptions::currency_symbols`] is set and it has one
+pub(crate) fn currency(input: &str, options: &EvalOptions) -> Option<char> {
+    let mut lexer = Lexer::with_options(input, options);
+    lexer.by_ref().for_each(drop);
+    lexer.currency
+}
+
+/// Returns the SI prefix at the start of `rest` and its power of ten, if it
+/// is a whole word
+///
+/// Prefixes are case-sensitive, since `m` is milli and `M` mega, and `e` is
+/// never one because it starts an exponent.
+fn si_prefix(rest: &str) -> Option<(char, i32)> {
+    let mut chars = rest.chars();
+    let prefix = chars.next()?;
+    if chars
+        .next()
+        .is_some_and(|next| next.is_alphanumeric() || next == '_')
+    {
+        return None;
+    }
+    SI_PREFIXES
+        .iter()
+        .find(|(symbol, _)| *symbol == prefix)
+        .copied()
+}
+
+/// Splits a number lexed with [`EvalOptions::si_suffixes`] into its digits and
+/// the power of ten of its SI prefix, if it has one
+#[cfg(feature = "decimal")]
+pub(crate) fn split_si_prefix(text: &str) -> Option<(&str, i32)> {
+    let prefix = text.chars().next_back()?;
+    let (_, power) = SI_PREFIXES.iter().find(|(symbol, _)| *symbol == prefix)?;
+    Some((&text[..text.len() - prefix.len_utf8()], *power))
+}
+
+/// Rewrites a decimal number as lexed with `separator` in the form Rust
+/// parses, without digit separators and with `.` as the decimal point
+pub(crate) fn number_text(text: &str, separator: Separator) -> String {
+    let (point, grouping) = separator.marks();
+    text.chars()
+        .filter(|&c| c != '_' && c != grouping)
+        .map(|c| if c == point { '.' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        AngleMode, CalcError, EvalOptions, Separator, Span, Value, evaluate, evaluate_value,
+        evaluate_with_options, extract_number_literals, extract_operators, render_diagnostic,
+        validate,
+    };
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(evaluate("1_000_000*3"), Ok(3000000.0));
+        assert_eq!(evaluate("1_0.5_0+1"), Ok(11.5));
+        assert!(evaluate("_5+1").is_err());
+        assert!(evaluate("5_+1").is_err());
+        assert!(evaluate("1__0+1").is_err());
+        assert_eq!(evaluate("1,234.56 + 2,000"), Ok(3234.56));
+        assert!(
+            evaluate("1,23+4")
+                .unwrap_err()
+                .to_string()
+                .contains("digit groups must have three digits in '1,23'")
+        );
+        assert_eq!(evaluate("1.5e3+2E-1"), Ok(1500.2));
+        assert_eq!(evaluate("2e"), Ok(2.0 * std::f64::consts::E));
+        assert_eq!(evaluate("0xFF+1"), Ok(256.0));
+        assert_eq!(evaluate("0x10*0X10"), Ok(256.0));
+        assert!(
+            evaluate("0xZ1")
+                .unwrap_err()
+                .to_string()
+                .contains("invalid hexadecimal literal")
+        );
+        assert!(evaluate("0x20000000000001").is_err());
+        assert_eq!(evaluate("0b1010+0o10"), Ok(18.0));
+        assert_eq!(
+            evaluate("0b102").unwrap_err().to_string(),
+            "invalid binary literal '0b102' at position 0"
+        );
+        assert_eq!(
+            evaluate("1+0o9").unwrap_err().to_string(),
+            "invalid octal literal '0o9' at position 2"
+        );
+    }
+
+    #[test]
+    fn literals_in_a_base() {
+        assert_eq!(evaluate("36#Z+1"), Ok(36.0));
+        assert_eq!(evaluate("7#66"), Ok(48.0));
+        assert_eq!(evaluate("2#1011"), Ok(11.0));
+        assert_eq!(evaluate("16#ff - 16#F0"), Ok(15.0));
+        assert_eq!(evaluate("2#0.1"), Ok(0.5));
+        assert_eq!(evaluate("16#A.8"), Ok(10.5));
+        assert_eq!(evaluate("2#1111_0000"), Ok(240.0));
+        assert_eq!(evaluate("3*4 #note"), Ok(12.0));
+        assert_eq!(
+            evaluate("7#68").unwrap_err().to_string(),
+            "digit '8' is out of range for base 7 at position 3"
+        );
+        assert_eq!(
+            evaluate("37#1").unwrap_err().to_string(),
+            "base 37 must be from 2 to 36 at position 0"
+        );
+        assert_eq!(
+            evaluate("1 + 1#0").unwrap_err().to_string(),
+            "base 1 must be from 2 to 36 at position 4"
+        );
+        assert!(
+            evaluate("36#ZZZZZZZZZZZZ")
+                .unwrap_err()
+                .to_string()
+                .contains("cannot be represented exactly")
+        );
+    }
+
+    #[test]
+    fn degrees_minutes_and_seconds() {
+        let degrees = EvalOptions::new().angle_mode(AngleMode::Degrees);
+        let in_degrees = |input| evaluate_with_options(input, &degrees);
+        assert_eq!(
+            in_degrees("45°30'15\""),
+            Ok(45.0 + 30.0 / 60.0 + 15.0 / 3600.0)
+        );
+        assert_eq!(in_degrees("45°"), Ok(45.0));
+        assert_eq!(in_degrees("45°30'"), Ok(45.5));
+        assert_eq!(in_degrees("12°30′36″"), Ok(12.51));
+        assert_eq!(in_degrees("-10°15'"), Ok(-10.25));
+        assert_eq!(in_degrees("2*90°30' - 1"), Ok(180.0));
+        assert_eq!(in_degrees("0°0'36\""), Ok(0.01));
+        assert_eq!(in_degrees("sin(30°)"), Ok(0.49999999999999994));
+
+        use std::f64::consts::{FRAC_PI_2, PI};
+        assert_eq!(evaluate("90°"), Ok(FRAC_PI_2));
+        assert_eq!(evaluate("180° - pi"), Ok(0.0));
+        assert_eq!(evaluate("45°30'"), Ok(45.5f64.to_radians()));
+        assert_eq!(evaluate("-10°15'"), Ok(-(10.25f64.to_radians())));
+        for sin_ninety in [evaluate("sin(90°)").ok(), in_degrees("sin(90°)").ok()] {
+            assert_eq!(sin_ninety, Some(1.0));
+        }
+        fn close<E: std::fmt::Debug>(result: Result<f64, E>, expected: f64) -> bool {
+            (result.unwrap() - expected).abs() < 1e-12
+        }
+        for options in [EvalOptions::default(), degrees.clone()] {
+            let evaluate = |input| evaluate_with_options(input, &options);
+            assert!(close(evaluate("cos(2*45°)"), 0.0));
+            assert!(close(evaluate("cos(180°)"), -1.0));
+            assert!(close(evaluate("tan(45°) + sin(cos(0°)*30°)"), 1.5));
+            assert!(close(evaluate("sin(max(30°, 60°) - 30°)"), 0.5));
+            assert!(close(evaluate("2 * sin(15°) * cos(15°)"), 0.5));
+            assert!(close(evaluate("sin(90°30') - cos(0°30')"), 0.0));
+        }
+        assert!(close(evaluate("sin(pi/2) - sin(90°)"), 0.0));
+        assert!(close(in_degrees("sin(90) - sin(90°)"), 0.0));
+        assert!(close(evaluate("180° / pi"), 1.0));
+        assert!(close(in_degrees("180° / pi"), 180.0 / PI));
+
+        assert_eq!(
+            evaluate("x = 3; x°").unwrap_err().to_string(),
+            "'°' must follow a number at position 8"
+        );
+        assert_eq!(
+            evaluate("(45)°").unwrap_err().to_string(),
+            "'°' must follow a number at position 4"
+        );
+        assert_eq!(
+            evaluate("sin(°)").unwrap_err().to_string(),
+            "'°' must follow a number at position 4"
+        );
+        assert_eq!(
+            evaluate_with_options("cos(59°60')", &degrees)
+                .unwrap_err()
+                .to_string(),
+            "minutes must be less than 60, got 60 at position 8"
+        );
+        assert!(
+            evaluate("45°30'75\"")
+                .unwrap_err()
+                .to_string()
+                .contains("seconds must be less than 60")
+        );
+        assert_eq!(
+            evaluate("45.5°30'").unwrap_err().to_string(),
+            "degrees must be whole when minutes follow at position 6"
+        );
+        assert!(
+            evaluate("45°30.5'10\"")
+                .unwrap_err()
+                .to_string()
+                .contains("minutes must be whole")
+        );
+        assert!(
+            evaluate("dms(45.5)")
+                .unwrap_err()
+                .to_string()
+                .contains("not a number")
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(evaluate_value("1:30 + 0:45").unwrap().to_string(), "2:15");
+        assert_eq!(evaluate_value("7:15 * 2").unwrap().to_string(), "14:30");
+        assert_eq!(
+            evaluate_value("2 * 0:20 - 0:50").unwrap().to_string(),
+            "-0:10"
+        );
+        assert_eq!(
+            evaluate_value("2:00:30 / 2").unwrap().to_string(),
+            "1:00:15"
+        );
+        assert_eq!(
+            evaluate_value("max(0:45, 1:05)").unwrap().to_string(),
+            "1:05"
+        );
+        assert_eq!(evaluate_value("1:30:00"), Ok(Value::Duration(5400.0)));
+        assert_eq!(evaluate_value("3:00 / 1:30"), Ok(Value::Number(2.0)));
+        assert_eq!(evaluate_value("1:30 > 0:45"), Ok(Value::Number(1.0)));
+        assert_eq!(evaluate("1:30"), Ok(5400.0));
+        assert_eq!(evaluate("1 < 2 ? 1:30"), Ok(1.0));
+        assert_eq!(
+            evaluate_value("1 < 2 ? (1:30) : 0:45").unwrap().to_string(),
+            "1:30"
+        );
+        assert_eq!(
+            evaluate("1:30 + 5").unwrap_err().to_string(),
+            "cannot add a number to a duration at position 5"
+        );
+        assert!(
+            evaluate("1:30 - 5")
+                .unwrap_err()
+                .to_string()
+                .contains("cannot subtract a number from a duration")
+        );
+        assert!(
+            evaluate("1:30 * 0:30")
+                .unwrap_err()
+                .to_string()
+                .contains("cannot multiply two durations")
+        );
+        assert!(
+            evaluate("2 / 1:30")
+                .unwrap_err()
+                .to_string()
+                .contains("cannot divide a number by a duration")
+        );
+        assert!(
+            evaluate("1:30 ^ 2")
+                .unwrap_err()
+                .to_string()
+                .contains("cannot apply '^' to a duration")
+        );
+        assert!(
+            evaluate("sqrt(1:30)")
+                .unwrap_err()
+                .to_string()
+                .contains("sqrt doesn't take a duration")
+        );
+        assert_eq!(
+            evaluate("1:75").unwrap_err().to_string(),
+            "minutes must be less than 60, got 75 at position 2"
+        );
+        assert!(evaluate("1:3").is_err());
+    }
+
+    #[test]
+    fn currencies() {
+        assert_eq!(
+            evaluate_value("$1,299.99 + $49.99").unwrap().to_string(),
+            "$1349.98"
+        );
+        assert_eq!(
+            evaluate_value("€20 * 3"),
+            Ok(Value::Money {
+                symbol: '€',
+                amount: 60.0
+            })
+        );
+        assert_eq!(evaluate_value("£10 / 3").unwrap().to_string(), "£3.33");
+        assert_eq!(
+            evaluate_value("¥500 - ¥1000").unwrap().to_string(),
+            "-¥500.00"
+        );
+        assert_eq!(evaluate("$1,299.99 + $49.99"), Ok(1349.98));
+        let prices = extract_number_literals("$1,299.99 + $49.99").unwrap();
+        assert_eq!((prices[0].value, prices[0].text), (1299.99, "1,299.99"));
+        assert_eq!(
+            evaluate("$5 + €3").unwrap_err().to_string(),
+            "cannot mix currencies '$' and '€' at position 5"
+        );
+        assert!(
+            evaluate("$ 5")
+                .unwrap_err()
+                .to_string()
+                .contains("unexpected character '$'")
+        );
+
+        let plain = EvalOptions::new().currency_symbols(false);
+        assert!(evaluate_with_options("$5", &plain).is_err());
+    }
+
+    #[test]
+    fn si_suffixes() {
+        let si = EvalOptions::new().si_suffixes(true);
+        assert_eq!(evaluate_with_options("4.7k*2", &si), Ok(9400.0));
+        assert_eq!(evaluate_with_options("100n*1e9", &si), Ok(100.0));
+        assert_eq!(evaluate_with_options("1M / 1m", &si), Ok(1e9));
+        assert_eq!(evaluate_with_options("2G + 3T", &si), Ok(3.002e12));
+        assert_eq!(evaluate_with_options("10u + 10µ", &si), Ok(2e-5));
+        assert_eq!(evaluate_with_options("47p", &si), Ok(4.7e-11));
+        assert_eq!(evaluate_with_options("2k(3)", &si), Ok(6000.0));
+        assert_eq!(evaluate_with_options("1_000k", &si), Ok(1e6));
+        assert!(
+            evaluate_with_options("3kg", &si)
+                .unwrap_err()
+                .to_string()
+                .contains("undefined variable 'kg'")
+        );
+        assert!(
+            evaluate_with_options("1e3k", &si)
+                .unwrap_err()
+                .to_string()
+                .contains("undefined variable 'k'")
+        );
+        assert!(
+            evaluate_with_options("5P", &si)
+                .unwrap_err()
+                .to_string()
+                .contains("undefined variable 'P'")
+        );
+        assert!(
+            evaluate("4.7k")
+                .unwrap_err()
+                .to_string()
+                .contains("undefined variable 'k'")
+        );
+    }
+
+    #[test]
+    fn comma_decimal_separator() {
+        let comma = EvalOptions::new().decimal_separator(Separator::Comma);
+        assert_eq!(evaluate_with_options("3,5+1,25", &comma), Ok(4.75));
+        assert_eq!(evaluate_with_options("1.234,5 * 2", &comma), Ok(2469.0));
+        assert_eq!(
+            evaluate_with_options("1.000.000 + 0,5e3", &comma),
+            Ok(1000500.0)
+        );
+        assert_eq!(evaluate_with_options("max(1, 2,5)", &comma), Ok(2.5));
+        assert_eq!(
+            evaluate_with_options("max(1,2)", &comma)
+                .unwrap_err()
+                .to_string(),
+            "max expects at least 2 arguments, got 1"
+        );
+        assert_eq!(evaluate_with_options("max(1 ,2,5 , 3)", &comma), Ok(3.0));
+        assert_eq!(evaluate_with_options("min(pi,0,5)", &comma), Ok(0.5));
+        assert!(evaluate_with_options("1.5", &comma).is_err());
+        assert!(evaluate_with_options(",5", &comma).is_err());
+        assert!(evaluate_with_options("1,2,3", &comma).is_err());
+        assert_eq!(evaluate("1,234.5"), Ok(1234.5));
+    }
+
+    #[test]
+    fn full_width_and_other_digits() {
+        assert_eq!(evaluate("１２３＋４"), Ok(127.0));
+        assert_eq!(evaluate("１2＊（3－１）"), Ok(24.0));
+        assert_eq!(evaluate("１．５ ／ 3"), Ok(0.5));
+        assert_eq!(evaluate("max（1，２）"), Ok(2.0));
+        assert_eq!(evaluate("٣.٥ + ۲"), Ok(5.5));
+        assert_eq!(evaluate("2＾١٠"), Ok(1024.0));
+        assert_eq!(
+            evaluate("１＋＊２").unwrap_err().to_string(),
+            "unexpected '*' at position 6"
+        );
+        assert_eq!(
+            evaluate("½ + )"),
+            Err(CalcError::Syntax {
+                message: "unmatched closing parenthesis".to_string(),
+                span: Span { start: 5, end: 6 },
+            })
+        );
+        let diagnostics = validate("１２＋*3").unwrap_err();
+        assert_eq!(diagnostics[0].span, Span { start: 9, end: 10 });
+        assert_eq!(
+            render_diagnostic("１２＋*3", &diagnostics[0]),
+            "１２＋*3\n      ^ unexpected '*' at position 9"
+        );
+        assert_eq!(
+            evaluate("１２ + ⅓/0"),
+            Err(CalcError::DivisionByZero {
+                span: Some(Span { start: 12, end: 13 })
+            })
+        );
+        assert!(validate("１０％＋x").is_ok());
+        let numbers = extract_number_literals("１２＋٣,٠٠٠").unwrap();
+        assert_eq!((numbers[0].value, numbers[0].text), (12.0, "１２"));
+        assert_eq!((numbers[1].value, numbers[1].text), (3000.0, "٣,٠٠٠"));
+        assert_eq!(extract_operators("１２＋3－４"), vec!["+", "-"]);
+
+        let strict = EvalOptions::new().normalize_unicode(false);
+        assert_eq!(
+            evaluate_with_options("１２＋4", &strict)
+                .unwrap_err()
+                .to_string(),
+            "unexpected character '１' at position 0"
+        );
+        assert_eq!(evaluate_with_options("12+4", &strict), Ok(16.0));
+    }
+
+    #[test]
+    fn superscripts_and_vulgar_fractions() {
+        let strict = EvalOptions::new().normalize_unicode(false);
+        assert_eq!(evaluate("5²"), Ok(25.0));
+        assert_eq!(evaluate("10³ + 1"), Ok(1001.0));
+        assert_eq!(evaluate("2¹⁰"), Ok(1024.0));
+        assert_eq!(evaluate("2⁻¹"), Ok(0.5));
+        assert_eq!(evaluate("(1+2)²"), Ok(9.0));
+        assert_eq!(evaluate("-3²"), Ok(-9.0));
+        assert_eq!(evaluate("2*3²"), Ok(18.0));
+        assert_eq!(evaluate("１０²"), Ok(100.0));
+        assert_eq!(evaluate("1½+¼"), Ok(1.75));
+        assert_eq!(evaluate("2*1½"), Ok(3.0));
+        assert_eq!(evaluate("-2¾"), Ok(-2.75));
+        assert_eq!(evaluate("½ + ¼ + ¼"), Ok(1.0));
+        assert_eq!(evaluate("2·½"), Ok(1.0));
+        assert_eq!(evaluate("3(½)"), Ok(1.5));
+        assert_eq!(evaluate("pi½"), Ok(std::f64::consts::FRAC_PI_2));
+        assert_eq!(evaluate("⅛²"), Ok(1.0 / 64.0));
+        assert_eq!(
+            evaluate("²+1").unwrap_err().to_string(),
+            "superscript '²' must follow a number, a name or ')' at position 0"
+        );
+        assert_eq!(
+            evaluate("2ⁿ").unwrap_err().to_string(),
+            "only digits, '⁺' and '⁻' can be raised, not 'ⁿ' at position 1"
+        );
+        assert!(
+            evaluate("5²ⁱ")
+                .unwrap_err()
+                .to_string()
+                .starts_with("only digits, '⁺' and '⁻' can be raised, not 'ⁱ'")
+        );
+        assert!(evaluate_with_options("5²", &strict).is_err());
+    }
+
+    #[test]
+    fn radical_signs() {
+        use std::f64::consts::{PI, SQRT_2};
+
+        assert_eq!(evaluate("√9+7"), Ok(10.0));
+        assert_eq!(evaluate("√(9+7)"), Ok(4.0));
+        assert_eq!(evaluate("∛27 - ∛-8"), Ok(5.0));
+        assert_eq!(evaluate("2π*√2"), Ok(2.0 * PI * SQRT_2));
+        assert_eq!(evaluate("2√9"), Ok(6.0));
+        assert_eq!(evaluate("√4√9"), Ok(6.0));
+        assert_eq!(evaluate("-√4"), Ok(-2.0));
+        assert_eq!(evaluate("√√16"), Ok(2.0));
+        assert_eq!(evaluate("√2^2"), Ok(2.0000000000000004));
+        assert_eq!(evaluate("√4!"), Ok(24f64.sqrt()));
+        assert_eq!(evaluate("10 % √9"), Ok(1.0));
+        assert_eq!(evaluate("√max(4, 16) / 2"), Ok(2.0));
+        assert!(validate("2π*√2 + ∛8").is_ok());
+
+        assert_eq!(
+            evaluate("√-4").unwrap_err().to_string(),
+            "domain error: sqrt is undefined for -4"
+        );
+        let lenient = EvalOptions::new().nan_on_domain_error(true);
+        assert!(evaluate_with_options("√-4", &lenient).unwrap().is_nan());
+        assert_eq!(
+            evaluate("√").unwrap_err().to_string(),
+            "unexpected end of input, expected a number at position 3"
+        );
+        assert!(evaluate("4√").is_err());
+
+        let explicit = EvalOptions::new().implicit_multiplication(false);
+        assert!(evaluate_with_options("2√9", &explicit).is_err());
+        assert_eq!(evaluate_with_options("2*√9", &explicit), Ok(6.0));
+    }
+}
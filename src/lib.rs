@@ -1,155 +1,2369 @@
-/// Validates if the input string is a valid math equation
-/// Returns true if it contains numbers and operators in a valid pattern
-pub fn validate_equation(input: &str) -> bool {
-    // First, check if the regex pattern for math equations is valid using regex_parser
-    // Pattern: one or more digits, followed by operator(s) and more digits
-    // We'll validate the pattern structure conceptually
+//! A small calculator library that parses and evaluates math expressions.
+//!
+//! # Operators
+//!
+//! From loosest to tightest binding:
+//!
+//! * `cond ? a : b` is `a` if `cond` is non-zero and `b` otherwise. Only the
+//!   selected branch is evaluated, so `1 ? 5 : 1/0` is `5`. It is
+//!   right-associative, so `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+//! * `or` and `and` (also written `||` and `&&`) are logical or and and,
+//!   treating zero as false and anything else as true and giving `1` or `0`.
+//!   `and` binds tighter, and the right-hand side is only evaluated if it
+//!   decides the result, so `0 and 1/0` is `0`. `||` is only `or` directly
+//!   after a number, a name or `)`, as a bar elsewhere opens or closes `|x|`,
+//!   so write `|x| or y`.
+//! * `not` is logical not, `1` for zero and `0` otherwise. It takes a whole
+//!   comparison, so `not x > 0` is `not (x > 0)`, while `!` before an operand
+//!   binds like a sign, so `!x > 0` is `(!x) > 0`. `true` and `false` are `1`
+//!   and `0`.
+//! * `<`, `<=`, `>`, `>=`, `==` and `!=` compare two numbers, giving `1` if the
+//!   comparison holds and `0` otherwise. Equality is exact unless
+//!   [`EvalOptions::comparison_tolerance`] is set. Comparisons can't be
+//!   chained, so `1 < 2 < 3` is an error. `≤`, `≥` and `≠` are accepted too.
+//! * `|`, `xor` (also written `^^`) and `&` are bitwise or, exclusive or and
+//!   and, binding in that order from loosest to tightest. Unlike in C, they bind
+//!   tighter than comparisons, so `x & 1 == 1` tests the lowest bit.
+//! * `<<` and `>>` shift the bits of an integer left or right. `>>` keeps the
+//!   sign, so `-16 >> 2` is `-4`. The shift amount must be from 0 to 63.
+//! * `+` and `-` are addition and subtraction
+//! * `±` (or `+-` directly after an operand) gives a value an uncertainty,
+//!   which only [`evaluate_value`] evaluates; see
+//!   [Uncertainties](#uncertainties)
+//! * `*`, `/`, `//` and `%` are multiplication, division, floor division and
+//!   remainder. `//` rounds towards negative infinity (`-7//2` is `-4`), and `%`
+//!   takes the sign of the dividend (`-7%3` is `-1`) unless
+//!   [`EvalOptions::remainder_mode`] says otherwise.
+//! * A leading `-` or `+` is the sign of an operand, and a leading `~` is the
+//!   bitwise complement. Signs may also directly follow a binary operator, as in
+//!   `3--2`.
+//! * `^` (or `**`) is exponentiation. It is right-associative and follows
+//!   `f64::powf`, so a negative base raised to a fractional power such as
+//!   `(-8)^(1/3)` evaluates to NaN.
+//! * Postfix `!` is the factorial of a non-negative integer, and a postfix `%`
+//!   divides by 100. A `%` followed by a number or `(` is a remainder, otherwise
+//!   it is a percent sign, so `50%*200` is `0.5*200`.
+//! * `of` after a percentage multiplies by it, so `20% of 50` is `10`. The
+//!   right-hand side is taken like that of `*`, so `20% of 30+20` is `26` and
+//!   `20% of (30+20)` is `10`. `of` anywhere else is an error.
+//!
+//! Parentheses group sub-expressions and may be nested up to the limit below.
+//! A number or `)` directly followed by `(` or a name is an implicit
+//! multiplication with the same precedence as `*`, so `2(3+4)` is `14`,
+//! `6/2(3)` is `9` and `2pi` is `2*pi`. Two numbers separated only by
+//! whitespace are an error rather than a product, reported at the second
+//! number. The Unicode operators `×` and `·` (multiplication), `÷` (division)
+//! and `−` (minus sign) are accepted as well. A `#` starts a comment that runs
+//! to the end of the line, so `3*4  # area of the rug` is `12`.
+//!
+//! The bitwise operators work on the two's complement 64-bit representation
+//! of their operands, which must be integers of at most 53 bits so they are
+//! exact as `f64`. Inside `|x|` bars, a `|` after an operand closes the bars, so
+//! bitwise or needs parentheses there: `|(a | b)|`.
+//!
+//! Setting [`EvalOptions::bit_width`] makes them work on that many bits
+//! instead, such as 8, and show their results unsigned, so `~0` is `255` and
+//! `1 << 8` is `0`. An operand must then fit in the width either as a signed
+//! or an unsigned number, from `-128` to `255` for 8 bits, and `>>` shifts in
+//! zeros. `hex`, `bin` and `oct` write a negative number as its two's
+//! complement in the width, so `hex(-1)` is `0xff`. `popcount(n)` counts the
+//! bits set in an integer and `bit_length(n)` is the number of bits up to its
+//! highest set one, both in the width if there is one and otherwise of the
+//! magnitude, so `popcount(-1)` is `1` unless a width is set. In the REPL,
+//! `:set bits 8`, `16`, `32` or `64` sets the width and `:set bits off`
+//! clears it.
+//!
+//! ```
+//! use calculator_rust::{EvalOptions, evaluate, evaluate_with_options};
+//!
+//! assert_eq!(evaluate("~0"), Ok(-1.0));
+//! assert_eq!(evaluate("popcount(255) + popcount(0)"), Ok(8.0));
+//! assert_eq!(evaluate("popcount(-1)"), Ok(1.0));
+//! assert_eq!(evaluate("popcount(1.5)").unwrap_err().to_string(), "popcount requires integers, got 1.5");
+//!
+//! let eight = EvalOptions::new().bit_width(8);
+//! assert_eq!(evaluate_with_options("-16 >> 2", &eight), Ok(60.0));
+//! assert_eq!(evaluate_with_options("~(-128)", &eight), Ok(127.0));
+//! assert_eq!(
+//!     evaluate_with_options("300 & 1", &eight).unwrap_err().to_string(),
+//!     "bitwise operators require integers from -128 to 255 at a width of 8 bits, got 300"
+//! );
+//! ```
+//!
+//! ```
+//! use calculator_rust::evaluate;
+//!
+//! assert_eq!(evaluate("2*(3+(4-1))"), Ok(12.0));
+//! assert_eq!(evaluate("2^3^2"), Ok(512.0));
+//! assert_eq!(evaluate("7+10%4"), Ok(9.0));
+//! assert_eq!(evaluate("3!+1"), Ok(7.0));
+//! assert_eq!(evaluate("200*15%"), Ok(30.0));
+//! assert_eq!(evaluate("20% of 50"), Ok(10.0));
+//! assert_eq!(evaluate("2(3+4)"), Ok(14.0));
+//! assert_eq!(evaluate("3*4  # area of the rug"), Ok(12.0));
+//! assert_eq!(evaluate("3+1 > 2*2"), Ok(0.0));
+//! assert_eq!(evaluate("10/2 == 5"), Ok(1.0));
+//! assert_eq!(evaluate("0xFF & 0x0F"), Ok(15.0));
+//! assert_eq!(evaluate("1 << 10"), Ok(1024.0));
+//! assert_eq!(evaluate("2 > 1 ? 10 : 20"), Ok(10.0));
+//! assert_eq!(evaluate("5 > 0 and 5 < 10"), Ok(1.0));
+//! assert_eq!(evaluate("not 2 > 3"), Ok(1.0));
+//! ```
+//!
+//! With [`RemainderMode::Floored`], `%` takes the sign of the divisor instead,
+//! so that `a == b*(a//b) + a%b`, and with [`RemainderMode::Euclidean`] it is
+//! never negative. The `mod(a, b)` function always uses floored semantics.
+//!
+//! ```
+//! use calculator_rust::{EvalOptions, RemainderMode, evaluate, evaluate_with_options};
+//!
+//! let floored = EvalOptions::new().remainder_mode(RemainderMode::Floored);
+//! assert_eq!(evaluate_with_options("-7 % 3", &floored), Ok(2.0));
+//! let euclidean = EvalOptions::new().remainder_mode(RemainderMode::Euclidean);
+//! assert_eq!(evaluate_with_options("7 % (-3)", &euclidean), Ok(1.0));
+//! assert_eq!(evaluate("mod(-7, 3) + mod(7, -3)"), Ok(0.0));
+//! assert_eq!(evaluate("mod(1, 0)").unwrap_err().to_string(), "division by zero");
+//! ```
+//!
+//! A sign binds looser than `^` on its left and is part of the exponent on
+//! its right, as in mathematical notation. So `-2^2` is `-(2^2) = -4` while
+//! `(-2)^2` is `4`, and `2^-3` is `2^(-3) = 0.125`. An exponent's sign applies
+//! to the whole rest of the tower, so `3^-1^2` is `3^(-(1^2))`. Factorial and
+//! percent bind tighter still, so `-3!` is `-(3!)` and `2^3!` is `2^(3!)`.
+//!
+//! ```
+//! use calculator_rust::evaluate;
+//!
+//! assert_eq!(evaluate("-2^2"), Ok(-4.0));
+//! assert_eq!(evaluate("(-2)^2"), Ok(4.0));
+//! assert_eq!(evaluate("2^-3"), Ok(0.125));
+//! assert_eq!(evaluate("-2^3^2"), Ok(-512.0));
+//! assert_eq!(evaluate("-3!"), Ok(-6.0));
+//! ```
+//!
+//! An expression may nest at most 256 levels deep, counting each group,
+//! argument, sign and operator around a part of it, so that very deep input
+//! is an error rather than running out of stack. A sum of 256 terms such as
+//! `1+1+…+1` nests as deep as 256 parentheses, since each `+` holds the sum
+//! before it.
+//!
+//! ```
+//! use calculator_rust::evaluate;
+//!
+//! let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+//! assert_eq!(evaluate(&nested(200)), Ok(1.0));
+//! assert_eq!(
+//!     evaluate(&nested(100_000)).unwrap_err().to_string(),
+//!     "expression nested more than 256 levels deep at position 256"
+//! );
+//! ```
+//!
+//! # Constants
+//!
+//! The constants `pi` (also written `π`), `e`, `tau` and `phi` (the golden
+//! ratio) can be used anywhere a number can. Names are case-sensitive, and all
+//! mathematical constants are lowercase. See [`constants`] for the full table.
+//!
+//! Physical constants in SI units are named with the `phys.` prefix: `phys.c`
+//! (speed of light), `phys.G` (gravitational constant), `phys.h` (Planck
+//! constant), `phys.Na` (Avogadro constant), `phys.kB` (Boltzmann constant) and
+//! `phys.g` (standard gravity). Their values are the CODATA 2018 ones, which are
+//! exact except for `G`. [`Context::insert_constants`] makes them available
+//! without the prefix too, and [`physical_constants`] lists them.
+//!
+//! ```
+//! use calculator_rust::evaluate;
+//!
+//! assert_eq!(evaluate("2*pi"), Ok(6.283185307179586));
+//! assert_eq!(evaluate("2π"), evaluate("tau"));
+//! assert_eq!(evaluate("2*pie").unwrap_err().to_string(), "undefined variable 'pie' at position 2");
+//! assert_eq!(evaluate("phys.c"), Ok(299792458.0));
+//! assert_eq!(evaluate("2phys.g"), Ok(19.6133));
+//! ```
+//!
+//! # Functions
+//!
+//! Functions are called by name with a parenthesized, comma-separated argument
+//! list, as in `sqrt(16)`. Calling a function with the wrong number of arguments
+//! is an error, and so is calling it outside its domain, such as `sqrt(-1)`,
+//! unless [`EvalOptions::nan_on_domain_error`] is set. See [`functions`] for the
+//! full list.
+//!
+//! `abs(x)` can also be written with bars as `|x|`. Bars can't be nested, so
+//! `||x|-1|` is an error; use `abs` for the inner level instead.
+//!
+//! The trigonometric functions `sin`, `cos` and `tan` take angles in radians,
+//! and their inverses `asin`, `acos`, `atan` and `atan2` return radians. Set
+//! [`EvalOptions::angle_mode`] to [`AngleMode::Degrees`] to work in degrees. The
+//! hyperbolic functions `sinh`, `cosh`, `tanh` and their inverses `asinh`,
+//! `acosh`, `atanh` are available too.
+//!
+//! The logarithms `ln`, `log10` and `log2` reject zero and negative arguments
+//! with a domain error. `log(x, base)` takes an optional base, which defaults
+//! to 10 when omitted. `exp(x)` is `e^x`, and `expm1(x)` and `ln1p(x)` are
+//! `exp(x) - 1` and `ln(1 + x)` computed without the cancellation that loses
+//! the digits of a tiny `x`.
+//!
+//! `gamma(x)` extends the factorial, so `gamma(n)` is `(n - 1)!`, and
+//! `lgamma(x)` is the natural logarithm of its absolute value, which stays
+//! finite for large `x`. They are accurate to about 15 significant digits,
+//! and exact at positive integers. Both have poles at zero and the negative
+//! integers, which are domain errors.
+//!
+//! `floor`, `ceil`, `round` and `trunc` round to an integer, or to a number of
+//! decimal places given as an optional second argument. A negative number of
+//! places rounds to tens, hundreds and so on. `round` breaks ties away from
+//! zero, so `round(2.5)` is `3` and `round(-2.5)` is `-3`, unless
+//! [`EvalOptions::rounding`] picks another [`RoundingMode`]. The mode also
+//! rounds amounts of money to two decimals in the REPL, where `:set rounding
+//! half-even` sets it.
+//!
+//! ```
+//! use calculator_rust::{EvalOptions, RoundingMode, evaluate_with_options};
+//!
+//! let options = EvalOptions::new().rounding(RoundingMode::HalfEven);
+//! assert_eq!(evaluate_with_options("round(2.5)", &options), Ok(2.0));
+//! assert_eq!(evaluate_with_options("round(2.6)", &options), Ok(3.0));
+//! assert_eq!(evaluate_with_options("round(1250, -2)", &options), Ok(1200.0));
+//! let options = EvalOptions::new().rounding(RoundingMode::TowardZero);
+//! assert_eq!(evaluate_with_options("round(-2.9)", &options), Ok(-2.0));
+//! ```
+//!
+//! `min` and `max` take two or more arguments, and `clamp(x, lo, hi)` limits
+//! `x` to a range, failing if `lo` is greater than `hi`.
+//!
+//! `lerp(a, b, t)` interpolates linearly, giving `a` at `t = 0` and `b` at
+//! `t = 1`. It isn't clamped, so other values of `t` extrapolate past `a` and
+//! `b`; `lerp_clamped` limits `t` to the range 0 to 1 first.
+//! `map_range(x, in_lo, in_hi, out_lo, out_hi)` rescales `x` from one range
+//! to the other, also without clamping, and fails if the input range is
+//! empty.
+//!
+//! `isnan`, `isinf` and `isfinite` give 1 or 0, for testing results that
+//! overflowed to infinity, as `1e308 * 10` does, or that a domain error gave
+//! as NaN under [`EvalOptions::nan_on_domain_error`]. `ulp(x)` is the gap from
+//! `|x|` to the next larger floating point number, and `nextafter(x, y)` is
+//! the floating point number next to `x` in the direction of `y`.
+//!
+//! `gcd` and `lcm` take two or more integers and ignore their signs. They are
+//! computed with exact integer arithmetic, and fail if an argument is not an
+//! integer or the result is too large to represent exactly.
+//!
+//! `if(cond, a, b)` is the function form of `cond ? a : b`, and likewise only
+//! evaluates the selected branch.
+//!
+//! `sum(i, lo, hi, body)` adds up `body` for each integer `i` from `lo` to `hi`,
+//! and `prod` multiplies the terms instead. The loop variable is only visible in
+//! the body, where it hides any variable or constant of the same name. The
+//! bounds must be integers, and a range with `lo` greater than `hi` is empty, so
+//! its sum is 0 and its product 1. A series may have at most 1,000,000 terms,
+//! and so may all the series inside one, or inside an integral or the like,
+//! put together.
+//!
+//! Called any other way, as in `sum(1, 2, 3)`, `sum` adds up its arguments.
+//! Only a call with four arguments of which the first is a bare name is read as
+//! the indexed form, so four variables are added up with `sum(+a, b, c, d)`.
+//!
+//! `count`, `mean` and `median` take one or more arguments, and `median` of an
+//! even number of them averages the two in the middle. `var` and `stddev` are
+//! the sample variance and standard deviation, dividing by one less than the
+//! number of arguments, so they need at least two.
+//!
+//! `deriv(body, x, at)` estimates the derivative of `body` with respect to `x`
+//! at `x = at` with a central difference, which is accurate to about 10
+//! significant digits for smooth functions. An error in `body` at the point
+//! itself, as in `deriv(1/x, x, 0)`, is reported rather than giving a huge or
+//! NaN result.
+//!
+//! `integrate(body, x, a, b)` integrates `body` with respect to `x` from `a` to
+//! `b` with adaptive Simpson quadrature, aiming for an absolute error below
+//! 1e-10. Swapping the bounds negates the result. The bounds must be finite, and
+//! an integrand that still hasn't converged after 10,000 subdivisions, such as
+//! one with a singularity inside the range, is an error.
+//!
+//! `solve(lhs = rhs, x)` finds a value of `x` for which both sides are equal,
+//! and `solve(lhs = rhs, x, lo, hi)` looks for one between `lo` and `hi`. A
+//! linear equation such as `2*x + 3 = 11` is solved exactly by rearranging it.
+//! Any other equation is searched for a change of sign between `lo` and `hi`,
+//! or -100 and 100 by default, and the lowest root found is narrowed down by
+//! bisection. Roots where the sides touch without crossing, or closer together
+//! than a thousandth of the range, may be missed. A lone expression counts as
+//! an equation with 0 on the right.
+//!
+//! `powmod(b, e, m)` is `b^e` modulo `m`, computed exactly with integer
+//! arithmetic even when `b^e` itself would overflow, as in `powmod(7, 128, 13)`.
+//! All three arguments must be non-negative integers up to 2^53, and `m` at
+//! least 1. `powmod(0, 0, m)` treats `0^0` as 1.
+//!
+//! `fib(n)` is the nth Fibonacci number, `triangular(n)` is `1 + 2 + ... + n`
+//! and `isqrt(n)` is the largest integer whose square is at most `n`. Their
+//! argument must be a non-negative integer up to 2^53, and they are computed
+//! with integer arithmetic, so `isqrt` is exact where `floor(sqrt(n))` can
+//! round up just below a perfect square. A result above 2^53, from `fib(79)`
+//! or `triangular(2^27)` on, is an error rather than a rounded number.
+//!
+//! `isprime(n)` is 1 if `n` is prime and 0 otherwise, using a deterministic
+//! Miller–Rabin test, and `nextprime(n)` is the smallest prime greater than `n`.
+//! Their argument must be a positive integer up to 2^53. The prime
+//! factorization of such a number, shown as `2^2*3*5` for 60, isn't a number
+//! itself, so `factorize(n)` can only make up a whole expression evaluated with
+//! [`evaluate_value`], which returns it as text, while [`factorize`] returns the
+//! factors to Rust code.
+//!
+//! `random()` returns a uniformly distributed number in `[0, 1)` and
+//! `randint(a, b)` an integer between `a` and `b` inclusive. Set
+//! [`EvalOptions::seed`] to get the same sequence on every evaluation.
+//!
+//! `c_to_f`, `f_to_c`, `c_to_k`, `k_to_c`, `f_to_k` and `k_to_f` convert
+//! temperatures between Celsius, Fahrenheit and kelvins, failing below
+//! absolute zero. They use the same scales as the `degC`, `degF` and `K` units.
+//!
+//! `sign(x)` is -1, 0 or 1, and is 0 for both `0` and `-0`. `copysign(x, y)`
+//! takes the sign from `y`, including that of a zero, so `copysign(2, -0)` is
+//! -2. `hypot(a, b)` doesn't overflow when the squares of its arguments would.
+//!
+//! `root(x, n)` is the `n`th root of `x`. Odd roots of negative numbers are
+//! negative, so `root(-8, 3)` is -2, while even roots of them and roots of
+//! degree 0 are domain errors.
+//!
+//! `tan` has no exact poles in floating point, so at odd multiples of `pi/2` it
+//! returns a very large value rather than an error.
+//!
+//! Because a comma between groups of three digits is a thousands separator,
+//! arguments should be separated by `, ` with a space when the next argument
+//! has exactly three digits.
+//!
+//! ```
+//! use calculator_rust::{AngleMode, EvalOptions, evaluate, evaluate_with_options};
+//!
+//! assert_eq!(evaluate("sqrt(16)+1"), Ok(5.0));
+//! assert_eq!(evaluate("hypot(3, 4)"), Ok(5.0));
+//! assert_eq!(evaluate("root(27, 3)"), Ok(3.0));
+//! assert_eq!(evaluate("sqrt(-4)").unwrap_err().to_string(), "domain error: sqrt is undefined for -4");
+//! assert_eq!(evaluate("|2-5|*2"), Ok(6.0));
+//! assert_eq!(evaluate("sin(0)+cos(0)"), Ok(1.0));
+//!
+//! let degrees = EvalOptions::new().angle_mode(AngleMode::Degrees);
+//! assert_eq!(evaluate_with_options("sin(90)", &degrees), Ok(1.0));
+//! assert_eq!(evaluate_with_options("asin(1)", &degrees), Ok(90.0));
+//!
+//! assert_eq!(evaluate("log(8, 2)"), Ok(3.0));
+//! assert_eq!(evaluate("ln(0)").unwrap_err().to_string(), "domain error: ln is undefined for 0");
+//! assert_eq!(evaluate("gamma(5)"), Ok(24.0));
+//! assert_eq!(evaluate("round(3.14159, 2)"), Ok(3.14));
+//! assert_eq!(evaluate("max(1, 5, 3)"), Ok(5.0));
+//! assert_eq!(evaluate("sum(i, 1, 10, i^2)"), Ok(385.0));
+//! assert_eq!(evaluate("solve(2*x + 3 = 11, x)"), Ok(4.0));
+//! assert_eq!(evaluate("powmod(7, 128, 13)"), Ok(3.0));
+//! ```
+//!
+//! # Numbers
+//!
+//! Numbers are decimal, optionally with a fractional part. Digits may be grouped
+//! with `_` separators (`1_000_000`) or with commas between groups of exactly
+//! three digits (`1,234,567.89`). A comma that does not fit that pattern is not
+//! part of a number. An exponent may follow the digits, as in `1.5e3` or `2E-4`;
+//! `e` only starts an exponent when digits follow, otherwise it is the constant.
+//!
+//! Integers can also be written in hexadecimal, binary or octal with a `0x`,
+//! `0b` or `0o` prefix. Such a literal must be exactly representable as an
+//! `f64`, so it may have at most 53 significant bits. Going the other way,
+//! `hex(n)`, `bin(n)` and `oct(n)` write a non-negative integer in those bases,
+//! as text returned by [`evaluate_value`].
+//!
+//! A number in any base from 2 to 36 is written `base#digits`, as in `16#FF`
+//! or `36#Z`, with the letters `a` to `z` in either case standing for the
+//! digits from 10 up. It may have a fractional part after the decimal point,
+//! as in `2#0.1`. A `#` only starts a comment after whitespace or a non-digit,
+//! so `3#note` is an error while `3 #note` is a comment.
+//!
+//! An angle can be written in degrees, minutes and seconds, as in `45°30'15"`,
+//! which is the number of degrees `45.504166...`. The minutes and seconds are
+//! optional (`45°`, `45°30'`), must be less than 60, and may also be marked
+//! with `′` and `″`. Only the last part present may have a fractional part.
+//! Since the `°` says the angle is in degrees, its value is in the unit of the
+//! angle mode: `90°` is `π/2` in radian mode and `90` in degree mode, so
+//! `sin(90°)` is `1` in both. A `°` anywhere but after a number is an error.
+//! `dms(x)` converts a number of degrees back for display with
+//! [`evaluate_value`]; see [`dms`].
+//!
+//! ```
+//! use calculator_rust::{AngleMode, EvalOptions, evaluate, evaluate_with_options};
+//!
+//! assert_eq!(evaluate("1_000_000*3"), Ok(3000000.0));
+//! assert_eq!(evaluate("1,234.56 + 2,000"), Ok(3234.56));
+//! assert_eq!(evaluate("1.5e3+2E-1"), Ok(1500.2));
+//! assert_eq!(evaluate("0xFF+1"), Ok(256.0));
+//! assert_eq!(evaluate("0b1010+0o10"), Ok(18.0));
+//! assert_eq!(evaluate("36#Z+1"), Ok(36.0));
+//! assert_eq!(evaluate("16#ff - 16#F0"), Ok(15.0));
+//!
+//! let degrees = EvalOptions::new().angle_mode(AngleMode::Degrees);
+//! let in_degrees = |input| evaluate_with_options(input, &degrees);
+//! assert_eq!(in_degrees("45°30'15\""), Ok(45.0 + 30.0 / 60.0 + 15.0 / 3600.0));
+//! assert_eq!(in_degrees("45°"), Ok(45.0));
+//! assert_eq!(evaluate("90°"), Ok(std::f64::consts::FRAC_PI_2));
+//! ```
+//!
+//! A duration can be written in hours and minutes, as in `1:30`, or with
+//! seconds as well, as in `1:30:15`. Its value is the number of seconds, and
+//! [`evaluate_value`] shows a result that is a duration as `h:mm` or
+//! `h:mm:ss`. The minutes and seconds take exactly two digits and must be less
+//! than 60. Durations add to and subtract from each other, and can be
+//! multiplied or divided by a number, but a plain number can't be added to
+//! one. Dividing two durations gives their ratio. Inside `?:`, a colon after a
+//! number belongs to the conditional, so a duration there needs parentheses:
+//! `c ? (1:30) : 0:45`.
+//!
+//! ```
+//! use calculator_rust::{evaluate, evaluate_value};
+//!
+//! assert_eq!(evaluate_value("1:30 + 0:45").unwrap().to_string(), "2:15");
+//! assert_eq!(evaluate_value("7:15 * 2").unwrap().to_string(), "14:30");
+//! assert_eq!(evaluate("1:30"), Ok(5400.0));
+//! assert_eq!(
+//!     evaluate("1:30 + 5").unwrap_err().to_string(),
+//!     "cannot add a number to a duration at position 5"
+//! );
+//! ```
+//!
+//! A number may start with a currency symbol, `$`, `€`, `£` or `¥`, as in
+//! `$1,299.99`. The symbol doesn't change the number, but [`evaluate_value`]
+//! shows the result of an expression with one as an amount of money with two
+//! decimals. All the symbols in an expression must be the same. Clear
+//! [`EvalOptions::currency_symbols`] to reject them.
+//!
+//! ```
+//! use calculator_rust::{evaluate, evaluate_value};
+//!
+//! assert_eq!(evaluate_value("$1,299.99 + $49.99").unwrap().to_string(), "$1349.98");
+//! assert_eq!(evaluate_value("£10 / 3").unwrap().to_string(), "£3.33");
+//! assert_eq!(evaluate("$1,299.99 + $49.99"), Ok(1349.98));
+//! assert_eq!(
+//!     evaluate("$5 + €3").unwrap_err().to_string(),
+//!     "cannot mix currencies '$' and '€' at position 5"
+//! );
+//! ```
+//!
+//! With [`EvalOptions::si_suffixes`], a number may end in an SI prefix that
+//! scales it by a power of ten: `k` (10^3), `M` (10^6), `G` (10^9), `T`
+//! (10^12), `m` (10^-3), `u` or `µ` (10^-6), `n` (10^-9) and `p` (10^-12).
+//! Prefixes are case-sensitive, so `1m` is a thousandth and `1M` a million. A
+//! prefix must be the last letter of the number, so `3kg` is `3*kg` (or 3
+//! kilograms with units), and it can't follow an exponent.
+//!
+//! ```
+//! use calculator_rust::{EvalOptions, evaluate, evaluate_with_options};
+//!
+//! let si = EvalOptions::new().si_suffixes(true);
+//! assert_eq!(evaluate_with_options("4.7k*2", &si), Ok(9400.0));
+//! assert_eq!(evaluate_with_options("100n*1e9", &si), Ok(100.0));
+//! assert_eq!(evaluate_with_options("1M / 1m", &si), Ok(1e9));
+//! assert!(evaluate("4.7k").unwrap_err().to_string().contains("undefined variable 'k'"));
+//! ```
+//!
+//! With [`Separator::Comma`] as the [`EvalOptions::decimal_separator`], numbers
+//! are written the European way, with a decimal comma and `.` grouping
+//! thousands: `3,5` and `1.234,5`. A comma is only a decimal point directly
+//! between two digits, so function arguments must be separated by a comma and
+//! a space (`max(1, 2,5)` is `2,5`) or by a comma next to a name or a
+//! parenthesis (`max(x,y)`). `max(1,2)` has the single argument `1,2`.
+//!
+//! ```
+//! use calculator_rust::{EvalOptions, Separator, evaluate_with_options};
+//!
+//! let comma = EvalOptions::new().decimal_separator(Separator::Comma);
+//! assert_eq!(evaluate_with_options("3,5+1,25", &comma), Ok(4.75));
+//! assert_eq!(evaluate_with_options("1.234,5 * 2", &comma), Ok(2469.0));
+//! assert_eq!(evaluate_with_options("max(1, 2,5)", &comma), Ok(2.5));
+//! assert_eq!(
+//!     evaluate_with_options("max(1,2)", &comma).unwrap_err().to_string(),
+//!     "max expects at least 2 arguments, got 1"
+//! );
+//! ```
+//!
+//! Digits and operators typed with an East Asian input method, such as
+//! `１２３＋４`, are read as their ASCII equivalents, and so are Arabic-Indic
+//! digits, so `٣.٥` is `3.5`. This covers the full-width digits, `＋`, `－`, `＊`, `／`, `％`, `＾`, `＝`, `，`, `．` and
+//! parentheses. Error positions are byte offsets into the input as it was
+//! typed, so they point at the character written there. Clear
+//! [`EvalOptions::normalize_unicode`] to reject them instead.
+//!
+//! With the same option, superscripts directly after a number, a name or `)`
+//! are a power, so `5²` is `25`, `x³` is `x^3` and `2⁻¹` is `0.5`. Vulgar
+//! fractions such as `½`, `⅓` and `¾` stand for their value, and one directly
+//! after a whole number is added to it, so `1½` is `1.5`, while `2·½` or `2(½)`
+//! is `1`.
+//!
+//! ```
+//! use calculator_rust::{CalcError, Span, evaluate, render_diagnostic, validate};
+//!
+//! assert_eq!(evaluate("１２３＋４"), Ok(127.0));
+//! assert_eq!(evaluate("max（1，２）"), Ok(2.0));
+//! assert_eq!(evaluate("5²"), Ok(25.0));
+//! assert_eq!(evaluate("1½+¼"), Ok(1.75));
+//! assert_eq!(evaluate("１＋＊２").unwrap_err().to_string(), "unexpected '*' at position 6");
+//! assert_eq!(
+//!     evaluate("½ + )"),
+//!     Err(CalcError::Syntax {
+//!         message: "unmatched closing parenthesis".to_string(),
+//!         span: Span { start: 5, end: 6 },
+//!     })
+//! );
+//! let diagnostics = validate("１２＋*3").unwrap_err();
+//! assert_eq!(diagnostics[0].span, Span { start: 9, end: 10 });
+//! assert_eq!(
+//!     render_diagnostic("１２＋*3", &diagnostics[0]),
+//!     "１２＋*3\n      ^ unexpected '*' at position 9"
+//! );
+//! assert_eq!(
+//!     evaluate("１２ + ⅓/0"),
+//!     Err(CalcError::DivisionByZero { span: Some(Span { start: 12, end: 13 }) })
+//! );
+//! ```
+//!
+//! `√` and `∛` before an operand are its square and cube root, whatever the
+//! option. They take a number, a name, a call or a group in parentheses before
+//! any other operator, so `√9+7` is `10` and `√(9+7)` is `4`, and a number
+//! directly before one multiplies it, so `2√9` is `6`. `√` of a negative
+//! number is a domain error, as for `sqrt`.
+//!
+//! ```
+//! use calculator_rust::evaluate;
+//!
+//! assert_eq!(evaluate("√9+7"), Ok(10.0));
+//! assert_eq!(evaluate("√(9+7)"), Ok(4.0));
+//! assert_eq!(evaluate("∛27 - ∛-8"), Ok(5.0));
+//! assert_eq!(evaluate("2√9"), Ok(6.0));
+//! assert_eq!(evaluate("√-4").unwrap_err().to_string(), "domain error: sqrt is undefined for -4");
+//! ```
+//!
+//! With [`EvalOptions::number_words`], English number words and the operator
+//! words `plus`, `minus`, `times`, `divided by` and `over` are read as digits
+//! and symbols, for text from voice dictation such as `two plus three times
+//! four`. Any other word must be a name the calculator knows, or an error
+//! names it. [`words_to_expression`] shows the conversion.
+//!
+//! ```
+//! use calculator_rust::{Context, EvalOptions, evaluate_with_context, evaluate_with_options};
+//!
+//! let words = EvalOptions::new().number_words(true);
+//! assert_eq!(evaluate_with_options("two plus three times four", &words), Ok(14.0));
+//! assert_eq!(evaluate_with_options("one hundred twenty-three minus 23", &words), Ok(100.0));
+//! assert_eq!(evaluate_with_options("nine over two", &words), Ok(4.5));
+//! assert_eq!(
+//!     evaluate_with_options("two plus banana", &words).unwrap_err().to_string(),
+//!     "unrecognized word 'banana' at position 9"
+//! );
+//! assert!(evaluate_with_options("two plus three", &EvalOptions::default()).is_err());
+//!
+//! let mut context = Context::with_options(words);
+//! assert_eq!(evaluate_with_context("width = twelve", &mut context), Ok(12.0));
+//! assert_eq!(evaluate_with_context("width times three", &mut context), Ok(36.0));
+//! ```
+//!
+//! # Complex numbers
+//!
+//! With the `complex` feature enabled, `evaluate_complex` evaluates the same
+//! syntax over the complex numbers, with `i` as the imaginary unit, and returns
+//! a `Complex` that prints as `a+bi`. In that mode `sqrt(-1)` is `i` rather than
+//! a domain error.
+//!
+//! # Decimal numbers
+//!
+//! With the `decimal` feature enabled, `evaluate_decimal` computes in base 10,
+//! keeping the digits that were written, so `0.1+0.2` is exactly `0.3` and
+//! `1.10*3` is `3.30`. Results that don't terminate, such as `1/3`, are rounded
+//! to `EvalOptions::decimal_scale` digits with ties going to the even digit.
+//! Setting `EvalOptions::number_mode` to `NumberMode::Decimal` makes the other
+//! evaluation functions use decimal arithmetic and convert the result to `f64`.
+//!
+//! # Batch evaluation
+//!
+//! `evaluate_batch` evaluates many independent expressions, such as the
+//! formulas of a spreadsheet, returning a result for each in order. With the
+//! `parallel` feature enabled, `evaluate_batch_parallel` does the same on
+//! several threads with rayon.
+//!
+//! # Exact arithmetic
+//!
+//! [`evaluate_exact`] evaluates with exact fractions instead of floating point,
+//! so `0.1+0.2` is exactly `3/10`. Operations that leave the rational numbers,
+//! such as `sqrt(2)`, fall back to `f64` and mark the result as approximate.
+//!
+//! Going the other way, `frac(x)` shows a floating point result as the nearest
+//! fraction with a denominator up to 10000, as text returned by
+//! [`evaluate_value`], adding `(approx)` when the fraction isn't exactly `x`.
+//! [`to_fraction`] takes another bound.
+//!
+//! [`format_repeating`] writes a fraction as a decimal with its repeating
+//! digits in parentheses, so `22/7` is `3.(142857)`. The REPL shows results
+//! that way after `:set display repeating`, when they are exactly a fraction
+//! with a denominator up to 10000, until `:set display decimal`.
+//!
+//! ```
+//! use calculator_rust::{evaluate, evaluate_exact, evaluate_value};
+//!
+//! assert_eq!(evaluate("0.1+0.2"), Ok(0.30000000000000004));
+//! assert_eq!(evaluate_exact("0.1+0.2").unwrap().to_string(), "3/10 (0.3)");
+//! assert_eq!(evaluate_value("frac(0.375)").unwrap().to_string(), "3/8");
+//! assert_eq!(evaluate_value("frac(-6/4)").unwrap().to_string(), "-3/2");
+//! assert_eq!(evaluate_value("frac(0.1+0.2)").unwrap().to_string(), "3/10 (approx)");
+//! ```
+//!
+//! # Integer arithmetic
+//!
+//! [`evaluate_integer`] computes with 128-bit integers instead, so large
+//! products and powers keep all of their digits. Decimals and functions fall
+//! back to floating point, which the [`IntegerResult`] makes visible.
+//!
+//! ```
+//! use calculator_rust::{evaluate, evaluate_integer};
+//!
+//! assert_eq!(evaluate("999999999999*999999999999"), Ok(999999999998000000000000.0));
+//! assert_eq!(
+//!     evaluate_integer("999999999999*999999999999").unwrap().to_string(),
+//!     "999999999998000000000001"
+//! );
+//! ```
+//!
+//! # Interval arithmetic
+//!
+//! [`evaluate_interval`] shows how much rounding error a floating point result
+//! can carry. It returns an [`Interval`] whose bounds contain the exact result.
+//!
+//! ```
+//! use calculator_rust::evaluate_interval;
+//!
+//! let result = evaluate_interval("(1e16 + 1.1) - 1e16").unwrap();
+//! assert!(result.contains(1.1));
+//! assert!(result.width() > 1.0);
+//! ```
+//!
+//! # Uncertainties
+//!
+//! A measurement with an uncertainty is written `5 ± 0.1`, or `5 +- 0.1`,
+//! and [`evaluate_value`] gives the result as a [`Value::Uncertain`]. The
+//! uncertainties propagate to first order through `+`, `-`, `*`, `/`, `^` and
+//! functions of one argument such as `sqrt` and `sin`, and add linearly, so
+//! that the result covers the largest error the inputs allow. Numbers without
+//! one are exact. It is shown rounded to two significant figures, with the
+//! value rounded to match. Other operators and functions, and conditions,
+//! can't take an uncertain value.
+//!
+//! `+-` only stands for `±` after an operand, so `3+-2` is `3 ± 2` while
+//! `3+ -2` and `2*+-3` are still signs.
+//!
+//! ```
+//! use calculator_rust::evaluate_value;
+//!
+//! assert_eq!(evaluate_value("(5 ± 0.1) * (2 ± 0.05)").unwrap().to_string(), "10 ± 0.45");
+//! assert_eq!(evaluate_value("(10 ± 0.1) - (4 ± 0.2)").unwrap().to_string(), "6 ± 0.3");
+//! assert_eq!(evaluate_value("sqrt(16 ± 0.4)").unwrap().to_string(), "4 ± 0.05");
+//! assert_eq!(
+//!     evaluate_value("(2 ± 0.1) < 3").unwrap_err().to_string(),
+//!     "'<' has no rule for propagating an uncertainty at position 11"
+//! );
+//! ```
+//!
+//! # Lists
+//!
+//! A list of numbers is written `[1, 2, 3]`, and [`evaluate_value`] gives it
+//! as a [`Value::List`]. Operators apply to each item: a number pairs with
+//! every item, and two lists pair up item by item, so they must have the same
+//! length. Functions that take any number of arguments, such as `sum`, `mean`
+//! and `max`, take the items of a list as their arguments, while other
+//! built-in functions apply to each item. Items are numbers, not lists, and a
+//! list result leaves `ans` unchanged. A variable assigned a list, as in
+//! `c = [1, -3, 2]`, holds it for later lines.
+//!
+//! `dot(a, b)` and `cross(a, b)` give the dot and cross products of two
+//! lists, and `norm(a)` the length of one. `polyval(c, x)` evaluates the
+//! polynomial with coefficients `c`, highest degree first, at `x`, so
+//! `polyval([1, -3, 2], x)` is `x^2 - 3x + 2`. `divmod(a, b)` gives the list
+//! of the quotient and remainder of two integers, `[a // b, mod(a, b)]`, so
+//! the quotient rounds down and the remainder has the sign of `b`, and
+//! `b * q + r` is `a`. `cf(x, n)` gives the first `n` terms, up to 64, of
+//! the continued fraction of `x`, so `cf(pi, 4)` is `[3, 7, 15, 1]`. The
+//! terms stop early where the expansion ends, as it does for a fraction, or
+//! where rounding error could change the next term. As in function arguments, a comma
+//! followed by three digits groups thousands, so write `[1, 234]` with a
+//! space.
+//!
+//! ```
+//! use calculator_rust::{Value, evaluate_value};
+//!
+//! assert_eq!(evaluate_value("[1, 2, 3] * 2"), Ok(Value::List(vec![2.0, 4.0, 6.0])));
+//! assert_eq!(evaluate_value("[1, 2, 3] + [10, 20, 30]").unwrap().to_string(), "[11, 22, 33]");
+//! assert_eq!(evaluate_value("sqrt([4, 9, 16])").unwrap().to_string(), "[2, 3, 4]");
+//! assert_eq!(evaluate_value("sum([1, 2, 3])"), Ok(Value::Number(6.0)));
+//! assert_eq!(evaluate_value("dot([1, 2, 3], [4, 5, 6])"), Ok(Value::Number(32.0)));
+//! assert_eq!(evaluate_value("cross([1, 0, 0], [0, 1, 0])").unwrap().to_string(), "[0, 0, 1]");
+//! assert_eq!(evaluate_value("polyval([1, -3, 2], 5)"), Ok(Value::Number(12.0)));
+//! assert_eq!(evaluate_value("divmod(17, 5)"), Ok(Value::List(vec![3.0, 2.0])));
+//! ```
+//!
+//! ```
+//! use calculator_rust::{Context, Value, evaluate_value_with_context};
+//!
+//! let mut context = Context::new();
+//! evaluate_value_with_context("c = [1, -3, 2]", &mut context).unwrap();
+//! assert_eq!(evaluate_value_with_context("polyval(c, 2 + 1)", &mut context), Ok(Value::Number(2.0)));
+//! assert_eq!(evaluate_value_with_context("c * 2", &mut context).unwrap().to_string(), "[2, -6, 4]");
+//! assert_eq!(context.ans(), None);
+//! evaluate_value_with_context("qr = divmod(23, 4)", &mut context).unwrap();
+//! assert_eq!(context.get_list("qr"), Some(&[5.0, 3.0][..]));
+//! assert_eq!(evaluate_value_with_context("dot(qr, [4, 1])", &mut context), Ok(Value::Number(23.0)));
+//! assert_eq!(evaluate_value_with_context("c = 4; c * 2", &mut context), Ok(Value::Number(8.0)));
+//! ```
+//!
+//! # Units
+//!
+//! [`evaluate_units`] evaluates quantities with units of length, mass, time,
+//! data and temperature, and returns a [`Quantity`] that carries its unit. Quantities that
+//! measure the same thing can be added and compared, and `in` or `to` converts
+//! to another unit. The full list is available from [`units`].
+//!
+//! ```
+//! use calculator_rust::evaluate_units;
+//!
+//! assert_eq!(evaluate_units("5 km + 300 m").unwrap().to_string(), "5.3 km");
+//! assert_eq!(evaluate_units("1.5 h to min").unwrap().to_string(), "90 min");
+//! assert!(evaluate_units("1 km + 1 s").unwrap_err().to_string().starts_with("incompatible units"));
+//! ```
+//!
+//! # Variables
+//!
+//! [`evaluate_with_context`] accepts assignments of the form `name = expr`,
+//! which store the value in a [`Context`] and return it. Any other name in an
+//! expression is looked up among the constants and then the variables.
+//! Variable names follow the same rules as other names: letters, digits and
+//! `_`, not starting with a digit. The built-in constants can't be reassigned.
+//!
+//! `ans` holds the result of the previous successful evaluation in the same
+//! context, so calculations can be chained. It is set by assignments too, and
+//! can't be assigned directly. [`continue_previous`] turns input that starts
+//! with an operator, such as `*2`, into `ans*2`, which is what the REPL does.
+//! A [`Calculator`] bundles a context with the history of what was evaluated
+//! in it, continuing such input by itself.
+//!
+//! Functions can be defined too, as in `f(x) = x^2 + 1`, and then called like
+//! the built-in ones. Defining a function again replaces it. The body sees its
+//! parameters and the variables of the context at the time of the call, and
+//! an error in it points at the call. A definition returns NaN, or a
+//! [`Value::Definition`] from [`evaluate_value_with_context`], and does not
+//! change `ans`. Built-in functions can't be redefined, and functions can't
+//! call themselves, directly or indirectly.
+//!
+//! ```
+//! use calculator_rust::{Context, evaluate_with_context};
+//!
+//! let mut context = Context::new();
+//! assert_eq!(evaluate_with_context("x = 5", &mut context), Ok(5.0));
+//! assert_eq!(evaluate_with_context("x*2+1", &mut context), Ok(11.0));
+//! assert_eq!(evaluate_with_context("x = x + 1", &mut context), Ok(6.0));
+//! assert_eq!(evaluate_with_context("big_2 = 2x", &mut context), Ok(12.0));
+//! assert_eq!(evaluate_with_context("6*7", &mut context), Ok(42.0));
+//! assert_eq!(evaluate_with_context("ans/2", &mut context), Ok(21.0));
+//! assert_eq!(evaluate_with_context("ans^2+1", &mut context), Ok(442.0));
+//!
+//! let mut context = Context::new();
+//! assert!(evaluate_with_context("f(x) = x^2 + 1", &mut context).unwrap().is_nan());
+//! assert_eq!(evaluate_with_context("f(3)", &mut context), Ok(10.0));
+//! ```
+
+mod angles;
+mod cache;
+mod calculator;
+mod compile;
+#[cfg(feature = "complex")]
+mod complex;
+mod context;
+#[cfg(feature = "decimal")]
+mod decimal;
+mod duration;
+mod error;
+mod eval;
+mod functions;
+mod integer;
+mod interval;
+mod lexer;
+mod list;
+mod options;
+mod parser;
+mod physics;
+mod primes;
+mod random;
+mod rational;
+mod uncertain;
+mod unicode;
+mod units;
+mod value;
+mod words;
+
+pub use angles::{Dms, dms};
+pub use cache::ExprCache;
+pub use calculator::{Calculator, HistoryEntry};
+pub use compile::{CompiledExpr, Vars};
+#[cfg(feature = "complex")]
+pub use complex::{Complex, evaluate_complex};
+pub use context::{Arity, Context};
+#[cfg(feature = "decimal")]
+pub use decimal::{Decimal, evaluate_decimal};
+pub use error::{CalcError, Diagnostic, Severity, render_diagnostic, render_error};
+pub use integer::{IntegerResult, evaluate_integer, evaluate_integer_with_options};
+pub use interval::{Interval, evaluate_interval, evaluate_interval_with_options};
+pub use lexer::{NumberLiteral, Span, Token, TokenKind};
+pub use options::{
+    AngleMode, DivisionByZero, EvalOptions, IntegerDivision, NumberMode, Overflow, RemainderMode,
+    RoundingMode, Separator,
+};
+pub use parser::{BinaryOp, BindingForm, Expr, UnaryOp};
+pub use physics::ConstantSet;
+pub use primes::{Factorization, factorize};
+pub use rational::{
+    DEFAULT_MAX_DENOMINATOR, EvalResult, Ratio, evaluate_exact, format_repeating, to_fraction,
+};
+pub use units::{Quantity, Unit, evaluate_units, evaluate_units_with_context};
+pub use value::Value;
+pub use words::words_to_expression;
+
+use lexer::{Lexer, is_percent_sign};
+
+/// Operators understood by the calculator, as `(symbol, description)` pairs
+/// in order of increasing precedence
+const OPERATORS: &[(&str, &str)] = &[
+    (
+        "in",
+        "unit conversion, 10 mi in km, also written to; see evaluate_units",
+    ),
+    (
+        "? :",
+        "conditional, cond ? a : b evaluates only the selected branch",
+    ),
+    (
+        "or",
+        "logical or, giving 1 or 0 and evaluating the right side only if needed; also ||",
+    ),
+    (
+        "and",
+        "logical and, giving 1 or 0 and evaluating the right side only if needed; also &&",
+    ),
+    (
+        "not",
+        "logical not, written before a comparison or other operand",
+    ),
+    ("==", "equal to, giving 1 or 0"),
+    ("!=", "not equal to, giving 1 or 0"),
+    ("<", "less than, giving 1 or 0"),
+    ("<=", "less than or equal to, giving 1 or 0"),
+    (">", "greater than, giving 1 or 0"),
+    (">=", "greater than or equal to, giving 1 or 0"),
+    ("|", "bitwise or of integers, or |x| for the absolute value"),
+    ("xor", "bitwise exclusive or of integers"),
+    ("^^", "bitwise exclusive or, same as xor"),
+    ("&", "bitwise and of integers"),
+    ("<<", "left shift of an integer"),
+    (">>", "right shift of an integer, keeping the sign"),
+    ("+", "addition"),
+    ("-", "subtraction, or negation before an operand"),
+    ("*", "multiplication"),
+    ("/", "division"),
+    ("//", "floor division, rounding towards negative infinity"),
+    (
+        "%",
+        "remainder, with the sign of the dividend; percent when not followed by a number",
+    ),
+    (
+        "±",
+        "value with an uncertainty, as in 5 ± 0.1; also written +-",
+    ),
+    ("of", "percentage of a value, as in 20% of 50"),
+    ("~", "bitwise complement, written before its operand"),
+    ("√", "square root, written before its operand, as in √9"),
+    ("∛", "cube root, written before its operand, as in ∛27"),
+    ("^", "exponentiation (right-associative)"),
+    ("**", "exponentiation, same as ^"),
+    (
+        "!",
+        "factorial, written after its operand; logical not before one",
+    ),
+];
+
+/// Returns the operators understood by the calculator as `(symbol, description)` pairs
+pub fn operators() -> &'static [(&'static str, &'static str)] {
+    OPERATORS
+}
+
+/// Named constants, as `(name, value)` pairs
+const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", std::f64::consts::TAU),
+    ("phi", 1.618033988749895),
+    ("true", 1.0),
+    ("false", 0.0),
+];
+
+/// Returns the named constants understood by the calculator as `(name, value)` pairs
+pub fn constants() -> &'static [(&'static str, f64)] {
+    CONSTANTS
+}
+
+/// Returns the physical constants as `(name, value, description)`, with names
+/// such as `phys.c` and values in SI units
+pub fn physical_constants() -> Vec<(String, f64, &'static str)> {
+    physics::PHYSICAL_CONSTANTS
+        .iter()
+        .map(|(name, value, description)| {
+            (
+                format!("{}.{}", physics::NAMESPACE, name),
+                *value,
+                *description,
+            )
+        })
+        .collect()
+}
+
+/// Looks up a built-in constant, mathematical or physical, by name
+pub(crate) fn constant(name: &str) -> Option<f64> {
+    CONSTANTS
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|(_, value)| *value)
+        .or_else(|| physics::lookup(name))
+}
+
+/// Returns the built-in functions as `(signature, description)` pairs,
+/// such as `("sqrt(x)", "square root")`
+pub fn functions() -> Vec<(String, &'static str)> {
+    let builtins = functions::FUNCTIONS
+        .iter()
+        .map(|function| (function.name, function.params, function.description));
+    builtins
+        .chain(functions::SPECIAL_FORMS.iter().copied())
+        .map(|(name, params, description)| (format!("{}({})", name, params), description))
+        .collect()
+}
+
+/// Returns the units understood by [`evaluate_units`] as `(symbol, dimension)`
+/// pairs, such as `("km", "length")`
+pub fn units() -> Vec<(&'static str, &'static str)> {
+    units::all().collect()
+}
 
-    let trimmed = input.trim();
+/// Rewrites input that starts with an operator, such as `*2`, to apply to the
+/// previous result in `context`, as `ans*2`, the way a pocket calculator does
+///
+/// Input starting with any binary operator other than `of`, or with a postfix
+/// `!`, continues the previous result, since it couldn't be an expression by
+/// itself. A leading `+` or `-` does so only when followed by whitespace,
+/// because `-5` is a negative number but `- 5` subtracts 5. Any other input is
+/// returned unchanged. Error positions in the rewritten input count the `ans`
+/// that was added.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{Context, continue_previous, evaluate_with_context};
+///
+/// let mut context = Context::new();
+/// assert_eq!(
+///     continue_previous("*2", &context).unwrap_err().to_string(),
+///     "no previous result for the leading '*' to apply to"
+/// );
+/// assert_eq!(continue_previous("6*7", &context), Ok("6*7".to_string()));
+/// evaluate_with_context("6*7", &mut context).unwrap();
+///
+/// assert_eq!(continue_previous("*2", &context), Ok("ans*2".to_string()));
+/// assert_eq!(continue_previous("  / 7", &context), Ok("ans/ 7".to_string()));
+/// assert_eq!(continue_previous("- 2", &context), Ok("ans- 2".to_string()));
+/// assert_eq!(continue_previous("-2", &context), Ok("-2".to_string()));
+/// assert_eq!(continue_previous("+2", &context), Ok("+2".to_string()));
+/// assert_eq!(continue_previous("^2 + 1", &context), Ok("ans^2 + 1".to_string()));
+/// assert_eq!(continue_previous("%5", &context), Ok("ans%5".to_string()));
+/// assert_eq!(continue_previous("|-3|", &context), Ok("|-3|".to_string()));
+/// assert_eq!(continue_previous("of 10", &context), Ok("of 10".to_string()));
+///
+/// let input = continue_previous("*2", &context).unwrap();
+/// assert_eq!(evaluate_with_context(&input, &mut context), Ok(84.0));
+/// let input = continue_previous("- 4", &context).unwrap();
+/// assert_eq!(evaluate_with_context(&input, &mut context), Ok(80.0));
+/// ```
+pub fn continue_previous(input: &str, context: &Context) -> Result<String, CalcError> {
+    let input = input.trim_start();
+    let normalized = unicode::normalize(input, context.options());
+    let Some(Ok(first)) = Lexer::new(&normalized).next() else {
+        return Ok(input.to_string());
+    };
+    let continues = match first.kind {
+        TokenKind::Plus | TokenKind::Minus => {
+            normalized[first.span.end..].starts_with(char::is_whitespace)
+        }
+        // A leading bar opens `|x|`, and `of` needs a percentage before it
+        TokenKind::Pipe | TokenKind::Of => false,
+        TokenKind::Bang => true,
+        ref kind => kind.operator_symbol().is_some(),
+    };
+    if !continues {
+        return Ok(input.to_string());
+    }
+    if context.ans().is_none() {
+        return Err(CalcError::other(format!(
+            "no previous result for the leading '{}' to apply to",
+            &normalized[first.span.start..first.span.end]
+        )));
+    }
+    Ok(format!("ans{}", input))
+}
 
-    // Basic validation: must have at least one digit, one operator, and another digit
-    let has_digit = trimmed.chars().any(|c| c.is_ascii_digit());
-    let has_operator = trimmed.chars().any(|c| matches!(c, '+' | '-' | '*' | '/'));
+/// Returns whether the input is the start of an expression that more input
+/// could complete, such as `2 +` or `max(1, (2`
+///
+/// Input ending after a binary operator, a `,`, an unanswered `?` or inside
+/// unclosed parentheses, brackets or bars is incomplete. Input that is already
+/// complete, or has an error before its end, is not. The REPL keeps reading
+/// on the next line while [`is_incomplete_with_options`] holds for its
+/// settings.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::is_incomplete;
+///
+/// assert!(is_incomplete("2 +"));
+/// assert!(is_incomplete("(1 + 2"));
+/// assert!(is_incomplete("max(1,"));
+/// assert!(is_incomplete("x > 0 ?"));
+/// assert!(is_incomplete("|x - 1"));
+/// assert!(is_incomplete("[1, 2"));
+/// assert!(is_incomplete("x ="));
+/// assert!(is_incomplete("a = 1; 2 *"));
+/// assert!(is_incomplete("3 *\n(4 +\n"));
+/// assert!(!is_incomplete("3 *\n(4 +\n5)"));
+/// assert!(!is_incomplete("2 + 3"));
+/// assert!(!is_incomplete("50%"));
+/// assert!(!is_incomplete("1 +* 2 +"));
+/// assert!(!is_incomplete("(1 + 2))"));
+/// assert!(!is_incomplete(""));
+/// ```
+pub fn is_incomplete(input: &str) -> bool {
+    is_incomplete_with_options(input, &EvalOptions::default())
+}
 
-    if !has_digit || !has_operator {
+/// Returns whether the input could be completed by more input like
+/// [`is_incomplete`], reading it with the parsing settings in `options`, such
+/// as [`EvalOptions::decimal_separator`]
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{EvalOptions, Separator, is_incomplete, is_incomplete_with_options};
+///
+/// let comma = EvalOptions::new().decimal_separator(Separator::Comma);
+/// assert!(is_incomplete_with_options("max(1,5", &comma));
+/// assert!(!is_incomplete_with_options("1,5", &comma));
+/// let words = EvalOptions::new().number_words(true);
+/// assert!(is_incomplete_with_options("two plus", &words));
+/// assert!(!is_incomplete("two plus"));
+/// ```
+pub fn is_incomplete_with_options(input: &str, options: &EvalOptions) -> bool {
+    if input.trim().is_empty() {
         return false;
     }
+    let Ok(input) = &words::rewrite(input, options, |_| true) else {
+        return false;
+    };
+    // These are the only errors the parser reports on running out of input
+    parser::parse_statements(input, options).is_err_and(|e| {
+        matches!(
+            e.inner(),
+            CalcError::UnexpectedEnd { .. } | CalcError::UnclosedDelimiter { .. }
+        )
+    })
+}
+
+/// Splits the input into the tokens that [`evaluate`] reads, each with the
+/// byte range of `input` it was written in
+///
+/// This is the tokenizer behind [`evaluate`], [`validate`],
+/// [`extract_number_literals`] and [`extract_operators`], so a number or operator
+/// found here is one they see too. Characters that are rewritten before
+/// reading, such as full-width digits and superscripts, keep the span of what
+/// was written, so slicing `input` with a span never splits a character.
+/// Errors are those of [`evaluate`], with positions in `input` too.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Span, TokenKind, tokenize};
+///
+/// let tokens = tokenize("2 × π").unwrap();
+/// let kinds: Vec<_> = tokens.iter().map(|token| token.kind.clone()).collect();
+/// assert_eq!(
+///     kinds,
+///     vec![TokenKind::Number(2.0), TokenKind::Star, TokenKind::Identifier("pi".to_string())]
+/// );
+/// let spans: Vec<_> = tokens.iter().map(|token| token.span).collect();
+/// assert_eq!(
+///     spans,
+///     vec![Span { start: 0, end: 1 }, Span { start: 2, end: 4 }, Span { start: 5, end: 7 }]
+/// );
+///
+/// let input = "sqrt(x)·2 ÷ 1,000";
+/// let text: Vec<_> = tokenize(input)
+///     .unwrap()
+///     .iter()
+///     .map(|token| &input[token.span.start..token.span.end])
+///     .collect();
+/// assert_eq!(text, vec!["sqrt", "(", "x", ")", "·", "2", "÷", "1,000"]);
+/// assert_eq!(tokenize("sqrt").unwrap()[0].kind, TokenKind::Identifier("sqrt".to_string()));
+/// assert_eq!(tokenize("  ").unwrap(), vec![]);
+///
+/// // `１２` is read as `12` and `²` as `^2`
+/// let input = "１２²−1";
+/// let tokens = tokenize(input).unwrap();
+/// assert_eq!(tokens[0].kind, TokenKind::Number(12.0));
+/// assert_eq!(tokens[0].span, Span { start: 0, end: 6 });
+/// assert_eq!(tokens[1].kind, TokenKind::Caret);
+/// assert_eq!(&input[tokens[2].span.start..tokens[2].span.end], "²");
+/// assert_eq!(&input[tokens[3].span.start..tokens[3].span.end], "−");
+/// for token in &tokens {
+///     assert!(input.is_char_boundary(token.span.start) && input.is_char_boundary(token.span.end));
+/// }
+///
+/// assert_eq!(
+///     tokenize("2 $ 3"),
+///     Err(CalcError::InvalidCharacter { ch: '$', span: Span { start: 2, end: 3 } })
+/// );
+/// // Positions of errors are in `input` too
+/// assert_eq!(
+///     tokenize("１２ × 3 $"),
+///     Err(CalcError::InvalidCharacter { ch: '$', span: Span { start: 12, end: 13 } })
+/// );
+/// assert_eq!(
+///     tokenize("π × 0b12").unwrap_err().to_string(),
+///     "invalid binary literal '0b12' at position 6"
+/// );
+/// ```
+pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    tokenize_with_options(input, &EvalOptions::default())
+}
 
-    // Validate that it's not just operators or just numbers
-    // Must alternate between numbers and operators (simplified check)
-    let mut prev_was_digit = false;
-    let mut prev_was_operator = false;
-    let mut valid_structure = false;
+/// Splits the input into tokens like [`tokenize`], with the parsing settings
+/// in `options`, such as [`EvalOptions::decimal_separator`]
+///
+/// With [`EvalOptions::number_words`] set, the spans are those of the
+/// expression the words were converted to, as in [`words_to_expression`].
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{EvalOptions, Separator, TokenKind, tokenize_with_options};
+///
+/// let options = EvalOptions::new().decimal_separator(Separator::Comma);
+/// let tokens = tokenize_with_options("3,5+1", &options).unwrap();
+/// assert_eq!(tokens[0].kind, TokenKind::Number(3.5));
+/// assert_eq!(tokens.len(), 3);
+/// ```
+pub fn tokenize_with_options(input: &str, options: &EvalOptions) -> Result<Vec<Token>, CalcError> {
+    let (rewritten, origins) = words::rewrite_with_origins(input, options, |_| true)?;
+    let mut tokens = lexer::tokenize(&rewritten, options).map_err(|e| origins.error(e))?;
+    for token in &mut tokens {
+        token.span = origins.span(token.span);
+    }
+    Ok(tokens)
+}
+
+/// Checks that the input is something [`evaluate`] can read, reporting every
+/// problem it finds rather than only the first
+///
+/// A character that can't start a token is reported and then read as a
+/// space, so that the rest of the input is still checked, which finds an
+/// invalid character and a trailing operator in one pass. The parser stops at
+/// its first problem. A division by a literal zero is a warning, which is
+/// only reported along with the errors, since input with nothing but
+/// warnings is valid. Diagnostics are in the order of their positions, which
+/// are byte offsets into `input`.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{Diagnostic, Severity, Span, validate};
+///
+/// assert_eq!(validate("(3+5)*2"), Ok(()));
+/// assert_eq!(validate("x = 5; f(x) = x^2"), Ok(()));
+/// assert_eq!(validate("1/0"), Ok(()));
+/// assert_eq!(
+///     validate("2 + @3 *"),
+///     Err(vec![
+///         Diagnostic {
+///             span: Span { start: 4, end: 5 },
+///             message: "unexpected character '@' at position 4".to_string(),
+///             severity: Severity::Error,
+///         },
+///         Diagnostic {
+///             span: Span { start: 8, end: 8 },
+///             message: "unexpected end of input, expected a number at position 8".to_string(),
+///             severity: Severity::Error,
+///         },
+///     ])
+/// );
+///
+/// let diagnostics = validate("1 % 0 + @ + 2 // 0").unwrap_err();
+/// let found: Vec<_> = diagnostics.iter().map(|d| (d.span.start, d.severity)).collect();
+/// assert_eq!(
+///     found,
+///     vec![(2, Severity::Warning), (8, Severity::Error), (14, Severity::Warning)]
+/// );
+/// assert_eq!(validate("€").unwrap_err().len(), 1);
+/// assert_eq!(validate("").unwrap_err()[0].message, "empty expression");
+/// ```
+pub fn validate(input: &str) -> Result<(), Vec<Diagnostic>> {
+    validate_with_options(input, &EvalOptions::default())
+}
+
+/// Checks the input like [`validate`], with the parsing settings in
+/// `options`, such as [`EvalOptions::decimal_separator`]
+///
+/// A division by a literal zero isn't a warning when
+/// [`EvalOptions::division_by_zero`] gives infinity for it.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{DivisionByZero, EvalOptions, Separator, validate_with_options};
+///
+/// let options = EvalOptions::new().decimal_separator(Separator::Comma);
+/// assert_eq!(validate_with_options("3,5+1,25", &options), Ok(()));
+///
+/// let infinite = EvalOptions::new().division_by_zero(DivisionByZero::Infinity);
+/// assert_eq!(validate_with_options("1/0 + 2 @", &infinite).unwrap_err().len(), 1);
+/// assert_eq!(validate_with_options("1/0 + 2 @", &EvalOptions::new()).unwrap_err().len(), 2);
+/// ```
+pub fn validate_with_options(input: &str, options: &EvalOptions) -> Result<(), Vec<Diagnostic>> {
+    // Names are only checked once the input is evaluated
+    let (rewritten, origins) = words::rewrite_with_origins(input, options, |_| true)
+        .map_err(|e| vec![Diagnostic::from(e)])?;
+    let mut text = rewritten.into_owned();
 
-    for ch in trimmed.chars() {
-        if ch.is_ascii_digit() || ch == '.' {
-            if prev_was_operator {
-                valid_structure = true; // Found digit after operator
+    let mut diagnostics = Vec::new();
+    while let Err(error) = lexer::tokenize(&text, options) {
+        let span = error.span().filter(|span| span.start < span.end);
+        diagnostics.push(Diagnostic::from(origins.error(error)));
+        // Spaces of the same length keep the positions of what follows
+        match span {
+            Some(span) if text.get(span.start..span.end).is_some() => {
+                text.replace_range(span.start..span.end, &" ".repeat(span.end - span.start));
             }
-            prev_was_digit = true;
-            prev_was_operator = false;
-        } else if matches!(ch, '+' | '-' | '*' | '/') {
-            if prev_was_digit {
-                prev_was_operator = true;
-                prev_was_digit = false;
-            } else {
-                return false; // Two operators in a row or operator at start
+            _ => return Err(diagnostics),
+        }
+    }
+    match parser::parse_statements(&text, options) {
+        Ok(statements) => {
+            for statement in &statements {
+                let expr = match statement {
+                    parser::Statement::Expr(expr) => expr,
+                    parser::Statement::Assign { value, .. } => value,
+                    parser::Statement::Define { body, .. } => body,
+                };
+                if options.division_by_zero == DivisionByZero::Error {
+                    let warnings = diagnostics.len();
+                    zero_divisions(expr, &mut diagnostics);
+                    for warning in &mut diagnostics[warnings..] {
+                        warning.span = origins.span(warning.span);
+                    }
+                }
             }
-        } else if !ch.is_whitespace() {
-            return false; // Invalid character
         }
+        // Input that was nothing but unreadable characters says so already
+        Err(CalcError::EmptyExpression) if !diagnostics.is_empty() => {}
+        Err(e) => diagnostics.push(Diagnostic::from(origins.error(e))),
     }
 
-    // Must end with a digit and have valid structure
-    valid_structure && prev_was_digit
+    if diagnostics.iter().all(|d| d.severity != Severity::Error) {
+        return Ok(());
+    }
+    diagnostics.sort_by_key(|d| d.span.start);
+    Err(diagnostics)
 }
 
-/// Extracts all math operators from the input string
-pub fn extract_operators(input: &str) -> Vec<char> {
-    input
-        .chars()
-        .filter(|c| matches!(c, '+' | '-' | '*' | '/'))
-        .collect()
+/// Adds a warning for each division or remainder by a literal zero in `expr`
+fn zero_divisions(expr: &Expr, diagnostics: &mut Vec<Diagnostic>) {
+    let mut visit = |expr| zero_divisions(expr, diagnostics);
+    match expr {
+        Expr::Number { .. } | Expr::Var { .. } => {}
+        Expr::Unary { operand, .. } => visit(operand),
+        Expr::Binary { op, lhs, rhs, span } => {
+            visit(lhs);
+            visit(rhs);
+            if matches!(op, BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem)
+                && matches!(**rhs, Expr::Number { value, .. } if value == 0.0)
+            {
+                diagnostics.push(Diagnostic {
+                    span: *span,
+                    message: "division by zero".to_string(),
+                    severity: Severity::Warning,
+                });
+            }
+        }
+        Expr::Call { args: items, .. } | Expr::List { items, .. } => items.iter().for_each(visit),
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            visit(condition);
+            visit(then);
+            visit(otherwise);
+        }
+        Expr::Binding { body, args, .. } => {
+            visit(body);
+            args.iter().for_each(visit);
+        }
+    }
 }
 
-/// Extracts all numbers from the input string
-pub fn extract_numbers(input: &str) -> Vec<String> {
-    let mut numbers = Vec::new();
-    let mut current_number = String::new();
+/// Validates if the input string is a valid math equation
+/// Returns true if the tokenizer and parser accept it, so that anything valid
+/// here is also something `evaluate` can compute
+///
+/// # Examples
+/// ```
+/// #![allow(deprecated)]
+/// use calculator_rust::validate_equation;
+///
+/// assert!(validate_equation("(3+5)*2"));
+/// assert!(!validate_equation("(1+2"));
+/// assert!(!validate_equation("3+*2"));
+/// assert!(validate_equation("x = 5"));
+/// assert!(validate_equation("3*4  # area of the rug"));
+/// assert!(!validate_equation("3*  # area"));
+/// ```
+#[deprecated(since = "0.1.0", note = "use `validate`, which says what is wrong")]
+pub fn validate_equation(input: &str) -> bool {
+    validate(input).is_ok()
+}
+
+/// Validates the input like [`validate_equation`], with the parsing settings
+/// in `options`, such as [`EvalOptions::decimal_separator`]
+///
+/// # Examples
+/// ```
+/// #![allow(deprecated)]
+/// use calculator_rust::{EvalOptions, Separator, validate_equation, validate_equation_with_options};
+///
+/// let options = EvalOptions::new().decimal_separator(Separator::Comma);
+/// assert!(validate_equation_with_options("3,5+1,25", &options));
+/// assert!(!validate_equation("3,5+1,25"));
+/// ```
+#[deprecated(
+    since = "0.1.0",
+    note = "use `validate_with_options`, which says what is wrong"
+)]
+pub fn validate_equation_with_options(input: &str, options: &EvalOptions) -> bool {
+    validate_with_options(input, options).is_ok()
+}
+
+/// Lexes as much of the input as possible, pairing each token with whether it
+/// directly follows a complete operand
+///
+/// This is what tells a binary `-` from a sign, a percent sign from a remainder
+/// and a bitwise or from the bars of `|x|`. Closing bars are left out, since
+/// they are neither operators nor numbers.
+fn tokens_in_context(input: &str, options: &EvalOptions) -> Vec<(Token, bool)> {
+    let tokens: Vec<Token> = Lexer::with_options(input, options)
+        .map_while(Result::ok)
+        .collect();
+    in_context(tokens)
+}
+
+/// Pairs each token with whether it directly follows a complete operand, like
+/// [`tokens_in_context`]
+fn in_context(tokens: Vec<Token>) -> Vec<(Token, bool)> {
+    let mut result = Vec::with_capacity(tokens.len());
+    let mut after_operand = false;
+    // Whether we are inside `|x|`, saved on entering each parenthesis
+    let mut in_bars = false;
+    let mut outer_bars = Vec::new();
 
-    for ch in input.chars() {
-        if ch.is_ascii_digit() || ch == '.' {
-            current_number.push(ch);
-        } else if !current_number.is_empty() {
-            numbers.push(current_number.clone());
-            current_number.clear();
+    for (i, token) in tokens.iter().enumerate() {
+        let before = after_operand;
+        after_operand = match token.kind {
+            TokenKind::Percent => is_percent_sign(tokens.get(i + 1).map(|t| &t.kind)),
+            // A bar before an operand opens `|x|`, one after an operand closes
+            // it, or is a bitwise or outside of bars. Closing bars are dropped.
+            TokenKind::Pipe if !before => {
+                in_bars = true;
+                false
+            }
+            TokenKind::Pipe if in_bars => {
+                in_bars = false;
+                continue;
+            }
+            TokenKind::LParen => {
+                outer_bars.push(in_bars);
+                in_bars = false;
+                false
+            }
+            TokenKind::RParen => {
+                in_bars = outer_bars.pop().unwrap_or(false);
+                true
+            }
+            _ => token.kind.ends_operand(),
+        };
+        result.push((token.clone(), before));
+    }
+
+    result
+}
+
+/// Extracts all binary math operators from the input string
+/// Unicode operators such as `×` and `÷` are reported as their ASCII equivalent.
+/// Parentheses are grouping symbols, not operators, and neither a `-` written
+/// as the sign of an operand nor a `%` used as a percent sign is reported
+pub fn extract_operators(input: &str) -> Vec<String> {
+    extract_operators_with_options(input, &EvalOptions::default())
+}
+
+/// Extracts the binary operators of the input like [`extract_operators`],
+/// with the parsing settings in `options`, such as
+/// [`EvalOptions::number_words`]
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{EvalOptions, extract_operators, extract_operators_with_options};
+///
+/// let words = EvalOptions::new().number_words(true);
+/// assert_eq!(extract_operators_with_options("two plus three times four", &words), vec!["+", "*"]);
+/// assert!(extract_operators("two plus three").is_empty());
+/// ```
+pub fn extract_operators_with_options(input: &str, options: &EvalOptions) -> Vec<String> {
+    let Ok(input) = &words::rewrite(input, options, |_| true) else {
+        return Vec::new();
+    };
+    let tokens = tokens_in_context(input, options);
+    let mut operators = Vec::new();
+
+    for (i, (token, after_operand)) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1).map(|(t, _)| &t.kind);
+        if token.kind == TokenKind::Percent && is_percent_sign(next) {
+            continue;
+        }
+        if let Some(op) = token.kind.operator_symbol()
+            && *after_operand
+        {
+            operators.push(op.to_string());
         }
     }
 
-    // Don't forget the last number if the string ends with a digit
-    if !current_number.is_empty() {
-        numbers.push(current_number);
+    operators
+}
+
+/// Extracts the numbers written in the input, each with its value and where
+/// it was written
+///
+/// A sign written before a number is part of it, so `-2*-3` has the numbers
+/// `-2` and `-3`, while the `-` of `1-2` is an operator. The text of each
+/// number is a slice of `input`, from the sign if it has one, with any digit
+/// separators and digits such as full-width ones as they were written, though
+/// a currency symbol is left out. Digits that don't make a number, such as
+/// `1.2.3`, a lone `.` or the `1e5.3` of an exponent with a fraction, are an
+/// error here rather than when the expression is evaluated, and so is
+/// anything else [`tokenize`] rejects, with its span in `input`.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, NumberLiteral, Span, extract_number_literals};
+///
+/// let numbers = extract_number_literals("-2*-3 + 1_000").unwrap();
+/// let values: Vec<f64> = numbers.iter().map(|n| n.value).collect();
+/// assert_eq!(values, vec![-2.0, -3.0, 1000.0]);
+/// assert_eq!(
+///     numbers[1],
+///     NumberLiteral { value: -3.0, text: "-3", span: Span { start: 3, end: 5 } }
+/// );
+/// assert_eq!(numbers[2].text, "1_000");
+/// assert_eq!(extract_number_literals("0xFF+0b1010").unwrap()[0].value, 255.0);
+/// assert_eq!(extract_number_literals("|-3|-1").unwrap()[1].text, "1");
+/// assert_eq!(extract_number_literals("2 × x").unwrap().len(), 1);
+/// assert_eq!(extract_number_literals(""), Ok(vec![]));
+///
+/// assert_eq!(
+///     extract_number_literals("2 + 1.2.3"),
+///     Err(CalcError::InvalidNumber { text: "1.2.3".to_string(), span: Span { start: 4, end: 9 } })
+/// );
+/// assert_eq!(
+///     extract_number_literals("1e5.3 * 2"),
+///     Err(CalcError::InvalidNumber { text: "1e5.3".to_string(), span: Span { start: 0, end: 5 } })
+/// );
+/// assert_eq!(
+///     extract_number_literals("π × .").unwrap_err().span(),
+///     Some(Span { start: 6, end: 7 })
+/// );
+/// assert!(extract_number_literals("1__0").is_err());
+/// ```
+pub fn extract_number_literals(input: &str) -> Result<Vec<NumberLiteral<'_>>, CalcError> {
+    extract_number_literals_with_options(input, &EvalOptions::default())
+}
+
+/// Extracts the numbers written in the input like [`extract_number_literals`],
+/// with the parsing settings in `options`, such as
+/// [`EvalOptions::decimal_separator`]
+///
+/// Since the text of each number is a slice of `input`, number words aren't
+/// converted even with [`EvalOptions::number_words`] set, and are read as
+/// names.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{EvalOptions, Separator, extract_number_literals_with_options};
+///
+/// let options = EvalOptions::new().decimal_separator(Separator::Comma);
+/// let numbers = extract_number_literals_with_options("1.234,5 - -0,25", &options).unwrap();
+/// assert_eq!((numbers[0].value, numbers[0].text), (1234.5, "1.234,5"));
+/// assert_eq!((numbers[1].value, numbers[1].text), (-0.25, "-0,25"));
+/// ```
+pub fn extract_number_literals_with_options<'a>(
+    input: &'a str,
+    options: &EvalOptions,
+) -> Result<Vec<NumberLiteral<'a>>, CalcError> {
+    let (rewritten, origins) = unicode::normalize_with_origins(input, options);
+    let tokens = lexer::tokenize(&rewritten, options).map_err(|e| origins.error(e))?;
+    Ok(signed_numbers(in_context(tokens))
+        .into_iter()
+        .map(|(sign, negative, token)| {
+            let TokenKind::Number(value) = token.kind else {
+                unreachable!("a number token")
+            };
+            let span = origins.span(Span {
+                start: sign,
+                end: token.span.end,
+            });
+            NumberLiteral {
+                value: if negative { -value } else { value },
+                text: &input[span.start..span.end],
+                span,
+            }
+        })
+        .collect())
+}
+
+/// The number tokens of `tokens`, each with the position of the signs written
+/// before it, or its own if there are none, and whether they negate it
+fn signed_numbers(tokens: Vec<(Token, bool)>) -> Vec<(usize, bool, Token)> {
+    let mut numbers = Vec::new();
+    let mut sign: Option<(usize, bool)> = None;
+
+    for (token, after_operand) in tokens {
+        match token.kind {
+            TokenKind::Number(_) => {
+                let (start, negative) = sign.take().unwrap_or((token.span.start, false));
+                numbers.push((start, negative, token));
+            }
+            TokenKind::Minus | TokenKind::Plus if !after_operand => {
+                let (start, negative) = sign.unwrap_or((token.span.start, false));
+                sign = Some((start, negative ^ (token.kind == TokenKind::Minus)));
+            }
+            _ => sign = None,
+        }
     }
 
     numbers
 }
 
+/// Extracts all numbers from the input string, as they were written
+/// A leading minus sign is kept with its number, so `-5+3` yields `["-5", "3"]`,
+/// while a leading plus sign and `_` or `,` digit separators are dropped.
+/// Numbers after one that can't be read are left out.
+///
+/// # Examples
+/// ```
+/// # #![allow(deprecated)]
+/// use calculator_rust::{extract_numbers, extract_operators};
+///
+/// assert_eq!(extract_numbers("-2*-3"), vec!["-2", "-3"]);
+/// assert_eq!(extract_operators("-2*-3"), vec!["*"]);
+/// assert_eq!(extract_operators("7//2+1"), vec!["//", "+"]);
+/// assert_eq!(extract_operators("50%*200"), vec!["*"]);
+/// assert_eq!(extract_operators("6×7−2÷1"), vec!["*", "-", "/"]);
+/// assert_eq!(extract_numbers("1_000_000*3"), vec!["1000000", "3"]);
+/// assert_eq!(extract_numbers("1,234.56 + 2,000"), vec!["1234.56", "2000"]);
+/// assert_eq!(extract_numbers("0xFF+0b1010"), vec!["0xFF", "0b1010"]);
+/// assert_eq!(extract_numbers("|-3|-1"), vec!["-3", "1"]);
+/// assert_eq!(extract_operators("|-3|-1"), vec!["-"]);
+/// assert_eq!(extract_operators("2*3 >= -1"), vec!["*", ">="]);
+/// assert_eq!(extract_operators("|-3| | 4 & ~1"), vec!["|", "&"]);
+/// assert_eq!(extract_operators("1 << 2 >= 3 >> 1"), vec!["<<", ">=", ">>"]);
+/// assert_eq!(extract_numbers("|-3| | 4 ^^ 1"), vec!["-3", "4", "1"]);
+/// assert_eq!(extract_numbers("1 2+3"), vec!["1"]);
+/// ```
+#[deprecated(
+    since = "0.1.0",
+    note = "use `extract_number_literals`, which gives the value of each number and rejects malformed ones"
+)]
+#[allow(deprecated)]
+pub fn extract_numbers(input: &str) -> Vec<String> {
+    extract_numbers_with_options(input, &EvalOptions::default())
+}
+
+/// Extracts all numbers from the input string like [`extract_numbers`], with
+/// numbers written using [`EvalOptions::decimal_separator`]
+///
+/// # Examples
+/// ```
+/// # #![allow(deprecated)]
+/// use calculator_rust::{EvalOptions, Separator, extract_numbers_with_options};
+///
+/// let options = EvalOptions::new().decimal_separator(Separator::Comma);
+/// assert_eq!(extract_numbers_with_options("1.234,5 - -0,25", &options), vec!["1234,5", "-0,25"]);
+/// assert_eq!(extract_numbers_with_options("max(1, 2,5)", &options), vec!["1", "2,5"]);
+/// ```
+#[deprecated(
+    since = "0.1.0",
+    note = "use `extract_number_literals_with_options`, which gives the value of each number and rejects malformed ones"
+)]
+pub fn extract_numbers_with_options(input: &str, options: &EvalOptions) -> Vec<String> {
+    let Ok(input) = &words::rewrite(input, options, |_| true) else {
+        return Vec::new();
+    };
+    let (_, grouping) = options.decimal_separator.marks();
+    signed_numbers(tokens_in_context(input, options))
+        .into_iter()
+        .map(|(_, negative, token)| {
+            let text = input[token.span.start..token.span.end].replace(['_', grouping], "");
+            if negative { format!("-{}", text) } else { text }
+        })
+        .collect()
+}
+
+/// Parses a mathematical expression into the tree that [`evaluate`] computes
+/// the value of, without evaluating it
+///
+/// Names aren't looked up, so an expression may use variables and functions
+/// that don't exist yet. Spans and the positions in errors refer to the
+/// input after the rewriting of [`EvalOptions::normalize_unicode`], which
+/// only differs from `input` where it has characters such as full-width
+/// digits, superscripts and vulgar fractions.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{BinaryOp, CalcError, Expr, Span, parse};
+///
+/// let expr = parse("1 + 2 * x").unwrap();
+/// let Expr::Binary { op: BinaryOp::Add, lhs, rhs, span } = expr else {
+///     panic!("expected an addition");
+/// };
+/// assert_eq!(span, Span { start: 2, end: 3 });
+/// assert_eq!(*lhs, Expr::Number { value: 1.0, span: Span { start: 0, end: 1 } });
+/// let Expr::Binary { op: BinaryOp::Mul, rhs: x, .. } = *rhs else {
+///     panic!("expected a product");
+/// };
+/// assert_eq!(*x, Expr::Var { name: "x".to_string(), span: Span { start: 8, end: 9 } });
+///
+/// let Expr::Call { name, args, .. } = parse("max(1, -y)").unwrap() else {
+///     panic!("expected a call");
+/// };
+/// assert_eq!(name, "max");
+/// assert_eq!(args.len(), 2);
+/// assert!(matches!(parse("|x|"), Ok(Expr::Call { name, .. }) if name == "abs"));
+/// assert_eq!(parse("(2)"), Ok(Expr::Number { value: 2.0, span: Span { start: 1, end: 2 } }));
+/// // `^` is right-associative
+/// let Ok(Expr::Binary { op: BinaryOp::Pow, lhs, rhs, .. }) = parse("2 ^ 3 ^ 2") else {
+///     panic!("expected a power");
+/// };
+/// assert!(matches!(*lhs, Expr::Number { value: 2.0, .. }));
+/// assert!(matches!(*rhs, Expr::Binary { op: BinaryOp::Pow, .. }));
+///
+/// assert_eq!(
+///     parse("2 + * 3"),
+///     Err(CalcError::UnexpectedToken { token: "*".to_string(), span: Span { start: 4, end: 5 } })
+/// );
+/// assert_eq!(
+///     parse("(1 + 2"),
+///     Err(CalcError::UnclosedDelimiter { delimiter: '(', span: Span { start: 0, end: 1 } })
+/// );
+/// assert_eq!(parse("2 +"), Err(CalcError::UnexpectedEnd { span: Span { start: 3, end: 3 } }));
+/// assert_eq!(parse(""), Err(CalcError::EmptyExpression));
+/// assert!(parse("x = 1").unwrap_err().is_parse_error());
+/// ```
+pub fn parse(input: &str) -> Result<Expr, CalcError> {
+    parse_with_options(input, &EvalOptions::default())
+}
+
+/// Parses a mathematical expression like [`parse`], with the parsing settings
+/// in `options`, such as [`EvalOptions::implicit_multiplication`]
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{BinaryOp, EvalOptions, Expr, parse, parse_with_options};
+///
+/// let strict = EvalOptions::new().implicit_multiplication(false);
+/// assert!(matches!(parse("2(3 + 4)"), Ok(Expr::Binary { op: BinaryOp::Mul, .. })));
+/// assert!(parse_with_options("2(3 + 4)", &strict).unwrap_err().is_parse_error());
+/// ```
+pub fn parse_with_options(input: &str, options: &EvalOptions) -> Result<Expr, CalcError> {
+    let (input, origins) = words::rewrite_with_origins(input, options, |_| true)?;
+    parser::parse(&input, options).map_err(|e| origins.error(e))
+}
+
+/// Evaluates a tree from [`parse`], looking names up in `context`
+///
+/// This computes what [`evaluate_with_context`] would for the expression the
+/// tree was parsed from, without reading the input again, so an expression
+/// can be parsed once and evaluated for many values of its variables. Errors
+/// are those of evaluation, such as division by zero or an undefined
+/// variable, since the tree has already been parsed. Nothing in `context`
+/// changes, not even `ans`.
+///
+/// The tree holds each number as an `f64` rather than the digits it was
+/// written with, so evaluation is always in floating point, whatever
+/// [`EvalOptions::number_mode`] says, and a duration such as `1:30` is its
+/// number of seconds.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Context, Span, eval_ast, evaluate, parse};
+///
+/// let expr = parse("x^2 - 3x + 2").unwrap();
+/// let mut context = Context::new();
+/// for x in 0..100 {
+///     context.set("x", x as f64).unwrap();
+///     let expected = evaluate(&format!("{x}^2 - 3*{x} + 2")).unwrap();
+///     assert_eq!(eval_ast(&expr, &context), Ok(expected));
+/// }
+/// assert_eq!(context.ans(), None);
+///
+/// let expr = parse("1 / (x - 3)").unwrap();
+/// context.set("x", 3.0).unwrap();
+/// assert_eq!(
+///     eval_ast(&expr, &context),
+///     Err(CalcError::DivisionByZero { span: Some(Span { start: 2, end: 3 }) })
+/// );
+/// assert_eq!(
+///     eval_ast(&parse("y + 1").unwrap(), &context),
+///     Err(CalcError::UnknownIdentifier { name: "y".to_string(), span: Span { start: 0, end: 1 } })
+/// );
+/// assert_eq!(
+///     eval_ast(&parse("sqrt(-x)").unwrap(), &context),
+///     Err(CalcError::DomainError {
+///         message: "sqrt is undefined for -3".to_string(),
+///         span: Some(Span { start: 0, end: 4 }),
+///     })
+/// );
+/// ```
+pub fn eval_ast(expr: &Expr, context: &Context) -> Result<f64, CalcError> {
+    start_evaluation(context);
+    eval::eval(expr, context)
+}
+
+/// Compiles a mathematical expression to a [`CompiledExpr`], which evaluates
+/// it for many values of its variables faster than [`eval_ast`] does
+///
+/// The expression is parsed once and turned into a flat program for a stack
+/// of numbers. Each variable gets a slot, so evaluating doesn't look names up
+/// or walk a tree, and the constants are read once here. Names of functions
+/// and their numbers of arguments are checked here too. Any name that isn't a
+/// constant is a variable, which fails when evaluated without a value; give
+/// the names with [`compile_with_variables`] to have others fail here.
+///
+/// As with [`eval_ast`], evaluation is in floating point. A compiled
+/// expression only sees the built-in functions, not ones defined or
+/// registered in a [`Context`]. Sums, products, derivatives, integrals and
+/// `solve` are evaluated as trees, so they gain little from compiling.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Context, CompiledExpr, Span, compile, eval_ast, parse};
+///
+/// let compiled = compile("x^2 - 3x + 2").unwrap();
+/// assert_eq!(compiled.variables(), ["x"]);
+/// let mut vars = compiled.vars();
+/// let mut context = Context::new();
+/// let expr = parse("x^2 - 3x + 2").unwrap();
+/// for x in 0..100 {
+///     vars.set("x", x as f64).unwrap();
+///     context.set("x", x as f64).unwrap();
+///     assert_eq!(compiled.eval(&vars), eval_ast(&expr, &context));
+/// }
+///
+/// // Setting a variable by its slot doesn't look its name up
+/// let compiled = compile("hypot(a, b) > 5 ? 1 : sum(k, 1, n, k^2) / c").unwrap();
+/// assert_eq!(compiled.variables(), ["a", "b", "n", "c"]);
+/// let (a, b) = (compiled.slot("a").unwrap(), compiled.slot("b").unwrap());
+/// let mut vars = compiled.vars();
+/// vars.set_slot(a, 3.0);
+/// vars.set_slot(b, 5.0);
+/// assert_eq!(compiled.eval(&vars), Ok(1.0));
+///
+/// // Only the variables whose values are needed must be set
+/// vars.set_slot(b, 4.0);
+/// assert_eq!(
+///     compiled.eval(&vars),
+///     Err(CalcError::UnknownIdentifier { name: "n".to_string(), span: Span { start: 32, end: 33 } })
+/// );
+/// vars.set("n", 3.0).unwrap();
+/// vars.set("c", 0.0).unwrap();
+/// assert_eq!(
+///     compiled.eval(&vars),
+///     Err(CalcError::DivisionByZero { span: Some(Span { start: 40, end: 41 }) })
+/// );
+/// assert!(vars.set("d", 1.0).is_err());
+/// assert_eq!(vars.get("n"), Some(3.0));
+///
+/// assert!(compile("2 * pi * r").unwrap().variables() == ["r"]);
+/// assert_eq!(
+///     compile("sqrt(1, 2) + x").map(|_| ()),
+///     Err(CalcError::ArityMismatch {
+///         name: "sqrt".to_string(),
+///         expected: "1 argument".to_string(),
+///         got: 2,
+///         span: Some(Span { start: 0, end: 4 }),
+///     })
+/// );
+/// assert!(matches!(compile("frob(x)"), Err(CalcError::UnknownFunction { .. })));
+/// assert!(compile("2 +").is_err());
+///
+/// // A compiled expression can be shared between threads
+/// fn assert_send_sync<T: Send + Sync>() {}
+/// assert_send_sync::<CompiledExpr>();
+/// let compiled = compile("x / 2").unwrap();
+/// let halves: Vec<f64> = std::thread::scope(|scope| {
+///     let handles: Vec<_> = (0..4)
+///         .map(|x| {
+///             let compiled = &compiled;
+///             scope.spawn(move || {
+///                 let mut vars = compiled.vars();
+///                 vars.set("x", x as f64).unwrap();
+///                 compiled.eval(&vars).unwrap()
+///             })
+///         })
+///         .collect();
+///     handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+/// });
+/// assert_eq!(halves, [0.0, 0.5, 1.0, 1.5]);
+/// ```
+pub fn compile(input: &str) -> Result<CompiledExpr, CalcError> {
+    compile_with_options(input, &EvalOptions::default())
+}
+
+/// Compiles a mathematical expression like [`compile`], with custom settings
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{AngleMode, EvalOptions, compile_with_options};
+///
+/// let options = EvalOptions::new().angle_mode(AngleMode::Degrees);
+/// let compiled = compile_with_options("sin(angle)", &options).unwrap();
+/// let mut vars = compiled.vars();
+/// vars.set("angle", 90.0).unwrap();
+/// assert_eq!(compiled.eval(&vars), Ok(1.0));
+/// ```
+pub fn compile_with_options(input: &str, options: &EvalOptions) -> Result<CompiledExpr, CalcError> {
+    let expr = parse_with_options(input, options)?;
+    compile::compile(&expr, None, options)
+}
+
+/// Compiles a mathematical expression like [`compile_with_options`], whose
+/// only variables are `variables`, in the order of their slots
+///
+/// Any other name that isn't a constant fails here rather than when the
+/// expression is evaluated.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, EvalOptions, Span, compile_with_variables};
+///
+/// let options = EvalOptions::default();
+/// let compiled = compile_with_variables("price * qty", &["qty", "price", "tax"], &options).unwrap();
+/// assert_eq!(compiled.variables(), ["qty", "price", "tax"]);
+/// assert_eq!(compiled.slot("price"), Some(1));
+/// assert_eq!(
+///     compile_with_variables("price * quantity", &["qty", "price"], &options).map(|_| ()),
+///     Err(CalcError::UnknownIdentifier { name: "quantity".to_string(), span: Span { start: 8, end: 16 } })
+/// );
+/// assert!(compile_with_variables("2e", &["e"], &options).is_err());
+/// ```
+pub fn compile_with_variables(
+    input: &str,
+    variables: &[&str],
+    options: &EvalOptions,
+) -> Result<CompiledExpr, CalcError> {
+    let expr = parse_with_options(input, options)?;
+    compile::compile(&expr, Some(variables), options)
+}
+
 /// Evaluates a mathematical expression and returns the result
-/// Supports +, -, *, / operators with proper precedence (PEMDAS)
+/// Supports +, -, *, /, //, % and ^ operators with proper precedence (PEMDAS)
+/// and parentheses; see the crate documentation for the full syntax
 ///
 /// # Arguments
 /// * `input` - A string slice containing the mathematical equation
 ///
 /// # Returns
 /// * `Ok(f64)` - The calculated result
-/// * `Err(String)` - An error message if evaluation fails
+/// * `Err(CalcError)` - The kind of error if evaluation fails, which displays
+///   as its message
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Span, evaluate};
+///
+/// assert_eq!(evaluate("3+5*2"), Ok(13.0));
+/// assert_eq!(evaluate("10/2-3"), Ok(2.0));
+/// assert_eq!(evaluate("(1+2)*(3+4)"), Ok(21.0));
+/// assert_eq!(evaluate("-2*-3"), Ok(6.0));
+/// assert_eq!(evaluate("15.5+8.2"), Ok(23.7));
+/// assert_eq!(evaluate("10-4-3"), Ok(3.0));
+/// assert_eq!(evaluate("8/4/2"), Ok(1.0));
+/// assert_eq!(evaluate("2*3^2"), Ok(18.0));
+/// assert_eq!(evaluate("2^3^2"), Ok(512.0));
+/// assert_eq!(evaluate("-2^2"), Ok(-4.0));
+/// assert_eq!(evaluate("1/0"), Err(CalcError::DivisionByZero { span: Some(Span { start: 1, end: 2 }) }));
+/// assert_eq!(evaluate("5%0").unwrap_err().to_string(), "division by zero");
+/// assert!(evaluate("(1+2").unwrap_err().to_string().contains("unclosed parenthesis"));
+/// assert!(evaluate("1+2)").unwrap_err().to_string().contains("unmatched closing parenthesis"));
+/// ```
+pub fn evaluate(input: &str) -> Result<f64, CalcError> {
+    evaluate_with_options(input, &EvalOptions::default())
+}
+
+/// Evaluates each of `inputs` like [`evaluate`], returning their results in
+/// the same order
+///
+/// The inputs are independent: nothing one of them does is seen by another,
+/// and one that fails doesn't stop the rest.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Span, evaluate_batch};
+///
+/// let results = evaluate_batch(&["1 + 2", "1 / 0", "2 +", "sqrt(16)", "x = 1"]);
+/// assert_eq!(results.len(), 5);
+/// assert_eq!(results[0], Ok(3.0));
+/// assert_eq!(results[1], Err(CalcError::DivisionByZero { span: Some(Span { start: 2, end: 3 }) }));
+/// assert!(matches!(results[2], Err(CalcError::UnexpectedEnd { .. })));
+/// assert_eq!(results[3], Ok(4.0));
+/// assert!(results[4].is_err());
+/// assert!(evaluate_batch(&[]).is_empty());
+/// ```
+pub fn evaluate_batch(inputs: &[&str]) -> Vec<Result<f64, CalcError>> {
+    inputs.iter().map(|input| evaluate(input)).collect()
+}
+
+/// Evaluates each of `inputs` like [`evaluate_batch`], spreading them over
+/// threads with rayon
+///
+/// The results are the same as those of [`evaluate_batch`], in the same
+/// order, except for inputs that draw random numbers, since each thread has a
+/// random sequence of its own.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{evaluate_batch, evaluate_batch_parallel};
+///
+/// let corpus = [
+///     "1 + 2 * 3", "0.1 + 0.2", "2^0.5", "1 / 0", "(1 + 2", "sin(pi / 6)",
+///     "10 % 3", "2 +", "5!", "frob(2)", "1e308 * 10", "max(1, 2, 5)",
+///     "sqrt(-1)", "x + 1", "ln(0)", "0xFF & 0x0F", "hypot(3)", "6 / 4 // 1",
+/// ];
+/// let inputs: Vec<String> = (0..2000).map(|i| format!("{} + {}", corpus[i % corpus.len()], i)).collect();
+/// let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+///
+/// let sequential = evaluate_batch(&inputs);
+/// let parallel = evaluate_batch_parallel(&inputs);
+/// assert_eq!(format!("{:?}", sequential), format!("{:?}", parallel));
+/// let bits = |results: &[Result<f64, _>]| -> Vec<Option<u64>> {
+///     results.iter().map(|r| r.as_ref().ok().map(|x| x.to_bits())).collect()
+/// };
+/// assert_eq!(bits(&sequential), bits(&parallel));
+/// assert!(sequential.iter().any(Result::is_ok) && sequential.iter().any(Result::is_err));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn evaluate_batch_parallel(inputs: &[&str]) -> Vec<Result<f64, CalcError>> {
+    use rayon::prelude::*;
+
+    inputs.par_iter().map(|input| evaluate(input)).collect()
+}
+
+/// Evaluates a mathematical expression like [`evaluate`], with custom settings
+///
+/// Assignments are not accepted here; use [`evaluate_with_context`] for those.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{
+///     CalcError, DivisionByZero, EvalOptions, Overflow, Span, evaluate, evaluate_with_options,
+/// };
+///
+/// let strict = EvalOptions::new().implicit_multiplication(false);
+/// assert_eq!(evaluate_with_options("2(3+4)", &EvalOptions::default()), Ok(14.0));
+/// assert!(evaluate_with_options("2(3+4)", &strict).is_err());
+///
+/// // The default options give the results of `evaluate`
+/// let defaults = EvalOptions::default();
+/// let cases: [(&str, Result<f64, &str>); 12] = [
+///     ("3+5*2", Ok(13.0)),
+///     ("-7%3", Ok(-1.0)),
+///     ("sin(30)", Ok(30f64.sin())),
+///     ("round(2.5)", Ok(3.0)),
+///     ("１２＋３", Ok(15.0)),
+///     ("1e308*10", Ok(f64::INFINITY)),
+///     ("1/0", Err("division by zero")),
+///     ("172!", Err("factorial of 172 is too large")),
+///     ("sqrt(-1)", Err("domain error: sqrt is undefined for -1")),
+///     ("4.7k", Err("undefined variable 'k' at position 3")),
+///     ("3,5 + 1", Err("digit groups must have three digits in '3,5' at position 0")),
+///     ("(1 + 2", Err("unclosed parenthesis at position 0")),
+/// ];
+/// for (input, expected) in cases {
+///     let expected = expected.map_err(str::to_string);
+///     assert_eq!(evaluate_with_options(input, &defaults).map_err(|e| e.to_string()), expected, "{}", input);
+///     assert_eq!(evaluate(input).map_err(|e| e.to_string()), expected, "{}", input);
+/// }
+///
+/// let infinite = EvalOptions::new().division_by_zero(DivisionByZero::Infinity);
+/// assert_eq!(evaluate_with_options("1/0", &infinite), Ok(f64::INFINITY));
+/// assert_eq!(evaluate_with_options("-1 // 0", &infinite), Ok(f64::NEG_INFINITY));
+/// assert!(evaluate_with_options("5%0", &infinite).unwrap().is_nan());
+///
+/// let checked = EvalOptions::new().overflow(Overflow::Error);
+/// assert_eq!(
+///     evaluate_with_options("1e308*10", &checked),
+///     Err(CalcError::Overflow {
+///         message: "the result of '*' is too large".to_string(),
+///         span: Some(Span { start: 5, end: 6 }),
+///     })
+/// );
+/// assert_eq!(
+///     evaluate_with_options("2^2000", &checked).unwrap_err().to_string(),
+///     "the result of '^' is too large"
+/// );
+/// assert_eq!(evaluate_with_options("1/0", &checked), Err(CalcError::DivisionByZero { span: Some(Span { start: 1, end: 2 }) }));
+/// ```
+pub fn evaluate_with_options(input: &str, options: &EvalOptions) -> Result<f64, CalcError> {
+    let (rewritten, origins) = words::rewrite_with_origins(input, options, |_| false)?;
+    let input = &rewritten;
+    let expr = parser::parse(input, options).map_err(|e| origins.error(e))?;
+    let context = Context::with_options(options.clone());
+    start_evaluation(&context);
+    eval_in_mode(&expr, input, &context).map_err(|e| origins.error(e))
+}
+
+/// Evaluates an expression or an assignment, reading and updating the variables
+/// and settings in `context`
+///
+/// An assignment `name = expr` stores the value of `expr` and returns it. After
+/// every successful evaluation, `ans` refers to the result.
+///
+/// Several statements can be separated by `;`. They run from left to right and
+/// the value of the last one is returned. Nothing runs if any of them fails to
+/// parse, and evaluation stops at the first one that fails, leaving the effects
+/// of the earlier ones in place.
 ///
 /// # Examples
 /// ```
-/// let result = evaluate("3+5*2"); // Returns Ok(13.0)
-/// let result = evaluate("10/2-3"); // Returns Ok(2.0)
+/// use calculator_rust::{Context, evaluate_with_context};
+///
+/// let mut context = Context::new();
+/// assert_eq!(evaluate_with_context("r = 2", &mut context), Ok(2.0));
+/// assert_eq!(evaluate_with_context("pi*r^2", &mut context), Ok(4.0 * std::f64::consts::PI));
+///
+/// assert_eq!(evaluate_with_context("a = 2; b = 3; a*b", &mut context), Ok(6.0));
+/// assert_eq!(evaluate_with_context("6*7; ans/2;", &mut context), Ok(21.0));
+/// assert_eq!(
+///     evaluate_with_context("a = 5; c*a", &mut context).unwrap_err().to_string(),
+///     "error in statement 2: undefined variable 'c' at position 7"
+/// );
+/// assert_eq!(context.get("a"), Some(5.0));
+/// assert!(evaluate_with_context("a = 1; 2+", &mut context).unwrap_err().to_string().starts_with("error in statement 2:"));
+/// assert_eq!(context.get("a"), Some(5.0));
+/// assert!(evaluate_with_context(";;", &mut context).is_err());
 /// ```
-pub fn evaluate(input: &str) -> Result<f64, String> {
-    if !validate_equation(input) {
-        return Err("Invalid equation format".to_string());
+pub fn evaluate_with_context(input: &str, context: &mut Context) -> Result<f64, CalcError> {
+    let (rewritten, origins) =
+        words::rewrite_with_origins(input, context.options(), words::in_context(context))?;
+    let input = &rewritten;
+    let statements =
+        parser::parse_statements(input, context.options()).map_err(|e| origins.error(e))?;
+    start_evaluation(context);
+    let count = statements.len();
+    let mut result = f64::NAN;
+    for (i, statement) in statements.into_iter().enumerate() {
+        result = run_statement(statement, input, context)
+            .map_err(|e| origins.error(e.in_statement(i, count)))?;
     }
+    Ok(result)
+}
 
-    let numbers = extract_numbers(input);
-    let operators = extract_operators(input);
+/// Evaluates an expression like [`evaluate`], except that it may also be a call
+/// to a function that shows its argument as text rather than computing a number
+///
+/// `hex(n)`, `bin(n)` and `oct(n)` write an integer in another base,
+/// `factorize(n)` gives the prime factorization, `dms(x)` an angle in
+/// degrees, minutes and seconds and `frac(x)` the nearest simple fraction. Their result can't be used in arithmetic, so
+/// they must make up the whole expression.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{Value, evaluate_value};
+///
+/// assert_eq!(evaluate_value("hex(255)"), Ok(Value::Text("0xff".to_string())));
+/// assert_eq!(evaluate_value("bin(10)"), Ok(Value::Text("0b1010".to_string())));
+/// assert_eq!(evaluate_value("oct(8)"), Ok(Value::Text("0o10".to_string())));
+/// assert_eq!(evaluate_value("hex(0)").unwrap().to_string(), "0x0");
+/// assert_eq!(evaluate_value("hex(2^53)").unwrap().to_string(), "0x20000000000000");
+/// assert_eq!(evaluate_value("factorize(4!)").unwrap().to_string(), "2^3*3");
+/// assert_eq!(evaluate_value("dms(10.5)").unwrap().to_string(), "10°30'0\"");
+/// assert_eq!(evaluate_value("frac(0.75)"), Ok(Value::Text("3/4".to_string())));
+/// assert_eq!(evaluate_value("2^8 - 1"), Ok(Value::Number(255.0)));
+/// assert_eq!(evaluate_value("0xff + 1").unwrap().to_string(), "256");
+///
+/// assert_eq!(
+///     evaluate_value("hex(-1)").unwrap_err().to_string(),
+///     "hex argument must be a non-negative integer up to 2^53, got -1"
+/// );
+/// assert!(evaluate_value("bin(2.5)").unwrap_err().to_string().contains("must be a non-negative integer"));
+/// assert!(evaluate_value("oct(2^60)").is_err());
+/// assert_eq!(
+///     evaluate_value("hex(255) + 1").unwrap_err().to_string(),
+///     "type error: hex returns text, not a number, so it can't be part of an expression"
+/// );
+/// assert!(evaluate_value("bin(bin(1))").unwrap_err().to_string().starts_with("type error"));
+/// assert_eq!(
+///     evaluate_value("hex(1, 2)").unwrap_err().to_string(),
+///     "hex expects 1 argument, got 2"
+/// );
+/// ```
+pub fn evaluate_value(input: &str) -> Result<Value, CalcError> {
+    let options = EvalOptions::default();
+    let (rewritten, origins) = unicode::normalize_with_origins(input, &options);
+    let input = &rewritten;
+    let expr = parser::parse(input, &options).map_err(|e| origins.error(e))?;
+    let context = Context::with_options(options);
+    start_evaluation(&context);
+    eval_value(&expr, input, &context).map_err(|e| origins.error(e))
+}
 
-    // Parse numbers into f64
-    let mut nums: Vec<f64> = Vec::new();
-    for num_str in numbers {
-        match num_str.parse::<f64>() {
-            Ok(n) => nums.push(n),
-            Err(_) => return Err(format!("Invalid number: {}", num_str)),
+/// Evaluates statements like [`evaluate_with_context`], returning the value of
+/// the last one like [`evaluate_value`]
+///
+/// A text or uncertain result leaves `ans` unchanged, since it isn't a single
+/// number.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{Context, Value, evaluate_value_with_context};
+///
+/// let mut context = Context::new();
+/// assert_eq!(evaluate_value_with_context("x = 3*5", &mut context), Ok(Value::Number(15.0)));
+/// assert_eq!(evaluate_value_with_context("bin(x)", &mut context).unwrap().to_string(), "0b1111");
+/// assert_eq!(evaluate_value_with_context("y = 4; hex(x*y)", &mut context).unwrap().to_string(), "0x3c");
+/// assert_eq!(context.ans(), Some(4.0));
+/// assert!(evaluate_value_with_context("z = hex(1)", &mut context).unwrap_err().to_string().starts_with("type error"));
+/// assert!(evaluate_value_with_context("hex(1); 2", &mut context).unwrap_err().to_string().contains("statement 1"));
+/// assert_eq!(
+///     evaluate_value_with_context("area(w, h) = w*h", &mut context),
+///     Ok(Value::Definition("area(w, h)".to_string()))
+/// );
+/// assert_eq!(context.ans(), Some(4.0));
+/// ```
+pub fn evaluate_value_with_context(input: &str, context: &mut Context) -> Result<Value, CalcError> {
+    let (rewritten, origins) =
+        words::rewrite_with_origins(input, context.options(), words::in_context(context))?;
+    let input = &rewritten;
+    let statements =
+        parser::parse_statements(input, context.options()).map_err(|e| origins.error(e))?;
+    run_statements(statements, input, context).map_err(|e| origins.error(e))
+}
+
+/// Evaluates a line like [`evaluate_value_with_context`], taking its parse
+/// from `cache` if it was parsed before
+pub(crate) fn evaluate_value_cached(
+    input: &str,
+    context: &mut Context,
+    cache: &mut ExprCache,
+) -> Result<Value, CalcError> {
+    let (rewritten, origins) =
+        words::rewrite_with_origins(input, context.options(), words::in_context(context))?;
+    let input = &rewritten;
+    let statements = cache
+        .parse(input, context.options())
+        .map_err(|e| origins.error(e))?;
+    run_statements(statements, input, context).map_err(|e| origins.error(e))
+}
+
+/// Runs the statements parsed from a line of normalized input, returning the
+/// value of the last one
+fn run_statements(
+    mut statements: Vec<parser::Statement>,
+    input: &str,
+    context: &mut Context,
+) -> Result<Value, CalcError> {
+    start_evaluation(context);
+    let count = statements.len();
+    let last = statements
+        .pop()
+        .expect("parse_statements returns at least one statement");
+    for (i, statement) in statements.into_iter().enumerate() {
+        run_statement(statement, input, context).map_err(|e| e.in_statement(i, count))?;
+    }
+    let result = match last {
+        parser::Statement::Expr(expr)
+            if is_text_call(&expr)
+                || list::has_list(&expr, context)
+                || uncertain::has_uncertainty(&expr) =>
+        {
+            eval_value(&expr, input, context)
+        }
+        parser::Statement::Assign { name, value, .. } if list::has_list(&value, context) => {
+            assign_list(&name, &value, input, context).map(|item| match item {
+                list::Item::Number(n) => {
+                    context.set_ans(n);
+                    Value::Number(n)
+                }
+                list::Item::List(items) => Value::List(items),
+            })
+        }
+        parser::Statement::Define {
+            ref name,
+            ref params,
+            ..
+        } => {
+            let signature = format!("{}({})", name, params.join(", "));
+            run_statement(last, input, context).map(|_| Value::Definition(signature))
+        }
+        last => {
+            let is_duration = match &last {
+                parser::Statement::Expr(expr) => duration::is_duration(expr, input),
+                _ => Ok(false),
+            };
+            is_duration.and_then(|is_duration| {
+                let n = run_statement(last, input, context)?;
+                Ok(number_value(n, is_duration, input, context))
+            })
+        }
+    };
+    result.map_err(|e| e.in_statement(count - 1, count))
+}
+
+/// Returns whether `expr` is a call to a function whose result is text
+fn is_text_call(expr: &parser::Expr) -> bool {
+    matches!(expr, parser::Expr::Call { name, .. } if value::text_function(name).is_some())
+}
+
+/// Evaluates an expression that may be a call to a function whose result is
+/// text, in which case only its argument is evaluated as a number
+fn eval_value(expr: &parser::Expr, input: &str, context: &Context) -> Result<Value, CalcError> {
+    if let parser::Expr::Call { name, args, span } = expr
+        && let Some(format) = value::text_function(name)
+    {
+        if let Some(function) = functions::lookup(name) {
+            function.check_arity(args.len()).map_err(|e| e.at(*span))?;
         }
+        let n = eval_in_mode(&args[0], input, context)?;
+        return format(n, context.options())
+            .map(Value::Text)
+            .map_err(|e| e.at(*span));
     }
+    if list::has_list(expr, context) {
+        return Ok(match eval_list(expr, input, context)? {
+            list::Item::Number(n) => Value::Number(n),
+            list::Item::List(items) => Value::List(items),
+        });
+    }
+    if uncertain::has_uncertainty(expr) {
+        let (value, sigma) = uncertain::eval_uncertain(expr, context)?;
+        return Ok(Value::Uncertain { value, sigma });
+    }
+    let n = eval_in_mode(expr, input, context)?;
+    let is_duration = duration::is_duration(expr, input)?;
+    Ok(number_value(n, is_duration, input, context))
+}
 
-    if nums.is_empty() {
-        return Err("No numbers found in equation".to_string());
+/// Evaluates an expression with lists in it, which can't also have durations
+fn eval_list(expr: &parser::Expr, input: &str, context: &Context) -> Result<list::Item, CalcError> {
+    if duration::is_duration(expr, input)? {
+        return Err(CalcError::other("a list can't hold durations"));
     }
+    list::eval_list(expr, context)
+}
 
-    if nums.len() != operators.len() + 1 {
-        return Err("Mismatch between numbers and operators".to_string());
+/// Assigns the result of an expression with lists in it to `name`, which is
+/// a list or, for an aggregate such as `sum([1, 2])`, a number
+fn assign_list(
+    name: &str,
+    expr: &parser::Expr,
+    input: &str,
+    context: &mut Context,
+) -> Result<list::Item, CalcError> {
+    let item = eval_list(expr, input, context)?;
+    match &item {
+        list::Item::Number(n) => context.set(name, *n)?,
+        list::Item::List(items) => context.set_list(name, items.clone())?,
     }
+    Ok(item)
+}
 
-    // First pass: handle multiplication and division (left to right)
-    let mut values = vec![nums[0]];
-    let mut ops = Vec::new();
+/// Wraps a numeric result as a duration if it is one, or as an amount of money
+/// if `input` writes a currency symbol before a number
+fn number_value(n: f64, is_duration: bool, input: &str, context: &Context) -> Value {
+    if is_duration {
+        return Value::Duration(n);
+    }
+    match lexer::currency(input, context.options()) {
+        Some(symbol) => Value::Money { symbol, amount: n },
+        None => Value::Number(n),
+    }
+}
 
-    for (i, op) in operators.iter().enumerate() {
-        match op {
-            '*' => {
-                let last = values.pop().unwrap();
-                values.push(last * nums[i + 1]);
-            }
-            '/' => {
-                let last = values.pop().unwrap();
-                if nums[i + 1] == 0.0 {
-                    return Err("Division by zero".to_string());
-                }
-                values.push(last / nums[i + 1]);
+/// Runs a single statement against `context`, updating `ans` if it has a value
+fn run_statement(
+    statement: parser::Statement,
+    input: &str,
+    context: &mut Context,
+) -> Result<f64, CalcError> {
+    let result = match statement {
+        parser::Statement::Expr(expr) => eval_in_mode(&expr, input, context)?,
+        parser::Statement::Assign { name, value, .. } if list::has_list(&value, context) => {
+            match assign_list(&name, &value, input, context)? {
+                list::Item::Number(n) => n,
+                // A list has no single value and leaves `ans` unchanged
+                list::Item::List(_) => return Ok(f64::NAN),
             }
-            '+' | '-' => {
-                values.push(nums[i + 1]);
-                ops.push(*op);
+        }
+        parser::Statement::Assign { name, value, .. } => {
+            if constant(&name).is_some() {
+                return Err(CalcError::other(format!(
+                    "cannot assign to constant '{}'",
+                    name
+                )));
             }
-            _ => return Err(format!("Unknown operator: {}", op)),
+            let value = eval_in_mode(&value, input, context)?;
+            context.set(&name, value)?;
+            value
         }
-    }
-
-    // Second pass: handle addition and subtraction (left to right)
-    let mut result = values[0];
-    for (i, op) in ops.iter().enumerate() {
-        match op {
-            '+' => result += values[i + 1],
-            '-' => result -= values[i + 1],
-            _ => return Err(format!("Unknown operator: {}", op)),
+        // A definition has no value of its own and leaves `ans` unchanged
+        parser::Statement::Define {
+            name, params, body, ..
+        } => {
+            context.define(&name, context::UserFunction { params, body })?;
+            return Ok(f64::NAN);
         }
+    };
+    context.set_ans(result);
+    Ok(result)
+}
+
+/// Evaluates an expression parsed from `input` with the arithmetic selected by
+/// [`EvalOptions::number_mode`]
+fn eval_in_mode(expr: &parser::Expr, input: &str, context: &Context) -> Result<f64, CalcError> {
+    duration::is_duration(expr, input)?;
+    match context.options().number_mode {
+        NumberMode::Float => eval::eval(expr, context),
+        #[cfg(feature = "decimal")]
+        NumberMode::Decimal => decimal::eval_decimal(expr, input, context).map(|d| d.to_f64()),
     }
+}
 
-    Ok(result)
+/// Prepares per-evaluation state, restarting the random sequence if seeded
+fn start_evaluation(context: &Context) {
+    if let Some(seed) = context.options().seed {
+        random::reseed(seed);
+    }
 }
 
 // Decoration banner
@@ -167,6 +2381,6 @@ pub fn banner() {
     // Decorative input prompt
     println!("┌───────────────────────────────────────────────────┐");
     println!("│  Please enter your math equation:                 │");
-    println!("│  (e.g., 3+5*2, 10/2-3, 15.5+8.2)                  │");
+    println!("│  (e.g., 3+5*2, (10-2)/4, 15.5+8.2)                │");
     println!("└───────────────────────────────────────────────────┘");
 }
@@ -0,0 +1,366 @@
+//! Evaluation of lists such as `[1, 2, 3]`, with element-wise arithmetic
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions::{self, Function, MAX_EXACT_INTEGER};
+use crate::lexer::Span;
+use crate::options::RemainderMode;
+use crate::parser::Expr;
+
+/// A number or a list of numbers
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Item {
+    Number(f64),
+    List(Vec<f64>),
+}
+
+/// Returns whether `expr` writes a list, names a variable in `context` that
+/// holds one or calls a function that returns one from numbers
+pub(crate) fn has_list(expr: &Expr, context: &Context) -> bool {
+    let has_list = |expr| has_list(expr, context);
+    match expr {
+        Expr::List { .. } => true,
+        Expr::Number { .. } => false,
+        Expr::Var { name, .. } => context.get_list(name).is_some(),
+        Expr::Unary { operand, .. } => has_list(operand),
+        Expr::Binary { lhs, rhs, .. } => has_list(lhs) || has_list(rhs),
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => has_list(condition) || has_list(then) || has_list(otherwise),
+        Expr::Call { name, args, .. } => {
+            LIST_RESULTS.contains(&name.as_str()) || args.iter().any(has_list)
+        }
+        Expr::Binding { body, args, .. } => has_list(body) || args.iter().any(has_list),
+    }
+}
+
+/// Evaluates an expression with lists in it, whose result is a list or, for
+/// an aggregate such as `sum([1, 2, 3])`, a number
+///
+/// Operators apply to each item, pairing up the items of two lists, which
+/// must have the same length, and a number with each item of a list. A
+/// function that takes any number of arguments, such as `max`, takes the
+/// items of a list as its arguments, while any other built-in function is
+/// applied to each item.
+pub(crate) fn eval_list(expr: &Expr, context: &Context) -> Result<Item, CalcError> {
+    if !has_list(expr, context) {
+        return eval::eval(expr, context).map(Item::Number);
+    }
+    let eval = |expr| eval_list(expr, context);
+    match expr {
+        Expr::List { items, span } => items
+            .iter()
+            .map(|item| match eval(item)? {
+                Item::Number(n) => Ok(n),
+                Item::List(_) => Err(CalcError::other_at("nested lists are not supported", *span)),
+            })
+            .collect::<Result<_, _>>()
+            .map(Item::List),
+        Expr::Unary { op, operand, span } => element_wise(&[eval(operand)?], *span, |x| {
+            eval::unary(*op, x[0], context.options())
+        }),
+        Expr::Binary { op, lhs, rhs, span } => {
+            element_wise(&[eval(lhs)?, eval(rhs)?], *span, |x| {
+                eval::binary(*op, x[0], x[1], context.options())
+            })
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => match eval(condition)? {
+            Item::Number(n) if n != 0.0 => eval(then),
+            Item::Number(_) => eval(otherwise),
+            Item::List(_) => Err(CalcError::other("a condition can't be a list")),
+        },
+        Expr::Call { name, args, span } => {
+            let builtin = functions::lookup(name).filter(|_| context.native(name).is_none());
+            let Some(function) = builtin else {
+                return Err(
+                    if context.function(name).is_some() || context.native(name).is_some() {
+                        CalcError::other_at(format!("{} can't take a list", name), *span)
+                    } else {
+                        CalcError::UnknownFunction {
+                            name: name.clone(),
+                            span: *span,
+                        }
+                    },
+                );
+            };
+            let args = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            if let Some(call) = list_function(name) {
+                function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+                return call(&args).map_err(|e| e.at(*span));
+            }
+            call(function, &args, *span, context)
+        }
+        Expr::Binding { form, span, .. } => Err(CalcError::other_at(
+            format!("{} can't take a list", form.name()),
+            *span,
+        )),
+        Expr::Var { name, .. } => Ok(Item::List(
+            context.get_list(name).expect("a list variable").to_vec(),
+        )),
+        Expr::Number { .. } => unreachable!("a number has no list"),
+    }
+}
+
+/// A function whose arguments are lists rather than numbers
+type ListFunction = fn(&[Item]) -> Result<Item, CalcError>;
+
+/// Functions whose result is a list even when their arguments are numbers
+const LIST_RESULTS: &[&str] = &["divmod", "cf"];
+
+/// The most terms `cf` gives
+const MAX_CF_TERMS: f64 = 64.0;
+
+/// Functions that take lists as a whole, which are registered with the other
+/// built-in functions
+const LIST_FUNCTIONS: &[(&str, ListFunction)] = &[
+    ("dot", dot),
+    ("cross", cross),
+    ("norm", norm),
+    ("polyval", polyval),
+    ("divmod", divmod),
+    ("cf", continued_fraction),
+];
+
+fn list_function(name: &str) -> Option<ListFunction> {
+    LIST_FUNCTIONS
+        .iter()
+        .find(|(function, _)| *function == name)
+        .map(|(_, call)| *call)
+}
+
+/// The error for a function whose result is a list used as a number
+pub(crate) fn list_result(name: &str) -> CalcError {
+    CalcError::other(format!(
+        "{} returns a list, which isn't a single number",
+        name
+    ))
+}
+
+/// The error for a function of lists given something else, which describes
+/// what it takes
+pub(crate) fn not_a_list(name: &str) -> CalcError {
+    let expected = match name {
+        "dot" => "two lists of equal length",
+        "cross" => "two lists of three numbers",
+        "polyval" => "a list of coefficients",
+        _ => "a list",
+    };
+    CalcError::other(format!("{} expects {}, got a number", name, expected))
+}
+
+/// The items of each argument to `name`, which must all be lists
+fn lists<'a>(name: &str, args: &'a [Item]) -> Result<Vec<&'a [f64]>, CalcError> {
+    args.iter()
+        .map(|arg| match arg {
+            Item::List(items) => Ok(items.as_slice()),
+            Item::Number(_) => Err(not_a_list(name)),
+        })
+        .collect()
+}
+
+fn dot(args: &[Item]) -> Result<Item, CalcError> {
+    let lists = lists("dot", args)?;
+    let (a, b) = (lists[0], lists[1]);
+    if a.len() != b.len() {
+        return Err(CalcError::other(format!(
+            "dot expects two lists of equal length, got {} and {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    Ok(Item::Number(
+        a.iter().zip(b).fold(0.0, |sum, (x, y)| sum + x * y),
+    ))
+}
+
+fn cross(args: &[Item]) -> Result<Item, CalcError> {
+    let lists = lists("cross", args)?;
+    let (&[a0, a1, a2], &[b0, b1, b2]) = (lists[0], lists[1]) else {
+        return Err(CalcError::other(format!(
+            "cross expects two lists of three numbers, got {} and {}",
+            lists[0].len(),
+            lists[1].len()
+        )));
+    };
+    Ok(Item::List(vec![
+        a1 * b2 - a2 * b1,
+        a2 * b0 - a0 * b2,
+        a0 * b1 - a1 * b0,
+    ]))
+}
+
+fn norm(args: &[Item]) -> Result<Item, CalcError> {
+    let lists = lists("norm", args)?;
+    // `hypot` keeps squares of large items from overflowing
+    Ok(Item::Number(
+        lists[0].iter().fold(0.0, |norm, x| norm.hypot(*x)),
+    ))
+}
+
+/// Evaluates the polynomial with coefficients `args[0]`, highest degree first,
+/// at `args[1]` or at each item of it
+fn polyval(args: &[Item]) -> Result<Item, CalcError> {
+    let Item::List(coefficients) = &args[0] else {
+        return Err(not_a_list("polyval"));
+    };
+    if coefficients.is_empty() {
+        return Err(CalcError::other(
+            "polyval expects at least one coefficient, got an empty list",
+        ));
+    }
+    // Horner's method takes one multiplication and one addition per
+    // coefficient, rounding less than adding up powers
+    let at = |x: f64| coefficients.iter().fold(0.0, |sum, c| sum * x + c);
+    Ok(match &args[1] {
+        Item::Number(x) => Item::Number(at(*x)),
+        Item::List(xs) => Item::List(xs.iter().map(|x| at(*x)).collect()),
+    })
+}
+
+/// The quotient and remainder of two integers, rounding the quotient down
+/// like `//` so that the remainder takes the sign of the divisor like `mod`
+fn divmod(args: &[Item]) -> Result<Item, CalcError> {
+    let [Item::Number(a), Item::Number(b)] = args else {
+        return Err(CalcError::other("divmod expects two integers, got a list"));
+    };
+    for n in [a, b] {
+        if n.fract() != 0.0 || !n.is_finite() {
+            return Err(CalcError::other(format!(
+                "divmod requires integers, got {}",
+                n
+            )));
+        }
+    }
+    if *b == 0.0 {
+        return Err(CalcError::division_by_zero());
+    }
+    Ok(Item::List(vec![
+        (a / b).floor(),
+        eval::remainder(*a, *b, RemainderMode::Floored),
+    ]))
+}
+
+/// The first `args[1]` terms of the continued fraction of `args[0]`, which
+/// stop early once rounding error could change the next term
+fn continued_fraction(args: &[Item]) -> Result<Item, CalcError> {
+    let [Item::Number(x), Item::Number(n)] = args else {
+        return Err(CalcError::other(
+            "cf expects a number and a count of terms, got a list",
+        ));
+    };
+    if !x.is_finite() || x.abs() > MAX_EXACT_INTEGER {
+        return Err(CalcError::other(format!(
+            "cf argument must be finite and at most 2^53 in size, got {}",
+            x
+        )));
+    }
+    if n.fract() != 0.0 || !(1.0..=MAX_CF_TERMS).contains(n) {
+        return Err(CalcError::other(format!(
+            "cf count must be a whole number from 1 to {}, got {}",
+            MAX_CF_TERMS, n
+        )));
+    }
+    let mut terms = Vec::new();
+    let mut rest = *x;
+    // How far `rest` may be from the exact expansion of `x`, which taking the
+    // reciprocal of a fractional part `f` scales up by `1/f^2`
+    let mut error = 0.0;
+    while terms.len() < *n as usize && error < 0.5 {
+        let term = rest.floor();
+        let fraction = rest - term;
+        // A fractional part within the error may be none at all, which ends
+        // the expansion of a rational number
+        if fraction <= error {
+            terms.push(term);
+            break;
+        }
+        if 1.0 - fraction <= error {
+            terms.push(term + 1.0);
+            break;
+        }
+        terms.push(term);
+        rest = 1.0 / fraction;
+        error = error / (fraction * fraction) + rest * f64::EPSILON;
+    }
+    Ok(Item::List(terms))
+}
+
+/// Calls a built-in function with arguments of which at least one is a list
+fn call(
+    function: &Function,
+    args: &[Item],
+    span: Span,
+    context: &Context,
+) -> Result<Item, CalcError> {
+    let options = context.options();
+    if function.max_args.is_some() {
+        function.check_arity(args.len()).map_err(|e| e.at(span))?;
+        return element_wise(args, span, |x| (function.call)(x, options));
+    }
+    // The items of each list are arguments in their own right
+    let values: Vec<f64> = args
+        .iter()
+        .flat_map(|arg| match arg {
+            Item::Number(n) => std::slice::from_ref(n),
+            Item::List(items) => items.as_slice(),
+        })
+        .copied()
+        .collect();
+    if values.is_empty() {
+        return Err(CalcError::other_at(
+            format!("{} can't take an empty list", function.name),
+            span,
+        ));
+    }
+    function.check_arity(values.len()).map_err(|e| e.at(span))?;
+    (function.call)(&values, options)
+        .map(Item::Number)
+        .map_err(|e| e.at(span))
+}
+
+/// Applies `f` to the numbers in `args`, or to the items at each index of
+/// the lists in them, with numbers standing for every item. The result is a
+/// list if any argument is one, and its errors point at `span`.
+fn element_wise(
+    args: &[Item],
+    span: Span,
+    f: impl Fn(&[f64]) -> Result<f64, CalcError>,
+) -> Result<Item, CalcError> {
+    let mut len = None;
+    for arg in args {
+        if let Item::List(items) = arg {
+            match len {
+                Some(len) if len != items.len() => {
+                    return Err(CalcError::other_at(
+                        format!("lists of different lengths {} and {}", len, items.len()),
+                        span,
+                    ));
+                }
+                _ => len = Some(items.len()),
+            }
+        }
+    }
+    let f = |x: &[f64]| f(x).map_err(|e| e.at(span));
+    let at = |i: usize| -> Vec<f64> {
+        args.iter()
+            .map(|arg| match arg {
+                Item::Number(n) => *n,
+                Item::List(items) => items[i],
+            })
+            .collect()
+    };
+    match len {
+        None => f(&at(0)).map(Item::Number),
+        Some(len) => (0..len)
+            .map(|i| f(&at(i)))
+            .collect::<Result<_, _>>()
+            .map(Item::List),
+    }
+}
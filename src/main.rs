@@ -1,48 +1,474 @@
-use calculator_rust::{banner, evaluate, extract_numbers, extract_operators, validate_equation};
+#[cfg(feature = "decimal")]
+use calculator_rust::evaluate_decimal;
+use calculator_rust::{
+    AngleMode, CalcError, Calculator, DEFAULT_MAX_DENOMINATOR, EvalOptions, IntegerResult,
+    NumberMode, RemainderMode, RoundingMode, Separator, Value, banner, constants,
+    continue_previous, evaluate_integer_with_options, evaluate_interval_with_options,
+    evaluate_units_with_context, extract_number_literals_with_options,
+    extract_operators_with_options, format_repeating, is_incomplete_with_options, operators,
+    physical_constants, render_diagnostic, render_error, to_fraction, units, validate_with_options,
+};
 use std::io;
 
+/// Interval width, relative to the result, above which the REPL shows the
+/// bounds of the rounding error
+const INTERVAL_WARNING_WIDTH: f64 = 1e-12;
+
 fn main() {
     banner();
+    // Shared by every line, so variables assigned on one line can be used on the next
+    let mut calculator = Calculator::new();
+    // Whether results that are fractions show their repeating digits, as in 0.(3)
+    let mut repeating = false;
     loop {
         println!("➤  ");
         let mut input = String::new();
         io::stdin()
             .read_line(&mut input)
             .expect("Failed to read line");
+        if !input.trim_start().starts_with(':')
+            && is_incomplete_with_options(&input, calculator.options())
+            && !read_rest(&mut input, calculator.options())
+        {
+            println!("\n  ✗ Abandoned the unfinished expression\n");
+            continue;
+        }
 
         let trimmed_input = input.trim();
 
+        // A line with only a comment has nothing to evaluate
+        if trimmed_input.starts_with('#') {
+            continue;
+        }
+
         if trimmed_input.to_lowercase() == "exit" {
             println!("Goodbye! Have a nice day!");
             break;
         }
 
+        if run_command(trimmed_input, &mut calculator, &mut repeating) {
+            continue;
+        }
+
         println!();
         println!("═══════════════════════════════════════════════════");
+        evaluate_line(trimmed_input, &mut calculator, repeating);
+        println!("═══════════════════════════════════════════════════\n");
+    }
+}
 
-        // Validate the equation
-        if validate_equation(trimmed_input) {
-            // Extract operators and numbers
-            let operators = extract_operators(trimmed_input);
-            let numbers = extract_numbers(trimmed_input);
+/// Runs a command such as `:help` or `:set si on`, returning false if `input`
+/// isn't one
+fn run_command(input: &str, calculator: &mut Calculator, repeating: &mut bool) -> bool {
+    if let Some(mode) = input.strip_prefix(":mode") {
+        set_angle_mode(mode, calculator);
+        return true;
+    }
+    if let Some(setting) = input.strip_prefix(":set") {
+        set(setting, calculator, repeating);
+        return true;
+    }
+    match input {
+        ":help" => {
+            println!("\n  Supported operators:");
+            for (symbol, description) in operators() {
+                println!("  ├─ {:<4} {}", symbol, description);
+            }
+            println!("\n  Functions:");
+            for (signature, description) in calculator.context().functions() {
+                println!("  ├─ {:<10} {}", signature, description);
+            }
+            println!("\n  Units:");
+            for (symbol, dimension) in units() {
+                println!("  ├─ {:<4} {}", symbol, dimension);
+            }
+            println!("\n  Constants:");
+            for (name, value) in constants() {
+                println!("  ├─ {:<4} {}", name, value);
+            }
+            println!();
+        }
+        ":constants" => {
+            println!("\n  Constants:");
+            for (name, value) in constants() {
+                println!("  ├─ {:<8} {}", name, value);
+            }
+            println!("\n  Physical constants (CODATA 2018):");
+            for (name, value, description) in physical_constants() {
+                println!("  ├─ {:<8} {:<14e} {}", name, value, description);
+            }
+            println!();
+        }
+        ":history" => {
+            println!("\n  History:");
+            for (i, entry) in calculator.history().iter().enumerate() {
+                match &entry.result {
+                    Ok(value) => println!("  ├─ {:>3}  {} → {}", i + 1, entry.input, value),
+                    Err(e) => println!("  ├─ {:>3}  {} ✗ {}", i + 1, entry.input, e),
+                }
+            }
+            println!();
+        }
+        ":clear" => {
+            calculator.clear();
+            println!("\n  🧹 Cleared the variables, functions and history\n");
+        }
+        _ => return false,
+    }
+    true
+}
 
-            // Print the results with decorative formatting
-            println!("\n  ✓ Valid equation detected!");
-            println!("\n  📊 Analysis:");
-            println!("  ├─ Operators found: {:?}", operators);
-            println!("  ├─ Numbers found:   {:?}", numbers);
+/// Switches to the angle mode of `:mode deg` or `:mode rad`, or shows the
+/// current one for a bare `:mode`
+fn set_angle_mode(mode: &str, calculator: &mut Calculator) {
+    match mode.trim() {
+        "deg" | "degrees" => calculator.options_mut().angle_mode = AngleMode::Degrees,
+        "rad" | "radians" => calculator.options_mut().angle_mode = AngleMode::Radians,
+        "" => {}
+        other => {
+            println!(
+                "\n  ✗ Unknown angle mode '{}', use :mode deg or :mode rad\n",
+                other
+            );
+            return;
+        }
+    }
+    println!("\n  📐 Angle mode: {}\n", calculator.options().angle_mode);
+}
 
-            // Evaluate the equation
-            let result = evaluate(&trimmed_input);
-            println!("  └─ Result:   {:?}", result);
+/// Changes the setting named by a `:set` command, as in `:set si on`, or shows
+/// it when no value is given
+fn set(setting: &str, calculator: &mut Calculator, repeating: &mut bool) {
+    let words = setting.split_whitespace().collect::<Vec<_>>();
+    if let ["mod", rest @ ..] = &words[..] {
+        let remainder_mode = match rest {
+            ["truncated"] => RemainderMode::Truncated,
+            ["floored"] => RemainderMode::Floored,
+            ["euclidean"] => RemainderMode::Euclidean,
+            [] => calculator.options().remainder_mode,
+            _ => {
+                println!(
+                    "\n  ✗ Unknown remainder mode, use :set mod truncated, floored or euclidean\n"
+                );
+                return;
+            }
+        };
+        calculator.options_mut().remainder_mode = remainder_mode;
+        println!("\n  ➗ Remainder mode: {}\n", remainder_mode);
+        return;
+    }
+    if let ["rounding", rest @ ..] = &words[..] {
+        let rounding = match rest {
+            ["half-up"] => RoundingMode::HalfUp,
+            ["half-even"] => RoundingMode::HalfEven,
+            ["half-down"] => RoundingMode::HalfDown,
+            ["toward-zero"] => RoundingMode::TowardZero,
+            [] => calculator.options().rounding,
+            _ => {
+                println!(
+                    "\n  ✗ Unknown rounding mode, use :set rounding half-up, half-even, half-down or toward-zero\n"
+                );
+                return;
+            }
+        };
+        calculator.options_mut().rounding = rounding;
+        println!("\n  🎯 Rounding mode: {}\n", rounding);
+        return;
+    }
+    if let ["bits", rest @ ..] = &words[..] {
+        let bit_width = match rest {
+            ["8"] => Some(8),
+            ["16"] => Some(16),
+            ["32"] => Some(32),
+            ["64"] => Some(64),
+            ["off"] => None,
+            [] => calculator.options().bit_width,
+            _ => {
+                println!("\n  ✗ Unknown bit width, use :set bits 8, 16, 32, 64 or off\n");
+                return;
+            }
+        };
+        calculator.options_mut().bit_width = bit_width;
+        match bit_width {
+            Some(width) => println!("\n  🧮 Bit width: {} bits, shown unsigned\n", width),
+            None => println!("\n  🧮 Bit width: off, 64 bits with a sign\n"),
+        }
+        return;
+    }
+    if let ["display", rest @ ..] = &words[..] {
+        *repeating = match rest {
+            ["repeating"] => true,
+            ["decimal"] => false,
+            [] => *repeating,
+            _ => {
+                println!(
+                    "\n  ✗ Unknown display, use :set display repeating or :set display decimal\n"
+                );
+                return;
+            }
+        };
+        let display = if *repeating { "repeating" } else { "decimal" };
+        println!("\n  🔁 Display: {}\n", display);
+        return;
+    }
+    if let ["si", rest @ ..] = &words[..] {
+        let si_suffixes = match rest {
+            ["on"] => true,
+            ["off"] => false,
+            [] => calculator.options().si_suffixes,
+            _ => {
+                println!("\n  ✗ Unknown setting, use :set si on or :set si off\n");
+                return;
+            }
+        };
+        calculator.options_mut().si_suffixes = si_suffixes;
+        let state = if si_suffixes { "on" } else { "off" };
+        println!("\n  📏 SI suffixes such as 4.7k: {}\n", state);
+        return;
+    }
+    if let ["words", rest @ ..] = &words[..] {
+        let number_words = match rest {
+            ["on"] => true,
+            ["off"] => false,
+            [] => calculator.options().number_words,
+            _ => {
+                println!("\n  ✗ Unknown setting, use :set words on or :set words off\n");
+                return;
+            }
+        };
+        calculator.options_mut().number_words = number_words;
+        let state = if number_words { "on" } else { "off" };
+        println!("\n  🗣 Number words such as two plus three: {}\n", state);
+        return;
+    }
+    if let ["locale", rest @ ..] = &words[..] {
+        let separator = match rest {
+            ["de"] => Separator::Comma,
+            ["en"] => Separator::Period,
+            [] => calculator.options().decimal_separator,
+            _ => {
+                println!("\n  ✗ Unknown locale, use :set locale de or :set locale en\n");
+                return;
+            }
+        };
+        calculator.options_mut().decimal_separator = separator;
+        println!("\n  🌍 Decimal separator: {}\n", separator);
+        return;
+    }
+    let mode = match words[..] {
+        ["mode", "float"] => Some(NumberMode::Float),
+        #[cfg(feature = "decimal")]
+        ["mode", "decimal"] => Some(NumberMode::Decimal),
+        ["mode"] => Some(calculator.options().number_mode),
+        _ => None,
+    };
+    match mode {
+        Some(mode) => {
+            calculator.options_mut().number_mode = mode;
+            println!("\n  🔢 Number mode: {}\n", mode);
+        }
+        None if cfg!(feature = "decimal") => {
+            println!("\n  ✗ Unknown setting, use :set mode decimal or :set mode float\n")
+        }
+        None => println!("\n  ✗ Unknown setting, use :set mode float\n"),
+    }
+}
 
+/// Validates and evaluates a line of input, printing what it is made of and
+/// its result, or what is wrong with it
+fn evaluate_line(input: &str, calculator: &mut Calculator, repeating: bool) {
+    // Input such as `*2` continues from the previous result
+    let continued = match continue_previous(input, calculator.context()) {
+        Ok(continued) => continued,
+        Err(e) => {
+            println!("\n  ✗ {}\n", e);
+            return;
+        }
+    };
+    let input = continued.as_str();
+
+    // Validate the equation
+    match validate_with_options(input, calculator.options()) {
+        Ok(()) => {
+            print_analysis(input, calculator);
+            let result = calculator.eval(input);
+            print_result(result, input, calculator, repeating);
             println!();
-        } else {
+        }
+        Err(diagnostics) => {
             println!("\n  ✗ Invalid equation format!");
-            println!("  💡 Tip: Please enter equation like 3+5*2 or 10/2-3");
-            println!();
+            // Each problem found, with carets under what is wrong, as in `2 of 3`
+            for diagnostic in &diagnostics {
+                for line in render_diagnostic(input, diagnostic).lines() {
+                    println!("    {}", line);
+                }
+            }
+            print_tips();
         }
+    }
+}
 
-        println!("═══════════════════════════════════════════════════\n");
+/// Prints the operators and numbers of a valid line, and its exact digits or
+/// rounding error where they are worth showing
+fn print_analysis(input: &str, calculator: &Calculator) {
+    let options = calculator.options();
+    // Extract operators and numbers
+    let operators = extract_operators_with_options(input, options);
+    let numbers = extract_number_literals_with_options(input, options)
+        .map(|numbers| {
+            let values: Vec<String> = numbers.iter().map(|n| n.value.to_string()).collect();
+            format!("[{}]", values.join(", "))
+        })
+        .unwrap_or_else(|e| e.to_string());
+
+    // Print the results with decorative formatting
+    println!("\n  ✓ Valid equation detected!");
+    println!("\n  📊 Analysis:");
+    println!("  ├─ Operators found: {:?}", operators);
+    // Numbers spelled out as words aren't literals of the input
+    if !options.number_words {
+        println!("  ├─ Numbers found:   {}", numbers);
+    }
+    println!("  ├─ Angle mode:      {}", options.angle_mode);
+
+    // Show all the digits of integer results too large for an f64 to hold,
+    // unless a bit width makes the result wrap where exact integers don't
+    if options.bit_width.is_none()
+        && let Ok(IntegerResult::Integer(n)) = evaluate_integer_with_options(input, options)
+        && n.unsigned_abs() > 1 << 53
+    {
+        println!("  ├─ Exact:    {}", n);
+    }
+
+    // Digits of decimal results, which the f64 result may round
+    #[cfg(feature = "decimal")]
+    if options.number_mode == NumberMode::Decimal
+        && let Ok(decimal) = evaluate_decimal(input, options)
+    {
+        println!(
+            "  ├─ Decimal:  {} (scale {}, precision {})",
+            localized(decimal.to_string(), options.decimal_separator),
+            decimal.scale(),
+            decimal.precision()
+        );
+    }
+
+    // Warn when rounding could have changed the result noticeably
+    if let Ok(interval) = evaluate_interval_with_options(input, options)
+        && interval.width()
+            > INTERVAL_WARNING_WIDTH * interval.lo.abs().max(interval.hi.abs()).max(1.0)
+    {
+        println!(
+            "  ├─ Interval: {} (width {:.1e})",
+            localized(interval.to_string(), options.decimal_separator),
+            interval.width()
+        );
+    }
+}
+
+/// Prints the result of evaluating `input`, in the form that suits its kind
+fn print_result(
+    result: Result<Value, CalcError>,
+    input: &str,
+    calculator: &Calculator,
+    repeating: bool,
+) {
+    match result {
+        Ok(Value::Number(n)) => {
+            let result = match to_fraction(n, DEFAULT_MAX_DENOMINATOR) {
+                (numer, denom, false) if repeating && denom > 1 => {
+                    format!("Ok({})", format_repeating(numer, denom))
+                }
+                _ => format!("{:?}", Ok::<f64, String>(n)),
+            };
+            println!(
+                "  └─ Result:   {}",
+                localized(result, calculator.options().decimal_separator)
+            )
+        }
+        // Results such as hex(255) are shown as they are
+        Ok(Value::Text(text)) => println!("  └─ Result:   {}", text),
+        // Money is shown with two decimals, rounded in the rounding mode
+        Ok(Value::Money { symbol, amount }) => {
+            let sign = if amount < 0.0 { "-" } else { "" };
+            let amount = calculator.options().rounding.format(amount.abs(), 2);
+            println!("  └─ Result:   {}{}{}", sign, symbol, amount)
+        }
+        // Durations such as 1:30 + 0:45 are shown as h:mm, uncertain
+        // values as 10 ± 0.45 and lists as [2, 4, 6]
+        Ok(value @ (Value::Duration(_) | Value::Uncertain { .. } | Value::List(_))) => {
+            println!("  └─ Result:   {}", value)
+        }
+        Ok(Value::Definition(signature)) => println!("  └─ Defined:  {}", signature),
+        // Names that aren't variables may be units, as in 5 km + 300 m
+        Err(e) if matches!(e.inner(), CalcError::UnknownIdentifier { .. }) => {
+            match evaluate_units_with_context(input, calculator.context()) {
+                Ok(quantity) => println!("  └─ Result:   {}", quantity),
+                Err(e) => print_error(input, &e),
+            }
+        }
+        Err(e) => print_error(input, &e),
+    }
+}
+
+/// Prints an error from evaluating `input`, with carets under what it points
+/// at, as in `1/0`
+fn print_error(input: &str, error: &CalcError) {
+    println!("  └─ Error:");
+    for line in render_error(input, error).lines() {
+        println!("    {}", line);
+    }
+}
+
+/// Prints examples of what can be entered, after a line that isn't valid
+fn print_tips() {
+    println!("  💡 Tip: Please enter equation like 3+5*2 or 10/2-3");
+    println!("  💡 Assign variables with x = 5 and use them later as x*2");
+    println!("  💡 Define functions with f(x) = x^2 + 1 and call them as f(3)");
+    println!("  💡 Numbers can have units, as in 5 km + 300 m or 10 mi in km");
+    println!("  💡 Write durations as h:mm or h:mm:ss, as in 1:30 + 0:45");
+    println!("  💡 Write lists as [1, 2, 3], as in [1, 2, 3] * 2 or sum([4, 5])");
+    println!("  💡 Use ans to refer to the previous result, as in ans/2");
+    println!("  💡 End a line with an operator or an open ( to continue on the next");
+    println!("  💡 Start with an operator, as in *2, to continue from the previous result");
+    println!("  💡 Type :help to list the supported operators and constants");
+    println!("  💡 Type :constants to list the constants, such as phys.c");
+    println!("  💡 Type :history to list what you entered, or :clear to start afresh");
+    println!("  💡 Type :mode deg or :mode rad to switch the angle mode");
+    println!("  💡 Type :set si on to read 4.7k as 4700 and 100n as 1e-7");
+    println!("  💡 Type :set words on to type two plus three for 2 + 3");
+    println!("  💡 Type :set locale de to write decimals with a comma, as in 3,5");
+    println!("  💡 Type :set mod floored or :set mod euclidean to change what % gives");
+    println!("  💡 Type :set bits 8 to make ~0 255 and hex(-1) 0xff");
+    println!("  💡 Type :set display repeating to show 1/3 as 0.(3)");
+    println!("  💡 Type :set rounding half-even to round ties such as 2.5 to even");
+    #[cfg(feature = "decimal")]
+    println!("  💡 Type :set mode decimal for exact decimal arithmetic");
+    println!();
+}
+
+/// Keeps reading lines onto `input` until it is a complete expression, returning
+/// false if a blank line or `:cancel` abandons it instead
+fn read_rest(input: &mut String, options: &EvalOptions) -> bool {
+    while is_incomplete_with_options(input, options) {
+        println!("... ");
+        let mut line = String::new();
+        let read = io::stdin()
+            .read_line(&mut line)
+            .expect("Failed to read line");
+        if read == 0 || line.trim().is_empty() || line.trim() == ":cancel" {
+            return false;
+        }
+        input.push_str(&line);
+    }
+    true
+}
+
+/// Writes the decimal points of a result as `separator`, so that a result reads
+/// the way numbers are entered
+fn localized(text: String, separator: Separator) -> String {
+    match separator {
+        Separator::Period => text,
+        Separator::Comma => text.replace('.', ","),
     }
 }
@@ -0,0 +1,551 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// How trigonometric functions interpret and return angles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+impl fmt::Display for AngleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AngleMode::Radians => write!(f, "radians"),
+            AngleMode::Degrees => write!(f, "degrees"),
+        }
+    }
+}
+
+/// What `/` does in [`evaluate_integer`](crate::evaluate_integer) when the
+/// division leaves a remainder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegerDivision {
+    /// Give a floating point result, so `7/2` is `3.5`
+    #[default]
+    Promote,
+    /// Fail, so that results stay exact integers
+    RequireExact,
+}
+
+/// What `/`, `//` and `%` do when the divisor is zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DivisionByZero {
+    /// Fail with [`CalcError::DivisionByZero`](crate::CalcError::DivisionByZero)
+    #[default]
+    Error,
+    /// Give the IEEE 754 result, so `1/0` is `inf`, `-1/0` is `-inf` and
+    /// `0/0` and `5 % 0` are `NaN`
+    Infinity,
+}
+
+/// What an operator or built-in function does when its result is too large
+/// for an `f64`, as `1e308 * 10` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Give `inf` or `-inf`, as floating point arithmetic does
+    #[default]
+    Infinity,
+    /// Fail with [`CalcError::Overflow`](crate::CalcError::Overflow)
+    Error,
+}
+
+/// The sign of the result of `%` when its operands have different signs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RemainderMode {
+    /// The sign of the dividend, like Rust's `%`, so `-7 % 3` is `-1`
+    #[default]
+    Truncated,
+    /// The sign of the divisor, matching `//`, so `-7 % 3` is `2` and
+    /// `7 % -3` is `-2`
+    Floored,
+    /// Never negative, so `-7 % 3` and `-7 % -3` are both `2`
+    Euclidean,
+}
+
+impl RemainderMode {
+    /// Returns `k` such that `r + k*divisor` is the remainder in this mode,
+    /// where `r` is the truncated remainder and `remainder` its sign
+    pub(crate) fn correction(self, remainder: Ordering, divisor_negative: bool) -> i8 {
+        match (self, remainder) {
+            (RemainderMode::Truncated, _) | (_, Ordering::Equal) => 0,
+            (RemainderMode::Floored, remainder) => {
+                ((remainder == Ordering::Less) != divisor_negative) as i8
+            }
+            (RemainderMode::Euclidean, Ordering::Less) if divisor_negative => -1,
+            (RemainderMode::Euclidean, Ordering::Less) => 1,
+            (RemainderMode::Euclidean, Ordering::Greater) => 0,
+        }
+    }
+}
+
+impl fmt::Display for RemainderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemainderMode::Truncated => write!(f, "truncated"),
+            RemainderMode::Floored => write!(f, "floored"),
+            RemainderMode::Euclidean => write!(f, "euclidean"),
+        }
+    }
+}
+
+/// Where a number goes when it is rounded, by `round` and by
+/// [`RoundingMode::format`]
+///
+/// Every mode is symmetric about zero, so `-2.5` rounds the way `2.5` does,
+/// with its sign.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::RoundingMode;
+///
+/// let ties = [2.5, 3.5, -2.5, -3.5, 2.6, -2.4];
+/// let rounded = |mode: RoundingMode| ties.map(|x| mode.round(x));
+/// assert_eq!(rounded(RoundingMode::HalfUp), [3.0, 4.0, -3.0, -4.0, 3.0, -2.0]);
+/// assert_eq!(rounded(RoundingMode::HalfEven), [2.0, 4.0, -2.0, -4.0, 3.0, -2.0]);
+/// assert_eq!(rounded(RoundingMode::HalfDown), [2.0, 3.0, -2.0, -3.0, 3.0, -2.0]);
+/// assert_eq!(rounded(RoundingMode::TowardZero), [2.0, 3.0, -2.0, -3.0, 2.0, -2.0]);
+///
+/// assert_eq!(RoundingMode::HalfUp.format(2.675, 2), "2.68");
+/// assert_eq!(RoundingMode::HalfEven.format(2.675, 2), "2.68");
+/// assert_eq!(RoundingMode::HalfEven.format(2.665, 2), "2.66");
+/// assert_eq!(RoundingMode::HalfDown.format(-0.125, 2), "-0.12");
+/// assert_eq!(RoundingMode::HalfUp.format(-0.125, 2), "-0.13");
+/// assert_eq!(RoundingMode::TowardZero.format(9.999, 2), "9.99");
+/// assert_eq!(RoundingMode::HalfUp.format(9.999, 2), "10.00");
+/// assert_eq!(RoundingMode::HalfUp.format(0.5, 0), "1");
+/// assert_eq!(RoundingMode::HalfEven.format(0.5, 0), "0");
+/// assert_eq!(RoundingMode::HalfUp.format(-0.001, 2), "0.00");
+/// assert_eq!(RoundingMode::HalfUp.format(1.5e-7, 3), "0.000");
+/// assert_eq!(RoundingMode::HalfUp.format(12345.0, 1), "12345.0");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Ties away from zero, so `2.5` is `3` and `-2.5` is `-3`
+    #[default]
+    HalfUp,
+    /// Ties to the even neighbour, so `2.5` is `2` and `3.5` is `4`. Also
+    /// called banker's rounding, since it doesn't drift up over many sums.
+    HalfEven,
+    /// Ties towards zero, so `2.5` is `2` and `-2.5` is `-2`
+    HalfDown,
+    /// Always towards zero, dropping the fraction, so `2.9` is `2`
+    TowardZero,
+}
+
+impl RoundingMode {
+    /// Rounds `x` to an integer
+    pub fn round(self, x: f64) -> f64 {
+        match self {
+            RoundingMode::HalfUp => x.round(),
+            RoundingMode::HalfEven => x.round_ties_even(),
+            RoundingMode::HalfDown if (x - x.trunc()).abs() == 0.5 => x.trunc(),
+            RoundingMode::HalfDown => x.round(),
+            RoundingMode::TowardZero => x.trunc(),
+        }
+    }
+
+    /// Writes `value` with exactly `decimals` digits after the decimal point
+    ///
+    /// Ties are judged on the shortest decimal form of `value`, the digits it
+    /// is written with, so `2.675` is a tie even though the nearest `f64` is
+    /// slightly below it.
+    pub fn format(self, value: f64, decimals: usize) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        // `d.ddde±x`, whose digits stand for `0.dddd * 10^(x + 1)`
+        let text = format!("{:e}", value.abs());
+        let (mantissa, exponent) = text.split_once('e').expect("exponent notation");
+        let exponent: i64 = exponent.parse().expect("an integer exponent");
+        let mut digits: Vec<u8> = mantissa.bytes().filter(u8::is_ascii_digit).collect();
+        // Digits that come before the last decimal kept
+        let keep = exponent + 1 + decimals as i64;
+        if keep < 0 {
+            digits.clear();
+        } else if (keep as usize) < digits.len() {
+            let rest = digits.split_off(keep as usize);
+            let above_half =
+                rest[0] > b'5' || (rest[0] == b'5' && rest[1..].iter().any(|d| *d != b'0'));
+            let tie = rest[0] == b'5' && !above_half;
+            let last_odd = digits.last().is_some_and(|d| (d - b'0') % 2 == 1);
+            let up = match self {
+                RoundingMode::TowardZero => false,
+                _ if above_half => true,
+                RoundingMode::HalfUp => tie,
+                RoundingMode::HalfEven => tie && last_odd,
+                RoundingMode::HalfDown => false,
+            };
+            if up {
+                increment(&mut digits);
+            }
+        } else {
+            digits.resize(keep as usize, b'0');
+        }
+
+        // The digits are now the value times 10^decimals
+        let zero = digits.iter().all(|d| *d == b'0');
+        if digits.len() <= decimals {
+            let padding = decimals + 1 - digits.len();
+            digits.splice(0..0, std::iter::repeat_n(b'0', padding));
+        }
+        let point = digits.len() - decimals;
+        let whole = std::str::from_utf8(&digits[..point]).expect("ASCII digits");
+        let fraction = std::str::from_utf8(&digits[point..]).expect("ASCII digits");
+        let sign = if value < 0.0 && !zero { "-" } else { "" };
+        if decimals == 0 {
+            format!("{}{}", sign, whole)
+        } else {
+            format!("{}{}.{}", sign, whole, fraction)
+        }
+    }
+}
+
+/// Adds one to the last of a run of ASCII digits, carrying as needed
+fn increment(digits: &mut Vec<u8>) {
+    for digit in digits.iter_mut().rev() {
+        if *digit == b'9' {
+            *digit = b'0';
+        } else {
+            *digit += 1;
+            return;
+        }
+    }
+    digits.insert(0, b'1');
+}
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoundingMode::HalfUp => write!(f, "half-up"),
+            RoundingMode::HalfEven => write!(f, "half-even"),
+            RoundingMode::HalfDown => write!(f, "half-down"),
+            RoundingMode::TowardZero => write!(f, "toward-zero"),
+        }
+    }
+}
+
+/// The character that separates the whole and fractional parts of a number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Separator {
+    /// `3.5`, with `,` grouping thousands as in `1,234.5`
+    #[default]
+    Period,
+    /// `3,5`, with `.` grouping thousands as in `1.234,5`, as in much of
+    /// Europe. A `,` is only a decimal point between two digits, so function
+    /// arguments must be separated by a comma and a space: `max(1, 2,5)`.
+    Comma,
+}
+
+impl Separator {
+    /// Returns the decimal point and the thousands separator
+    pub(crate) fn marks(self) -> (char, char) {
+        match self {
+            Separator::Period => ('.', ','),
+            Separator::Comma => (',', '.'),
+        }
+    }
+}
+
+impl fmt::Display for Separator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Separator::Period => write!(f, "period"),
+            Separator::Comma => write!(f, "comma"),
+        }
+    }
+}
+
+/// Which arithmetic [`evaluate_with_options`](crate::evaluate_with_options) and
+/// [`evaluate_with_context`](crate::evaluate_with_context) use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberMode {
+    /// 64-bit floating point
+    #[default]
+    Float,
+    /// Base 10 decimals, as in [`evaluate_decimal`](crate::evaluate_decimal),
+    /// so `0.1+0.2` is exactly `0.3`. Enabled by the `decimal` feature.
+    #[cfg(feature = "decimal")]
+    Decimal,
+}
+
+impl fmt::Display for NumberMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberMode::Float => write!(f, "float"),
+            #[cfg(feature = "decimal")]
+            NumberMode::Decimal => write!(f, "decimal"),
+        }
+    }
+}
+
+/// Settings that change how expressions are parsed and evaluated
+///
+/// Options are built from the defaults, which [`evaluate`](crate::evaluate)
+/// uses, by changing one setting at a time. New settings may be added, so
+/// options can't be written as a struct literal outside this crate.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{
+///     AngleMode, CalcError, DivisionByZero, EvalOptions, Overflow, Span, evaluate,
+///     evaluate_with_options,
+/// };
+///
+/// let options = EvalOptions::new()
+///     .angle_mode(AngleMode::Degrees)
+///     .division_by_zero(DivisionByZero::Infinity);
+/// assert_eq!(options.angle_mode, AngleMode::Degrees);
+/// assert!((evaluate_with_options("sin(30)", &options).unwrap() - 0.5).abs() < 1e-12);
+/// assert_eq!(evaluate_with_options("1/0", &options), Ok(f64::INFINITY));
+/// assert_eq!(evaluate_with_options("-1 // 0", &options), Ok(f64::NEG_INFINITY));
+/// assert!(evaluate_with_options("0/0", &options).unwrap().is_nan());
+/// assert!(evaluate_with_options("5 % 0", &options).unwrap().is_nan());
+/// assert_eq!(evaluate("1/0"), Err(CalcError::DivisionByZero { span: Some(Span { start: 1, end: 2 }) }));
+///
+/// let checked = EvalOptions::new().overflow(Overflow::Error);
+/// assert_eq!(evaluate("1e308 * 10"), Ok(f64::INFINITY));
+/// assert_eq!(
+///     evaluate_with_options("1e308 * 10", &checked),
+///     Err(CalcError::Overflow {
+///         message: "the result of '*' is too large".to_string(),
+///         span: Some(Span { start: 6, end: 7 }),
+///     })
+/// );
+/// assert_eq!(
+///     evaluate_with_options("exp(1000)", &checked).unwrap_err().to_string(),
+///     "the result of exp is too large"
+/// );
+/// assert_eq!(evaluate_with_options("1e300 * 10", &checked), Ok(1e301));
+/// assert_eq!(evaluate_with_options("1e400 * 2", &checked), Ok(f64::INFINITY));
+/// assert_eq!(EvalOptions::new(), EvalOptions::default());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct EvalOptions {
+    /// Treat a number or `)` directly followed by `(` as a multiplication,
+    /// so `2(3+4)` is `2*(3+4)`. Enabled by default.
+    pub implicit_multiplication: bool,
+    /// Return NaN instead of an error when a function is called outside its
+    /// domain, such as `sqrt(-1)`. Disabled by default.
+    pub nan_on_domain_error: bool,
+    /// Unit for the arguments of `sin`, `cos` and `tan` and the results of the
+    /// inverse trigonometric functions. Radians by default.
+    pub angle_mode: AngleMode,
+    /// Seed for `random` and `randint`. When set, every evaluation restarts
+    /// the same random sequence, which makes results reproducible. `None`
+    /// by default, which uses a randomly seeded per-thread generator.
+    pub seed: Option<u64>,
+    /// Largest difference at which two numbers still compare as equal in
+    /// `==`, `!=`, `<=` and `>=`. Zero by default, which compares exactly, so
+    /// `0.1+0.2 == 0.3` is false unless a tolerance is set.
+    pub comparison_tolerance: f64,
+    /// How [`evaluate_integer`](crate::evaluate_integer) handles a division
+    /// with a remainder. Promotes to floating point by default.
+    pub integer_division: IntegerDivision,
+    /// Arithmetic used by [`evaluate_with_options`](crate::evaluate_with_options)
+    /// and [`evaluate_with_context`](crate::evaluate_with_context). Floating
+    /// point by default.
+    pub number_mode: NumberMode,
+    /// Digits kept after the decimal point when a decimal result can't be
+    /// represented exactly, such as `1/3`. Ties round to the even digit. 20 by
+    /// default.
+    pub decimal_scale: u32,
+    /// The sign of the result of `%` when its operands have different signs.
+    /// That of the dividend by default, like Rust's `%`.
+    pub remainder_mode: RemainderMode,
+    /// How `round` breaks ties, and how a result written with a fixed number
+    /// of decimals, such as an amount of money, is rounded. Ties away from
+    /// zero by default.
+    pub rounding: RoundingMode,
+    /// Width in bits of the two's complement integers that `~`, `&`, `|`,
+    /// `xor` and the shifts work on, such as `Some(8)`, whose results are
+    /// then shown unsigned, so `~0` is `255`. `hex`, `bin` and `oct` write a
+    /// negative number in the same width. `None` by default, which works on
+    /// 64 bits and keeps the sign. [`evaluate_integer`](crate::evaluate_integer)
+    /// always uses 128 bits.
+    pub bit_width: Option<u32>,
+    /// How numbers are written, as `3.5` or `3,5`. A period by default.
+    pub decimal_separator: Separator,
+    /// Read full-width digits and operators, such as `１２３＋４`, and
+    /// Arabic-Indic digits as their ASCII equivalents, superscripts as powers
+    /// and vulgar fractions such as `½` as numbers. Enabled by default;
+    /// disable it to accept only the usual characters.
+    pub normalize_unicode: bool,
+    /// Read a letter directly after a number as an SI prefix, so `4.7k` is
+    /// `4700` and `100n` is `1e-7`. Disabled by default, since it changes the
+    /// meaning of `2m` from `2*m`.
+    pub si_suffixes: bool,
+    /// Read English number and operator words, so `two plus three times four`
+    /// is `2 + 3 * 4`, as described for
+    /// [`words_to_expression`](crate::words_to_expression). Disabled by default.
+    pub number_words: bool,
+    /// Accept a currency symbol, `$`, `€`, `£` or `¥`, directly before a
+    /// number, as in `$1,299.99`, and show the result of an expression with
+    /// one as an amount in that currency. Enabled by default.
+    pub currency_symbols: bool,
+    /// What `/`, `//` and `%` do when the divisor is zero. An error by
+    /// default. Other arithmetic, such as that of
+    /// [`evaluate_integer`](crate::evaluate_integer) and `mod`, always fails.
+    pub division_by_zero: DivisionByZero,
+    /// What an operator or built-in function does when its operands are
+    /// finite but its result is too large for an `f64`. Gives infinity by
+    /// default.
+    pub overflow: Overflow,
+}
+
+impl EvalOptions {
+    /// The default options, which [`evaluate`](crate::evaluate) uses
+    pub fn new() -> Self {
+        EvalOptions::default()
+    }
+
+    /// Sets [`EvalOptions::implicit_multiplication`]
+    pub fn implicit_multiplication(mut self, implicit_multiplication: bool) -> Self {
+        self.implicit_multiplication = implicit_multiplication;
+        self
+    }
+
+    /// Sets [`EvalOptions::nan_on_domain_error`]
+    pub fn nan_on_domain_error(mut self, nan_on_domain_error: bool) -> Self {
+        self.nan_on_domain_error = nan_on_domain_error;
+        self
+    }
+
+    /// Sets [`EvalOptions::angle_mode`]
+    pub fn angle_mode(mut self, angle_mode: AngleMode) -> Self {
+        self.angle_mode = angle_mode;
+        self
+    }
+
+    /// Sets [`EvalOptions::seed`] to `Some(seed)`
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets [`EvalOptions::comparison_tolerance`]
+    pub fn comparison_tolerance(mut self, comparison_tolerance: f64) -> Self {
+        self.comparison_tolerance = comparison_tolerance;
+        self
+    }
+
+    /// Sets [`EvalOptions::integer_division`]
+    pub fn integer_division(mut self, integer_division: IntegerDivision) -> Self {
+        self.integer_division = integer_division;
+        self
+    }
+
+    /// Sets [`EvalOptions::number_mode`]
+    pub fn number_mode(mut self, number_mode: NumberMode) -> Self {
+        self.number_mode = number_mode;
+        self
+    }
+
+    /// Sets [`EvalOptions::decimal_scale`]
+    pub fn decimal_scale(mut self, decimal_scale: u32) -> Self {
+        self.decimal_scale = decimal_scale;
+        self
+    }
+
+    /// Sets [`EvalOptions::remainder_mode`]
+    pub fn remainder_mode(mut self, remainder_mode: RemainderMode) -> Self {
+        self.remainder_mode = remainder_mode;
+        self
+    }
+
+    /// Sets [`EvalOptions::rounding`]
+    pub fn rounding(mut self, rounding: RoundingMode) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Sets [`EvalOptions::bit_width`] to `Some(bit_width)`
+    pub fn bit_width(mut self, bit_width: u32) -> Self {
+        self.bit_width = Some(bit_width);
+        self
+    }
+
+    /// Sets [`EvalOptions::decimal_separator`]
+    pub fn decimal_separator(mut self, decimal_separator: Separator) -> Self {
+        self.decimal_separator = decimal_separator;
+        self
+    }
+
+    /// Sets [`EvalOptions::normalize_unicode`]
+    pub fn normalize_unicode(mut self, normalize_unicode: bool) -> Self {
+        self.normalize_unicode = normalize_unicode;
+        self
+    }
+
+    /// Sets [`EvalOptions::si_suffixes`]
+    pub fn si_suffixes(mut self, si_suffixes: bool) -> Self {
+        self.si_suffixes = si_suffixes;
+        self
+    }
+
+    /// Sets [`EvalOptions::number_words`]
+    pub fn number_words(mut self, number_words: bool) -> Self {
+        self.number_words = number_words;
+        self
+    }
+
+    /// Sets [`EvalOptions::currency_symbols`]
+    pub fn currency_symbols(mut self, currency_symbols: bool) -> Self {
+        self.currency_symbols = currency_symbols;
+        self
+    }
+
+    /// Sets [`EvalOptions::division_by_zero`]
+    pub fn division_by_zero(mut self, division_by_zero: DivisionByZero) -> Self {
+        self.division_by_zero = division_by_zero;
+        self
+    }
+
+    /// Sets [`EvalOptions::overflow`]
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+    /// Converts an angle in the configured unit to radians
+    pub(crate) fn angle_to_radians(&self, angle: f64) -> f64 {
+        match self.angle_mode {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_radians(),
+        }
+    }
+
+    /// Converts an angle in radians to the configured unit
+    pub(crate) fn radians_to_angle(&self, angle: f64) -> f64 {
+        match self.angle_mode {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_degrees(),
+        }
+    }
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        EvalOptions {
+            implicit_multiplication: true,
+            nan_on_domain_error: false,
+            angle_mode: AngleMode::Radians,
+            seed: None,
+            comparison_tolerance: 0.0,
+            integer_division: IntegerDivision::Promote,
+            number_mode: NumberMode::Float,
+            decimal_scale: 20,
+            remainder_mode: RemainderMode::Truncated,
+            rounding: RoundingMode::HalfUp,
+            bit_width: None,
+            decimal_separator: Separator::Period,
+            normalize_unicode: true,
+            si_suffixes: false,
+            number_words: false,
+            currency_symbols: true,
+            division_by_zero: DivisionByZero::Error,
+            overflow: Overflow::Infinity,
+        }
+    }
+}
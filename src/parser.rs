@@ -0,0 +1,1352 @@
+use crate::error::CalcError;
+use crate::lexer::{Span, Token, TokenKind, is_percent_sign, tokenize};
+use crate::options::EvalOptions;
+use crate::units;
+
+/// Binary operators supported by the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FloorDiv,
+    Rem,
+    Pow,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    /// `x in unit`, which expresses a quantity in another unit
+    Convert,
+    /// `x ± u`, a value with an uncertainty
+    PlusMinus,
+}
+
+impl BinaryOp {
+    /// Returns the canonical symbol of the operator
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::FloorDiv => "//",
+            BinaryOp::Rem => "%",
+            BinaryOp::Pow => "^",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "xor",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Convert => "in",
+            BinaryOp::PlusMinus => "±",
+        }
+    }
+
+    /// Returns true for the comparison operators, which can't be chained
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+                | BinaryOp::Equal
+                | BinaryOp::NotEqual
+        )
+    }
+}
+
+/// Prefix and postfix (unary) operators supported by the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `-x`
+    Neg,
+    /// `+x`
+    Pos,
+    /// `~x`
+    BitNot,
+    /// `x!`
+    Factorial,
+    /// `x%`, which is `x / 100`
+    Percent,
+}
+
+/// A built-in function that evaluates one of its arguments with a variable
+/// bound to values of its own choosing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingForm {
+    /// `sum(var, lo, hi, body)`
+    Sum,
+    /// `prod(var, lo, hi, body)`
+    Product,
+    /// `deriv(body, var, at)`
+    Derivative,
+    /// `integrate(body, var, a, b)`
+    Integral,
+    /// `solve(lhs = rhs, var)` or `solve(lhs = rhs, var, lo, hi)`
+    Solve,
+}
+
+impl BindingForm {
+    /// Returns the name of the function
+    pub fn name(self) -> &'static str {
+        match self {
+            BindingForm::Sum => "sum",
+            BindingForm::Product => "prod",
+            BindingForm::Derivative => "deriv",
+            BindingForm::Integral => "integrate",
+            BindingForm::Solve => "solve",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sum" => Some(BindingForm::Sum),
+            "prod" => Some(BindingForm::Product),
+            "deriv" => Some(BindingForm::Derivative),
+            "integrate" => Some(BindingForm::Integral),
+            "solve" => Some(BindingForm::Solve),
+            _ => None,
+        }
+    }
+
+    /// Returns the accepted numbers of arguments and the positions of the
+    /// variable and the body among them
+    fn layout(self) -> (&'static [usize], usize, usize) {
+        match self {
+            BindingForm::Sum | BindingForm::Product => (&[4], 0, 3),
+            BindingForm::Derivative => (&[3], 1, 0),
+            BindingForm::Integral => (&[4], 1, 0),
+            BindingForm::Solve => (&[2, 4], 1, 0),
+        }
+    }
+}
+
+/// A parsed expression tree
+///
+/// Each node has the span of the token it was read from, such as the
+/// operator of a binary operation or the name of a call. Some syntax is
+/// rewritten while parsing: `|x|` and `√x` are calls to `abs` and `sqrt`, and
+/// `not x` is `x == 0`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number {
+        value: f64,
+        span: Span,
+    },
+    Var {
+        name: String,
+        span: Span,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    /// A list of numbers such as `[1, 2, 3]`, whose items are never lists
+    List {
+        items: Vec<Expr>,
+        span: Span,
+    },
+    /// `cond ? then : otherwise` or `if(cond, then, otherwise)`, of which only
+    /// the selected branch is evaluated
+    Conditional {
+        condition: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+    /// A function such as `sum(i, 1, 10, i^2)` that evaluates `body` with `var`
+    /// bound to values it chooses. `args` holds its other arguments, such as
+    /// the bounds of the sum, which are evaluated as usual.
+    Binding {
+        form: BindingForm,
+        var: String,
+        body: Box<Expr>,
+        args: Vec<Expr>,
+        span: Span,
+    },
+}
+
+/// A complete line of input: an expression, an assignment of one to a variable,
+/// or a function definition
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Statement {
+    Expr(Expr),
+    Assign {
+        name: String,
+        span: Span,
+        value: Expr,
+    },
+    /// A function definition such as `f(x) = x^2 + 1`
+    Define {
+        name: String,
+        span: Span,
+        params: Vec<String>,
+        body: Expr,
+    },
+}
+
+/// Returns the operator and its left/right binding power for an infix token
+///
+/// Higher numbers bind tighter. A left power lower than the right power makes
+/// the operator left-associative, a higher one makes it right-associative
+/// (`2^3^2` is `2^(3^2)`). Levels are spaced apart to leave room for new ones.
+fn infix_binding_power(kind: &TokenKind) -> Option<(BinaryOp, u8, u8)> {
+    let entry = match kind {
+        TokenKind::Less => (BinaryOp::Less, 10, 11),
+        TokenKind::LessEqual => (BinaryOp::LessEqual, 10, 11),
+        TokenKind::Greater => (BinaryOp::Greater, 10, 11),
+        TokenKind::GreaterEqual => (BinaryOp::GreaterEqual, 10, 11),
+        TokenKind::EqualEqual => (BinaryOp::Equal, 10, 11),
+        TokenKind::NotEqual => (BinaryOp::NotEqual, 10, 11),
+        TokenKind::Pipe => (BinaryOp::BitOr, 12, 13),
+        TokenKind::Xor => (BinaryOp::BitXor, 14, 15),
+        TokenKind::Ampersand => (BinaryOp::BitAnd, 16, 17),
+        TokenKind::ShiftLeft => (BinaryOp::ShiftLeft, 18, 19),
+        TokenKind::ShiftRight => (BinaryOp::ShiftRight, 18, 19),
+        TokenKind::Plus => (BinaryOp::Add, 20, 21),
+        TokenKind::Minus => (BinaryOp::Sub, 20, 21),
+        TokenKind::PlusMinus => (BinaryOp::PlusMinus, 25, 26),
+        TokenKind::Star => (BinaryOp::Mul, 30, 31),
+        TokenKind::Slash => (BinaryOp::Div, 30, 31),
+        TokenKind::DoubleSlash => (BinaryOp::FloorDiv, 30, 31),
+        TokenKind::Percent => (BinaryOp::Rem, 30, 31),
+        TokenKind::Caret => (BinaryOp::Pow, 51, 50),
+        _ => return None,
+    };
+    Some(entry)
+}
+
+/// Left and right binding power of the conditional operator `? :`
+const CONDITIONAL_BP: (u8, u8) = (5, 4);
+
+/// Left and right binding power of `or` and `and`, which sit below the
+/// comparisons so that `x > 0 and x < 10` needs no parentheses. `and` binds
+/// tighter, so `a or b and c` is `a or (b and c)`.
+const OR_BP: (u8, u8) = (6, 7);
+const AND_BP: (u8, u8) = (8, 9);
+
+/// Binding power of the operand of `not`, which takes a whole comparison, so
+/// `not x > 0` is `not (x > 0)`. `!x` binds like a sign instead.
+const NOT_BP: u8 = 9;
+
+/// Left and right binding power of unit conversion with `in` or `to`, which
+/// applies to everything before it, so `2 km + 1 mi in m` converts the sum
+const CONVERSION_BP: (u8, u8) = (2, 3);
+
+/// Left and right binding power of `of` in `20% of 50`. It takes its
+/// percentage ahead of a sign or a division, so `-20% of 50` is `-(20% of 50)`
+/// and `100 / 20% of 50` is `100 / (20% of 50)`, and its right-hand side like `*`.
+const OF_BP: (u8, u8) = (42, 31);
+
+/// Binding power of a unit written after a number, which takes a power but
+/// binds tighter than any other operator, so `10 km / 2 h` is `(10 km)/(2 h)`
+const UNIT_BP: u8 = 45;
+
+/// Right binding power of `√` and `∛`, which take their operand before any
+/// operator but a postfix one, so `√9+7` is `10` and `√2^2` is `(√2)^2`
+const RADICAL_BP: u8 = 55;
+
+/// Returns the operator and its right binding power for a prefix token
+///
+/// Signs and `~` bind tighter than `*` and `/`, so `2*-3` is `2*(-3)`, but
+/// looser than `^`, so `-2^2` is `-(2^2)`. Because a sign is parsed as the start of an
+/// operand, runs of signs after a binary operator (`3--2`, `3+ +4`) are
+/// accepted, while a non-sign operator in that position is not. That includes
+/// the right of `^`, where the sign takes in the rest of the power at 40, so
+/// `2^-3` is `2^(-3)` and `3^-1^2` is `3^(-(1^2))`.
+fn prefix_binding_power(kind: &TokenKind) -> Option<(UnaryOp, u8)> {
+    match kind {
+        TokenKind::Minus => Some((UnaryOp::Neg, 40)),
+        TokenKind::Plus => Some((UnaryOp::Pos, 40)),
+        TokenKind::Tilde => Some((UnaryOp::BitNot, 40)),
+        _ => None,
+    }
+}
+
+/// Returns the operator and its left binding power for a postfix token
+///
+/// Factorial and percent bind tighter than every other operator, so `2^3!` is
+/// `2^(3!)`. `next` is the token after the operator, which decides whether `%`
+/// is a percent sign or the modulo operator.
+fn postfix_binding_power(kind: &TokenKind, next: Option<&TokenKind>) -> Option<(UnaryOp, u8)> {
+    match kind {
+        TokenKind::Bang => Some((UnaryOp::Factorial, 60)),
+        TokenKind::Percent if is_percent_sign(next) => Some((UnaryOp::Percent, 60)),
+        _ => None,
+    }
+}
+
+/// Compares `expr` with zero using `op`, which gives 1 for true and 0 for false
+fn truth(expr: Expr, op: BinaryOp, span: Span) -> Expr {
+    Expr::Binary {
+        op,
+        lhs: Box::new(expr),
+        rhs: Box::new(Expr::Number { value: 0.0, span }),
+        span,
+    }
+}
+
+/// Turns the arguments of `if(cond, then, otherwise)` into a conditional, so
+/// that the branches are evaluated lazily like those of `? :`
+fn conditional_call(args: Vec<Expr>, span: Span) -> Result<Expr, CalcError> {
+    match <[Expr; 3]>::try_from(args) {
+        Ok([condition, then, otherwise]) => Ok(Expr::Conditional {
+            condition: Box::new(condition),
+            then: Box::new(then),
+            otherwise: Box::new(otherwise),
+        }),
+        Err(args) => Err(CalcError::ArityMismatch {
+            name: "if".to_string(),
+            expected: "3 arguments".to_string(),
+            got: args.len(),
+            span: Some(span),
+        }),
+    }
+}
+
+/// Turns the arguments of a function such as `sum(var, lo, hi, body)` into a
+/// binding, so that the body is evaluated by the function rather than up front
+fn binding_call(form: BindingForm, args: Vec<Expr>, span: Span) -> Result<Expr, CalcError> {
+    let (counts, var_index, body_index) = form.layout();
+    if !counts.contains(&args.len()) {
+        let counts: Vec<String> = counts.iter().map(|count| count.to_string()).collect();
+        return Err(CalcError::ArityMismatch {
+            name: form.name().to_string(),
+            expected: format!("{} arguments", counts.join(" or ")),
+            got: args.len(),
+            span: Some(span),
+        });
+    }
+    let (mut var, mut body, mut rest) = (String::new(), None, Vec::new());
+    for (i, arg) in args.into_iter().enumerate() {
+        match arg {
+            Expr::Var { name, .. } if i == var_index => var = name,
+            _ if i == var_index => {
+                let message = format!(
+                    "{} expects a variable name as argument {}",
+                    form.name(),
+                    var_index + 1
+                );
+                return Err(CalcError::syntax(message, span));
+            }
+            arg if i == body_index => body = Some(Box::new(arg)),
+            arg => rest.push(arg),
+        }
+    }
+    Ok(Expr::Binding {
+        form,
+        var,
+        body: body.expect("the layout has a body"),
+        args: rest,
+        span,
+    })
+}
+
+/// The `name(params) =` part of a function definition
+struct DefinitionHeader {
+    name: String,
+    span: Span,
+    params: Vec<String>,
+    body_start: usize,
+}
+
+/// The deepest that an expression may nest, counting each group, argument
+/// and operator around a part of it, so that the trees of very long or very
+/// nested input, such as `((((…))))` or `1+1+…+1`, can be evaluated without
+/// running out of stack
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    // How deeply the part being parsed nests, up to `MAX_DEPTH`
+    depth: usize,
+    implicit_multiplication: bool,
+    // Set while parsing the inside of `|x|`, which cannot be nested
+    in_bars: bool,
+    // Set when parsing for `evaluate_units`, to recognize units after numbers
+    // and the `in` and `to` conversions
+    units: bool,
+}
+
+impl Parser<'_> {
+    /// The position just after the last token, where the input ran out
+    fn end(&self) -> usize {
+        self.tokens.last().map_or(0, |token| token.span.end)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Nests what is being parsed one level deeper, at `span`
+    fn deeper(&mut self, span: Span) -> Result<(), CalcError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            let message = format!("expression nested more than {} levels deep", MAX_DEPTH);
+            return Err(CalcError::syntax(message, span));
+        }
+        Ok(())
+    }
+
+    /// Parses an expression whose operators all bind at least as tight as `min_bp`
+    fn expression(&mut self, min_bp: u8) -> Result<Expr, CalcError> {
+        let depth = self.depth;
+        let end = self.end();
+        let span = self
+            .peek()
+            .map_or(Span { start: end, end }, |token| token.span);
+        self.deeper(span)?;
+        let mut lhs = self.operand()?;
+        // Set once a comparison has been parsed at this level, so that
+        // `1 < 2 < 3` is rejected while `(1 < 2) < 3` is not
+        let mut compared = false;
+
+        while let Some(token) = self.peek() {
+            // The conditional operator binds loosest of all and is right-associative,
+            // so `a ? b : c ? d : e` is `a ? b : (c ? d : e)`
+            if token.kind == TokenKind::Question {
+                if CONDITIONAL_BP.0 < min_bp {
+                    break;
+                }
+                let question = token.span;
+                self.pos += 1;
+                let then = self.expression(0)?;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::Colon,
+                        ..
+                    }) => {}
+                    Some(other) => return Err(self.unexpected(&other)),
+                    None => {
+                        return Err(CalcError::UnclosedDelimiter {
+                            delimiter: '?',
+                            span: question,
+                        });
+                    }
+                }
+                let otherwise = self.expression(CONDITIONAL_BP.1)?;
+                lhs = Expr::Conditional {
+                    condition: Box::new(lhs),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                };
+                self.deeper(question)?;
+                continue;
+            }
+
+            if self.units
+                && matches!(&token.kind, TokenKind::Identifier(word) if word == "in" || word == "to")
+            {
+                if CONVERSION_BP.0 < min_bp {
+                    break;
+                }
+                let span = token.span;
+                self.pos += 1;
+                let unit = self.expression(CONVERSION_BP.1)?;
+                lhs = Expr::Binary {
+                    op: BinaryOp::Convert,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(unit),
+                    span,
+                };
+                self.deeper(span)?;
+                continue;
+            }
+
+            // `of` only takes a percentage, so `20% of 50` is `20% * 50`
+            if token.kind == TokenKind::Of {
+                if OF_BP.0 < min_bp {
+                    break;
+                }
+                if !matches!(
+                    lhs,
+                    Expr::Unary {
+                        op: UnaryOp::Percent,
+                        ..
+                    }
+                ) {
+                    return Err(self.unexpected(token));
+                }
+                let span = token.span;
+                self.pos += 1;
+                let rhs = self.expression(OF_BP.1)?;
+                lhs = Expr::Binary {
+                    op: BinaryOp::Mul,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                    span,
+                };
+                self.deeper(span)?;
+                continue;
+            }
+
+            // `a and b` and `a or b` become conditionals, so that the right-hand
+            // side is only evaluated if it decides the result
+            if matches!(token.kind, TokenKind::And | TokenKind::Or) {
+                let (left_bp, right_bp) = if token.kind == TokenKind::And {
+                    AND_BP
+                } else {
+                    OR_BP
+                };
+                if left_bp < min_bp {
+                    break;
+                }
+                let (kind, span) = (token.kind.clone(), token.span);
+                self.pos += 1;
+                let rhs = truth(self.expression(right_bp)?, BinaryOp::NotEqual, span);
+                let (then, otherwise) = if kind == TokenKind::And {
+                    (rhs, Expr::Number { value: 0.0, span })
+                } else {
+                    (Expr::Number { value: 1.0, span }, rhs)
+                };
+                lhs = Expr::Conditional {
+                    condition: Box::new(lhs),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                };
+                self.deeper(span)?;
+                continue;
+            }
+
+            // Inside `|x|` a bar after an operand closes the bars rather than
+            // being a bitwise or
+            if token.kind == TokenKind::Pipe && self.in_bars {
+                break;
+            }
+
+            let next = self.tokens.get(self.pos + 1).map(|t| &t.kind);
+            if let Some((op, left_bp)) = postfix_binding_power(&token.kind, next) {
+                if left_bp < min_bp {
+                    break;
+                }
+                let span = token.span;
+                lhs = Expr::Unary {
+                    op,
+                    operand: Box::new(lhs),
+                    span,
+                };
+                self.pos += 1;
+                self.deeper(span)?;
+                continue;
+            }
+
+            // An operand directly followed by `(`, a name or a radical sign is
+            // an implicit multiplication with the same precedence as `*`, so
+            // `6/2(3)` is `(6/2)*3`, `2pi` is `2*pi` and `2√9` is `2*√9`. The
+            // token is not consumed because it starts the right-hand operand.
+            let implicit = self.implicit_multiplication
+                && matches!(
+                    token.kind,
+                    TokenKind::LParen | TokenKind::Identifier(_) | TokenKind::Radical(_)
+                );
+            let entry = if implicit {
+                Some((BinaryOp::Mul, 30, 31))
+            } else {
+                infix_binding_power(&token.kind)
+            };
+            let (op, left_bp, right_bp) = match entry {
+                Some(entry) => entry,
+                None => break,
+            };
+            if left_bp < min_bp {
+                break;
+            }
+
+            let span = token.span;
+            if op.is_comparison() {
+                if compared {
+                    let message = "chained comparisons are not supported";
+                    return Err(CalcError::syntax(message, span));
+                }
+                compared = true;
+            }
+            if !implicit {
+                self.pos += 1;
+            }
+            let rhs = self.expression(right_bp)?;
+            lhs = Expr::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                span,
+            };
+            self.deeper(span)?;
+        }
+
+        self.depth = depth;
+        Ok(lhs)
+    }
+
+    /// Parses a number, a name, a function call, a parenthesized group, `|x|` or
+    /// a prefix operator applied to an operand
+    fn operand(&mut self) -> Result<Expr, CalcError> {
+        let token = match self.next() {
+            Some(token) => token,
+            None => {
+                let end = self.end();
+                let span = Span { start: end, end };
+                return Err(CalcError::UnexpectedEnd { span });
+            }
+        };
+
+        // `not x` and `!x` are `x == 0`
+        if matches!(token.kind, TokenKind::Not | TokenKind::Bang) {
+            let right_bp = if token.kind == TokenKind::Not {
+                NOT_BP
+            } else {
+                40
+            };
+            let operand = self.expression(right_bp)?;
+            return Ok(truth(operand, BinaryOp::Equal, token.span));
+        }
+
+        // `√x` and `∛x` are calls to `sqrt` and `cbrt`
+        if let TokenKind::Radical(name) = token.kind {
+            let operand = self.expression(RADICAL_BP)?;
+            return Ok(Expr::Call {
+                name: name.to_string(),
+                args: vec![operand],
+                span: token.span,
+            });
+        }
+
+        if let Some((op, right_bp)) = prefix_binding_power(&token.kind) {
+            let operand = self.expression(right_bp)?;
+            return Ok(Expr::Unary {
+                op,
+                operand: Box::new(operand),
+                span: token.span,
+            });
+        }
+
+        match token.kind {
+            TokenKind::Number(value) => {
+                let number = Expr::Number {
+                    value,
+                    span: token.span,
+                };
+                match self.unit_after_number() {
+                    Some(span) => Ok(Expr::Binary {
+                        op: BinaryOp::Mul,
+                        lhs: Box::new(number),
+                        rhs: Box::new(self.expression(UNIT_BP)?),
+                        span,
+                    }),
+                    None => Ok(number),
+                }
+            }
+            TokenKind::Identifier(name) => {
+                if self
+                    .peek()
+                    .is_some_and(|next| next.kind == TokenKind::LParen)
+                {
+                    self.pos += 1;
+                    let args = self.arguments(token.span, name == "solve")?;
+                    if name == "if" {
+                        return conditional_call(args, token.span);
+                    }
+                    // Any call to `sum` other than `sum(i, lo, hi, body)` adds
+                    // up its arguments
+                    let aggregate =
+                        name == "sum" && !(args.len() == 4 && matches!(args[0], Expr::Var { .. }));
+                    if !aggregate && let Some(form) = BindingForm::from_name(&name) {
+                        return binding_call(form, args, token.span);
+                    }
+                    return Ok(Expr::Call {
+                        name,
+                        args,
+                        span: token.span,
+                    });
+                }
+                Ok(Expr::Var {
+                    name,
+                    span: token.span,
+                })
+            }
+            TokenKind::Pipe => {
+                // `||x|-1|` can't be told apart from other readings, so bars
+                // only support a single level
+                if self.in_bars {
+                    let message = "use abs() instead of nested absolute value bars";
+                    return Err(CalcError::syntax(message, token.span));
+                }
+                self.in_bars = true;
+                let inner = self.expression(0)?;
+                self.in_bars = false;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::Pipe,
+                        ..
+                    }) => Ok(Expr::Call {
+                        name: "abs".to_string(),
+                        args: vec![inner],
+                        span: token.span,
+                    }),
+                    Some(other) => Err(self.unexpected(&other)),
+                    None => Err(CalcError::UnclosedDelimiter {
+                        delimiter: '|',
+                        span: token.span,
+                    }),
+                }
+            }
+            TokenKind::LParen => {
+                // Parentheses make a bar unambiguous again, so `|(1 | 2)|` works
+                let in_bars = std::mem::replace(&mut self.in_bars, false);
+                let inner = self.expression(0)?;
+                self.in_bars = in_bars;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::RParen,
+                        ..
+                    }) => Ok(inner),
+                    Some(other) => Err(self.unexpected(&other)),
+                    None => Err(CalcError::UnclosedDelimiter {
+                        delimiter: '(',
+                        span: token.span,
+                    }),
+                }
+            }
+            TokenKind::LBracket => self.list(token.span),
+            _ => Err(self.unexpected(&token)),
+        }
+    }
+
+    /// Parses the comma-separated items of a list after its opening `[`
+    fn list(&mut self, span: Span) -> Result<Expr, CalcError> {
+        let mut items = Vec::new();
+        if self.peek().is_some_and(|t| t.kind == TokenKind::RBracket) {
+            self.pos += 1;
+            return Ok(Expr::List { items, span });
+        }
+
+        let in_bars = std::mem::replace(&mut self.in_bars, false);
+        loop {
+            let item = self.expression(0)?;
+            if let Expr::List { span, .. } = item {
+                return Err(CalcError::syntax("nested lists are not supported", span));
+            }
+            items.push(item);
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => continue,
+                Some(Token {
+                    kind: TokenKind::RBracket,
+                    ..
+                }) => {
+                    self.in_bars = in_bars;
+                    return Ok(Expr::List { items, span });
+                }
+                Some(other) => return Err(self.unexpected(&other)),
+                None => {
+                    return Err(CalcError::UnclosedDelimiter {
+                        delimiter: '[',
+                        span,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Parses a comma-separated argument list after the opening `(` of a call.
+    /// With `equation`, the first argument may be `lhs = rhs`, which becomes
+    /// `lhs - rhs` so that its roots are the solutions.
+    fn arguments(&mut self, name_span: Span, equation: bool) -> Result<Vec<Expr>, CalcError> {
+        let mut args = Vec::new();
+        if self.peek().is_some_and(|t| t.kind == TokenKind::RParen) {
+            self.pos += 1;
+            return Ok(args);
+        }
+
+        let in_bars = std::mem::replace(&mut self.in_bars, false);
+        loop {
+            let mut arg = self.expression(0)?;
+            if equation
+                && args.is_empty()
+                && let Some(equals) = self.peek().filter(|t| t.kind == TokenKind::Equals)
+            {
+                let span = equals.span;
+                self.pos += 1;
+                arg = Expr::Binary {
+                    op: BinaryOp::Sub,
+                    lhs: Box::new(arg),
+                    rhs: Box::new(self.expression(0)?),
+                    span,
+                };
+            }
+            args.push(arg);
+            match self.next() {
+                Some(Token {
+                    kind: TokenKind::Comma,
+                    ..
+                }) => continue,
+                Some(Token {
+                    kind: TokenKind::RParen,
+                    ..
+                }) => {
+                    self.in_bars = in_bars;
+                    return Ok(args);
+                }
+                Some(other) => return Err(self.unexpected(&other)),
+                None => {
+                    let span = Span {
+                        start: name_span.end,
+                        end: name_span.end,
+                    };
+                    return Err(CalcError::UnclosedDelimiter {
+                        delimiter: '(',
+                        span,
+                    });
+                }
+            }
+        }
+    }
+
+    fn unexpected(&self, token: &Token) -> CalcError {
+        let span = token.span;
+        match token.kind {
+            TokenKind::RParen => CalcError::syntax("unmatched closing parenthesis", span),
+            TokenKind::RBracket => CalcError::syntax("unmatched closing bracket", span),
+            TokenKind::Percent => CalcError::syntax("'%' must follow a number", span),
+            TokenKind::Of => CalcError::syntax("'of' must follow a percentage such as 20%", span),
+            TokenKind::Comma if let Some(error) = self.malformed_grouping(token) => error,
+            _ => CalcError::UnexpectedToken {
+                token: self.input[span.start..span.end].to_string(),
+                span,
+            },
+        }
+    }
+
+    /// Reports a comma squeezed between two numbers, such as `1,23`, as a badly
+    /// grouped thousands separator
+    fn malformed_grouping(&self, comma: &Token) -> Option<CalcError> {
+        let index = self.tokens.iter().position(|t| t.span == comma.span)?;
+        let before = self.tokens.get(index.checked_sub(1)?)?;
+        let after = self.tokens.get(index + 1)?;
+
+        let adjacent = before.span.end == comma.span.start && after.span.start == comma.span.end;
+        if !adjacent
+            || !matches!(before.kind, TokenKind::Number(_))
+            || !matches!(after.kind, TokenKind::Number(_))
+        {
+            return None;
+        }
+
+        let span = Span {
+            start: before.span.start,
+            end: after.span.end,
+        };
+        let message = format!(
+            "digit groups must have three digits in '{}'",
+            &self.input[span.start..span.end]
+        );
+        Some(CalcError::syntax(message, span))
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, tokens: Vec<Token>, options: &EvalOptions) -> Self {
+        Parser {
+            input,
+            tokens,
+            pos: 0,
+            depth: 0,
+            implicit_multiplication: options.implicit_multiplication,
+            in_bars: false,
+            units: false,
+        }
+    }
+
+    /// Returns the position of the unit following the number just parsed,
+    /// if in units mode and there is one
+    fn unit_after_number(&self) -> Option<Span> {
+        if !self.units {
+            return None;
+        }
+        let [unit, rest @ ..] = self.tokens.get(self.pos..)? else {
+            return None;
+        };
+        let TokenKind::Identifier(name) = &unit.kind else {
+            return None;
+        };
+        let call = rest
+            .first()
+            .is_some_and(|next| next.kind == TokenKind::LParen);
+        (units::is_unit(name) && !call).then_some(unit.span)
+    }
+
+    /// Parses the tokens as an expression, an assignment of the form
+    /// `name = expr` or a definition `name(params) = expr`
+    fn statement(&mut self) -> Result<Statement, CalcError> {
+        if let Some(header) = self.definition_header()? {
+            self.pos = header.body_start;
+            let body = self.complete_expression()?;
+            return Ok(Statement::Define {
+                name: header.name,
+                span: header.span,
+                params: header.params,
+                body,
+            });
+        }
+
+        if let [
+            Token {
+                kind: TokenKind::Identifier(name),
+                span,
+            },
+            Token {
+                kind: TokenKind::Equals,
+                ..
+            },
+            ..,
+        ] = self.tokens.as_slice()
+        {
+            let (name, span) = (name.clone(), *span);
+            self.pos = 2;
+            let value = self.complete_expression()?;
+            return Ok(Statement::Assign { name, span, value });
+        }
+
+        self.complete_expression().map(Statement::Expr)
+    }
+
+    /// Parses an expression that must extend to the end of the input
+    fn complete_expression(&mut self) -> Result<Expr, CalcError> {
+        let expr = self.expression(0)?;
+
+        // Anything left over means the expression did not end where it should have
+        if let Some(token) = self.peek() {
+            return Err(self.unexpected(token));
+        }
+
+        Ok(expr)
+    }
+
+    /// Recognizes the start of a function definition, `name(a, b) =`, returning
+    /// the name, the parameter names and the index of the token after the `=`
+    fn definition_header(&self) -> Result<Option<DefinitionHeader>, CalcError> {
+        let [
+            Token {
+                kind: TokenKind::Identifier(name),
+                span,
+            },
+            Token {
+                kind: TokenKind::LParen,
+                ..
+            },
+            ..,
+        ] = self.tokens.as_slice()
+        else {
+            return Ok(None);
+        };
+
+        let mut params: Vec<String> = Vec::new();
+        let mut i = 2;
+        // An empty parameter list closes immediately
+        if self.tokens.get(i).map(|t| &t.kind) != Some(&TokenKind::RParen) {
+            loop {
+                match self.tokens.get(i) {
+                    Some(Token {
+                        kind: TokenKind::Identifier(param),
+                        span,
+                    }) => {
+                        if params.contains(param) {
+                            let message = format!("duplicate parameter '{}'", param);
+                            return Err(CalcError::syntax(message, *span));
+                        }
+                        params.push(param.clone());
+                    }
+                    _ => return Ok(None),
+                }
+                match self.tokens.get(i + 1).map(|t| &t.kind) {
+                    Some(TokenKind::Comma) => i += 2,
+                    Some(TokenKind::RParen) => {
+                        i += 1;
+                        break;
+                    }
+                    _ => return Ok(None),
+                }
+            }
+        }
+
+        match self.tokens.get(i + 1).map(|t| &t.kind) {
+            Some(TokenKind::Equals) => Ok(Some(DefinitionHeader {
+                name: name.clone(),
+                span: *span,
+                params,
+                body_start: i + 2,
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Parses a complete input string into an expression tree
+pub(crate) fn parse(input: &str, options: &EvalOptions) -> Result<Expr, CalcError> {
+    let tokens = tokenize(input, options)?;
+    if tokens.is_empty() {
+        return Err(CalcError::EmptyExpression);
+    }
+    Parser::new(input, tokens, options).complete_expression()
+}
+
+/// Parses a complete input string like [`parse`], also accepting quantities
+/// such as `5 km` and conversions such as `10 mi in km`
+pub(crate) fn parse_with_units(input: &str, options: &EvalOptions) -> Result<Expr, CalcError> {
+    let tokens = tokenize(input, options)?;
+    if tokens.is_empty() {
+        return Err(CalcError::EmptyExpression);
+    }
+    let mut parser = Parser::new(input, tokens, options);
+    parser.units = true;
+    parser.complete_expression()
+}
+
+/// Parses a complete input string of statements separated by `;`
+///
+/// Empty statements, such as after a trailing `;`, are skipped. When there is
+/// more than one statement, errors say which one failed, counting from 1.
+pub(crate) fn parse_statements(
+    input: &str,
+    options: &EvalOptions,
+) -> Result<Vec<Statement>, CalcError> {
+    let tokens = tokenize(input, options)?;
+    let segments: Vec<&[Token]> = tokens
+        .split(|token| token.kind == TokenKind::Semicolon)
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    if segments.is_empty() {
+        return Err(CalcError::EmptyExpression);
+    }
+
+    let count = segments.len();
+    segments
+        .into_iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            Parser::new(input, segment.to_vec(), options)
+                .statement()
+                .map_err(|e| e.in_statement(i, count))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CalcError, Context, EvalOptions, evaluate, evaluate_with_context, evaluate_with_options,
+        extract_number_literals, extract_operators, validate,
+    };
+
+    #[test]
+    fn operators_and_precedence() {
+        assert_eq!(evaluate("2*(3+(4-1))"), Ok(12.0));
+        assert_eq!(evaluate("3 - -2"), Ok(5.0));
+        assert_eq!(evaluate("+7/2"), Ok(3.5));
+        assert_eq!(
+            evaluate("3++*2").unwrap_err().to_string(),
+            "unexpected '*' at position 3"
+        );
+        assert_eq!(evaluate("2^3^2"), Ok(512.0));
+        assert_eq!(evaluate("2*3^2"), Ok(18.0));
+        assert_eq!(evaluate("3*2**4"), Ok(48.0));
+        assert_eq!(evaluate("2**-3"), Ok(0.125));
+        assert!(evaluate("2* *3").is_err());
+        assert!(evaluate("(-8)^(1/3)").unwrap().is_nan());
+        assert_eq!(evaluate("7+10%4"), Ok(9.0));
+        assert_eq!(evaluate("-7%3"), Ok(-1.0));
+        assert_eq!(evaluate("-7//2"), Ok(-4.0));
+        assert_eq!(evaluate("10//3*2"), Ok(6.0));
+        assert_eq!(evaluate("3!+1"), Ok(7.0));
+        assert_eq!(
+            evaluate("2.5!").unwrap_err().to_string(),
+            "domain error: factorial requires a non-negative integer"
+        );
+        assert!(evaluate("171!").is_err());
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(evaluate("200*15%"), Ok(30.0));
+        assert_eq!(evaluate("50%*200"), Ok(100.0));
+        assert_eq!(
+            evaluate("%50").unwrap_err().to_string(),
+            "'%' must follow a number at position 0"
+        );
+        assert_eq!(evaluate("20% of 50"), Ok(10.0));
+        assert_eq!(evaluate("20% of (30+20)"), Ok(10.0));
+        assert_eq!(evaluate("20% of 30+20"), Ok(26.0));
+        assert_eq!(evaluate("10 + 20% of 50"), Ok(20.0));
+        assert_eq!(evaluate("-20% of 50"), Ok(-10.0));
+        assert_eq!(evaluate("100 / 20% of 50"), Ok(10.0));
+        assert_eq!(evaluate("50% of 50% of 8"), Ok(2.0));
+        assert_eq!(
+            evaluate("2 of 3").unwrap_err().to_string(),
+            "'of' must follow a percentage such as 20% at position 2"
+        );
+        assert!(
+            evaluate("of 3")
+                .unwrap_err()
+                .to_string()
+                .starts_with("'of' must follow a percentage")
+        );
+        assert!(evaluate("20% of").is_err());
+        assert_eq!(extract_operators("20% of 50"), vec!["of"]);
+    }
+
+    #[test]
+    fn unicode_operators_and_implicit_multiplication() {
+        assert_eq!(evaluate("6×7−2"), Ok(40.0));
+        assert_eq!(evaluate("8÷2·3 - 1*2"), Ok(10.0));
+        assert_eq!(evaluate("2(3+4)"), Ok(14.0));
+        assert_eq!(evaluate("(1+1)(2+2)"), Ok(8.0));
+        assert_eq!(evaluate("6/2(3)"), Ok(9.0));
+    }
+
+    #[test]
+    fn adjacent_numbers_and_comments() {
+        assert_eq!(
+            evaluate("1 2+3").unwrap_err().to_string(),
+            "missing an operator before '2' at position 2"
+        );
+        assert!(
+            evaluate("12   34+1")
+                .unwrap_err()
+                .to_string()
+                .starts_with("missing an operator before '34' at position 5")
+        );
+        assert!(
+            evaluate("1\t2")
+                .unwrap_err()
+                .to_string()
+                .starts_with("missing an operator before '2' at position 2")
+        );
+        assert!(
+            evaluate("1 # one\n 2")
+                .unwrap_err()
+                .to_string()
+                .starts_with("missing an operator before '2'")
+        );
+        assert!(validate("12 \t 34+1").is_err());
+        assert!(extract_number_literals("1 2+3").is_err());
+        assert_eq!(evaluate("3*4  # area of the rug"), Ok(12.0));
+        assert_eq!(evaluate("2 + # two\n3 # three"), Ok(5.0));
+        assert_eq!(
+            evaluate("# just a note").unwrap_err().to_string(),
+            "empty expression"
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(evaluate("3+1 > 2*2"), Ok(0.0));
+        assert_eq!(evaluate("10/2 == 5"), Ok(1.0));
+        assert_eq!(evaluate("2 <= 2"), Ok(1.0));
+        assert_eq!(evaluate("-1 >= 0"), Ok(0.0));
+        assert_eq!(evaluate("1 != 2"), Ok(1.0));
+        assert_eq!(evaluate("3! == 6"), Ok(1.0));
+        assert_eq!(evaluate("(1 < 2) + (3 ≥ 4)"), Ok(1.0));
+        assert_eq!(evaluate("0.1+0.2 == 0.3"), Ok(0.0));
+        assert_eq!(
+            evaluate("1 < 2 < 3").unwrap_err().to_string(),
+            "chained comparisons are not supported at position 6"
+        );
+        assert_eq!(evaluate("(1 < 2) < 3"), Ok(1.0));
+
+        let tolerant = EvalOptions::new().comparison_tolerance(1e-9);
+        assert_eq!(evaluate_with_options("0.1+0.2 == 0.3", &tolerant), Ok(1.0));
+        assert_eq!(evaluate_with_options("0.1+0.2 > 0.3", &tolerant), Ok(0.0));
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(evaluate("0xFF & 0x0F"), Ok(15.0));
+        assert_eq!(evaluate("1 | 6"), Ok(7.0));
+        assert_eq!(evaluate("~0 & 0xFF"), Ok(255.0));
+        assert_eq!(evaluate("~5"), Ok(-6.0));
+        assert_eq!(evaluate("6 xor 3"), Ok(5.0));
+        assert_eq!(evaluate("6 ^^ 3"), Ok(5.0));
+        assert_eq!(evaluate("1+2 & 3"), Ok(3.0));
+        assert_eq!(evaluate("2*3 | 1"), Ok(7.0));
+        assert_eq!(evaluate("1 | 2 & 3"), Ok(3.0));
+        assert_eq!(evaluate("5 xor 1 | 8"), Ok(12.0));
+        assert_eq!(evaluate("5 & 1 == 1"), Ok(1.0));
+        assert_eq!(evaluate("|-3| | 4"), Ok(7.0));
+        assert_eq!(evaluate("|(1 | -8)|"), Ok(7.0));
+        assert_eq!(evaluate("1 << 10"), Ok(1024.0));
+        assert_eq!(evaluate("256 >> 4"), Ok(16.0));
+        assert_eq!(evaluate("3 * (1 << 4)"), Ok(48.0));
+        assert_eq!(evaluate("1 << 2 + 1"), Ok(8.0));
+        assert_eq!(evaluate("1 << 4 | 1 << 1"), Ok(18.0));
+        assert_eq!(evaluate("-16 >> 2"), Ok(-4.0));
+        assert_eq!(evaluate("1 << 3 < 1 << 4"), Ok(1.0));
+        assert_eq!(
+            evaluate("1 << -1").unwrap_err().to_string(),
+            "shift amount must be between 0 and 63, got -1"
+        );
+        assert!(evaluate("1 << 64").is_err());
+        assert!(
+            evaluate("2.5 >> 1")
+                .unwrap_err()
+                .to_string()
+                .contains("require integers")
+        );
+        assert_eq!(
+            evaluate("1.5 & 1").unwrap_err().to_string(),
+            "bitwise operators require integers, got 1.5"
+        );
+    }
+
+    #[test]
+    fn conditional_operator() {
+        assert_eq!(evaluate("2 > 1 ? 10 : 20"), Ok(10.0));
+        assert_eq!(evaluate("0 ? 10 : 20"), Ok(20.0));
+        assert_eq!(evaluate("1 > 0 ? 5 : 1/0"), Ok(5.0));
+        assert_eq!(evaluate("0 ? 1 : 0 ? 2 : 3"), Ok(3.0));
+        assert_eq!(evaluate("1 ? 0 ? 1 : 2 : 3"), Ok(2.0));
+        assert_eq!(evaluate("(1 ? 2 : 3) + 1"), Ok(3.0));
+        assert_eq!(evaluate("1 ? 2 : 3 + 1"), Ok(2.0));
+        assert_eq!(
+            evaluate("1 ? 2").unwrap_err().to_string(),
+            "missing ':' for the '?' at position 2"
+        );
+        assert!(evaluate("1 : 2").is_err());
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(evaluate("5 > 0 and 5 < 10"), Ok(1.0));
+        assert_eq!(evaluate("15 > 0 and 15 < 10"), Ok(0.0));
+        assert_eq!(evaluate("-1 < 0 or -1 > 10"), Ok(1.0));
+        assert_eq!(evaluate("2 > 1 && 3 > 2"), Ok(1.0));
+        assert_eq!(evaluate("0 || 5"), Ok(1.0));
+        assert_eq!(evaluate("1 or 0 and 0"), Ok(1.0));
+        assert_eq!(evaluate("(1 or 0) and 0"), Ok(0.0));
+        assert_eq!(evaluate("1 + 1 and 0 + 2"), Ok(1.0));
+        assert_eq!(evaluate("2 * 3 > 5 and 1"), Ok(1.0));
+        assert_eq!(evaluate("not 2 > 3"), Ok(1.0));
+        assert_eq!(evaluate("not 1 + 1"), Ok(0.0));
+        assert_eq!(evaluate("not 0 and 0"), Ok(0.0));
+        assert_eq!(evaluate("!0 + 1"), Ok(2.0));
+        assert_eq!(evaluate("!2 > -1"), Ok(1.0));
+        assert_eq!(evaluate("3! == 6 and !0"), Ok(1.0));
+        assert_eq!(evaluate("true and not false"), Ok(1.0));
+        assert_eq!(evaluate("true + true"), Ok(2.0));
+        assert_eq!(evaluate("1 and 0 ? 10 : 20"), Ok(20.0));
+        assert_eq!(evaluate("if(3 > 1 and 3 < 5, 100, 0)"), Ok(100.0));
+        assert_eq!(evaluate("0 and 1/0"), Ok(0.0));
+        assert_eq!(evaluate("1 or 1/0"), Ok(1.0));
+        assert_eq!(
+            evaluate("1 and 1/0").unwrap_err().to_string(),
+            "division by zero"
+        );
+        assert_eq!(evaluate("(2) || 0"), Ok(1.0));
+        assert_eq!(evaluate("|-2| or 0"), Ok(1.0));
+        assert!(evaluate("and = 1").is_err());
+    }
+
+    #[test]
+    fn signs_and_powers() {
+        assert_eq!(evaluate("-2^2"), Ok(-4.0));
+        assert_eq!(evaluate("(-2)^2"), Ok(4.0));
+        assert_eq!(evaluate("2^-3"), Ok(0.125));
+        assert_eq!(evaluate("-2^-2"), Ok(-0.25));
+        assert_eq!(evaluate("(-2)^-2"), Ok(0.25));
+        assert_eq!(evaluate("3^-1^2"), Ok(1.0 / 3.0));
+        assert_eq!(evaluate("2^-1^-1"), Ok(0.5));
+        assert_eq!(evaluate("2^--2"), Ok(4.0));
+        assert_eq!(evaluate("2^+3"), Ok(8.0));
+        assert_eq!(evaluate("-2^3^2"), Ok(-512.0));
+        assert_eq!(evaluate("1-2^2"), Ok(-3.0));
+        assert_eq!(evaluate("1 - -2^2"), Ok(5.0));
+        assert_eq!(evaluate("2*-2^2"), Ok(-8.0));
+        assert_eq!(evaluate("-2**2"), Ok(-4.0));
+        assert_eq!(evaluate("-3!"), Ok(-6.0));
+        assert_eq!(evaluate("2^-2!"), Ok(0.25));
+        assert_eq!(evaluate("-50%^2"), Ok(-0.25));
+        assert_eq!(evaluate("~2^2"), Ok(-5.0));
+
+        let mut context = Context::new();
+        assert_eq!(evaluate_with_context("x = 3; -x^2", &mut context), Ok(-9.0));
+        assert_eq!(evaluate_with_context("2^-x", &mut context), Ok(0.125));
+    }
+
+    #[test]
+    fn nesting_depth_limit() {
+        // Test threads have a smaller stack than the main thread, which
+        // unoptimized parsing of `MAX_DEPTH` levels needs
+        let limits = || {
+            let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+            assert_eq!(evaluate(&nested(200)), Ok(1.0));
+            assert_eq!(
+                evaluate(&nested(100_000)).unwrap_err().to_string(),
+                "expression nested more than 256 levels deep at position 256"
+            );
+            assert!(matches!(
+                evaluate(&format!("{}1", "-".repeat(100_000))),
+                Err(CalcError::Syntax { .. })
+            ));
+            assert!(
+                evaluate(&format!("1{}", "^1".repeat(100_000)))
+                    .unwrap_err()
+                    .is_parse_error()
+            );
+            assert!(
+                evaluate(&format!("1{}", "+1".repeat(100_000)))
+                    .unwrap_err()
+                    .is_parse_error()
+            );
+            assert!(
+                evaluate(&format!(
+                    "{}1{}",
+                    "abs(".repeat(100_000),
+                    ")".repeat(100_000)
+                ))
+                .is_err()
+            );
+            assert_eq!(evaluate(&format!("1{}", "+1".repeat(200))), Ok(201.0));
+        };
+        std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(limits)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+/// Prefix that physical constants are addressed by, as in `phys.c`
+pub(crate) const NAMESPACE: &str = "phys";
+
+/// Physical constants as `(name, value, description)`, in SI units, with the
+/// CODATA 2018 recommended values. All but `G` are exact by definition of the
+/// SI since 2019, and `g` by the 1901 CGPM.
+pub(crate) const PHYSICAL_CONSTANTS: &[(&str, f64, &str)] = &[
+    ("c", 299792458.0, "speed of light in vacuum, m/s"),
+    (
+        "G",
+        6.67430e-11,
+        "Newtonian constant of gravitation, m^3/(kg s^2)",
+    ),
+    ("h", 6.62607015e-34, "Planck constant, J s"),
+    ("Na", 6.02214076e23, "Avogadro constant, 1/mol"),
+    ("kB", 1.380649e-23, "Boltzmann constant, J/K"),
+    ("g", 9.80665, "standard acceleration of gravity, m/s^2"),
+];
+
+/// A group of constants that [`Context::insert_constants`](crate::Context::insert_constants)
+/// can make available under their short names
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstantSet {
+    /// The physical constants, such as `c` and `G`, which are always available
+    /// as `phys.c` and `phys.G`
+    Physics,
+}
+
+impl ConstantSet {
+    /// Returns the constants in the set as `(name, value, description)`
+    pub(crate) fn constants(self) -> &'static [(&'static str, f64, &'static str)] {
+        match self {
+            ConstantSet::Physics => PHYSICAL_CONSTANTS,
+        }
+    }
+}
+
+/// Looks up a namespaced name such as `phys.c`
+pub(crate) fn lookup(name: &str) -> Option<f64> {
+    let short = name.strip_prefix(NAMESPACE)?.strip_prefix('.')?;
+    PHYSICAL_CONSTANTS
+        .iter()
+        .find(|(constant, _, _)| *constant == short)
+        .map(|(_, value, _)| *value)
+}
@@ -0,0 +1,182 @@
+use crate::error::CalcError;
+use crate::functions::{MAX_EXACT_INTEGER, gcd_u64};
+use std::fmt;
+
+/// Bases for which Miller–Rabin gives the right answer for every 64-bit number
+const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// The prime factorization of a positive integer, as returned by [`factorize`]
+///
+/// Displays as a product of prime powers, such as `2^2*3*5` for 60, or `1` for 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Factorization {
+    factors: Vec<(u64, u32)>,
+}
+
+impl Factorization {
+    /// Returns the distinct prime factors in increasing order, each with its
+    /// exponent
+    pub fn factors(&self) -> &[(u64, u32)] {
+        &self.factors
+    }
+}
+
+impl fmt::Display for Factorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.factors.is_empty() {
+            return write!(f, "1");
+        }
+        for (i, (prime, exponent)) in self.factors.iter().enumerate() {
+            if i > 0 {
+                write!(f, "*")?;
+            }
+            write!(f, "{}", prime)?;
+            if *exponent > 1 {
+                write!(f, "^{}", exponent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Factorizes a positive integer up to 2^53 into primes
+///
+/// This is the library form of the `factorize` function, whose result isn't a
+/// number and so can't be part of an expression.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::factorize;
+///
+/// assert_eq!(factorize(60.0).unwrap().to_string(), "2^2*3*5");
+/// assert_eq!(factorize(97.0).unwrap().factors(), &[(97, 1)]);
+/// assert_eq!(factorize(1.0).unwrap().to_string(), "1");
+/// assert_eq!(factorize(2f64.powi(53)).unwrap().to_string(), "2^53");
+/// assert_eq!(factorize(9007199254740881.0).unwrap().to_string(), "9007199254740881");
+/// assert_eq!(factorize(999999000001.0).unwrap().to_string(), "999999000001");
+/// assert_eq!(factorize(94906247.0 * 94906249.0).unwrap().to_string(), "94906247*94906249");
+/// assert!(factorize(0.0).unwrap_err().to_string().contains("must be a positive integer"));
+/// assert!(factorize(12.5).is_err());
+/// assert!(factorize(1e17).is_err());
+/// ```
+pub fn factorize(n: f64) -> Result<Factorization, CalcError> {
+    let mut remaining = positive_integer("factorize", n)?;
+    let mut primes = Vec::new();
+    // Small factors are quicker to divide out directly
+    for p in 2..1000 {
+        while remaining % p == 0 {
+            primes.push(p);
+            remaining /= p;
+        }
+    }
+    let mut pending = vec![remaining];
+    while let Some(n) = pending.pop() {
+        if n == 1 {
+            continue;
+        }
+        if is_prime(n) {
+            primes.push(n);
+            continue;
+        }
+        let divisor = pollard_rho(n);
+        pending.push(divisor);
+        pending.push(n / divisor);
+    }
+    primes.sort_unstable();
+
+    let mut factors: Vec<(u64, u32)> = Vec::new();
+    for p in primes {
+        match factors.last_mut() {
+            Some((last, exponent)) if *last == p => *exponent += 1,
+            _ => factors.push((p, 1)),
+        }
+    }
+    Ok(Factorization { factors })
+}
+
+/// Converts the argument of `name` to an integer, failing unless it is
+/// positive and small enough to be exact
+pub(crate) fn positive_integer(name: &str, value: f64) -> Result<u64, CalcError> {
+    if value.fract() != 0.0 || !(1.0..=MAX_EXACT_INTEGER).contains(&value) {
+        return Err(CalcError::other(format!(
+            "{} argument must be a positive integer up to 2^53, got {}",
+            name, value
+        )));
+    }
+    Ok(value as u64)
+}
+
+/// Returns whether `n` is prime, with a deterministic Miller–Rabin test
+pub(crate) fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in WITNESSES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    // n - 1 = d * 2^s with d odd
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    WITNESSES.iter().all(|&a| {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Returns the smallest prime greater than `n`, or `None` if it doesn't fit
+/// in 64 bits
+pub(crate) fn next_prime(n: u64) -> Option<u64> {
+    (n.checked_add(1)?..=u64::MAX).find(|&candidate| is_prime(candidate))
+}
+
+/// Finds a non-trivial divisor of an odd composite `n` with Pollard's rho
+/// method
+fn pollard_rho(n: u64) -> u64 {
+    // A cycle without a divisor is retried with a different polynomial
+    for c in 1.. {
+        let step = |x: u64| (mul_mod(x, x, n) + c) % n;
+        let (mut slow, mut fast) = (2, 2);
+        loop {
+            slow = step(slow);
+            fast = step(step(fast));
+            let divisor = gcd_u64(slow.abs_diff(fast), n);
+            if divisor == n {
+                break;
+            }
+            if divisor > 1 {
+                return divisor;
+            }
+        }
+    }
+    unreachable!("some polynomial finds a divisor of a composite number")
+}
+
+pub(crate) fn mul_mod(a: u64, b: u64, n: u64) -> u64 {
+    (a as u128 * b as u128 % n as u128) as u64
+}
+
+/// Computes `base^exponent mod n` by repeated squaring, with 128-bit products
+/// so that no step overflows
+pub(crate) fn pow_mod(mut base: u64, mut exponent: u64, n: u64) -> u64 {
+    let mut result = 1;
+    base %= n;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, n);
+        }
+        base = mul_mod(base, base, n);
+        exponent >>= 1;
+    }
+    result
+}
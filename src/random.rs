@@ -0,0 +1,36 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+thread_local! {
+    // Seeded from the standard library's per-process random keys
+    static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish());
+}
+
+/// Restarts this thread's random sequence from a fixed seed
+pub(crate) fn reseed(seed: u64) {
+    STATE.with(|state| state.set(seed));
+}
+
+/// Returns the next value of this thread's splitmix64 sequence
+pub(crate) fn next_u64() -> u64 {
+    STATE.with(|state| {
+        let next = state.get().wrapping_add(0x9E3779B97F4A7C15);
+        state.set(next);
+        let mut z = next;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    })
+}
+
+/// Returns a uniformly distributed value in `[0, 1)`
+pub(crate) fn next_f64() -> f64 {
+    // The top 53 bits fill the mantissa exactly
+    (next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Returns a uniformly distributed integer in `0..bound`, for a non-zero `bound`
+pub(crate) fn below(bound: u64) -> u64 {
+    ((next_u64() as u128 * bound as u128) >> 64) as u64
+}
@@ -0,0 +1,619 @@
+//! Exact evaluation over the rational numbers
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions::{self, MAX_EXACT_INTEGER};
+use crate::options::{EvalOptions, RoundingMode};
+use crate::parser::{self, BinaryOp, Expr, UnaryOp};
+use crate::unicode;
+
+/// An exact fraction in lowest terms, with a positive denominator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ratio {
+    numer: i128,
+    denom: i128,
+}
+
+impl Ratio {
+    /// Creates the fraction `numer/denom` in lowest terms, or `None` if the
+    /// denominator is zero
+    pub fn new(numer: i128, denom: i128) -> Option<Self> {
+        if denom == 0 {
+            return None;
+        }
+        let divisor = gcd(numer.unsigned_abs(), denom.unsigned_abs()) as i128;
+        let (numer, denom) = (numer / divisor, denom / divisor);
+        // `checked_neg` only fails for `i128::MIN`, which has no positive form
+        if denom < 0 {
+            Some(Ratio {
+                numer: numer.checked_neg()?,
+                denom: denom.checked_neg()?,
+            })
+        } else {
+            Some(Ratio { numer, denom })
+        }
+    }
+
+    pub fn from_integer(n: i128) -> Self {
+        Ratio { numer: n, denom: 1 }
+    }
+
+    pub fn numer(&self) -> i128 {
+        self.numer
+    }
+
+    pub fn denom(&self) -> i128 {
+        self.denom
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denom == 1
+    }
+
+    /// Returns the nearest `f64`, which may not be exact
+    pub fn to_f64(&self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+
+    /// Converts a number to a fraction through its shortest decimal form, so
+    /// the literal `0.1` becomes exactly `1/10`
+    fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        let text = value.to_string();
+        let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        let digits: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+        let denom = 10i128.checked_pow(fraction.len() as u32)?;
+        Ratio::new(digits, denom)
+    }
+
+    fn checked_add(self, other: Ratio) -> Option<Ratio> {
+        let numer = self
+            .numer
+            .checked_mul(other.denom)?
+            .checked_add(other.numer.checked_mul(self.denom)?)?;
+        Ratio::new(numer, self.denom.checked_mul(other.denom)?)
+    }
+
+    fn checked_neg(self) -> Option<Ratio> {
+        Some(Ratio {
+            numer: self.numer.checked_neg()?,
+            denom: self.denom,
+        })
+    }
+
+    fn checked_mul(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(
+            self.numer.checked_mul(other.numer)?,
+            self.denom.checked_mul(other.denom)?,
+        )
+    }
+
+    fn checked_recip(self) -> Option<Ratio> {
+        Ratio::new(self.denom, self.numer)
+    }
+
+    fn checked_cmp(self, other: Ratio) -> Option<Ordering> {
+        let lhs = self.numer.checked_mul(other.denom)?;
+        let rhs = other.numer.checked_mul(self.denom)?;
+        Some(lhs.cmp(&rhs))
+    }
+
+    fn checked_pow(self, exponent: i32) -> Option<Ratio> {
+        let base = if exponent < 0 {
+            self.checked_recip()?
+        } else {
+            self
+        };
+        let n = exponent.unsigned_abs();
+        Some(Ratio {
+            numer: base.numer.checked_pow(n)?,
+            denom: base.denom.checked_pow(n)?,
+        })
+    }
+
+    fn floor(self) -> Ratio {
+        Ratio::from_integer(self.numer.div_euclid(self.denom))
+    }
+
+    fn trunc(self) -> Ratio {
+        Ratio::from_integer(self.numer / self.denom)
+    }
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+/// Formats as `numer/denom`, or just the numerator for integers
+impl fmt::Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numer)
+        } else {
+            write!(f, "{}/{}", self.numer, self.denom)
+        }
+    }
+}
+
+/// The largest denominator the `frac` function tries, as the default for
+/// [`to_fraction`]
+pub const DEFAULT_MAX_DENOMINATOR: u64 = 10_000;
+
+/// Finds the fraction nearest to `value` whose denominator is at most
+/// `max_denom`, returning its numerator, its denominator and whether it is
+/// only an approximation of `value`
+///
+/// This is the library form of the `frac` function, whose result isn't a
+/// number and so can't be part of an expression. The fraction is the best
+/// one within the bound, found from the continued fraction of `value`, and
+/// is in lowest terms. A `max_denom` of 0 is taken as 1, and a value that
+/// isn't finite gives `(0, 1, true)`.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{DEFAULT_MAX_DENOMINATOR, to_fraction};
+///
+/// assert_eq!(to_fraction(0.375, DEFAULT_MAX_DENOMINATOR), (3, 8, false));
+/// assert_eq!(to_fraction(-0.375, DEFAULT_MAX_DENOMINATOR), (-3, 8, false));
+/// assert_eq!(to_fraction(0.1, DEFAULT_MAX_DENOMINATOR), (1, 10, false));
+/// assert_eq!(to_fraction(0.333333, DEFAULT_MAX_DENOMINATOR), (1, 3, true));
+/// assert_eq!(to_fraction(1.0 / 3.0, DEFAULT_MAX_DENOMINATOR), (1, 3, false));
+/// assert_eq!(to_fraction(std::f64::consts::PI, DEFAULT_MAX_DENOMINATOR), (355, 113, true));
+/// assert_eq!(to_fraction(std::f64::consts::PI, 100), (311, 99, true));
+/// assert_eq!(to_fraction(7.0, DEFAULT_MAX_DENOMINATOR), (7, 1, false));
+/// assert_eq!(to_fraction(-2.0, 1), (-2, 1, false));
+/// assert_eq!(to_fraction(2.7, 2), (5, 2, true));
+/// assert_eq!(to_fraction(0.00001, 100), (0, 1, true));
+/// assert_eq!(to_fraction(0.5, 0), (0, 1, true));
+/// assert_eq!(to_fraction(f64::NAN, 10), (0, 1, true));
+/// ```
+pub fn to_fraction(value: f64, max_denom: u64) -> (i64, u64, bool) {
+    if !value.is_finite() {
+        return (0, 1, true);
+    }
+    if value.fract() == 0.0 {
+        return (value as i64, 1, value as i64 as f64 != value);
+    }
+    let max_denom = max_denom.max(1) as u128;
+    let target = value.abs();
+    let distance = |(numer, denom): (u128, u128)| (target - numer as f64 / denom as f64).abs();
+
+    // The last two convergents, starting from the conventional 0/1 and 1/0
+    let (mut before, mut last) = ((0u128, 1u128), (1u128, 0u128));
+    let mut rest = target;
+    loop {
+        let term = rest.floor() as u128;
+        let numer = term * last.0 + before.0;
+        let denom = term * last.1 + before.1;
+        if denom > max_denom || numer > i64::MAX as u128 {
+            // The largest step toward the next convergent that stays within
+            // the bound may still be closer than the last convergent
+            let steps = ((max_denom - before.1) / last.1).min(term);
+            let semiconvergent = (before.0 + steps * last.0, before.1 + steps * last.1);
+            if steps > 0 && distance(semiconvergent) < distance(last) {
+                last = semiconvergent;
+            }
+            break;
+        }
+        (before, last) = (last, (numer, denom));
+        let fraction = rest - rest.floor();
+        if fraction == 0.0 || distance(last) == 0.0 {
+            break;
+        }
+        rest = 1.0 / fraction;
+    }
+
+    let numer = last.0 as i64;
+    let signed = if value < 0.0 { -numer } else { numer };
+    (signed, last.1 as u64, distance(last) != 0.0)
+}
+
+/// Writes the fraction nearest to `n` for the `frac` function, such as `3/8`,
+/// marking one that is only close as `1/3 (approx)`
+pub(crate) fn fraction_text(n: f64) -> Result<String, CalcError> {
+    if !n.is_finite() || n.abs() > MAX_EXACT_INTEGER {
+        return Err(CalcError::other(format!(
+            "frac argument must be finite and at most 2^53 in size, got {}",
+            n
+        )));
+    }
+    let (numer, denom, approximate) = to_fraction(n, DEFAULT_MAX_DENOMINATOR);
+    let fraction = if denom == 1 {
+        numer.to_string()
+    } else {
+        format!("{}/{}", numer, denom)
+    };
+    Ok(if approximate {
+        format!("{} (approx)", fraction)
+    } else {
+        fraction
+    })
+}
+
+/// Most digits after the point that [`format_repeating`] writes out while
+/// looking for the repetend
+const MAX_REPEATING_DIGITS: usize = 100;
+
+/// Writes the fraction `numer/denom` as a decimal, with the digits that repeat
+/// forever in parentheses, as in `0.(3)` for `1/3` and `0.58(3)` for `7/12`
+///
+/// The digits come from long division, so they are exact rather than those of
+/// the nearest `f64`. A terminating decimal has no parentheses, and a whole
+/// number no point. If the digits don't start repeating within 100 places,
+/// the fraction is written as a plain floating point number instead, and a
+/// denominator of zero gives `inf`, `-inf` or `NaN` like a division by zero.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::format_repeating;
+///
+/// assert_eq!(format_repeating(1, 3), "0.(3)");
+/// assert_eq!(format_repeating(22, 7), "3.(142857)");
+/// assert_eq!(format_repeating(7, 12), "0.58(3)");
+/// assert_eq!(format_repeating(-1, 6), "-0.1(6)");
+/// assert_eq!(format_repeating(2, 6), "0.(3)");
+/// assert_eq!(format_repeating(1, 4), "0.25");
+/// assert_eq!(format_repeating(-10, 5), "-2");
+/// assert_eq!(format_repeating(0, 7), "0");
+/// assert_eq!(format_repeating(1, 81), "0.(012345679)");
+/// assert_eq!(format_repeating(3227, 555), "5.8(144)");
+/// assert_eq!(format_repeating(i64::MIN, 1), "-9223372036854775808");
+///
+/// // 1/97 repeats every 96 digits, and 1/109 every 108
+/// assert_eq!(format_repeating(1, 97).len(), "0.()".len() + 96);
+/// assert_eq!(format_repeating(1, 109), (1.0 / 109.0).to_string());
+///
+/// assert_eq!(format_repeating(1, 0), "inf");
+/// assert_eq!(format_repeating(-1, 0), "-inf");
+/// assert_eq!(format_repeating(0, 0), "NaN");
+/// ```
+pub fn format_repeating(numer: i64, denom: u64) -> String {
+    if denom == 0 {
+        return (numer as f64 / 0.0).to_string();
+    }
+    let sign = if numer < 0 { "-" } else { "" };
+    let (numer, denom) = (u128::from(numer.unsigned_abs()), u128::from(denom));
+    let whole = numer / denom;
+    let mut remainder = numer % denom;
+    if remainder == 0 {
+        return format!("{}{}", sign, whole);
+    }
+
+    // The remainder before each digit, which starts the repetend when it
+    // comes round again
+    let mut seen = Vec::new();
+    let mut digits = String::new();
+    while remainder != 0 {
+        if let Some(start) = seen.iter().position(|r| *r == remainder) {
+            let (fixed, repetend) = digits.split_at(start);
+            return format!("{}{}.{}({})", sign, whole, fixed, repetend);
+        }
+        if digits.len() == MAX_REPEATING_DIGITS {
+            let value = numer as f64 / denom as f64;
+            return format!("{}{}", sign, value);
+        }
+        seen.push(remainder);
+        remainder *= 10;
+        digits.push(char::from(b'0' + (remainder / denom) as u8));
+        remainder %= denom;
+    }
+    format!("{}{}.{}", sign, whole, digits)
+}
+
+/// The result of [`evaluate_exact`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalResult {
+    /// The result as a floating point number
+    pub value: f64,
+    /// The exact result, or `None` if some step had to be approximated
+    pub exact: Option<Ratio>,
+}
+
+impl EvalResult {
+    /// Returns true if the result could not be computed exactly
+    pub fn is_approximate(&self) -> bool {
+        self.exact.is_none()
+    }
+}
+
+/// Formats an exact fraction together with its decimal value, as in
+/// `3/10 (0.3)`, and marks approximate results
+impl fmt::Display for EvalResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exact {
+            Some(ratio) if ratio.is_integer() => write!(f, "{}", ratio),
+            Some(ratio) => write!(f, "{} ({})", ratio, self.value),
+            None => write!(f, "{} (approximate)", self.value),
+        }
+    }
+}
+
+/// A number during exact evaluation, which stays a fraction for as long as
+/// every operation on it can be done exactly
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Exact(Ratio),
+    Approx(f64),
+}
+
+impl Number {
+    fn to_f64(self) -> f64 {
+        match self {
+            Number::Exact(ratio) => ratio.to_f64(),
+            Number::Approx(value) => value,
+        }
+    }
+
+    /// Keeps an exact result when there is one, falling back to `fallback`
+    fn or_approx(
+        exact: Option<Ratio>,
+        fallback: impl FnOnce() -> Result<f64, CalcError>,
+    ) -> Result<Number, CalcError> {
+        match exact {
+            Some(ratio) => Ok(Number::Exact(ratio)),
+            None => fallback().map(Number::Approx),
+        }
+    }
+
+    /// Converts an integer valued result of an integer-only operation back to
+    /// an exact number, as long as `f64` holds it exactly
+    fn integer_result(value: f64) -> Number {
+        if value.fract() == 0.0 && value.abs() <= MAX_EXACT_INTEGER {
+            Number::Exact(Ratio::from_integer(value as i128))
+        } else {
+            Number::Approx(value)
+        }
+    }
+}
+
+/// Evaluates a mathematical expression exactly, using fractions
+///
+/// Number literals are read as exact decimal fractions, so `0.1` is `1/10`.
+/// `+`, `-`, `*`, `/`, `//`, `%`, integer powers, `!`, comparisons, `abs`,
+/// `floor`, `ceil`, `trunc`, `round`, `min` and `max` are computed exactly,
+/// and integer-only operations such as `gcd` or the bitwise operators stay
+/// exact on exact integers. Anything else, such as `sqrt(2)`, `pi` or a
+/// fractional power, falls back to `f64`, and so does a result whose numerator
+/// or denominator grows beyond 128 bits. Those results are flagged as
+/// approximate.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{CalcError, Span, evaluate_exact};
+///
+/// let result = evaluate_exact("0.1+0.2").unwrap();
+/// assert_eq!(result.exact.unwrap().to_string(), "3/10");
+/// assert_eq!(result.value, 0.3);
+/// assert_eq!(result.to_string(), "3/10 (0.3)");
+///
+/// assert_eq!(evaluate_exact("1/3 + 1/6").unwrap().to_string(), "1/2 (0.5)");
+/// assert_eq!(evaluate_exact("(2/3)^-2").unwrap().to_string(), "9/4 (2.25)");
+/// assert_eq!(evaluate_exact("10! / 9!").unwrap().to_string(), "10");
+/// assert_eq!(evaluate_exact("round(5/2) + round(-7/2)").unwrap().to_string(), "-1");
+/// assert_eq!(evaluate_exact("0.1*3 == 0.3").unwrap().to_string(), "1");
+/// assert_eq!(evaluate_exact("-7//2 + -7%3").unwrap().to_string(), "-5");
+/// assert_eq!(evaluate_exact("floor(7/2) + gcd(12, 18)").unwrap().to_string(), "9");
+///
+/// let root = evaluate_exact("sqrt(2)").unwrap();
+/// assert!(root.is_approximate());
+/// assert_eq!(root.value, std::f64::consts::SQRT_2);
+/// assert!(evaluate_exact("2^0.5").unwrap().is_approximate());
+/// assert!(evaluate_exact("2^200").unwrap().is_approximate());
+/// assert_eq!(
+///     evaluate_exact("1/(0.5-1/2)"),
+///     Err(CalcError::DivisionByZero { span: Some(Span { start: 1, end: 2 }) })
+/// );
+/// ```
+pub fn evaluate_exact(input: &str) -> Result<EvalResult, CalcError> {
+    let options = EvalOptions::default();
+    let (input, origins) = unicode::normalize_with_origins(input, &options);
+    let expr = parser::parse(&input, &options).map_err(|e| origins.error(e))?;
+    let result = eval_exact(&expr, &options).map_err(|e| origins.error(e))?;
+    Ok(match result {
+        Number::Exact(ratio) => EvalResult {
+            value: ratio.to_f64(),
+            exact: Some(ratio),
+        },
+        Number::Approx(value) => EvalResult { value, exact: None },
+    })
+}
+
+fn eval_exact(expr: &Expr, options: &EvalOptions) -> Result<Number, CalcError> {
+    let eval = |expr| eval_exact(expr, options);
+    match expr {
+        Expr::Number { value, .. } => Ok(match Ratio::from_f64(*value) {
+            Some(ratio) => Number::Exact(ratio),
+            None => Number::Approx(*value),
+        }),
+        Expr::Var { name, span } => {
+            crate::constant(name)
+                .map(Number::Approx)
+                .ok_or_else(|| CalcError::UnknownIdentifier {
+                    name: name.clone(),
+                    span: *span,
+                })
+        }
+        Expr::Unary { op, operand, span } => {
+            let value = eval(operand)?;
+            let exact = match value {
+                Number::Exact(ratio) => unary(*op, ratio),
+                Number::Approx(_) => None,
+            };
+            Number::or_approx(exact, || eval::unary(*op, value.to_f64(), options))
+                .map_err(|e| e.at(*span))
+        }
+        Expr::Binary { op, lhs, rhs, span } => {
+            let (lhs, rhs) = (eval(lhs)?, eval(rhs)?);
+            let at = |e: CalcError| e.at(*span);
+            let (Number::Exact(a), Number::Exact(b)) = (lhs, rhs) else {
+                return eval::binary(*op, lhs.to_f64(), rhs.to_f64(), options)
+                    .map(Number::Approx)
+                    .map_err(at);
+            };
+            if b.numer == 0 && matches!(op, BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem) {
+                return Err(at(CalcError::division_by_zero()));
+            }
+            let fallback = || eval::binary(*op, a.to_f64(), b.to_f64(), options);
+            if is_integer_operator(*op) {
+                return fallback().map(Number::integer_result).map_err(at);
+            }
+            Number::or_approx(binary(*op, a, b, options), fallback).map_err(at)
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            if eval(condition)?.to_f64() != 0.0 {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+        // Sums and the like are computed in floating point, which binds variables
+        Expr::Binding { .. } => {
+            eval::eval(expr, &Context::with_options(options.clone())).map(Number::Approx)
+        }
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "lists are not supported in exact mode",
+            *span,
+        )),
+        Expr::Call { name, args, span } => {
+            let function = functions::lookup(name).ok_or_else(|| CalcError::UnknownFunction {
+                name: name.clone(),
+                span: *span,
+            })?;
+            function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+            let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            let exact: Option<Vec<Ratio>> = values
+                .iter()
+                .map(|value| match value {
+                    Number::Exact(ratio) => Some(*ratio),
+                    Number::Approx(_) => None,
+                })
+                .collect();
+            let reals: Vec<f64> = values.iter().map(|value| value.to_f64()).collect();
+            let fallback = || (function.call)(&reals, options).map_err(|e| e.at(*span));
+            match exact {
+                Some(ratios) if matches!(name.as_str(), "gcd" | "lcm") => {
+                    if ratios.iter().all(Ratio::is_integer) {
+                        fallback().map(Number::integer_result)
+                    } else {
+                        fallback().map(Number::Approx)
+                    }
+                }
+                Some(ratios) => Number::or_approx(call(name, &ratios, options.rounding), fallback),
+                None => fallback().map(Number::Approx),
+            }
+        }
+    }
+}
+
+/// Returns true for operators that only work on integers, which stay exact
+/// as long as their operands are
+fn is_integer_operator(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::BitAnd
+            | BinaryOp::BitOr
+            | BinaryOp::BitXor
+            | BinaryOp::ShiftLeft
+            | BinaryOp::ShiftRight
+    )
+}
+
+fn unary(op: UnaryOp, value: Ratio) -> Option<Ratio> {
+    match op {
+        UnaryOp::Neg => value.checked_neg(),
+        UnaryOp::Pos => Some(value),
+        UnaryOp::Percent => value.checked_mul(Ratio::new(1, 100)?),
+        UnaryOp::Factorial if value.is_integer() && value.numer >= 0 => (2..=value.numer)
+            .try_fold(Ratio::from_integer(1), |acc, i| {
+                acc.checked_mul(Ratio::from_integer(i))
+            }),
+        _ => None,
+    }
+}
+
+/// Applies an operator to two fractions, or returns `None` if the result
+/// is not a fraction that fits
+fn binary(op: BinaryOp, a: Ratio, b: Ratio, options: &EvalOptions) -> Option<Ratio> {
+    let truth = |holds: bool| Some(Ratio::from_integer(holds as i128));
+    match op {
+        BinaryOp::Add => a.checked_add(b),
+        BinaryOp::Sub => a.checked_add(b.checked_neg()?),
+        BinaryOp::Mul => a.checked_mul(b),
+        BinaryOp::Div => a.checked_mul(b.checked_recip()?),
+        BinaryOp::FloorDiv => Some(a.checked_mul(b.checked_recip()?)?.floor()),
+        // `a - b*trunc(a/b)` like the floating point remainder, then
+        // corrected for the remainder mode
+        BinaryOp::Rem => {
+            let quotient = a.checked_mul(b.checked_recip()?)?.trunc();
+            let r = a.checked_add(b.checked_mul(quotient)?.checked_neg()?)?;
+            let k = options
+                .remainder_mode
+                .correction(r.numer.cmp(&0), b.numer < 0);
+            r.checked_add(b.checked_mul(Ratio::from_integer(k.into()))?)
+        }
+        BinaryOp::Pow if b.is_integer() => a.checked_pow(i32::try_from(b.numer).ok()?),
+        BinaryOp::Less => truth(a.checked_cmp(b)? == Ordering::Less),
+        BinaryOp::LessEqual => truth(a.checked_cmp(b)? != Ordering::Greater),
+        BinaryOp::Greater => truth(a.checked_cmp(b)? == Ordering::Greater),
+        BinaryOp::GreaterEqual => truth(a.checked_cmp(b)? != Ordering::Less),
+        BinaryOp::Equal => truth(a == b),
+        BinaryOp::NotEqual => truth(a != b),
+        _ => None,
+    }
+}
+
+/// Calls a function that can be computed exactly, or returns `None` to fall
+/// back to the floating point version. `round` breaks ties the way `rounding`
+/// does.
+fn call(name: &str, args: &[Ratio], rounding: RoundingMode) -> Option<Ratio> {
+    match (name, args) {
+        ("abs", [x]) if x.numer < 0 => x.checked_neg(),
+        ("abs", [x]) => Some(*x),
+        ("floor", [x]) => Some(x.floor()),
+        ("ceil", [x]) => x.checked_neg()?.floor().checked_neg(),
+        ("trunc", [x]) => Some(x.trunc()),
+        ("round", [x]) => {
+            // In lowest terms, only a tie has a denominator of 2
+            let tie = x.denom == 2;
+            let even = x.trunc().numer % 2 == 0;
+            match rounding {
+                RoundingMode::TowardZero => Some(x.trunc()),
+                RoundingMode::HalfDown if tie => Some(x.trunc()),
+                RoundingMode::HalfEven if tie && even => Some(x.trunc()),
+                // Adding a half with the sign of `x` takes ties away from zero
+                _ => {
+                    let half = Ratio::new(if x.numer < 0 { -1 } else { 1 }, 2)?;
+                    Some(x.checked_add(half)?.trunc())
+                }
+            }
+        }
+        ("min", _) => args.iter().copied().try_fold(args[0], |acc, x| {
+            Some(if x.checked_cmp(acc)? == Ordering::Less {
+                x
+            } else {
+                acc
+            })
+        }),
+        ("max", _) => args.iter().copied().try_fold(args[0], |acc, x| {
+            Some(if x.checked_cmp(acc)? == Ordering::Greater {
+                x
+            } else {
+                acc
+            })
+        }),
+        _ => None,
+    }
+}
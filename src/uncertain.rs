@@ -0,0 +1,196 @@
+//! Evaluation of values with an uncertainty, such as `5 ± 0.1`
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions;
+use crate::parser::{BinaryOp, Expr, UnaryOp};
+
+/// Functions of one argument that an uncertainty propagates through, by their
+/// derivative
+const PROPAGATING: &[&str] = &[
+    "sqrt", "cbrt", "exp", "ln", "log", "log10", "log2", "sin", "cos", "tan", "asin", "acos",
+    "atan", "sinh", "cosh", "tanh", "abs",
+];
+
+/// A value and its uncertainty, which is never negative
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Uncertain {
+    value: f64,
+    sigma: f64,
+}
+
+impl Uncertain {
+    fn exact(value: f64) -> Self {
+        Uncertain { value, sigma: 0.0 }
+    }
+
+    fn is_exact(&self) -> bool {
+        self.sigma == 0.0
+    }
+}
+
+/// Returns whether `expr` writes a value with an uncertainty anywhere
+pub(crate) fn has_uncertainty(expr: &Expr) -> bool {
+    match expr {
+        Expr::Number { .. } | Expr::Var { .. } => false,
+        Expr::Binary {
+            op: BinaryOp::PlusMinus,
+            ..
+        } => true,
+        Expr::Unary { operand, .. } => has_uncertainty(operand),
+        Expr::Binary { lhs, rhs, .. } => has_uncertainty(lhs) || has_uncertainty(rhs),
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => has_uncertainty(condition) || has_uncertainty(then) || has_uncertainty(otherwise),
+        Expr::Call { args, .. } | Expr::List { items: args, .. } => {
+            args.iter().any(has_uncertainty)
+        }
+        Expr::Binding { body, args, .. } => {
+            has_uncertainty(body) || args.iter().any(has_uncertainty)
+        }
+    }
+}
+
+/// Evaluates an expression with values such as `5 ± 0.1`, returning the
+/// central value and its uncertainty
+///
+/// Uncertainties propagate to first order and add linearly, which gives the
+/// largest error the inputs allow: `(5 ± 0.1) * (2 ± 0.05)` is `10 ± 0.45`.
+/// Numbers written without one are exact.
+pub(crate) fn eval_uncertain(expr: &Expr, context: &Context) -> Result<(f64, f64), CalcError> {
+    eval_expr(expr, context).map(|result| (result.value, result.sigma))
+}
+
+fn eval_expr(expr: &Expr, context: &Context) -> Result<Uncertain, CalcError> {
+    if !has_uncertainty(expr) {
+        return eval::eval(expr, context).map(Uncertain::exact);
+    }
+    let eval = |expr| eval_expr(expr, context);
+    match expr {
+        Expr::Unary {
+            op: op @ (UnaryOp::Neg | UnaryOp::Pos | UnaryOp::Percent),
+            operand,
+            ..
+        } => {
+            let x = eval(operand)?;
+            let scale = match op {
+                UnaryOp::Neg => -1.0,
+                UnaryOp::Pos => 1.0,
+                _ => 0.01,
+            };
+            Ok(Uncertain {
+                value: x.value * scale,
+                sigma: x.sigma * scale.abs(),
+            })
+        }
+        Expr::Binary { op, lhs, rhs, span } => {
+            let (a, b) = (eval(lhs)?, eval(rhs)?);
+            if *op == BinaryOp::PlusMinus {
+                if !a.is_exact() || !b.is_exact() {
+                    return Err(CalcError::other_at(
+                        "a value and its uncertainty must be exact numbers",
+                        *span,
+                    ));
+                }
+                return Ok(Uncertain {
+                    value: a.value,
+                    sigma: b.value.abs(),
+                });
+            }
+            let value =
+                eval::binary(*op, a.value, b.value, context.options()).map_err(|e| e.at(*span))?;
+            let sigma = match op {
+                BinaryOp::Add | BinaryOp::Sub => a.sigma + b.sigma,
+                BinaryOp::Mul => b.value.abs() * a.sigma + a.value.abs() * b.sigma,
+                BinaryOp::Div => (a.sigma + value.abs() * b.sigma) / b.value.abs(),
+                BinaryOp::Pow => {
+                    let by_base = (b.value * a.value.powf(b.value - 1.0)).abs() * a.sigma;
+                    // An exact exponent leaves out the logarithm, which a
+                    // negative base doesn't have
+                    let by_exponent = if b.is_exact() {
+                        0.0
+                    } else {
+                        (value * a.value.ln()).abs() * b.sigma
+                    };
+                    by_base + by_exponent
+                }
+                _ => {
+                    return Err(CalcError::other_at(
+                        format!(
+                            "'{}' has no rule for propagating an uncertainty",
+                            op.symbol()
+                        ),
+                        *span,
+                    ));
+                }
+            };
+            Ok(Uncertain { value, sigma })
+        }
+        Expr::Call { name, args, span } if PROPAGATING.contains(&name.as_str()) => {
+            let function = functions::lookup(name).expect("a built-in function");
+            function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+            let x = eval(&args[0])?;
+            let call = |at: f64| (function.call)(&[at], context.options()).map_err(|e| e.at(*span));
+            let slope = eval::derivative(x.value, call)?;
+            Ok(Uncertain {
+                value: call(x.value)?,
+                sigma: slope.abs() * x.sigma,
+            })
+        }
+        Expr::Call { name, span, .. } => Err(CalcError::other_at(
+            format!("{} has no rule for propagating an uncertainty", name),
+            *span,
+        )),
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let condition = eval(condition)?;
+            if !condition.is_exact() {
+                return Err(CalcError::other("a condition can't have an uncertainty"));
+            }
+            if condition.value != 0.0 {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+        Expr::Binding { form, .. } => Err(CalcError::other(format!(
+            "{} has no rule for propagating an uncertainty",
+            form.name()
+        ))),
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "a list can't hold values with an uncertainty",
+            *span,
+        )),
+        Expr::Unary { span, .. } => Err(CalcError::other_at(
+            "there is no rule for propagating an uncertainty through the operator",
+            *span,
+        )),
+        Expr::Number { .. } | Expr::Var { .. } => unreachable!("a number is exact"),
+    }
+}
+
+/// Writes a value and its uncertainty as `10 ± 0.45`, with the uncertainty
+/// rounded to two significant figures and the value to the same place
+pub(crate) fn format(value: f64, sigma: f64) -> String {
+    if sigma == 0.0 || !sigma.is_finite() {
+        return format!("{} ± {}", value, sigma);
+    }
+    // Decimal places of the second significant figure of the uncertainty
+    let places = 1 - sigma.log10().floor() as i32;
+    let round = |x: f64| {
+        if places > 0 {
+            let text = format!("{:.*}", places as usize, x);
+            text.parse::<f64>().unwrap_or(x)
+        } else {
+            let unit = 10f64.powi(-places);
+            (x / unit).round() * unit
+        }
+    };
+    format!("{} ± {}", round(value), round(sigma))
+}
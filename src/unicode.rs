@@ -0,0 +1,212 @@
+use std::borrow::Cow;
+
+use crate::error::CalcError;
+use crate::lexer::Span;
+use crate::options::EvalOptions;
+
+/// Vulgar fractions as `(character, numerator, denominator)`
+const FRACTIONS: &[(char, u8, u8)] = &[
+    ('½', 1, 2),
+    ('↉', 0, 3),
+    ('⅓', 1, 3),
+    ('⅔', 2, 3),
+    ('¼', 1, 4),
+    ('¾', 3, 4),
+    ('⅕', 1, 5),
+    ('⅖', 2, 5),
+    ('⅗', 3, 5),
+    ('⅘', 4, 5),
+    ('⅙', 1, 6),
+    ('⅚', 5, 6),
+    ('⅐', 1, 7),
+    ('⅛', 1, 8),
+    ('⅜', 3, 8),
+    ('⅝', 5, 8),
+    ('⅞', 7, 8),
+    ('⅑', 1, 9),
+    ('⅒', 1, 10),
+];
+
+/// Rewrites characters that have an ASCII equivalent, such as the full-width
+/// digits and operators typed with East Asian input methods and Arabic-Indic
+/// digits, so that `１２３＋４` reads as `123+4`
+///
+/// Superscripts after an operand become a power, so `5²` reads as `5^2`, and
+/// vulgar fractions become a division, with a whole number directly before
+/// one added to it, so `1½` reads as `(1+1/2)`. Superscripts in any other
+/// place are left for the lexer to report.
+///
+/// Nothing is rewritten unless [`EvalOptions::normalize_unicode`] is set.
+/// Positions in the rewritten input can be moved back to `input` with the
+/// [`Origins`] from [`normalize_with_origins`].
+pub(crate) fn normalize<'a>(input: &'a str, options: &EvalOptions) -> Cow<'a, str> {
+    normalize_with_origins(input, options).0
+}
+
+/// Where each byte of a rewriting of an input comes from, so that positions
+/// in the rewriting can be moved back to the input
+pub(crate) struct Origins<'a> {
+    input: &'a str,
+    /// The position in `input` of the character that each byte comes from,
+    /// or `None` if positions are kept as they are
+    positions: Option<Vec<usize>>,
+}
+
+impl<'a> Origins<'a> {
+    /// Origins that keep every position as it is, for a rewriting whose
+    /// positions can't be traced back
+    pub(crate) fn unchanged(input: &'a str) -> Self {
+        Origins {
+            input,
+            positions: None,
+        }
+    }
+
+    /// Returns the span of the input that `span` of its rewriting was written in
+    pub(crate) fn span(&self, span: Span) -> Span {
+        let Some(positions) = &self.positions else {
+            return span;
+        };
+        if span.start == span.end {
+            let start = positions
+                .get(span.start)
+                .copied()
+                .unwrap_or(self.input.len());
+            return Span { start, end: start };
+        }
+        // The end of the character that the last byte comes from
+        let last = positions[span.end - 1];
+        let width = self.input[last..].chars().next().map_or(0, char::len_utf8);
+        Span {
+            start: positions[span.start],
+            end: last + width,
+        }
+    }
+
+    /// Moves the position of `error` from the rewriting to the input
+    pub(crate) fn error(&self, error: CalcError) -> CalcError {
+        match self.positions {
+            Some(_) => error.map_span(|span| self.span(span)),
+            None => error,
+        }
+    }
+}
+
+/// Rewrites `input` like [`normalize`], also giving where in `input` each
+/// byte of the rewritten input comes from
+pub(crate) fn normalize_with_origins<'a>(
+    input: &'a str,
+    options: &EvalOptions,
+) -> (Cow<'a, str>, Origins<'a>) {
+    let rewritten =
+        |ch| ascii_equivalent(ch).is_some() || superscript(ch).is_some() || fraction(ch).is_some();
+    if !options.normalize_unicode || !input.chars().any(rewritten) {
+        return (Cow::Borrowed(input), Origins::unchanged(input));
+    }
+
+    let mut output = String::with_capacity(input.len());
+    let mut origins = Vec::with_capacity(input.len());
+    // Whether the output is inside a run of superscripts that make a power
+    let mut in_power = false;
+    for (position, ch) in input.char_indices() {
+        rewrite(ch, &mut output, &mut origins, &mut in_power);
+        origins.resize(output.len(), position);
+    }
+    let origins = Origins {
+        input,
+        positions: Some(origins),
+    };
+    (Cow::Owned(output), origins)
+}
+
+/// Writes the rewriting of `ch` to `output`, keeping `origins` in step with
+/// any bytes inserted before the end
+fn rewrite(ch: char, output: &mut String, origins: &mut Vec<usize>, in_power: &mut bool) {
+    if let Some(ascii) = superscript(ch) {
+        if !*in_power && ends_operand(output) {
+            output.push('^');
+            *in_power = true;
+        }
+        output.push(if *in_power { ascii } else { ch });
+        return;
+    }
+    *in_power = false;
+
+    if let Some((numerator, denominator)) = fraction(ch) {
+        let whole = output.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        let mixed = whole < output.len() && !ends_operand(&output[..whole]);
+        if mixed {
+            // The `(` comes from the first digit of the whole number
+            output.insert(whole, '(');
+            origins.insert(whole, origins[whole]);
+            output.push_str(&format!("+{}/{})", numerator, denominator));
+        } else {
+            // `x½` is a product, while `x(1/2)` would be a call
+            if ends_operand(output) {
+                output.push('*');
+            }
+            output.push_str(&format!("({}/{})", numerator, denominator));
+        }
+        return;
+    }
+
+    output.push(ascii_equivalent(ch).unwrap_or(ch));
+}
+
+/// Returns whether `text` ends with something a power can apply to
+fn ends_operand(text: &str) -> bool {
+    text.ends_with(|c: char| c.is_alphanumeric() || matches!(c, '_' | ')' | '!' | '%' | '.'))
+}
+
+/// Returns the ASCII character for a superscript that may be part of a power
+pub(crate) fn superscript(ch: char) -> Option<char> {
+    let ascii = match ch {
+        '⁰' => '0',
+        '¹' => '1',
+        '²' => '2',
+        '³' => '3',
+        '⁴'..='⁹' => char::from_digit(ch as u32 - '⁰' as u32, 10)?,
+        '⁺' => '+',
+        '⁻' => '-',
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+/// Returns whether `ch` is written raised, like the superscripts that
+/// [`superscript`] reads and letters such as `ⁿ` that it doesn't
+pub(crate) fn is_raised(ch: char) -> bool {
+    matches!(ch, '¹' | '²' | '³' | '\u{2070}'..='\u{207F}' | '\u{02B0}'..='\u{02B8}' | '\u{1D2C}'..='\u{1D6A}')
+}
+
+/// Returns the numerator and denominator of a vulgar fraction
+fn fraction(ch: char) -> Option<(u8, u8)> {
+    FRACTIONS
+        .iter()
+        .find(|(fraction, _, _)| *fraction == ch)
+        .map(|(_, numerator, denominator)| (*numerator, *denominator))
+}
+
+/// Returns the ASCII character that `ch` stands for, if it is a digit in
+/// another script or a full-width operator
+fn ascii_equivalent(ch: char) -> Option<char> {
+    let zero = match ch {
+        '０'..='９' => '０',
+        // Arabic-Indic and Eastern Arabic-Indic (Persian and Urdu) digits
+        '٠'..='٩' => '٠',
+        '۰'..='۹' => '۰',
+        '＋' => return Some('+'),
+        '－' => return Some('-'),
+        '＊' => return Some('*'),
+        '／' => return Some('/'),
+        '（' => return Some('('),
+        '）' => return Some(')'),
+        '％' => return Some('%'),
+        '＾' => return Some('^'),
+        '＝' => return Some('='),
+        '，' => return Some(','),
+        '．' => return Some('.'),
+        _ => return None,
+    };
+    char::from_digit(ch as u32 - zero as u32, 10)
+}
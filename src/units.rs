@@ -0,0 +1,532 @@
+//! Evaluation of quantities with units, such as `5 km + 300 m`
+
+use std::fmt;
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions;
+use crate::options::EvalOptions;
+use crate::parser::{self, BinaryOp, Expr, UnaryOp};
+use crate::words;
+
+/// Exponents of the base dimensions: length, mass, time, data and temperature
+type Dimension = [i32; 5];
+
+const LENGTH: Dimension = [1, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0];
+const DATA: Dimension = [0, 0, 0, 1, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1];
+
+/// Names of the base dimensions, in the order of a [`Dimension`]
+const DIMENSION_NAMES: [&str; 5] = ["length", "mass", "time", "data", "temperature"];
+
+/// A unit that can follow a number
+#[derive(Debug, PartialEq)]
+struct UnitDef {
+    symbol: &'static str,
+    dimension: Dimension,
+    /// Size in metres, kilograms, seconds, bits or kelvins
+    factor: f64,
+    /// Reading at 0 °C, for temperature scales that don't start at absolute
+    /// zero
+    offset: f64,
+}
+
+const fn unit(symbol: &'static str, dimension: Dimension, factor: f64) -> UnitDef {
+    UnitDef {
+        symbol,
+        dimension,
+        factor,
+        offset: 0.0,
+    }
+}
+
+const fn temperature(symbol: &'static str, factor: f64, offset: f64) -> UnitDef {
+    UnitDef {
+        symbol,
+        dimension: TEMPERATURE,
+        factor,
+        offset,
+    }
+}
+
+/// Kelvin reading at 0 °C
+const FREEZING_POINT: f64 = 273.15;
+
+/// Every supported unit. Inches are `inch`, since `in` converts.
+const UNITS: &[UnitDef] = &[
+    unit("m", LENGTH, 1.0),
+    unit("km", LENGTH, 1e3),
+    unit("cm", LENGTH, 1e-2),
+    unit("mm", LENGTH, 1e-3),
+    unit("um", LENGTH, 1e-6),
+    unit("nm", LENGTH, 1e-9),
+    unit("inch", LENGTH, 0.0254),
+    unit("ft", LENGTH, 0.3048),
+    unit("yd", LENGTH, 0.9144),
+    unit("mi", LENGTH, 1609.344),
+    unit("kg", MASS, 1.0),
+    unit("g", MASS, 1e-3),
+    unit("mg", MASS, 1e-6),
+    unit("t", MASS, 1e3),
+    unit("lb", MASS, 0.45359237),
+    unit("oz", MASS, 0.028349523125),
+    unit("s", TIME, 1.0),
+    unit("ms", TIME, 1e-3),
+    unit("us", TIME, 1e-6),
+    unit("ns", TIME, 1e-9),
+    unit("min", TIME, 60.0),
+    unit("h", TIME, 3600.0),
+    unit("day", TIME, 86400.0),
+    unit("week", TIME, 604800.0),
+    unit("bit", DATA, 1.0),
+    unit("B", DATA, 8.0),
+    unit("kB", DATA, 8e3),
+    unit("MB", DATA, 8e6),
+    unit("GB", DATA, 8e9),
+    unit("TB", DATA, 8e12),
+    unit("KiB", DATA, 8.0 * 1024.0),
+    unit("MiB", DATA, 8.0 * 1048576.0),
+    unit("GiB", DATA, 8.0 * 1073741824.0),
+    temperature("K", 1.0, FREEZING_POINT),
+    temperature("degC", 1.0, 0.0),
+    temperature("degF", 5.0 / 9.0, 32.0),
+];
+
+fn lookup(symbol: &str) -> Option<&'static UnitDef> {
+    UNITS.iter().find(|unit| unit.symbol == symbol)
+}
+
+/// Returns true if `name` is the symbol of a unit
+pub(crate) fn is_unit(name: &str) -> bool {
+    lookup(name).is_some()
+}
+
+/// Lists the supported units, as `(symbol, dimension)` pairs
+pub(crate) fn all() -> impl Iterator<Item = (&'static str, &'static str)> {
+    UNITS.iter().map(|unit| {
+        let index = unit.dimension.iter().position(|&power| power != 0);
+        (unit.symbol, DIMENSION_NAMES[index.unwrap_or(0)])
+    })
+}
+
+/// A product of powers of units, such as `km/h`, which is empty for plain
+/// numbers
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Unit {
+    terms: Vec<(&'static UnitDef, i32)>,
+}
+
+impl Unit {
+    fn of(def: &'static UnitDef) -> Self {
+        Unit {
+            terms: vec![(def, 1)],
+        }
+    }
+
+    /// Returns the temperature scale if the unit is just that, such as `degC`,
+    /// rather than a rate such as `degC/min`
+    fn scale(&self) -> Option<&'static UnitDef> {
+        match self.terms[..] {
+            [(def, 1)] if def.dimension == TEMPERATURE => Some(def),
+            _ => None,
+        }
+    }
+
+    /// Returns true for a plain number without units
+    pub fn is_none(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    fn dimension(&self) -> Dimension {
+        let mut dimension = [0; 5];
+        for (def, power) in &self.terms {
+            for (total, base) in dimension.iter_mut().zip(def.dimension) {
+                *total += base * power;
+            }
+        }
+        dimension
+    }
+
+    /// Size of the unit in metres, kilograms, seconds and bits
+    fn factor(&self) -> f64 {
+        self.terms
+            .iter()
+            .map(|(def, power)| def.factor.powi(*power))
+            .product()
+    }
+
+    fn mul(&self, other: &Unit) -> Unit {
+        let mut terms = self.terms.clone();
+        for &(def, power) in &other.terms {
+            match terms.iter_mut().find(|(existing, _)| *existing == def) {
+                Some((_, total)) => *total += power,
+                None => terms.push((def, power)),
+            }
+        }
+        terms.retain(|(_, power)| *power != 0);
+        Unit { terms }
+    }
+
+    fn powi(&self, exponent: i32) -> Unit {
+        let terms = self
+            .terms
+            .iter()
+            .map(|&(def, power)| (def, power * exponent))
+            .collect();
+        Unit { terms }
+    }
+
+    /// Describes the unit for error messages
+    fn describe(&self) -> String {
+        if self.is_none() {
+            "a plain number".to_string()
+        } else {
+            format!("'{}'", self)
+        }
+    }
+}
+
+/// Formats as `km`, `m^2` or `kg*m/s^2`, and plain numbers as nothing
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |positive: bool| {
+            self.terms
+                .iter()
+                .filter(|(_, power)| (*power > 0) == positive)
+                .map(|(def, power)| match power.abs() {
+                    1 => def.symbol.to_string(),
+                    power => format!("{}^{}", def.symbol, power),
+                })
+                .collect::<Vec<_>>()
+                .join("*")
+        };
+        let (numerator, denominator) = (join(true), join(false));
+        match (numerator.is_empty(), denominator.is_empty()) {
+            (_, true) => write!(f, "{}", numerator),
+            (true, false) => write!(f, "1/{}", denominator),
+            (false, false) => write!(f, "{}/{}", numerator, denominator),
+        }
+    }
+}
+
+/// A number with a unit, the result of [`evaluate_units`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    fn plain(value: f64) -> Self {
+        Quantity {
+            value,
+            unit: Unit::default(),
+        }
+    }
+
+    /// Expresses the quantity in `unit`, which must measure the same thing,
+    /// taking into account where temperature scales start
+    fn convert(&self, unit: &Unit) -> Result<Quantity, CalcError> {
+        let (Some(from), Some(to)) = (self.unit.scale(), unit.scale()) else {
+            return self.convert_difference(unit);
+        };
+        let celsius = (self.value - from.offset) * from.factor;
+        if celsius < -FREEZING_POINT {
+            return Err(CalcError::other(format!("{} is below absolute zero", self)));
+        }
+        Ok(Quantity {
+            value: celsius / to.factor + to.offset,
+            unit: unit.clone(),
+        })
+    }
+
+    /// Expresses the quantity in `unit` as a difference, so that a temperature
+    /// rise of `9 degF` is `5 degC`. The error names `unit` first, as it's the
+    /// left operand of `+` and `-`.
+    fn convert_difference(&self, unit: &Unit) -> Result<Quantity, CalcError> {
+        if self.unit == *unit {
+            return Ok(self.clone());
+        }
+        if self.unit.dimension() != unit.dimension() {
+            return Err(CalcError::other(format!(
+                "incompatible units {} and {}",
+                unit.describe(),
+                self.unit.describe()
+            )));
+        }
+        Ok(Quantity {
+            value: self.value * self.unit.factor() / unit.factor(),
+            unit: unit.clone(),
+        })
+    }
+
+    /// Returns the value of a quantity whose units cancel out, for operations
+    /// that only work on plain numbers
+    fn number(&self, operation: &str) -> Result<f64, CalcError> {
+        self.convert(&Unit::default())
+            .map(|plain| plain.value)
+            .map_err(|_| {
+                CalcError::other(format!(
+                    "{} requires a plain number, got {}",
+                    operation, self
+                ))
+            })
+    }
+}
+
+/// Formats as the value followed by the unit, such as `5.3 km`
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.unit.is_none() {
+            write!(f, "{}", self.value)
+        } else {
+            write!(f, "{} {}", self.value, self.unit)
+        }
+    }
+}
+
+/// Converts a plain temperature reading between two of the scales `K`,
+/// `degC` and `degF`
+pub(crate) fn convert_temperature(value: f64, from: &str, to: &str) -> Result<f64, CalcError> {
+    let unit = |symbol| Unit::of(lookup(symbol).expect("temperature scales are in the table"));
+    let quantity = Quantity {
+        value,
+        unit: unit(from),
+    };
+    quantity.convert(&unit(to)).map(|converted| converted.value)
+}
+
+/// Evaluates an expression of quantities with units
+///
+/// A unit follows a number, as in `5 km` or `9.81 m/s^2`, and binds tighter
+/// than any operator apart from `^`, so `10 km / 2 h` is `5 km/h`. Adding,
+/// subtracting and comparing quantities converts the right operand to the unit
+/// of the left one, and fails for quantities that measure different things.
+/// `x in unit` or `x to unit` converts the result to another unit.
+///
+/// The units cover length (`m`, `km`, `cm`, `mm`, `um`, `nm`, `inch`, `ft`,
+/// `yd`, `mi`), mass (`kg`, `g`, `mg`, `t`, `lb`, `oz`), time (`s`, `ms`, `us`,
+/// `ns`, `min`, `h`, `day`, `week`), data (`bit`, `B`, `kB`, `MB`, `GB`,
+/// `TB`, `KiB`, `MiB`, `GiB`) and temperature (`K`, `degC`, `degF`).
+/// Converting a temperature with `in` accounts for where each scale starts, and
+/// fails below absolute zero, while a temperature added to another one is a
+/// difference, so `20 degC + 9 degF` is `25 degC`. `abs`, `min`, `max`, `floor`, `ceil`, `round`
+/// and `trunc` keep the unit of their first argument, and other functions need
+/// plain numbers.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::evaluate_units;
+///
+/// assert_eq!(evaluate_units("5 km + 300 m").unwrap().to_string(), "5.3 km");
+/// assert_eq!(evaluate_units("10 mi in km").unwrap().to_string(), "16.09344 km");
+/// assert_eq!(evaluate_units("10 km / 2 h").unwrap().to_string(), "5 km/h");
+/// assert_eq!(evaluate_units("3 m * 4 m").unwrap().to_string(), "12 m^2");
+/// assert_eq!(evaluate_units("2 GiB to MiB").unwrap().to_string(), "2048 MiB");
+/// assert_eq!(evaluate_units("90 min in h").unwrap().to_string(), "1.5 h");
+/// assert_eq!(evaluate_units("1 km / 1 m").unwrap().to_string(), "1 km/m");
+/// assert_eq!(evaluate_units("1 km / 1 m in 1").unwrap().to_string(), "1000");
+/// assert_eq!(evaluate_units("1 kg > 900 g").unwrap().to_string(), "1");
+/// assert_eq!(evaluate_units("max(1 ft, 30 cm) in cm").unwrap().to_string(), "30.48 cm");
+/// assert_eq!(evaluate_units("sqrt(16)*2").unwrap().to_string(), "8");
+/// assert_eq!(evaluate_units("100 degC in degF").unwrap().to_string(), "212 degF");
+/// assert_eq!(evaluate_units("0 degC to K").unwrap().to_string(), "273.15 K");
+/// assert_eq!(evaluate_units("20 degC + 9 degF").unwrap().to_string(), "25 degC");
+/// assert_eq!(evaluate_units("0 degC > 30 degF").unwrap().to_string(), "1");
+/// assert!(evaluate_units("-300 degC in K").unwrap_err().to_string().contains("below absolute zero"));
+///
+/// let speed = evaluate_units("100 m / 9.58 s").unwrap();
+/// assert_eq!(speed.unit.to_string(), "m/s");
+///
+/// assert_eq!(
+///     evaluate_units("5 km + 3 s").unwrap_err().to_string(),
+///     "incompatible units 'km' and 's'"
+/// );
+/// assert!(evaluate_units("5 km + 3").unwrap_err().to_string().contains("incompatible units"));
+/// assert!(evaluate_units("sqrt(4 m)").unwrap_err().to_string().contains("requires a plain number"));
+/// ```
+pub fn evaluate_units(input: &str) -> Result<Quantity, CalcError> {
+    evaluate_units_with_context(input, &Context::new())
+}
+
+/// Evaluates an expression with units like [`evaluate_units`], with the
+/// settings and variables of `context`
+///
+/// Variables and `ans` are plain numbers, and hide a unit of the same name.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::{Context, EvalOptions, Separator, evaluate_units_with_context};
+///
+/// let mut context = Context::with_options(EvalOptions::new().decimal_separator(Separator::Comma));
+/// context.set("laps", 4.0).unwrap();
+/// assert_eq!(evaluate_units_with_context("laps * 0,4 km", &context).unwrap().to_string(), "1.6 km");
+/// context.set("m", 3.0).unwrap();
+/// assert_eq!(evaluate_units_with_context("2 m", &context).unwrap().to_string(), "6");
+/// ```
+pub fn evaluate_units_with_context(input: &str, context: &Context) -> Result<Quantity, CalcError> {
+    let options = context.options();
+    let (input, origins) = words::rewrite_with_origins(input, options, words::in_context(context))?;
+    let expr = parser::parse_with_units(&input, options).map_err(|e| origins.error(e))?;
+    eval_units(&expr, context).map_err(|e| origins.error(e))
+}
+
+fn eval_units(expr: &Expr, context: &Context) -> Result<Quantity, CalcError> {
+    let options = context.options();
+    let eval = |expr| eval_units(expr, context);
+    match expr {
+        Expr::Number { value, .. } => Ok(Quantity::plain(*value)),
+        Expr::Var { name, span } => {
+            if let Some(value) = context.lookup(name) {
+                return Ok(Quantity::plain(value));
+            }
+            lookup(name)
+                .map(|def| Quantity {
+                    value: 1.0,
+                    unit: Unit::of(def),
+                })
+                .ok_or_else(|| CalcError::UnknownIdentifier {
+                    name: name.clone(),
+                    span: *span,
+                })
+        }
+        Expr::Unary { op, operand, span } => {
+            let value = eval(operand)?;
+            let result = match op {
+                UnaryOp::Neg | UnaryOp::Pos | UnaryOp::Percent => {
+                    eval::unary(*op, value.value, options).map(|number| Quantity {
+                        value: number,
+                        unit: value.unit,
+                    })
+                }
+                _ => eval::unary(*op, value.number("this operator")?, options).map(Quantity::plain),
+            };
+            result.map_err(|e| e.at(*span))
+        }
+        Expr::Binary { op, lhs, rhs, span } => {
+            let (a, b) = (eval(lhs)?, eval(rhs)?);
+            binary(*op, a, b, options).map_err(|e| e.at(*span))
+        }
+        Expr::Conditional {
+            condition,
+            then,
+            otherwise,
+        } => {
+            if eval(condition)?.value != 0.0 {
+                eval(then)
+            } else {
+                eval(otherwise)
+            }
+        }
+        Expr::Binding { form, .. } => Err(CalcError::other(format!(
+            "{} is not supported with units",
+            form.name()
+        ))),
+        Expr::List { span, .. } => Err(CalcError::other_at(
+            "lists are not supported with units",
+            *span,
+        )),
+        Expr::Call { name, args, span } => {
+            let function = functions::lookup(name).ok_or_else(|| CalcError::UnknownFunction {
+                name: name.clone(),
+                span: *span,
+            })?;
+            function.check_arity(args.len()).map_err(|e| e.at(*span))?;
+            let values = args.iter().map(eval).collect::<Result<Vec<_>, _>>()?;
+            // Which arguments are in the unit of the first, as opposed to
+            // plain numbers such as the digits of `round`
+            let same_unit = match name.as_str() {
+                "min" | "max" | "clamp" => values.len(),
+                "abs" | "floor" | "ceil" | "round" | "trunc" => 1,
+                _ => 0,
+            };
+            let unit = if same_unit > 0 {
+                values[0].unit.clone()
+            } else {
+                Unit::default()
+            };
+            let numbers = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    if i < same_unit {
+                        value.convert(&unit).map(|value| value.value)
+                    } else {
+                        value.number(name)
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let value = (function.call)(&numbers, options).map_err(|e| e.at(*span))?;
+            Ok(Quantity { value, unit })
+        }
+    }
+}
+
+/// Applies a binary operator to two quantities
+fn binary(
+    op: BinaryOp,
+    a: Quantity,
+    b: Quantity,
+    options: &EvalOptions,
+) -> Result<Quantity, CalcError> {
+    let value = |value| Quantity {
+        value,
+        unit: a.unit.clone(),
+    };
+    match op {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Rem => {
+            let b = b.convert_difference(&a.unit)?;
+            eval::binary(op, a.value, b.value, options).map(value)
+        }
+        _ if op.is_comparison() => {
+            let b = b.convert(&a.unit)?;
+            eval::binary(op, a.value, b.value, options).map(Quantity::plain)
+        }
+        BinaryOp::Mul | BinaryOp::Div => {
+            let unit = match op {
+                BinaryOp::Mul => a.unit.mul(&b.unit),
+                _ => a.unit.mul(&b.unit.powi(-1)),
+            };
+            let value = eval::binary(op, a.value, b.value, options)?;
+            Ok(Quantity { value, unit })
+        }
+        BinaryOp::FloorDiv => {
+            let quotient = binary(BinaryOp::Div, a, b, options)?;
+            Ok(Quantity::plain(quotient.number("//")?.floor()))
+        }
+        BinaryOp::Pow => {
+            let exponent = b.number("an exponent")?;
+            let unit = if a.unit.is_none() {
+                Unit::default()
+            } else if exponent.fract() == 0.0 && exponent.abs() <= i32::MAX as f64 {
+                a.unit.powi(exponent as i32)
+            } else {
+                return Err(CalcError::other(format!(
+                    "can't raise {} to a fractional power",
+                    a.unit.describe()
+                )));
+            };
+            Ok(Quantity {
+                value: a.value.powf(exponent),
+                unit,
+            })
+        }
+        // The target's value is the size of the unit, which is 1 for `in km`
+        BinaryOp::Convert => {
+            if b.value != 1.0 {
+                return Err(CalcError::other(format!(
+                    "can only convert to a unit, got {}",
+                    b
+                )));
+            }
+            a.convert(&b.unit)
+        }
+        _ => {
+            let (x, y) = (a.number(op.symbol())?, b.number(op.symbol())?);
+            eval::binary(op, x, y, options).map(Quantity::plain)
+        }
+    }
+}
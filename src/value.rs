@@ -0,0 +1,124 @@
+use std::fmt;
+
+use crate::angles::dms;
+use crate::duration;
+use crate::error::CalcError;
+use crate::eval;
+use crate::functions::MAX_EXACT_INTEGER;
+use crate::options::{EvalOptions, RoundingMode};
+use crate::primes::factorize;
+use crate::rational;
+use crate::uncertain;
+
+/// The result of [`evaluate_value`](crate::evaluate_value), which is a number
+/// unless the expression is a call to a function that shows its argument as
+/// text, such as `hex(255)`, or works with durations such as `1:30`, amounts
+/// of money such as `$5`, uncertain values such as `5 ± 0.1` or lists such as
+/// `[1, 2, 3]`. With a context, a line ending in a function definition gives a
+/// [`Value::Definition`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    /// A duration in seconds, which displays as `h:mm` or `h:mm:ss`
+    Duration(f64),
+    /// An amount of money in the currency with `symbol`, which displays with
+    /// two decimals, as in `$1349.98`
+    Money {
+        symbol: char,
+        amount: f64,
+    },
+    /// A value with an uncertainty, as in `5 ± 0.1`, which displays with
+    /// the uncertainty rounded to two significant figures
+    Uncertain {
+        value: f64,
+        sigma: f64,
+    },
+    /// A list of numbers, as in `[2, 4, 6]`
+    List(Vec<f64>),
+    /// A function definition such as `f(x) = x^2`, which has no value and
+    /// displays as the function's name and parameters, as in `f(x)`
+    Definition(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Duration(seconds) => write!(f, "{}", duration::format(*seconds)),
+            Value::Money { symbol, amount } => {
+                let sign = if *amount < 0.0 { "-" } else { "" };
+                let amount = RoundingMode::default().format(amount.abs(), 2);
+                write!(f, "{}{}{}", sign, symbol, amount)
+            }
+            Value::Uncertain { value, sigma } => write!(f, "{}", uncertain::format(*value, *sigma)),
+            Value::List(items) => {
+                let items: Vec<_> = items.iter().map(f64::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Definition(signature) => write!(f, "{}", signature),
+        }
+    }
+}
+
+/// Shows the argument of a function whose result is text
+type Format = fn(f64, &EvalOptions) -> Result<String, CalcError>;
+
+/// Functions whose result is text rather than a number, which are only
+/// evaluated as the whole of an expression
+const TEXT_FUNCTIONS: &[(&str, Format)] = &[
+    ("hex", |n, options| in_base(n, options, "hex", 16, "0x")),
+    ("bin", |n, options| in_base(n, options, "bin", 2, "0b")),
+    ("oct", |n, options| in_base(n, options, "oct", 8, "0o")),
+    ("factorize", |n, _| {
+        factorize(n).map(|factors| factors.to_string())
+    }),
+    ("dms", |n, _| dms(n).map(|angle| angle.to_string())),
+    ("frac", |n, _| rational::fraction_text(n)),
+];
+
+/// Returns the function that shows its argument as text for a call to `name`,
+/// if it is one
+pub(crate) fn text_function(name: &str) -> Option<Format> {
+    TEXT_FUNCTIONS
+        .iter()
+        .find(|(function, _)| *function == name)
+        .map(|(_, format)| *format)
+}
+
+/// The error for using a function whose result is text inside an expression
+pub(crate) fn text_in_expression(name: &str) -> CalcError {
+    CalcError::other(format!(
+        "type error: {} returns text, not a number, so it can't be part of an expression",
+        name
+    ))
+}
+
+/// Writes a non-negative integer in base `radix` after `prefix`, the same form
+/// the lexer reads. With [`EvalOptions::bit_width`] set, a negative integer is
+/// written as its two's complement in that many bits.
+fn in_base(
+    n: f64,
+    options: &EvalOptions,
+    name: &str,
+    radix: u32,
+    prefix: &str,
+) -> Result<String, CalcError> {
+    let n = if let Some(width) = options.bit_width {
+        let requires = format!("{} requires", name);
+        eval::bit_pattern(eval::integer_bits(n, Some(width), &requires)?, Some(width))
+    } else if n.fract() != 0.0 || !(0.0..=MAX_EXACT_INTEGER).contains(&n) {
+        return Err(CalcError::other(format!(
+            "{} argument must be a non-negative integer up to 2^53, got {}",
+            name, n
+        )));
+    } else {
+        n as u64
+    };
+    Ok(match radix {
+        16 => format!("{}{:x}", prefix, n),
+        8 => format!("{}{:o}", prefix, n),
+        _ => format!("{}{:b}", prefix, n),
+    })
+}
@@ -0,0 +1,398 @@
+use std::borrow::Cow;
+
+use crate::context::Context;
+use crate::error::CalcError;
+use crate::lexer::Span;
+use crate::options::EvalOptions;
+use crate::unicode::Origins;
+use crate::{constant, functions, unicode};
+
+/// Words for the numbers below twenty, by value
+const SMALL: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+/// Words for the multiples of ten from twenty, by value over ten minus two
+const TENS: [&str; 8] = [
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+/// Words that scale everything before them in a number, largest first
+const SCALES: [(&str, u64); 3] = [
+    ("billion", 1_000_000_000),
+    ("million", 1_000_000),
+    ("thousand", 1_000),
+];
+
+/// Operator words and the symbols they stand for. A word followed by `by`
+/// takes it along, as in `divided by`.
+const OPERATORS: [(&str, &str); 7] = [
+    ("plus", "+"),
+    ("minus", "-"),
+    ("negative", "-"),
+    ("times", "*"),
+    ("multiplied", "*"),
+    ("over", "/"),
+    ("divided", "/"),
+];
+
+/// Rewrites `input` like [`unicode::normalize`], after converting number and
+/// operator words to symbols if [`EvalOptions::number_words`] is set. Names
+/// for which `known` is true are kept as they are, like the built-in ones.
+pub(crate) fn rewrite<'a>(
+    input: &'a str,
+    options: &EvalOptions,
+    known: impl Fn(&str) -> bool,
+) -> Result<Cow<'a, str>, CalcError> {
+    if !options.number_words {
+        return Ok(unicode::normalize(input, options));
+    }
+    let expression = convert(input, known)?;
+    Ok(Cow::Owned(
+        unicode::normalize(&expression, options).into_owned(),
+    ))
+}
+
+/// Rewrites `input` like [`rewrite`], also giving where in `input` each byte
+/// of the rewriting comes from
+///
+/// Positions are kept as they are once number words are converted, since
+/// the words don't line up with what they become.
+pub(crate) fn rewrite_with_origins<'a>(
+    input: &'a str,
+    options: &EvalOptions,
+    known: impl Fn(&str) -> bool,
+) -> Result<(Cow<'a, str>, Origins<'a>), CalcError> {
+    if !options.number_words {
+        return Ok(unicode::normalize_with_origins(input, options));
+    }
+    Ok((rewrite(input, options, known)?, Origins::unchanged(input)))
+}
+
+/// Converts English number words and operator words in `input` to digits and
+/// symbols, so that dictated text such as `two plus three times four` becomes
+/// `2 + 3 * 4`
+///
+/// Numbers may be spelled out up to the billions, with or without hyphens and
+/// `and`, as in `one hundred and twenty-three` or `four thousand five`, and
+/// `point` starts the digits of a fractional part, as in `three point one
+/// four`. The operator words are `plus`, `minus`, `negative`, `times`,
+/// `multiplied by`, `divided by` and `over`. Digits, symbols and the names of
+/// built-in functions and constants are kept as they are, so `sqrt(nine)`
+/// becomes `sqrt(9)`, and so is a name assigned to, as in `x = five`. Any
+/// other word is an error.
+///
+/// Positions in errors refer to `input`.
+///
+/// # Examples
+/// ```
+/// use calculator_rust::words_to_expression;
+///
+/// assert_eq!(words_to_expression("two plus three times four"), Ok("2 + 3 * 4".to_string()));
+/// assert_eq!(words_to_expression("one hundred twenty-three"), Ok("123".to_string()));
+/// assert_eq!(
+///     words_to_expression("one million two hundred and five thousand and six"),
+///     Ok("1205006".to_string())
+/// );
+/// assert_eq!(words_to_expression("a hundred"), Ok("100".to_string()));
+/// assert_eq!(words_to_expression("Ten divided by four"), Ok("10 / 4".to_string()));
+/// assert_eq!(words_to_expression("six over 3"), Ok("6 / 3".to_string()));
+/// assert_eq!(words_to_expression("negative seven multiplied by two"), Ok("- 7 * 2".to_string()));
+/// assert_eq!(words_to_expression("three point one four"), Ok("3.14".to_string()));
+/// assert_eq!(words_to_expression("(one plus two)^2"), Ok("(1 + 2)^2".to_string()));
+/// assert_eq!(words_to_expression("sqrt(sixteen) plus pi"), Ok("sqrt(16) + pi".to_string()));
+/// assert_eq!(words_to_expression("twenty thirty"), Ok("20 30".to_string()));
+/// assert_eq!(words_to_expression("0xff plus 2e3"), Ok("0xff + 2e3".to_string()));
+/// assert_eq!(words_to_expression("16#FF plus one"), Ok("16#FF + 1".to_string()));
+/// assert_eq!(words_to_expression("rate = two point five"), Ok("rate = 2.5".to_string()));
+///
+/// assert_eq!(
+///     words_to_expression("two plus banana").unwrap_err().to_string(),
+///     "unrecognized word 'banana' at position 9"
+/// );
+/// assert_eq!(
+///     words_to_expression("ten divided four").unwrap_err().to_string(),
+///     "expected 'by' after 'divided' at position 4"
+/// );
+/// assert!(words_to_expression("one and two").unwrap_err().to_string().contains("unrecognized word 'and'"));
+/// assert!(words_to_expression("point five").unwrap_err().to_string().contains("'point' must follow a number"));
+/// ```
+pub fn words_to_expression(input: &str) -> Result<String, CalcError> {
+    convert(input, |_| false)
+}
+
+/// Converts words like [`words_to_expression`], also keeping the names for
+/// which `known` is true
+fn convert(input: &str, known: impl Fn(&str) -> bool) -> Result<String, CalcError> {
+    let words = split(input);
+    let mut output = String::with_capacity(input.len());
+    let mut number = Number::default();
+    // Whitespace after a number word, written out once the number ends
+    let mut gap = String::new();
+    let mut i = 0;
+    while i < words.len() {
+        let (start, text) = words[i];
+        i += 1;
+        let lower = text.to_ascii_lowercase();
+
+        if !text.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            if text.trim().is_empty() && number.started {
+                gap.push_str(text);
+                continue;
+            }
+            number.flush(&mut output, &mut gap);
+            output.push_str(text);
+            continue;
+        }
+
+        if number.extend(&lower) {
+            gap.clear();
+            continue;
+        }
+        if lower == "point" {
+            if !number.started || number.fraction.is_some() {
+                let span = Span {
+                    start,
+                    end: start + text.len(),
+                };
+                return Err(CalcError::syntax("'point' must follow a number", span));
+            }
+            number.fraction = Some(String::new());
+            gap.clear();
+            continue;
+        }
+        number.flush(&mut output, &mut gap);
+        if number.start(&lower) {
+            continue;
+        }
+
+        if let Some((_, symbol)) = OPERATORS.iter().find(|(word, _)| *word == lower) {
+            if matches!(lower.as_str(), "multiplied" | "divided") {
+                // Whitespace comes between the two words
+                let by = words.get(i + 1).map(|(_, next)| next.to_ascii_lowercase());
+                if by.as_deref() != Some("by") {
+                    let span = Span {
+                        start,
+                        end: start + text.len(),
+                    };
+                    let message = format!("expected 'by' after '{}'", text);
+                    return Err(CalcError::syntax(message, span));
+                }
+                i += 2;
+            }
+            output.push_str(symbol);
+            continue;
+        }
+        if functions::lookup(text).is_some()
+            || constant(text).is_some()
+            || text == "ans"
+            || known(text)
+            || assigned(&words[i..])
+        {
+            output.push_str(text);
+            continue;
+        }
+        let span = Span {
+            start,
+            end: start + text.len(),
+        };
+        return Err(CalcError::syntax(
+            format!("unrecognized word '{}'", text),
+            span,
+        ));
+    }
+    number.flush(&mut output, &mut gap);
+    Ok(output)
+}
+
+/// Returns whether the pieces after a word start with a single `=`, which
+/// makes the word the name of a new variable, as in `x = five`
+fn assigned(after: &[(usize, &str)]) -> bool {
+    let mut rest = after
+        .iter()
+        .map(|(_, piece)| *piece)
+        .filter(|piece| !piece.trim().is_empty());
+    rest.next() == Some("=") && rest.next() != Some("=")
+}
+
+/// Splits `input` into words, runs of whitespace and single other characters,
+/// each with its position. A word starts with a letter and runs on through
+/// letters, digits and `_`, while a run that starts with a digit, such as
+/// `0xff` or `16#FF`, is kept whole. The hyphen of `twenty-three` separates
+/// two words.
+fn split(input: &str) -> Vec<(usize, &str)> {
+    let mut pieces = Vec::new();
+    let mut rest = input;
+    while let Some(ch) = rest.chars().next() {
+        let start = input.len() - rest.len();
+        let len = if ch.is_ascii_digit() {
+            // Including the `#` of a literal such as `16#FF`
+            rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_' && c != '#')
+                .unwrap_or(rest.len())
+        } else if ch.is_ascii_alphabetic() {
+            rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len())
+        } else if ch.is_whitespace() {
+            rest.find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len())
+        } else {
+            ch.len_utf8()
+        };
+        let hyphenated = ch == '-'
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+            && TENS
+                .iter()
+                .any(|tens| input[..start].to_ascii_lowercase().ends_with(tens));
+        pieces.push((start, if hyphenated { " " } else { &rest[..len] }));
+        rest = &rest[len..];
+    }
+    pieces
+}
+
+/// A number being read from words
+#[derive(Default)]
+struct Number {
+    started: bool,
+    /// The value of the scales already complete, such as the `4000` of
+    /// `four thousand five`
+    total: u64,
+    /// The value since the last scale, below a thousand
+    current: u64,
+    /// The smallest scale used so far, which the next one must be below
+    last_scale: Option<u64>,
+    /// Whether `and` may come next, as it may after `hundred` or a scale
+    after_hundred: bool,
+    /// Digits after `point`
+    fraction: Option<String>,
+}
+
+impl Number {
+    /// Starts a number with `word` if it is a number word, or with the `a` of
+    /// `a hundred`
+    fn start(&mut self, word: &str) -> bool {
+        if word == "a" {
+            *self = Number {
+                started: true,
+                current: 1,
+                ..Number::default()
+            };
+            return true;
+        }
+        let number = Number {
+            started: true,
+            ..Number::default()
+        };
+        let previous = std::mem::replace(self, number);
+        if self.extend(word) {
+            return true;
+        }
+        *self = previous;
+        false
+    }
+
+    /// Continues the number with `word`, returning false if it can't
+    fn extend(&mut self, word: &str) -> bool {
+        if !self.started {
+            return false;
+        }
+        if let Some(fraction) = &mut self.fraction {
+            let Some(digit) = SMALL[..10].iter().position(|small| *small == word) else {
+                return false;
+            };
+            fraction.push_str(&digit.to_string());
+            return true;
+        }
+        // The units of the current group, which a tens or units word may add to
+        let units = self.current % 100;
+        let tens_free = units == 0;
+        if let Some(n) = SMALL.iter().position(|small| *small == word) {
+            let fits = if n >= 10 {
+                tens_free
+            } else {
+                units.is_multiple_of(10)
+            };
+            if !fits || (n == 0 && self.current + self.total > 0) {
+                return false;
+            }
+            self.current += n as u64;
+            self.after_hundred = false;
+            return true;
+        }
+        if let Some(n) = TENS.iter().position(|tens| *tens == word) {
+            if !tens_free {
+                return false;
+            }
+            self.current += (n as u64 + 2) * 10;
+            self.after_hundred = false;
+            return true;
+        }
+        if word == "hundred" {
+            if self.current >= 100 {
+                return false;
+            }
+            self.current = self.current.max(1) * 100;
+            self.after_hundred = true;
+            return true;
+        }
+        if let Some((_, scale)) = SCALES.iter().find(|(name, _)| *name == word) {
+            if self.last_scale.is_some_and(|last| last <= *scale) {
+                return false;
+            }
+            self.total += self.current.max(1) * scale;
+            self.current = 0;
+            self.last_scale = Some(*scale);
+            self.after_hundred = true;
+            return true;
+        }
+        if word == "and" && self.after_hundred {
+            self.after_hundred = false;
+            return true;
+        }
+        false
+    }
+
+    /// Writes out the number, if one was started, and the whitespace after it
+    fn flush(&mut self, output: &mut String, gap: &mut String) {
+        if !self.started {
+            return;
+        }
+        output.push_str(&(self.total + self.current).to_string());
+        if let Some(fraction) = self.fraction.take() {
+            output.push('.');
+            output.push_str(&fraction);
+        }
+        output.push_str(gap);
+        gap.clear();
+        *self = Number::default();
+    }
+}
+
+/// Returns whether `name` is a variable, a list or a function defined or registered in `context`,
+/// which words mode keeps as it is
+pub(crate) fn in_context(context: &Context) -> impl Fn(&str) -> bool + '_ {
+    |name| {
+        context.lookup(name).is_some()
+            || context.get_list(name).is_some()
+            || context.function(name).is_some()
+            || context.native(name).is_some()
+    }
+}